@@ -8,6 +8,12 @@ use gtk4::prelude::*;
 use stateful::{React, Callbacks};
 use crate::ui::ConnectionList;
 use crate::ui::ConnectionBox;
+use crate::ui::SafetyDialog;
+use crate::ui::DisconnectDialog;
+use crate::ui::QueryExportDialog;
+use crate::ui::ParametersDialog;
+use crate::ui::QueriesWorkspace;
+use crate::sql::parsing::ScriptParameter;
 use std::boxed;
 use glib::MainContext;
 use std::collections::HashMap;
@@ -15,24 +21,36 @@ use super::listener::SqlListener;
 use crate::server::*;
 use std::thread;
 use crate::sql::object::{DBInfo};
-use crate::sql::StatementOutput;
+use crate::sql::{StatementOutput, ServerNotice, DatabaseError};
 use crate::ui::ExecButton;
 use chrono::prelude::*;
 use serde::{Serialize, Deserialize};
 use std::rc::Rc;
 use std::cell::RefCell;
 use crate::ui::QueriesWindow;
+use crate::ui::QueriesTitlebar;
+use crate::client::OpenedScripts;
+use filecase::MultiArchiverImpl;
 use crate::sql::object::DBObject;
 use crate::ui::{SchemaTree};
 use crate::sql::object::DBType;
 use crate::sql::copy::*;
 use std::time::Duration;
 use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use crate::client::SharedUserState;
-use super::listener::ExecMode;
+use super::listener::{ExecMode, ImportTransaction};
 use crate::tables::table::Table;
+use crate::tables::dialect::{CsvDialect, CsvRecords};
+use crate::tables::csv_import::{ImportPlan, csv_file_dialect, preview_csv_file, rejected_table};
+use std::path::Path;
 use crate::ui::Certificate;
 use crate::ui::TlsVersion;
+use crate::sql::{SafetyPolicies, SafetyRule, Policy};
+use crate::sql::parsing::AnyStatement;
+use sqlparser::ast::Statement;
+use sqlparser::tokenizer::Token;
 
 // The actual connection info that is persisted on disk (excludes password for obvious
 // security reasons).
@@ -60,7 +78,16 @@ pub struct ConnectionInfo {
     pub min_tls_version : Option<TlsVersion>,
     
     // When this connection was last established (datetime-formatted).
-    pub dt : Option<String>
+    pub dt : Option<String>,
+
+    // What to do with statements matching each safety rule for this connection.
+    #[serde(default)]
+    pub policies : SafetyPolicies,
+
+    // Sessions for this connection are started with default_transaction_read_only = on.
+    // This is only set before the connection is established.
+    #[serde(default)]
+    pub read_only : bool
 
 }
 
@@ -99,7 +126,9 @@ impl Default for ConnectionInfo {
             database : String::from(DEFAULT_DB),
             dt : None,
             cert : None,
-            min_tls_version : None
+            min_tls_version : None,
+            policies : SafetyPolicies::default(),
+            read_only : false
         }
     }
 
@@ -118,6 +147,8 @@ pub enum ConnectionAction {
     UpdateHost(String),
     UpdateUser(String),
     UpdateDB(String),
+    UpdatePolicy(SafetyRule, Policy),
+    UpdateReadOnly(bool),
     EraseCertificate(String),
     AddCertificate(Certificate),
     Remove(i32),
@@ -248,6 +279,18 @@ impl ConnectionSet {
                             conns.0[ix as usize].database = db;
                         }
                     },
+
+                    ConnectionAction::UpdatePolicy(rule, policy) => {
+                        if let Some(ix) = conns.1 {
+                            conns.0[ix as usize].policies.set(rule, policy);
+                        }
+                    },
+
+                    ConnectionAction::UpdateReadOnly(read_only) => {
+                        if let Some(ix) = conns.1 {
+                            conns.0[ix as usize].read_only = read_only;
+                        }
+                    },
                     
                     // Called when the user connects to the database
                     // and the date field is set at ActiveConnection::Accepted.
@@ -358,6 +401,21 @@ impl React<ConnectionBox> for ConnectionSet {
                
             }
         });
+        conn_bx.read_only_btn.connect_toggled({
+            let send = self.send.clone();
+            move |btn| {
+                send.send(ConnectionAction::UpdateReadOnly(btn.is_active())).unwrap();
+            }
+        });
+        for (rule, combo) in &conn_bx.policy_btn.combos {
+            let send = self.send.clone();
+            let rule = *rule;
+            combo.connect_changed(move |combo| {
+                if let Some(policy) = combo.active_id().and_then(|id| id.parse::<Policy>().ok() ) {
+                    send.send(ConnectionAction::UpdatePolicy(rule, policy)).unwrap();
+                }
+            });
+        }
     }
     
 }
@@ -580,6 +638,18 @@ pub enum ErrorKind {
 
 }
 
+/// Execution waiting for the user to confirm the statements matching safety rules set to Policy::Confirm.
+#[derive(Debug, Clone)]
+pub enum PendingExecution {
+
+    // Statement sequence, with its parameter values.
+    Script(String, HashMap<String, String>),
+
+    // CSV file path, with the column mapping chosen at the import wizard.
+    Import(String, ImportPlan)
+
+}
+
 pub enum ActiveConnectionAction {
 
     ConnectRequest(ConnURI),
@@ -588,11 +658,36 @@ pub enum ActiveConnectionAction {
 
     ConnectFailure(ConnectionInfo, String),
 
-    Disconnect,
+    // Closes the session opened for the given connection (if any). Sessions
+    // with uncommitted changes are kept open, and the user is warned instead.
+    Disconnect(ConnectionInfo),
+
+    // Closes the session even if it has uncommitted changes (which are rolled back).
+    DiscardAndDisconnect(ConnectionInfo),
+
+    // Turns auto-commit off (if true) or on (if false) for the focused session.
+    SetManualTransactions(bool),
+
+    TransactionRequest(TransactionCommand),
+
+    TransactionCompleted(usize, TransactionCommand, StatementOutput),
+
+    // Notices raised by the last execution of the session with the given id.
+    NoticesReceived(usize, Vec<ServerNotice>),
 
     // Requires an arbitrary sequence of SQL commands.
     ExecutionRequest(String),
 
+    // Same as ExecutionRequest, with the values the user informed for the script parameters.
+    ParameterizedExecutionRequest(String, HashMap<String, String>),
+
+    // Same as ParameterizedExecutionRequest, but the user already confirmed the statements matching
+    // safety rules set to Policy::Confirm.
+    ConfirmedExecutionRequest(String, HashMap<String, String>),
+
+    // Safety policies changed for the connection (applied to its session, if open).
+    UpdatePolicies(ConnectionInfo),
+
     // Requires a sigle table or view name to do a single SQL query.
     SingleQueryRequest,
 
@@ -600,22 +695,122 @@ pub enum ActiveConnectionAction {
 
     EndSchedule,
 
-    ExecutionCompleted(Vec<StatementOutput>),
+    // Results are tagged with the session that produced them.
+    ExecutionCompleted(usize, Vec<StatementOutput>),
 
-    SingleQueryCompleted(StatementOutput),
+    SingleQueryCompleted(usize, StatementOutput),
 
-    SchemaUpdate(Option<Vec<DBObject>>),
+    SchemaUpdate(usize, Option<Vec<DBObject>>),
 
     ObjectSelected(Option<Vec<usize>>),
 
     TableImport(String),
 
+    // Imports a CSV file with the column mapping chosen at the import wizard.
+    MappedImport(String, ImportPlan),
+
+    // Same as MappedImport, but the user already confirmed the generated statements matching
+    // safety rules set to Policy::Confirm.
+    ConfirmedMappedImport(String, ImportPlan),
+
+    // Streams the result of a query into the file at the given path.
+    QueryExportRequest(String, String),
+
+    // Number of rows written so far by the running query export.
+    QueryExportProgress(usize),
+
+    QueryExportCancel,
+
+    // Session id, file path and number of rows written (or the error that stopped the export).
+    QueryExportCompleted(usize, String, Result<usize, String>),
+
+    // The user selected a script at the file list (or no script at all).
+    ScriptSelected(Option<usize>),
+
+    ScriptClosed(usize),
+
+    // Binds the currently-selected script to the session with the given id.
+    BindScript(usize),
+
     Error(String)
 
 }
 
 pub type ActiveConnCallbacks = (Callbacks<(ConnectionInfo, Option<DBInfo>)>, Callbacks<()>, Callbacks<String>);
 
+/* State kept for each open database connection. Every session has its own listener
+thread, so a statement running against one database does not block the others. */
+struct Session {
+
+    info : ConnectionInfo,
+
+    db_info : Option<DBInfo>,
+
+    listener : SqlListener,
+
+    /* Keeps the current database schema. Must be Some(schema) when database information
+    was received after connection. Potentially updated when queries executes a DDL
+    statement (create table, create view...). */
+    schema : Option<Vec<DBObject>>,
+
+    /* Keeps the currently-selected object at the schema tree (might be a table, view,
+    column or schema. Must necessarily be a node of the schema variable above. */
+    selected_obj : Option<DBObject>,
+
+    schema_valid : bool,
+
+    /* Active schedule needs to be wrapped in a RefCell because it is shared with
+    any new callbacks that start when the user schedule a set of statements. */
+    active_schedule : Rc<RefCell<bool>>,
+
+    tx : TransactionStatus,
+
+    // Statement sequence of the last execution request, as typed by the user.
+    last_sql : String,
+
+    // Statement sequence sent at the last execution request, with parameters replaced by placeholders.
+    sent_sql : String
+
+}
+
+/// State of the transaction kept open across executions when the session
+/// is in manual transaction mode (auto-commit off).
+#[derive(Debug, Clone, Default)]
+pub struct TransactionStatus {
+
+    // Whether auto-commit is off for the session.
+    pub manual : bool,
+
+    // Whether there is a transaction open.
+    pub open : bool,
+
+    // Whether a statement failed within the open transaction, in which case the
+    // server ignores anything other than a rollback.
+    pub failed : bool,
+
+    // Whether any statement other than a query succeeded within the open transaction.
+    pub pending : bool,
+
+    // Savepoints created within the open transaction, in creation order.
+    pub savepoints : Vec<String>
+
+}
+
+impl TransactionStatus {
+
+    fn close(&mut self) {
+        self.open = false;
+        self.failed = false;
+        self.pending = false;
+        self.savepoints.clear();
+    }
+
+}
+
+pub fn session_label(info : &ConnectionInfo) -> String {
+    format!("{}@{}/{}", info.user, info.host, info.database)
+}
+
 pub struct ActiveConnection {
 
     user_state : SharedUserState,
@@ -634,6 +829,8 @@ pub struct ActiveConnection {
 
     on_exec_result : Callbacks<Vec<StatementOutput>>,
 
+    on_session_result : Callbacks<(usize, Vec<StatementOutput>, String)>,
+
     on_single_query_result : Callbacks<Table>,
 
     send : glib::Sender<ActiveConnectionAction>,
@@ -642,8 +839,70 @@ pub struct ActiveConnection {
     
     on_schema_update : Callbacks<Option<Vec<DBObject>>>,
 
-    on_object_selected : Callbacks<Option<DBObject>>
+    on_object_selected : Callbacks<Option<DBObject>>,
+
+    on_session_switched : Callbacks<Option<(usize, ConnectionInfo, Option<DBInfo>)>>,
+
+    on_session_closed : Callbacks<(usize, ConnectionInfo)>,
+
+    // Statement sequence waiting for confirmation (with its parameter values), and the statements that require it.
+    on_confirm_request : Callbacks<(PendingExecution, Vec<(SafetyRule, String)>)>,
+
+    // Statement sequence waiting for parameter values, with its parameters and the
+    // values last informed for the script.
+    on_parameters_request : Callbacks<(String, Vec<ScriptParameter>, HashMap<String, String>)>,
+
+    // Transaction state of the focused session.
+    on_transaction_changed : Callbacks<TransactionStatus>,
 
+    // Notices raised by the last execution of a session.
+    on_session_notices : Callbacks<(usize, Vec<ServerNotice>)>,
+
+    // Character offset and length of the token that caused an error, relative
+    // to the executed statement sequence.
+    on_error_location : Callbacks<(usize, usize)>,
+
+    // Called when the user tries to close a session with uncommitted changes.
+    on_disconnect_warning : Callbacks<ConnectionInfo>,
+
+    // CSV file chosen for import, the table it is imported to, its first records and their inferred types.
+    on_import_preview : Callbacks<(String, DBObject, CsvRecords, Vec<DBType>, CsvDialect)>,
+
+    // Path of the file a query export started writing to.
+    on_export_started : Callbacks<String>,
+
+    // Rows written so far by the running query export.
+    on_export_progress : Callbacks<usize>,
+
+    on_export_finished : Callbacks<()>,
+
+    // Connections with an open session, shared with the connection switch so that
+    // toggling it for an already-open (or already-closed) connection is a no-op.
+    open_infos : Rc<RefCell<Vec<ConnectionInfo>>>
+
+}
+
+fn launch_session_listener(id : usize, send : &glib::Sender<ActiveConnectionAction>) -> SqlListener {
+    let send = send.clone();
+    SqlListener::launch(move |mut results, notices, mode| {
+
+        // The messages of the last execution are always replaced, but schema
+        // and transaction control queries only replace them if they raised any.
+        if notices.len() > 0 || matches!(mode, ExecMode::Multiple) {
+            send.send(ActiveConnectionAction::NoticesReceived(id, notices)).unwrap();
+        }
+        match mode {
+            ExecMode::Single => {
+                send.send(ActiveConnectionAction::SingleQueryCompleted(id, results.remove(0))).unwrap();
+            },
+            ExecMode::Multiple => {
+                send.send(ActiveConnectionAction::ExecutionCompleted(id, results)).unwrap();
+            },
+            ExecMode::Transaction(cmd) => {
+                send.send(ActiveConnectionAction::TransactionCompleted(id, cmd, results.remove(0))).unwrap();
+            }
+        }
+    })
 }
 
 impl ActiveConnection {
@@ -659,6 +918,7 @@ impl ActiveConnection {
     pub fn new(user_state : &SharedUserState) -> Self {
         let (on_connected, on_disconnected, on_error) : ActiveConnCallbacks = Default::default();
         let on_exec_result : Callbacks<Vec<StatementOutput>> = Default::default();
+        let on_session_result : Callbacks<(usize, Vec<StatementOutput>, String)> = Default::default();
         let on_single_query_result : Callbacks<Table> = Default::default();
         let on_conn_failure : Callbacks<(ConnectionInfo, String)> = Default::default();
         let (send, recv) = glib::MainContext::channel::<ActiveConnectionAction>(glib::source::PRIORITY_DEFAULT);
@@ -667,39 +927,37 @@ impl ActiveConnection {
         let on_schema_invalidated : Callbacks<()> = Default::default();
         let on_schedule_start : Callbacks<()> = Default::default();
         let on_schedule_end : Callbacks<()> = Default::default();
-        
-        let mut schema_valid = true;
-        
-        /* Active schedule, unlike the other state variables, needs to be wrapped in a RefCell
-        because it is shared with any new callbacks that start when the user schedule a set of statements. */
-        let active_schedule = Rc::new(RefCell::new(false));
-        
-        // Thread that waits for SQL statements via the standard library mpsc channels (with a
-        // single producer).
-        let mut listener = SqlListener::launch({
-            let send = send.clone();
-            move |mut results, mode| {
-                match mode {
-                    ExecMode::Single => {
-                        send.send(ActiveConnectionAction::SingleQueryCompleted(results.remove(0))).unwrap();
-                    },
-                    ExecMode::Multiple => {
-                        send.send(ActiveConnectionAction::ExecutionCompleted(results)).unwrap();
-                    }
-                }
-            }
-        });
-        
-        /* Keeps the current database schema. Must be Some(schema) when connected,
-        or None when not connected OR database information could not be received after
-        connection (those two conditions aren't discriminated). Potentially updated when
-        queries executes a DDL statement (create table, create view...). */
-        let mut schema : Option<Vec<DBObject>> = None;
-        
-        /* Keeps the currently-selected object at the schema tree (might be a table, view,
-        column or schema. Must necessarily be a node of the schema variable above. */
-        let mut selected_obj : Option<DBObject> = None;
-        
+        let on_session_switched : Callbacks<Option<(usize, ConnectionInfo, Option<DBInfo>)>> = Default::default();
+        let on_session_closed : Callbacks<(usize, ConnectionInfo)> = Default::default();
+        let on_confirm_request : Callbacks<(PendingExecution, Vec<(SafetyRule, String)>)> = Default::default();
+        let on_parameters_request : Callbacks<(String, Vec<ScriptParameter>, HashMap<String, String>)> = Default::default();
+        let on_transaction_changed : Callbacks<TransactionStatus> = Default::default();
+        let on_session_notices : Callbacks<(usize, Vec<ServerNotice>)> = Default::default();
+        let on_error_location : Callbacks<(usize, usize)> = Default::default();
+        let on_disconnect_warning : Callbacks<ConnectionInfo> = Default::default();
+        let on_import_preview : Callbacks<(String, DBObject, CsvRecords, Vec<DBType>, CsvDialect)> = Default::default();
+        let on_export_started : Callbacks<String> = Default::default();
+        let on_export_progress : Callbacks<usize> = Default::default();
+        let on_export_finished : Callbacks<()> = Default::default();
+        let open_infos : Rc<RefCell<Vec<ConnectionInfo>>> = Default::default();
+
+        // All open sessions, keyed by an id that is never re-used during the application lifetime.
+        let mut sessions : HashMap<usize, Session> = HashMap::new();
+        let mut next_id : usize = 0;
+
+        // The session bound to the currently-selected script. Statements are always sent here.
+        let mut focused : Option<usize> = None;
+
+        // Maps the index of each opened script to the session it is bound to.
+        let mut bindings : HashMap<usize, usize> = HashMap::new();
+        let mut selected_script : Option<usize> = None;
+
+        // Parameter values last informed for each opened script.
+        let mut param_values : HashMap<usize, HashMap<String, String>> = HashMap::new();
+
+        // Set to stop the running query export (if any).
+        let mut export_cancel : Option<Arc<AtomicBool>> = None;
+
         recv.attach(None, {
             let send = send.clone();
             let (on_connected, on_disconnected, on_error, on_exec_result, on_single_query_result) = (
@@ -714,11 +972,42 @@ impl ActiveConnection {
             let on_object_selected = on_object_selected.clone();
             let on_schema_update = on_schema_update.clone();
             let on_schema_invalidated = on_schema_invalidated.clone();
+            let on_session_result = on_session_result.clone();
+            let on_session_switched = on_session_switched.clone();
+            let on_session_closed = on_session_closed.clone();
+            let on_confirm_request = on_confirm_request.clone();
+            let on_parameters_request = on_parameters_request.clone();
+            let on_transaction_changed = on_transaction_changed.clone();
+            let on_session_notices = on_session_notices.clone();
+            let on_error_location = on_error_location.clone();
+            let on_disconnect_warning = on_disconnect_warning.clone();
+            let on_import_preview = on_import_preview.clone();
+            let (on_export_started, on_export_progress, on_export_finished) = (
+                on_export_started.clone(),
+                on_export_progress.clone(),
+                on_export_finished.clone()
+            );
+            let open_infos = open_infos.clone();
             let user_state = (*user_state).clone();
             
             let mut trying_connection = false;
             
             move |action| {
+
+                // Confirmed statements go through the same checks as any other execution request.
+                let confirmed = matches!(
+                    action,
+                    ActiveConnectionAction::ConfirmedExecutionRequest(_, _) | ActiveConnectionAction::ConfirmedMappedImport(_, _)
+                );
+
+                let values = match &action {
+                    ActiveConnectionAction::ParameterizedExecutionRequest(_, values) |
+                    ActiveConnectionAction::ConfirmedExecutionRequest(_, values) => Some(values.clone()),
+                    _ => None
+                };
+
+                let force_disconnect = matches!(action, ActiveConnectionAction::DiscardAndDisconnect(_));
+
                 match action {
 
                     // At this stage, the connection URI was successfully parsed, 
@@ -730,6 +1019,11 @@ impl ActiveConnection {
                             on_error.call(format!("Previous connect attempt not finished yet"));
                             return glib::source::Continue(true);
                         }
+
+                        if sessions.values().any(|s| s.info.is_like(&uri.info) ) {
+                            on_error.call(format!("Connection already open"));
+                            return glib::source::Continue(true);
+                        }
                         
                         trying_connection = true;
                         
@@ -769,81 +1063,317 @@ impl ActiveConnection {
                     },
 
                     // At this stage, the connection is active, and the URI is already
-                    // forgotten.
+                    // forgotten. Each accepted connection gets its own session and listener thread,
+                    // which becomes the focused session and is bound to the selected script.
                     ActiveConnectionAction::ConnectAccepted(conn, db_info) => {
                         
                         trying_connection = false;
-                        schema = db_info.as_ref().map(|info| info.schema.clone() );
-                        selected_obj = None;
                         let info = conn.conn_info();
+                        let id = next_id;
+                        next_id += 1;
+                        let mut listener = launch_session_listener(id, &send);
                         if let Err(e) = listener.update_engine(conn) {
                             eprintln!("{}", e);
                         }
+                        sessions.insert(id, Session {
+                            info : info.clone(),
+                            schema : db_info.as_ref().map(|info| info.schema.clone() ),
+                            db_info : db_info.clone(),
+                            listener,
+                            selected_obj : None,
+                            schema_valid : true,
+                            active_schedule : Rc::new(RefCell::new(false)),
+                            tx : TransactionStatus::default(),
+                            last_sql : String::new(),
+                            sent_sql : String::new()
+                        });
+                        open_infos.borrow_mut().push(info.clone());
+                        if let Some(script_ix) = selected_script {
+                            bindings.insert(script_ix, id);
+                        }
+                        focused = Some(id);
                         on_connected.call((info, db_info));
+                        notify_focus(&sessions, focused, &on_session_switched, &on_schema_update, &on_schedule_start, &on_schedule_end, &on_transaction_changed);
                     },
                     
-                    ActiveConnectionAction::Disconnect => {
-                        trying_connection = false;
-                        schema = None;
-                        selected_obj = None;
-                        active_schedule.replace(false);
-                        on_disconnected.call(());
+                    ActiveConnectionAction::Disconnect(info) | ActiveConnectionAction::DiscardAndDisconnect(info) => {
+                        let opt_id = sessions.iter().find(|(_, s)| s.info.is_like(&info) ).map(|(id, _)| *id );
+                        if let Some(id) = opt_id {
+                            if sessions[&id].tx.pending && !force_disconnect {
+                                on_disconnect_warning.call(info.clone());
+                                return glib::Continue(true);
+                            }
+                            let session = sessions.remove(&id).unwrap();
+                            session.active_schedule.replace(false);
+                            open_infos.borrow_mut().retain(|i| !i.is_like(&session.info) );
+                            bindings.retain(|_, bound| *bound != id );
+                            on_session_closed.call((id, session.info.clone()));
+
+                            if focused == Some(id) {
+
+                                // Fall back to the most recently opened session, binding the
+                                // selected script to it.
+                                focused = sessions.keys().max().cloned();
+                                if let (Some(script_ix), Some(new_id)) = (selected_script, focused) {
+                                    bindings.insert(script_ix, new_id);
+                                }
+                                notify_focus(&sessions, focused, &on_session_switched, &on_schema_update, &on_schedule_start, &on_schedule_end, &on_transaction_changed);
+                            }
+
+                            if sessions.is_empty() {
+                                trying_connection = false;
+                                on_disconnected.call(());
+                            }
+                        }
+                    },
+
+                    ActiveConnectionAction::ScriptSelected(opt_ix) => {
+                        selected_script = opt_ix;
+                        if let Some(ix) = opt_ix {
+                            match bindings.get(&ix).cloned() {
+                                Some(id) => {
+                                    if focused != Some(id) {
+                                        focused = Some(id);
+                                        notify_focus(&sessions, focused, &on_session_switched, &on_schema_update, &on_schedule_start, &on_schedule_end, &on_transaction_changed);
+                                    }
+                                },
+                                None => {
+                                    if let Some(id) = focused {
+                                        bindings.insert(ix, id);
+                                    }
+                                }
+                            }
+                        }
+                    },
+
+                    ActiveConnectionAction::ScriptClosed(ix) => {
+                        bindings = bindings.drain()
+                            .filter(|(script_ix, _)| *script_ix != ix )
+                            .map(|(script_ix, id)| if script_ix > ix { (script_ix - 1, id) } else { (script_ix, id) })
+                            .collect();
+                        param_values = param_values.drain()
+                            .filter(|(script_ix, _)| *script_ix != ix )
+                            .map(|(script_ix, vals)| if script_ix > ix { (script_ix - 1, vals) } else { (script_ix, vals) })
+                            .collect();
+                        if let Some(sel_ix) = selected_script {
+                            if sel_ix == ix {
+                                selected_script = None;
+                            } else if sel_ix > ix {
+                                selected_script = Some(sel_ix - 1);
+                            }
+                        }
+                    },
+
+                    ActiveConnectionAction::SetManualTransactions(manual) => {
+                        if let Some(session) = focused.and_then(|id| sessions.get_mut(&id) ) {
+                            if session.tx.manual != manual {
+                                if !manual && session.tx.open {
+                                    on_error.call(format!("Commit or rollback the open transaction\nbefore enabling auto-commit"));
+                                } else {
+                                    session.tx.manual = manual;
+                                }
+                            }
+                            on_transaction_changed.call(session.tx.clone());
+                        }
+                    },
+
+                    ActiveConnectionAction::TransactionRequest(cmd) => {
+                        let session = match focused.and_then(|id| sessions.get(&id) ) {
+                            Some(session) => session,
+                            None => return glib::Continue(true)
+                        };
+                        if !session.tx.open {
+                            on_error.call(format!("No open transaction"));
+                            return glib::Continue(true);
+                        }
+                        if let TransactionCommand::Savepoint(name) | TransactionCommand::RollbackTo(name) = &cmd {
+                            if let Err(e) = crate::server::validate_savepoint_name(name) {
+                                on_error.call(e);
+                                return glib::Continue(true);
+                            }
+                        }
+                        if session.listener.is_running() {
+                            on_error.call(format!("Previous statement not completed yet."));
+                            return glib::Continue(true);
+                        }
+                        if let Err(e) = session.listener.send_transaction_command(cmd) {
+                            on_error.call(e);
+                        }
+                    },
+
+                    ActiveConnectionAction::TransactionCompleted(id, cmd, out) => {
+                        let session = match sessions.get_mut(&id) {
+                            Some(session) => session,
+                            None => return glib::Continue(true)
+                        };
+                        if let Some(e) = out.error_message() {
+                            on_error.call(e);
+                        } else {
+                            match cmd {
+                                TransactionCommand::Begin => {
+                                    session.tx.open = true;
+                                },
+                                TransactionCommand::Commit | TransactionCommand::Rollback => {
+                                    session.tx.close();
+
+                                    // Rolled back (or committed) DDL changes the catalog.
+                                    session.schema_valid = false;
+                                    let send = send.clone();
+                                    session.listener.spawn_db_info(move |info| {
+                                        send.send(ActiveConnectionAction::SchemaUpdate(id, info)).unwrap();
+                                    });
+                                },
+                                TransactionCommand::Savepoint(name) => {
+                                    session.tx.savepoints.push(name);
+                                },
+                                TransactionCommand::RollbackTo(name) => {
+                                    if let Some(pos) = session.tx.savepoints.iter().position(|s| &s[..] == &name[..] ) {
+                                        session.tx.savepoints.truncate(pos + 1);
+                                    }
+                                    session.tx.failed = false;
+                                }
+                            }
+                        }
+                        if focused == Some(id) {
+                            on_transaction_changed.call(session.tx.clone());
+                        }
+                    },
+
+                    ActiveConnectionAction::NoticesReceived(id, notices) => {
+                        if sessions.contains_key(&id) {
+                            on_session_notices.call((id, notices));
+                        }
+                    },
+
+                    ActiveConnectionAction::UpdatePolicies(info) => {
+                        if let Some(session) = sessions.values_mut().find(|s| s.info.is_like(&info) ) {
+                            session.info.policies = info.policies;
+                        }
+                    },
+
+                    ActiveConnectionAction::BindScript(id) => {
+                        if !sessions.contains_key(&id) {
+                            on_error.call(format!("Connection is not open"));
+                            return glib::Continue(true);
+                        }
+                        if let Some(script_ix) = selected_script {
+                            bindings.insert(script_ix, id);
+                        }
+                        if focused != Some(id) {
+                            focused = Some(id);
+                            notify_focus(&sessions, focused, &on_session_switched, &on_schema_update, &on_schedule_start, &on_schedule_end, &on_transaction_changed);
+                        }
                     },
                     
                     // When the user clicks the exec button or activates the execute action.
-                    ActiveConnectionAction::ExecutionRequest(stmts) => {
+                    ActiveConnectionAction::ExecutionRequest(stmts) |
+                    ActiveConnectionAction::ParameterizedExecutionRequest(stmts, _) |
+                    ActiveConnectionAction::ConfirmedExecutionRequest(stmts, _) => {
+
+                        let session = match focused.and_then(|id| sessions.get_mut(&id) ) {
+                            Some(session) => session,
+                            None => {
+                                on_error.call(format!("No connection bound to this script"));
+                                return glib::Continue(true);
+                            }
+                        };
                     
-                        if !schema_valid {
+                        if !session.schema_valid {
                             on_error.call(format!("Cannot execute command right now (schema update pending)"));
                             return glib::Continue(true);
                         }
 
-                        if *(active_schedule.borrow()) {
+                        if *(session.active_schedule.borrow()) {
                             on_error.call(format!("Attempted to execute statement during active schedule"));
                             return glib::Continue(true);
                         }
                         
-                        if listener.is_running() {
+                        if session.listener.is_running() {
                             // This shouldn't happen. The user is prevented from sending statements
                             // when the engine is working.
                             on_error.call(format!("Previous statement not completed yet."));
                             return glib::Continue(true);
                         }
 
+                        // Scripts with parameters are only sent after the user informs their values,
+                        // which replace the parameters by numbered placeholders.
+                        let params = crate::sql::parsing::find_parameters(&stmts);
+                        let values = match values {
+                            Some(values) => values,
+                            None if params.len() > 0 => {
+                                let last = selected_script.and_then(|ix| param_values.get(&ix).cloned() ).unwrap_or_default();
+                                on_parameters_request.call((stmts, params, last));
+                                return glib::Continue(true);
+                            },
+                            None => HashMap::new()
+                        };
+                        if params.len() > 0 {
+                            if let Some(script_ix) = selected_script {
+                                param_values.insert(script_ix, values.clone());
+                            }
+                        }
+                        let (sql, subs) = crate::sql::parsing::bind_parameters(&stmts, &values);
+
                         let us = user_state.borrow();
-                        match listener.send_commands(stmts, HashMap::new(), us.safety(), false) {
-                            Ok(_) => { },
-                            Err(e) => {
-                                on_error.call(e.clone());
+                        let mut lock = us.safety(&session.info);
+                        lock.confirmed = confirmed;
+
+                        // Parsing errors are reported by the listener, so only the successfully
+                        // parsed statements are verified here.
+                        if let Ok(parsed) = crate::sql::parsing::fully_parse_sql(&sql) {
+                            if session.tx.manual {
+                                if let Err(e) = check_manual_transaction(&parsed[..]) {
+                                    on_error.call(e);
+                                    return glib::Continue(true);
+                                }
+                            }
+                            let matched = lock.pending_confirmation(&parsed[..]);
+                            if matched.len() > 0 {
+                                on_confirm_request.call((PendingExecution::Script(stmts, values), matched));
+                                return glib::Continue(true);
                             }
                         }
+
+                        let begin = session.tx.manual && !session.tx.open;
+                        session.last_sql = stmts.clone();
+                        session.sent_sql = sql.clone();
+                        if let Err(e) = session.listener.send_commands(sql, subs, lock, false, begin) {
+                            on_error.call(e.clone());
+                        }
                     },
                     
                     // SingleQueryRequest is used when the schema tree is useed to generate a report.
                     ActiveConnectionAction::SingleQueryRequest => {
+
+                        let session = match focused.and_then(|id| sessions.get(&id) ) {
+                            Some(session) => session,
+                            None => {
+                                return glib::Continue(true);
+                            }
+                        };
                     
-                        if !schema_valid {
+                        if !session.schema_valid {
                             on_error.call(format!("Cannot execute command right now (schema update pending)"));
                             return glib::Continue(true);
                         }
                         
-                        if *(active_schedule.borrow()) {
+                        if *(session.active_schedule.borrow()) {
                             on_error.call(format!("Attempted to execute statement during active schedule"));
                             return glib::Continue(true);
                         }
                         
-                        if listener.is_running() {
+                        if session.listener.is_running() {
                             // This shouldn't happen. The user is prevented from sending statements
                             // when the engine is working.
                             on_error.call(format!("Previous statement not completed yet."));
                             return glib::Continue(true);
                         }
                         
-                        match &selected_obj {
+                        match &session.selected_obj {
                             Some(DBObject::View { schema, name, .. }) | Some(DBObject::Table { schema, name, .. }) => {
                                 let cmd = format!("select * from {schema}.{name};");
                                 let us = user_state.borrow();
-                                match listener.send_single_command(cmd, us.safety()) {
+                                match session.listener.send_single_command(cmd, us.safety(&session.info)) {
                                     Ok(_) => { },
                                     Err(e) => {
                                         on_error.call(e.clone());
@@ -856,17 +1386,32 @@ impl ActiveConnection {
                     
                     // Execute action was clicked while execution mode is set to scheduled.
                     ActiveConnectionAction::StartSchedule(stmts) => {
+
+                        let (id, session) = match focused.and_then(|id| sessions.get(&id).map(|s| (id, s) ) ) {
+                            Some(s) => s,
+                            None => {
+                                on_error.call(format!("No connection bound to this script"));
+                                return glib::Continue(true);
+                            }
+                        };
                     
-                        if *(active_schedule.borrow()) {
+                        if *(session.active_schedule.borrow()) {
                             on_error.call(format!("Tried to start schedule twice"));
                             return glib::Continue(true);
                         }
+
+                        if crate::sql::parsing::find_parameters(&stmts).len() > 0 {
+                            on_error.call(format!("Scripts with parameters cannot be scheduled"));
+                            on_schedule_end.call(());
+                            return glib::Continue(true);
+                        }
                         
-                        active_schedule.replace(true);
+                        session.active_schedule.replace(true);
+                        let info = session.info.clone();
                         let dur = Duration::from_secs(user_state.borrow().execution.execution_interval as u64);
                         glib::timeout_add_local(dur, {
-                            let active_schedule = active_schedule.clone();
-                            let listener = listener.clone();
+                            let active_schedule = session.active_schedule.clone();
+                            let listener = session.listener.clone();
                             let user_state = user_state.clone();
                             let send = send.clone();
                             move || {
@@ -886,15 +1431,18 @@ impl ActiveConnection {
                                 let send_ans = listener.send_commands(
                                     stmts.clone(),
                                     HashMap::new(),
-                                    us.safety(),
-                                    true
+                                    us.safety(&info),
+                                    true,
+                                    false
                                 );
                                 match send_ans {
                                     Ok(_) => { 
                                         Continue(should_continue)    
                                     },
                                     Err(e) => {
-                                        send.send(ActiveConnectionAction::Error(e)).unwrap();
+                                        // Reported as a result of this session, so that its schedule is ended
+                                        // even if the user switched to another connection meanwhile.
+                                        send.send(ActiveConnectionAction::ExecutionCompleted(id, vec![StatementOutput::Invalid(e, false)])).unwrap();
                                         Continue(false)
                                     }
                                 }
@@ -905,67 +1453,301 @@ impl ActiveConnection {
                     
                     // Execution was un-toggled in scheduled mode.
                     ActiveConnectionAction::EndSchedule => {
-                    
-                        if !*(active_schedule.borrow()) {
+
+                        let is_scheduled = focused.and_then(|id| sessions.get(&id) )
+                            .map(|s| *s.active_schedule.borrow() )
+                            .unwrap_or(false);
+                        if !is_scheduled {
                             on_error.call(format!("Tried to end schedule, but there is no active schedule."));
                             return glib::Continue(true);
                         }
                         
-                        active_schedule.replace(false);
+                        if let Some(session) = focused.and_then(|id| sessions.get(&id) ) {
+                            session.active_schedule.replace(false);
+                        }
                         on_schedule_end.call(());
                     },
                     
                     // Table import at the schema tree.
-                    ActiveConnectionAction::TableImport(csv_path) => {
-                        if let Some(obj) = &selected_obj {
-                            match obj {
-                                DBObject::Table { name, .. } => {
-                                    let copy = Copy {
-                                        table : name.clone(),
-                                        target : CopyTarget::From,
-                                        cols : Vec::new(),
-                                        options : String::new(),
-                                        client : CopyClient::Stdio
+                    ActiveConnectionAction::TableImport(path) => {
+                        if let Some(session) = focused.and_then(|id| sessions.get_mut(&id) ) {
+                            if let Some(obj) = &session.selected_obj {
+                                match obj {
+                                    DBObject::Table { name, .. } => {
+
+                                        // CSV files go through the import wizard, which maps their columns to the table columns.
+                                        let user_dialect = user_state.borrow().csv.clone();
+                                        if let Some(dialect) = csv_file_dialect(Path::new(&path), &user_dialect) {
+                                            match preview_csv_file(Path::new(&path), &dialect) {
+                                                Ok(records) => {
+                                                    let types = records.infer_types(&dialect);
+                                                    on_import_preview.call((path, obj.clone(), records, types, dialect));
+                                                },
+                                                Err(e) => {
+                                                    on_error.call(e);
+                                                }
+                                            }
+                                            return glib::Continue(true);
+                                        }
+
+                                        let copy = Copy {
+                                            table : name.clone(),
+                                            target : CopyTarget::From,
+                                            cols : Vec::new(),
+                                            options : String::new(),
+                                            client : CopyClient::Stdio
+                                        };
+                                        let send = send.clone();
+                                        let id = focused.unwrap();
+                                        let dialect = user_state.borrow().csv.clone();
+
+                                        // Import results are not produced by a script, so they are not matched against the last one.
+                                        session.last_sql = format!("-- Import of {} into {}", path, name);
+                                        session.sent_sql = session.last_sql.clone();
+                                        session.listener.spawn_import_and_then(path, copy, dialect, move |ans| {
+                                            match ans {
+                                                Ok(n) => {
+                                                    let msg = format!("{} row(s) imported", n);
+                                                    send.send(ActiveConnectionAction::ExecutionCompleted(id, vec![StatementOutput::Statement(msg)])).unwrap();
+                                                },
+                                                Err(e) => {
+                                                    send.send(ActiveConnectionAction::Error(e)).unwrap();
+                                                }
+                                            }
+                                        });
+                                    },
+                                    _ => { }
+                                }
+                            }
+                        }
+                    },
+                    
+                    ActiveConnectionAction::MappedImport(path, plan) |
+                    ActiveConnectionAction::ConfirmedMappedImport(path, plan) => {
+                        let (id, session) = match focused.and_then(|id| sessions.get_mut(&id).map(|s| (id, s) ) ) {
+                            Some(focused) => focused,
+                            None => {
+                                on_error.call(format!("No active connection to complete import action"));
+                                return glib::Continue(true);
+                            }
+                        };
+                        if let Err(e) = plan.validate() {
+                            on_error.call(e);
+                            return glib::Continue(true);
+                        }
+                        let dialect = match csv_file_dialect(Path::new(&path), &user_state.borrow().csv) {
+                            Some(dialect) => dialect,
+                            None => {
+                                on_error.call(format!("Only CSV files can be imported with a column mapping"));
+                                return glib::Continue(true);
+                            }
+                        };
+
+                        // The generated statements require confirmation as the statements typed by the user.
+                        let mut lock = user_state.borrow().safety(&session.info);
+                        lock.confirmed = confirmed;
+                        let mut generated = Vec::new();
+                        if plan.create {
+                            generated.push(plan.creation_sql());
+                        }
+                        generated.push(plan.insertion_sql());
+                        if let Ok(parsed) = crate::sql::parsing::fully_parse_sql(&generated.join("\n")) {
+                            let matched = lock.pending_confirmation(&parsed[..]);
+                            if matched.len() > 0 {
+                                on_confirm_request.call((PendingExecution::Import(path, plan), matched));
+                                return glib::Continue(true);
+                            }
+                        }
+
+                        // Within an open transaction (or in manual mode), the imported rows are left for the user to commit.
+                        let tx = if session.tx.open {
+                            ImportTransaction::Open
+                        } else if session.tx.manual {
+                            ImportTransaction::Begin
+                        } else {
+                            ImportTransaction::Atomic
+                        };
+                        let send = send.clone();
+                        let (table, create) = (plan.table.clone(), plan.create);
+                        session.last_sql = format!("-- Import of {} into {}", path, table);
+                        session.sent_sql = session.last_sql.clone();
+                        session.listener.spawn_mapped_import_and_then(path.clone(), plan, dialect, lock, tx, move |ans| {
+                            if tx == ImportTransaction::Begin && !matches!(ans, Err((_, false))) {
+                                let out = StatementOutput::Statement(format!("Transaction started"));
+                                send.send(ActiveConnectionAction::TransactionCompleted(id, TransactionCommand::Begin, out)).unwrap();
+                            }
+                            match ans {
+                                Ok((n, rejected)) => {
+                                    let mut results = Vec::new();
+                                    if create {
+                                        results.push(StatementOutput::Modification(format!("Create table")));
+                                    }
+                                    let msg = if rejected.len() == 0 {
+                                        format!("{} row(s) imported into {}", n, table)
+                                    } else {
+                                        format!("{} row(s) imported into {} ({} rejected)", n, table, rejected.len())
                                     };
-                                    let send = send.clone();
-                                    listener.spawn_import_and_then(csv_path, copy, move |ans| {
-                                        match ans {
-                                            Ok(n) => {
-                                                let msg = format!("{} row(s) imported", n);
-                                                send.send(ActiveConnectionAction::ExecutionCompleted(vec![StatementOutput::Statement(msg)])).unwrap();
+                                    results.push(StatementOutput::Statement(msg));
+                                    if rejected.len() > 0 {
+                                        match rejected_table(&rejected[..]) {
+                                            Ok(mut tbl) => {
+                                                tbl.set_name(Some(String::from("Rejected rows")));
+                                                results.push(StatementOutput::Valid(format!("-- Rejected rows of {}", path), tbl));
                                             },
                                             Err(e) => {
-                                                send.send(ActiveConnectionAction::Error(e)).unwrap();
+                                                eprintln!("{}", e);
                                             }
                                         }
-                                    });
+                                    }
+                                    send.send(ActiveConnectionAction::ExecutionCompleted(id, results)).unwrap();
+                                },
+
+                                // Errors raised by the server within the open transaction leave it failed, as
+                                // the errors of the scripts executed there.
+                                Err((e, true)) if tx != ImportTransaction::Atomic => {
+                                    send.send(ActiveConnectionAction::ExecutionCompleted(id, vec![StatementOutput::Invalid(e, true)])).unwrap();
                                 },
-                                _ => { }
+                                Err((e, _)) => {
+                                    send.send(ActiveConnectionAction::Error(e)).unwrap();
+                                }
+                            }
+                        });
+                    },
+
+                    // Export of a query result straight to a file, which never goes through the result tables.
+                    ActiveConnectionAction::QueryExportRequest(sql, path) => {
+                        if export_cancel.is_some() {
+                            on_error.call(format!("Previous query export not finished yet"));
+                            return glib::Continue(true);
+                        }
+                        let (id, session) = match focused.and_then(|id| sessions.get(&id).map(|s| (id, s) ) ) {
+                            Some(focused) => focused,
+                            None => {
+                                on_error.call(format!("No active connection to export the query from"));
+                                return glib::Continue(true);
+                            }
+                        };
+
+                        // The export runs within its own (read-only) transaction.
+                        if session.tx.open {
+                            on_error.call(format!("Queries cannot be exported within an open transaction.\nCommit or roll it back first."));
+                            return glib::Continue(true);
+                        }
+                        let query = match crate::sql::require_single_query(&sql) {
+                            Ok(query) => query,
+                            Err(e) => {
+                                on_error.call(e);
+                                return glib::Continue(true);
+                            }
+                        };
+                        let cancel = Arc::new(AtomicBool::new(false));
+                        export_cancel = Some(cancel.clone());
+                        let dialect = user_state.borrow().csv.clone();
+                        on_export_started.call(path.clone());
+                        let (progress_send, done_send) = (send.clone(), send.clone());
+                        session.listener.spawn_export_and_then(
+                            query,
+                            path.clone(),
+                            dialect,
+                            cancel,
+                            move |n| {
+                                progress_send.send(ActiveConnectionAction::QueryExportProgress(n)).unwrap();
+                            },
+                            move |ans| {
+                                done_send.send(ActiveConnectionAction::QueryExportCompleted(id, path.clone(), ans)).unwrap();
                             }
+                        );
+                    },
+
+                    ActiveConnectionAction::QueryExportProgress(n) => {
+                        if export_cancel.is_some() {
+                            on_export_progress.call(n);
                         }
                     },
-                    
+
+                    ActiveConnectionAction::QueryExportCancel => {
+                        if let Some(cancel) = &export_cancel {
+                            cancel.store(true, Ordering::Relaxed);
+                        }
+                    },
+
+                    ActiveConnectionAction::QueryExportCompleted(id, path, ans) => {
+                        export_cancel = None;
+                        on_export_finished.call(());
+                        match ans {
+                            Ok(n) => {
+                                let msg = format!("{} row(s) exported to {}", n, path);
+                                send.send(ActiveConnectionAction::ExecutionCompleted(id, vec![StatementOutput::Statement(msg)])).unwrap();
+                            },
+                            Err(e) => {
+                                on_error.call(e);
+                            }
+                        }
+                    },
+
                     // A new set of results arrived to the client.
-                    ActiveConnectionAction::ExecutionCompleted(results) => {
-                    
-                        // assert!(!listener.is_running());
+                    ActiveConnectionAction::ExecutionCompleted(id, results) => {
+
+                        // Results might arrive after the session was closed.
+                        let session = match sessions.get_mut(&id) {
+                            Some(session) => session,
+                            None => return glib::Continue(true)
+                        };
+                        let is_focused = focused == Some(id);
                         
-                        let fst_error = results.iter()
-                            .filter_map(|res| {
+                        let fst_error = results.iter().filter_map(|res| res.error_message() ).next();
+
+                        // Points the editor to the token that caused the first error, when reported by the server.
+                        let fst_error_ix = results.iter().position(|res| res.error_message().is_some() );
+                        if let (true, Some(ix)) = (is_focused, fst_error_ix) {
+                            if let StatementOutput::Rejected(DatabaseError { position : Some(pos), stmt, .. }) = &results[ix] {
+                                if let Some(loc) = crate::sql::parsing::error_location(&session.sent_sql, ix, stmt, *pos) {
+                                    on_error_location.call(crate::sql::parsing::unbind_location(&session.last_sql, loc));
+                                }
+                            }
+                        }
+                        if session.tx.open {
+                            if fst_error.is_some() {
+                                session.tx.failed = true;
+                            }
+
+                            // Savepoints typed by the user are tracked once the server accepted them.
+                            if let Ok(stmts) = crate::sql::parsing::fully_parse_sql(&session.sent_sql) {
+                                for (stmt, res) in stmts.iter().zip(results.iter()) {
+                                    if let (Some(name), None) = (statement_savepoint(stmt), res.error_message()) {
+                                        session.tx.savepoints.push(name);
+                                    }
+                                }
+                            }
+                            let any_changes = results.iter().any(|res| {
                                 match res {
-                                    StatementOutput::Invalid(e, _) => Some(e.clone()),
-                                    _ => None
+                                    StatementOutput::Statement(_) | StatementOutput::Modification(_) => true,
+                                    _ => false
                                 }
-                            }).next();
+                            });
+                            if any_changes {
+                                session.tx.pending = true;
+                            }
+                            if is_focused {
+                                on_transaction_changed.call(session.tx.clone());
+                            }
+                        }
+
                         if let Some(error) = fst_error {
                             on_error.call(error.clone());
                             
-                            if *(active_schedule.borrow()) == true {
-                                send.send(ActiveConnectionAction::EndSchedule).unwrap();
+                            if *(session.active_schedule.borrow()) == true {
+                                session.active_schedule.replace(false);
+                                if is_focused {
+                                    on_schedule_end.call(());
+                                }
                             }
                         
                         } else {
-                            on_exec_result.call(results.clone());
+                            on_session_result.call((id, results.clone(), session.last_sql.clone()));
+                            if is_focused {
+                                on_exec_result.call(results.clone());
+                            }
                         }
                         
                         // This will block any new user statements until the schema information is updated.
@@ -981,58 +1763,73 @@ impl ActiveConnection {
                                 }
                             }).is_some();
                         if any_schema_updates {
-                            schema_valid = false;
-                            on_schema_invalidated.call(());
+                            session.schema_valid = false;
+                            if is_focused {
+                                on_schema_invalidated.call(());
+                            }
                             let send = send.clone();
-                            listener.spawn_db_info(move |info| {
-                                send.send(ActiveConnectionAction::SchemaUpdate(info)).unwrap();
+                            session.listener.spawn_db_info(move |info| {
+                                send.send(ActiveConnectionAction::SchemaUpdate(id, info)).unwrap();
                             });
                         }
                         
                     },
                     
                     // Results arrived from a report request.
-                    ActiveConnectionAction::SingleQueryCompleted(out) => {
+                    ActiveConnectionAction::SingleQueryCompleted(_id, out) => {
                         match out {
                             StatementOutput::Valid(_, tbl) => {
                                 on_single_query_result.call(tbl.clone());
                             },
-                            StatementOutput::Invalid(msg, _) => {
-                                on_error.call(msg.clone());
+                            StatementOutput::Invalid(_, _) | StatementOutput::Rejected(_) => {
+                                on_error.call(out.error_message().unwrap_or_default());
                             },
                             _ => { }
                         }
                     },
                     
                     // Schema update after a DDL statement was executed by queries.
-                    ActiveConnectionAction::SchemaUpdate(opt_schema) => {
-                        schema_valid = true;
-                        schema = opt_schema.clone();
-                        selected_obj = None;
-                        on_schema_update.call(opt_schema.clone());
+                    ActiveConnectionAction::SchemaUpdate(id, opt_schema) => {
+                        if let Some(session) = sessions.get_mut(&id) {
+                            session.schema_valid = true;
+                            session.schema = opt_schema.clone();
+                            session.selected_obj = None;
+                            if focused == Some(id) {
+                                on_schema_update.call(opt_schema.clone());
+                            }
+                        }
                     },
                     
                     ActiveConnectionAction::ObjectSelected(obj_ixs) => {
-                        match (&schema, obj_ixs) {
-                            (Some(schema), Some(ixs)) => {
-                                selected_obj = crate::sql::object::index_db_object(&schema[..], ixs);
-                            },
-                            _ => {
-                                selected_obj = None;
+                        if let Some(session) = focused.and_then(|id| sessions.get_mut(&id) ) {
+                            match (&session.schema, obj_ixs) {
+                                (Some(schema), Some(ixs)) => {
+                                    session.selected_obj = crate::sql::object::index_db_object(&schema[..], ixs);
+                                },
+                                _ => {
+                                    session.selected_obj = None;
+                                }
                             }
+                            on_object_selected.call(session.selected_obj.clone());
+                        } else {
+                            on_object_selected.call(None);
                         }
-                        on_object_selected.call(selected_obj.clone());
                     },
                     
                     ActiveConnectionAction::ConnectFailure(info, e) => {
                         trying_connection = false;
                         on_conn_failure.call((info, e.clone()));
+
+                        // Restores the schema of the focused session, replaced while connecting.
+                        notify_focus(&sessions, focused, &on_session_switched, &on_schema_update, &on_schedule_start, &on_schedule_end, &on_transaction_changed);
                     },
                     
                     ActiveConnectionAction::Error(e) => {
                         on_error.call(e.clone());
-                        if *(active_schedule.borrow()) == true {
-                            send.send(ActiveConnectionAction::EndSchedule).unwrap();
+                        if let Some(session) = focused.and_then(|id| sessions.get(&id) ) {
+                            if *(session.active_schedule.borrow()) == true {
+                                send.send(ActiveConnectionAction::EndSchedule).unwrap();
+                            }
                         }
                     }
                 }
@@ -1047,13 +1844,27 @@ impl ActiveConnection {
             on_error,
             send,
             on_exec_result,
+            on_session_result,
             on_conn_failure,
             on_schema_update,
             on_object_selected,
             on_single_query_result,
             on_schema_invalidated,
             on_schedule_start,
-            on_schedule_end
+            on_schedule_end,
+            on_session_switched,
+            on_session_closed,
+            on_confirm_request,
+            on_parameters_request,
+            on_transaction_changed,
+            on_session_notices,
+            on_error_location,
+            on_disconnect_warning,
+            on_import_preview,
+            on_export_started,
+            on_export_progress,
+            on_export_finished,
+            open_infos
         }
     }
 
@@ -1068,6 +1879,7 @@ impl ActiveConnection {
         self.on_connected.bind(f);
     }
 
+    /// Called when the last open session is closed.
     pub fn connect_db_disconnected<F>(&self, f : F)
     where
         F : Fn(()) + 'static
@@ -1103,6 +1915,7 @@ impl ActiveConnection {
         self.on_conn_failure.bind(f);
     }
 
+    /// Called with results of the focused session only.
     pub fn connect_exec_result<F>(&self, f : F)
     where
         F : Fn(Vec<StatementOutput>) + 'static
@@ -1110,6 +1923,15 @@ impl ActiveConnection {
         self.on_exec_result.bind(f);
     }
 
+    /// Called with results of any session, tagged by the session id and followed by
+    /// the script that produced them.
+    pub fn connect_session_result<F>(&self, f : F)
+    where
+        F : Fn((usize, Vec<StatementOutput>, String)) + 'static
+    {
+        self.on_session_result.bind(f);
+    }
+
     pub fn connect_single_query_result<F>(&self, f : F)
     where
         F : Fn(Table) + 'static
@@ -1138,6 +1960,157 @@ impl ActiveConnection {
         self.on_object_selected.bind(f);
     }
 
+    pub fn connect_session_switched<F>(&self, f : F)
+    where
+        F : Fn(Option<(usize, ConnectionInfo, Option<DBInfo>)>) + 'static
+    {
+        self.on_session_switched.bind(f);
+    }
+
+    pub fn connect_session_closed<F>(&self, f : F)
+    where
+        F : Fn((usize, ConnectionInfo)) + 'static
+    {
+        self.on_session_closed.bind(f);
+    }
+
+    pub fn connect_confirm_request<F>(&self, f : F)
+    where
+        F : Fn((PendingExecution, Vec<(SafetyRule, String)>)) + 'static
+    {
+        self.on_confirm_request.bind(f);
+    }
+
+    pub fn connect_parameters_request<F>(&self, f : F)
+    where
+        F : Fn((String, Vec<ScriptParameter>, HashMap<String, String>)) + 'static
+    {
+        self.on_parameters_request.bind(f);
+    }
+
+    pub fn connect_transaction_changed<F>(&self, f : F)
+    where
+        F : Fn(TransactionStatus) + 'static
+    {
+        self.on_transaction_changed.bind(f);
+    }
+
+    pub fn connect_session_notices<F>(&self, f : F)
+    where
+        F : Fn((usize, Vec<ServerNotice>)) + 'static
+    {
+        self.on_session_notices.bind(f);
+    }
+
+    pub fn connect_error_location<F>(&self, f : F)
+    where
+        F : Fn((usize, usize)) + 'static
+    {
+        self.on_error_location.bind(f);
+    }
+
+    pub fn connect_import_preview<F>(&self, f : F)
+    where
+        F : Fn((String, DBObject, CsvRecords, Vec<DBType>, CsvDialect)) + 'static
+    {
+        self.on_import_preview.bind(f);
+    }
+
+    pub fn connect_export_started<F>(&self, f : F)
+    where
+        F : Fn(String) + 'static
+    {
+        self.on_export_started.bind(f);
+    }
+
+    pub fn connect_export_progress<F>(&self, f : F)
+    where
+        F : Fn(usize) + 'static
+    {
+        self.on_export_progress.bind(f);
+    }
+
+    pub fn connect_export_finished<F>(&self, f : F)
+    where
+        F : Fn(()) + 'static
+    {
+        self.on_export_finished.bind(f);
+    }
+
+    pub fn connect_disconnect_warning<F>(&self, f : F)
+    where
+        F : Fn(ConnectionInfo) + 'static
+    {
+        self.on_disconnect_warning.bind(f);
+    }
+
+}
+
+/* Informs the widgets that the focused session changed, so that the schema tree, details
+and execution button reflect the state of the connection bound to the selected script. */
+fn notify_focus(
+    sessions : &HashMap<usize, Session>,
+    focused : Option<usize>,
+    on_session_switched : &Callbacks<Option<(usize, ConnectionInfo, Option<DBInfo>)>>,
+    on_schema_update : &Callbacks<Option<Vec<DBObject>>>,
+    on_schedule_start : &Callbacks<()>,
+    on_schedule_end : &Callbacks<()>,
+    on_transaction_changed : &Callbacks<TransactionStatus>
+) {
+    match focused.and_then(|id| sessions.get(&id).map(|s| (id, s) ) ) {
+        Some((id, session)) => {
+            on_session_switched.call(Some((id, session.info.clone(), session.db_info.clone())));
+            on_schema_update.call(session.schema.clone());
+            if *session.active_schedule.borrow() {
+                on_schedule_start.call(());
+            } else {
+                on_schedule_end.call(());
+            }
+            on_transaction_changed.call(session.tx.clone());
+        },
+        None => {
+            on_session_switched.call(None);
+            on_transaction_changed.call(TransactionStatus::default());
+        }
+    }
+}
+
+/* In manual transaction mode, the transaction is controlled by the user via
+the commit and rollback buttons, so statements that would end it are rejected. */
+fn check_manual_transaction(stmts : &[AnyStatement]) -> Result<(), String> {
+    for stmt in stmts {
+        match stmt {
+            AnyStatement::ParsedTransaction { .. } |
+            AnyStatement::Parsed(Statement::StartTransaction { .. }, _) |
+            AnyStatement::Parsed(Statement::Commit { .. }, _) |
+            AnyStatement::Parsed(Statement::Rollback { .. }, _) => {
+                return Err(format!("Transaction blocks are not accepted when auto-commit is off\n(use the commit and rollback buttons instead)"));
+            },
+            _ => { }
+        }
+    }
+    Ok(())
+}
+
+/* Returns the name of the savepoint created by the statement. Savepoints the parser
+rejects are recognized by their leading keyword. */
+fn statement_savepoint(stmt : &AnyStatement) -> Option<String> {
+    match stmt {
+        AnyStatement::Parsed(Statement::Savepoint { name }, _) => Some(name.value.clone()),
+        AnyStatement::Raw(tokens, _, _) => {
+            let mut words = tokens.iter().filter_map(|tk| match tk {
+                Token::Word(w) => Some(w),
+                _ => None
+            });
+            match (words.next(), words.next()) {
+                (Some(kw), Some(name)) if kw.quote_style.is_none() && kw.value.eq_ignore_ascii_case("savepoint") => {
+                    Some(name.value.clone())
+                },
+                _ => None
+            }
+        },
+        _ => None
+    }
 }
 
 const NO_CERT : &'static str = "No SSL certificate associated with this host.\nConfigure one at the security settings";
@@ -1168,13 +2141,41 @@ impl React<ConnectionBox> for ActiveConnection {
             conn_bx.user.entry.clone(),
             conn_bx.password.entry.clone()
         );
+        for (_, combo) in &conn_bx.policy_btn.combos {
+            let send = self.send.clone();
+            let (host_entry, db_entry, user_entry) = (host_entry.clone(), db_entry.clone(), user_entry.clone());
+            let policy_btn = conn_bx.policy_btn.clone();
+            combo.connect_changed(move |_| {
+                if let Ok(mut info) = extract_conn_info(&host_entry, &db_entry, &user_entry) {
+                    info.policies = policy_btn.policies();
+                    send.send(ActiveConnectionAction::UpdatePolicies(info)).unwrap();
+                }
+            });
+        }
         let send = self.send.clone();
         let user_state = self.user_state.clone();
+        let open_infos = self.open_infos.clone();
+        let policy_btn = conn_bx.policy_btn.clone();
+        let read_only_btn = conn_bx.read_only_btn.clone();
         conn_bx.switch.connect_state_set(move |switch, _state| {
+
+            // The switch reflects whether the selected connection has an open session,
+            // and is also set when the user selects another connection at the list.
+            let opt_info = extract_conn_info(&host_entry, &db_entry, &user_entry).ok();
+            let is_open = opt_info.as_ref()
+                .map(|info| open_infos.borrow().iter().any(|open| open.is_like(info) ) )
+                .unwrap_or(false);
+            if switch.is_active() == is_open {
+                return Inhibit(false);
+            }
+
             if switch.is_active() {
                 match generate_conn_uri_from_entries(&host_entry, &db_entry, &user_entry, &password_entry) {
                     Ok(mut uri) => {
-                    
+
+                        uri.info.policies = policy_btn.policies();
+                        uri.info.read_only = read_only_btn.is_active();
+
                         let mut extra_args = Vec::new();
                         let us = user_state.borrow();
                         
@@ -1221,8 +2222,8 @@ impl React<ConnectionBox> for ActiveConnection {
                         send.send(ActiveConnectionAction::ConnectFailure(info, e)).unwrap();
                     }
                 }
-            } else {
-                send.send(ActiveConnectionAction::Disconnect).unwrap();
+            } else if let Some(info) = opt_info {
+                send.send(ActiveConnectionAction::Disconnect(info)).unwrap();
             }
 
             Inhibit(false)
@@ -1230,6 +2231,100 @@ impl React<ConnectionBox> for ActiveConnection {
     }
 }
 
+impl React<SafetyDialog> for ActiveConnection {
+
+    fn react(&self, dialog : &SafetyDialog) {
+        let send = self.send.clone();
+        let pending = dialog.pending.clone();
+        dialog.exec_btn.connect_clicked(move |_| {
+            match pending.borrow_mut().take() {
+                Some(PendingExecution::Script(stmts, values)) => {
+                    send.send(ActiveConnectionAction::ConfirmedExecutionRequest(stmts, values)).unwrap();
+                },
+                Some(PendingExecution::Import(path, plan)) => {
+                    send.send(ActiveConnectionAction::ConfirmedMappedImport(path, plan)).unwrap();
+                },
+                None => { }
+            }
+        });
+    }
+
+}
+
+impl React<ParametersDialog> for ActiveConnection {
+
+    fn react(&self, dialog : &ParametersDialog) {
+        let send = self.send.clone();
+        let pending = dialog.pending.clone();
+        let entries = dialog.entries.clone();
+        dialog.exec_btn.connect_clicked(move |_| {
+            if let Some((stmts, names)) = pending.borrow_mut().take() {
+                let values : HashMap<String, String> = names.into_iter()
+                    .zip(entries.borrow().iter())
+                    .filter(|(_, entry)| !entry.text().is_empty() )
+                    .map(|(name, entry)| (name, entry.text().to_string()) )
+                    .collect();
+                send.send(ActiveConnectionAction::ParameterizedExecutionRequest(stmts, values)).unwrap();
+            }
+        });
+    }
+
+}
+
+impl React<QueriesWorkspace> for ActiveConnection {
+
+    fn react(&self, workspace : &QueriesWorkspace) {
+        let send = self.send.clone();
+
+        // Changes to editable tables go through the same safety checks as the scripts.
+        workspace.apply_action.connect_activate(move |_, param| {
+            if let Some(stmts) = param.and_then(|p| p.get::<String>() ) {
+                send.send(ActiveConnectionAction::ExecutionRequest(stmts)).unwrap();
+            }
+        });
+    }
+
+}
+
+impl React<DisconnectDialog> for ActiveConnection {
+
+    fn react(&self, dialog : &DisconnectDialog) {
+        let send = self.send.clone();
+        let pending = dialog.pending.clone();
+        dialog.disconnect_btn.connect_clicked(move |_| {
+            if let Some(info) = pending.borrow_mut().take() {
+                send.send(ActiveConnectionAction::DiscardAndDisconnect(info)).unwrap();
+            }
+        });
+    }
+
+}
+
+impl React<QueryExportDialog> for ActiveConnection {
+
+    fn react(&self, dialog : &QueryExportDialog) {
+        let send = self.send.clone();
+        let pending = dialog.pending.clone();
+        dialog.dialog.connect_response(move |dialog, resp| {
+            let sql = pending.borrow_mut().take();
+            if let (ResponseType::Accept, Some(sql)) = (resp, sql) {
+                if let Some(path) = dialog.file().and_then(|f| f.path() ) {
+                    if let Some(p) = path.to_str() {
+                        send.send(ActiveConnectionAction::QueryExportRequest(sql, p.to_string())).unwrap();
+                    } else {
+                        send.send(ActiveConnectionAction::Error(format!("Export path is not valid UTF-8"))).unwrap();
+                    }
+                }
+            }
+        });
+        let send = self.send.clone();
+        dialog.cancel_btn.connect_clicked(move |_| {
+            send.send(ActiveConnectionAction::QueryExportCancel).unwrap();
+        });
+    }
+
+}
+
 impl React<ExecButton> for ActiveConnection {
 
     fn react(&self, btn : &ExecButton) {
@@ -1278,6 +2373,89 @@ impl React<ExecButton> for ActiveConnection {
              }
         });
 
+        // Switching to a session with an active schedule restores the schedule button state.
+        self.connect_schedule_start({
+            let is_scheduled = is_scheduled.clone();
+            let exec_btn = btn.btn.clone();
+            move|_| {
+                let mut is_scheduled = is_scheduled.borrow_mut();
+                if !*is_scheduled {
+                    *is_scheduled = true;
+                    exec_btn.set_icon_name("clock-app-symbolic");
+                }
+             }
+        });
+
+    }
+
+}
+
+impl React<OpenedScripts> for ActiveConnection {
+
+    fn react(&self, scripts : &OpenedScripts) {
+        scripts.connect_selected({
+            let send = self.send.clone();
+            move |opt_file| {
+                send.send(ActiveConnectionAction::ScriptSelected(opt_file.map(|f| f.index ))).unwrap();
+            }
+        });
+        scripts.connect_closed({
+            let send = self.send.clone();
+            move |(old_file, _)| {
+                send.send(ActiveConnectionAction::ScriptClosed(old_file.index)).unwrap();
+            }
+        });
+    }
+
+}
+
+impl React<QueriesTitlebar> for ActiveConnection {
+
+    fn react(&self, titlebar : &QueriesTitlebar) {
+        let send = self.send.clone();
+        titlebar.session_combo.connect_changed(move |combo| {
+            if let Some(id) = combo.active_id().and_then(|id| id.parse::<usize>().ok() ) {
+                send.send(ActiveConnectionAction::BindScript(id)).unwrap();
+            }
+        });
+
+        let tx_box = &titlebar.tx_box;
+        tx_box.manual_btn.connect_toggled({
+            let send = self.send.clone();
+            move |btn| {
+                send.send(ActiveConnectionAction::SetManualTransactions(btn.is_active())).unwrap();
+            }
+        });
+        tx_box.commit_btn.connect_clicked({
+            let send = self.send.clone();
+            move |_| {
+                send.send(ActiveConnectionAction::TransactionRequest(TransactionCommand::Commit)).unwrap();
+            }
+        });
+        tx_box.rollback_btn.connect_clicked({
+            let send = self.send.clone();
+            move |_| {
+                send.send(ActiveConnectionAction::TransactionRequest(TransactionCommand::Rollback)).unwrap();
+            }
+        });
+        tx_box.create_savepoint_btn.connect_clicked({
+            let send = self.send.clone();
+            let entry = tx_box.savepoint_entry.clone();
+            move |_| {
+                let name = entry.text().trim().to_string();
+                send.send(ActiveConnectionAction::TransactionRequest(TransactionCommand::Savepoint(name))).unwrap();
+                entry.set_text("");
+            }
+        });
+        tx_box.rollback_to_btn.connect_clicked({
+            let send = self.send.clone();
+            let tx_box = tx_box.clone();
+            move |_| {
+                if let Some(name) = tx_box.selected_savepoint() {
+                    send.send(ActiveConnectionAction::TransactionRequest(TransactionCommand::RollbackTo(name))).unwrap();
+                }
+            }
+        });
     }
 
 }
@@ -1325,6 +2503,21 @@ impl React<SchemaTree> for ActiveConnection {
                 }
             }
         });
+        tree.import_wizard.import_btn.connect_clicked({
+            let send = self.send.clone();
+            let wizard = tree.import_wizard.clone();
+            move |_| {
+                match wizard.plan() {
+                    Ok((path, plan)) => {
+                        send.send(ActiveConnectionAction::MappedImport(path, plan)).unwrap();
+                        wizard.dialog.close();
+                    },
+                    Err(e) => {
+                        send.send(ActiveConnectionAction::Error(e)).unwrap();
+                    }
+                }
+            }
+        });
         tree.report_dialog.btn_gen.connect_clicked({
             let send = self.send.clone();
             let dialog = tree.report_dialog.dialog.clone();
@@ -1427,7 +2620,11 @@ impl React<SchemaTree> for ActiveConnection {
                 match resp {
                     ResponseType::Accept => {
                         if let Some(path) = dialog.file().and_then(|f| f.path() ) {
-                            send.send(ActiveConnectionAction::TableImport(path.to_str().unwrap().to_string())).unwrap();
+                            if let Some(p) = path.to_str() {
+                                send.send(ActiveConnectionAction::TableImport(p.to_string())).unwrap();
+                            } else {
+                                send.send(ActiveConnectionAction::Error(format!("Import path is not valid UTF-8"))).unwrap();
+                            }
                         }
                     },
                     _ => { }
@@ -1479,7 +2676,7 @@ fn text_to_sql_literal(entry : &Entry, ty : &DBType) -> Result<String, String> {
         // Quote literals from types in the first branch, do not
         // quote literals from types in the second branch.
         let desired_lit = match ty {
-            DBType::Text | DBType::Date | DBType::Time | DBType::Bytes |
+            DBType::Text | DBType::Date | DBType::Time | DBType::Timestamp | DBType::Bytes |
             DBType::Json | DBType::Xml | DBType::Array | DBType::Bool => {
                 format!("'{}'", entry_s.trim())
             },
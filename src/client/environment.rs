@@ -14,19 +14,78 @@ use stateful::React;
 use crate::client::ActiveConnection;
 use crate::tables::table::TableSettings;
 use crate::tables::table::Columns;
+use crate::tables::spreadsheet;
+use crate::tables::dialect::CsvDialect;
+use crate::tables::tex::TexOptions;
 use papyri::render::Panel;
 use crate::ui::QueriesWorkspace;
-use std::io::Write;
+use std::io::{Write, BufWriter};
 use std::thread;
-use crate::ui::ExportDialog;
+use crate::ui::{ExportDialog, ExportAllDialog, OpenTableDialog, PlotExportOptions, PageSize};
 use crate::client::ExecutionSettings;
+use crate::client::ConnectionInfo;
 use crate::client::SharedUserState;
 use crate::ui::ExecButton;
+use std::collections::HashMap;
+use std::mem;
+use serde::Serialize;
 
 #[derive(Debug, Clone)]
 pub enum ExportItem {
-    Table(Table),
-    Panel(Panel)
+
+    // Table and the query that produced it.
+    Table(Table, String),
+
+    Panel(Panel),
+
+    // All result tables, written as the sheets of a single spreadsheet.
+    Workbook(Vec<Table>)
+}
+
+/// Options chosen at the export dialog.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+
+    // Table created by .sql exports. Defaults to the relation the table was queried from.
+    pub sql_target : Option<String>,
+
+    pub csv : CsvDialect,
+
+    pub plot : PlotExportOptions,
+
+    pub tex : TexOptions
+
+}
+
+// Maximum number of rows per INSERT statement of .sql exports.
+const SQL_INSERT_BATCH : usize = 1000;
+
+// Written alongside the files of "Export all", so the results can be traced back to
+// the SQL and the database that produced them.
+#[derive(Debug, Clone, Serialize)]
+struct ExportManifest {
+    exported_at : String,
+    executed_at : Option<String>,
+    connection : Option<ManifestConnection>,
+    sql : String,
+    files : Vec<ManifestFile>
+}
+
+// Connection details of the manifest. The password is never part of ConnectionInfo.
+#[derive(Debug, Clone, Serialize)]
+struct ManifestConnection {
+    host : String,
+    user : String,
+    database : String,
+    read_only : bool
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ManifestFile {
+    file : String,
+    kind : String,
+    query : String,
+    rows : Option<usize>
 }
 
 pub struct ExecutionError {
@@ -36,7 +95,16 @@ pub struct ExecutionError {
 
 pub enum EnvironmentAction {
 
-    Update(Vec<StatementOutput>),
+    // Results of the session with the given id, and the script that produced them.
+    Update(usize, Vec<StatementOutput>, String),
+
+    // Server notices raised by the last execution of the session with the given id.
+    Notices(usize, Vec<ServerNotice>),
+
+    // Shows the workspace of another session (or no workspace at all).
+    SwitchSession(Option<usize>),
+
+    CloseSession(usize),
 
     Restore,
 
@@ -45,11 +113,26 @@ pub enum EnvironmentAction {
     Select(Option<usize>),
 
     /// Request to export the currently selected item to the path given as the argument.
-    ExportRequest(String),
+    ExportRequest(String, ExportOptions),
+
+    /// Request to export all result tables (one sheet per table) to the spreadsheet at the given path.
+    ExportWorkbookRequest(String),
+
+    /// Request to write every table and plot to the directory given as the first argument, with
+    /// the table and plot file extensions given as the second and third arguments.
+    ExportAllRequest(String, String, String, ExportOptions),
+
+    // Connection of the session with the given id, recorded in the manifest of "Export all".
+    SessionInfo(usize, ConnectionInfo),
 
     ChangeSetting(ExecutionSettings),
 
-    ExportError(String)
+    ExportError(String),
+
+    /// Request to open the file at the given path as an external table.
+    OpenTable(String),
+
+    TableOpened(Result<Table, String>)
 
 }
 
@@ -61,9 +144,16 @@ pub struct Environment {
 
     on_tbl_update : Callbacks<Vec<Table>>,
 
+    // Queries that produced the tables, called just before on_tbl_update.
+    on_queries_update : Callbacks<Vec<String>>,
+
     on_tbl_error : Callbacks<String>,
 
-    on_export_error : Callbacks<String>
+    on_tbl_clear : Callbacks<()>,
+
+    on_export_error : Callbacks<String>,
+
+    on_notices_update : Callbacks<Vec<ServerNotice>>
 
 }
 
@@ -74,28 +164,51 @@ impl Environment {
         let mut tables = Tables::new();
         let mut plots = Plots::new();
         let on_tbl_update : Callbacks<Vec<Table>> = Default::default();
+        let on_queries_update : Callbacks<Vec<String>> = Default::default();
         let on_export_error : Callbacks<String> = Default::default();
         let on_tbl_error : Callbacks<String> = Default::default();
+        let on_tbl_clear : Callbacks<()> = Default::default();
+        let on_notices_update : Callbacks<Vec<ServerNotice>> = Default::default();
         let mut selected : Option<usize> = None;
+
+        // Each connection has its own workspace. The tables and plots above belong to
+        // the focused session; the workspaces of the other sessions are kept here.
+        let mut curr_session : Option<usize> = None;
+        let mut inactive : HashMap<usize, (Tables, Plots)> = HashMap::new();
+        let mut notices : HashMap<usize, Vec<ServerNotice>> = HashMap::new();
+        let mut conn_infos : HashMap<usize, ConnectionInfo> = HashMap::new();
+
         recv.attach(None, {
             let on_tbl_update = on_tbl_update.clone();
+            let on_queries_update = on_queries_update.clone();
+            let on_tbl_clear = on_tbl_clear.clone();
             let on_export_error = on_export_error.clone();
             let on_tbl_error = on_tbl_error.clone();
+            let on_notices_update = on_notices_update.clone();
             let send = send.clone();
+            let user_state = user_state.clone();
             move |action| {
                 match action {
-                    EnvironmentAction::Update(results) => {
-                        let has_error = results.iter().filter(|res| {
-                            match res {
-                                StatementOutput::Invalid(_, _) => true,
-                                _ => false
+                    EnvironmentAction::Update(session, results, script) => {
+                        let has_error = results.iter().any(|res| res.error_message().is_some() );
+                        if !has_error && curr_session != Some(session) {
+
+                            // Results of a connection that is not focused are kept
+                            // until the user switches to it.
+                            let (bg_tables, bg_plots) = inactive.entry(session)
+                                .or_insert_with(|| (Tables::new(), Plots::new()) );
+                            bg_tables.update_from_query_results(results);
+                            bg_tables.script = script;
+                            if let Err(e) = bg_plots.update_from_tables(&bg_tables.tables[..]) {
+                                on_tbl_error.call(e.clone());
                             }
-                        }).next().is_some();
-                        if !has_error {
+                        } else if !has_error {
                             tables.update_from_query_results(results);
+                            tables.script = script;
                             match plots.update_from_tables(&tables.tables[..]) {
                                 Ok(_) => {
                                     if tables.tables.len() >= 1 {
+                                        on_queries_update.call(tables.queries.clone());
                                         on_tbl_update.call(tables.tables.clone());
                                     }
                                 },
@@ -105,21 +218,72 @@ impl Environment {
                             }
                         }
                     },
+                    EnvironmentAction::Notices(session, session_notices) => {
+                        if curr_session == Some(session) {
+                            on_notices_update.call(session_notices.clone());
+                        }
+                        notices.insert(session, session_notices);
+                    },
                     EnvironmentAction::Restore => {
                         // Use the last state set at EnvironmentAction::Update.
                         if tables.tables.len() >= 1 {
+                            on_queries_update.call(tables.queries.clone());
                             on_tbl_update.call(tables.tables.clone());
                         }
                     },
+                    EnvironmentAction::SwitchSession(opt_session) => {
+                        if opt_session != curr_session {
+                            let old_tables = mem::replace(&mut tables, Tables::new());
+                            let old_plots = mem::replace(&mut plots, Plots::new());
+                            if let Some(old) = curr_session {
+                                inactive.insert(old, (old_tables, old_plots));
+                            }
+                            if let Some((new_tables, new_plots)) = opt_session.and_then(|new| inactive.remove(&new) ) {
+                                tables = new_tables;
+                                plots = new_plots;
+                            }
+                            curr_session = opt_session;
+                            selected = None;
+                            let session_notices = opt_session.and_then(|new| notices.get(&new).cloned() )
+                                .unwrap_or_default();
+                            on_notices_update.call(session_notices);
+                            if tables.tables.len() >= 1 {
+                                on_queries_update.call(tables.queries.clone());
+                                on_tbl_update.call(tables.tables.clone());
+                            } else {
+                                on_tbl_clear.call(());
+                            }
+                        }
+                    },
+                    EnvironmentAction::SessionInfo(session, info) => {
+                        conn_infos.insert(session, info);
+                    },
+                    EnvironmentAction::CloseSession(session) => {
+                        inactive.remove(&session);
+                        notices.remove(&session);
+                        conn_infos.remove(&session);
+                        if curr_session == Some(session) {
+                            on_notices_update.call(Vec::new());
+                            tables = Tables::new();
+                            plots = Plots::new();
+                            curr_session = None;
+                            selected = None;
+                            on_tbl_clear.call(());
+                        }
+                    },
                     EnvironmentAction::Select(opt_pos) => {
                         selected = opt_pos;
                     },
-                    EnvironmentAction::ExportRequest(path) => {
+                    EnvironmentAction::ExportRequest(path, opts) => {
                         let item = if let Some(ix) = selected {
                             if let Some(plot_ix) = plots.ixs.iter().position(|i| *i == ix ) {
                                 Some(ExportItem::Panel(plots.panels[plot_ix].clone()))
                             } else {
-                                Some(ExportItem::Table(tables.tables[ix].clone()))
+                                // The messages page (if any) follows the tables.
+                                tables.tables.get(ix).map(|tbl| {
+                                    let query = tables.queries.get(ix).cloned().unwrap_or_default();
+                                    ExportItem::Table(tbl.clone(), query)
+                                })
                             }
                         } else {
                             None
@@ -128,16 +292,103 @@ impl Environment {
                             thread::spawn({
                                 let send = send.clone();
                                 move || {
-                                    if let Err(e) = export_to_path(item, Path::new(&path)) {
+                                    if let Err(e) = export_to_path(item, Path::new(&path), &opts) {
                                         send.send(EnvironmentAction::ExportError(e)).unwrap();
                                     }
                                 }
                             });
                         }
                     },
+                    EnvironmentAction::ExportWorkbookRequest(path) => {
+                        let tbls : Vec<Table> = tables.tables.iter()
+                            .enumerate()
+                            .filter(|(ix, _)| !plots.ixs.contains(ix) )
+                            .map(|(_, tbl)| tbl.clone() )
+                            .collect();
+                        thread::spawn({
+                            let send = send.clone();
+                            move || {
+                                if let Err(e) = export_to_path(ExportItem::Workbook(tbls), Path::new(&path), &ExportOptions::default()) {
+                                    send.send(EnvironmentAction::ExportError(e)).unwrap();
+                                }
+                            }
+                        });
+                    },
+                    EnvironmentAction::ExportAllRequest(dir, tbl_ext, plot_ext, opts) => {
+                        let mut items = Vec::new();
+                        let mut plot_count = 0;
+                        for (ix, tbl) in tables.tables.iter().enumerate() {
+                            let query = tables.queries.get(ix).cloned().unwrap_or_default();
+                            if let Some(plot_ix) = plots.ixs.iter().position(|i| *i == ix ) {
+                                plot_count += 1;
+                                let file = format!("{:02}_plot_{}.{}", ix + 1, plot_count, plot_ext);
+                                items.push((file, ExportItem::Panel(plots.panels[plot_ix].clone()), query));
+                            } else {
+                                let source = tbl.source();
+                                let name = source.name.or(source.relation).unwrap_or(String::from("result"));
+                                let file = format!("{:02}_{}.{}", ix + 1, export_file_stem(&name), tbl_ext);
+                                items.push((file, ExportItem::Table(tbl.clone(), query.clone()), query));
+                            }
+                        }
+                        if items.len() == 0 {
+                            on_export_error.call(format!("No results to export"));
+                            return Continue(true);
+                        }
+                        let connection = curr_session.and_then(|id| conn_infos.get(&id) ).map(|info| {
+                            ManifestConnection {
+                                host : info.host.clone(),
+                                user : info.user.clone(),
+                                database : info.database.clone(),
+                                read_only : info.read_only
+                            }
+                        });
+                        let manifest = ExportManifest {
+                            exported_at : chrono::Local::now().to_rfc3339(),
+                            executed_at : tables.last_update.clone(),
+                            connection,
+                            sql : tables.script.clone(),
+                            files : Vec::new()
+                        };
+                        thread::spawn({
+                            let send = send.clone();
+                            move || {
+                                if let Err(e) = export_all_to_dir(items, manifest, Path::new(&dir), &opts) {
+                                    send.send(EnvironmentAction::ExportError(e)).unwrap();
+                                }
+                            }
+                        });
+                    },
                     EnvironmentAction::ExportError(msg) => {
                         on_export_error.call(msg.clone());
                     },
+                    EnvironmentAction::OpenTable(path) => {
+                        let dialect = user_state.borrow().csv.clone();
+                        thread::spawn({
+                            let send = send.clone();
+                            move || {
+                                let res = Table::new_from_file(Path::new(&path), &dialect);
+                                send.send(EnvironmentAction::TableOpened(res)).unwrap();
+                            }
+                        });
+                    },
+                    EnvironmentAction::TableOpened(res) => {
+                        match res {
+                            Ok(tbl) => {
+                                if let Err(e) = tables.append_external_table(tbl) {
+                                    on_tbl_error.call(e.to_string());
+                                    return Continue(true);
+                                }
+                                if let Err(e) = plots.update_from_tables(&tables.tables[..]) {
+                                    on_tbl_error.call(e.clone());
+                                }
+                                on_queries_update.call(tables.queries.clone());
+                                on_tbl_update.call(tables.tables.clone());
+                            },
+                            Err(e) => {
+                                on_tbl_error.call(e.clone());
+                            }
+                        }
+                    },
                     EnvironmentAction::ChangeSetting(_setting) => {
 
                     },
@@ -146,7 +397,7 @@ impl Environment {
                 Continue(true)
             }
         });
-        Self { send, on_tbl_update, on_export_error, on_tbl_error, user_state : user_state.clone() }
+        Self { send, on_tbl_update, on_queries_update, on_export_error, on_tbl_error, on_tbl_clear, on_notices_update, user_state : user_state.clone() }
     }
 
     pub fn connect_table_update<F>(&self, f : F)
@@ -156,6 +407,30 @@ impl Environment {
         self.on_tbl_update.bind(f);
     }
 
+    /// Called with the queries that produced the tables, just before connect_table_update.
+    pub fn connect_queries_update<F>(&self, f : F)
+    where
+        F : Fn(Vec<String>) + 'static
+    {
+        self.on_queries_update.bind(f);
+    }
+
+    /// Called when the focused connection has no tables to show.
+    pub fn connect_table_clear<F>(&self, f : F)
+    where
+        F : Fn(()) + 'static
+    {
+        self.on_tbl_clear.bind(f);
+    }
+
+    /// Called with the server notices of the focused connection, when they change.
+    pub fn connect_notices_update<F>(&self, f : F)
+    where
+        F : Fn(Vec<ServerNotice>) + 'static
+    {
+        self.on_notices_update.bind(f);
+    }
+
     pub fn connect_export_error<F>(&self, f : F)
     where
         F : Fn(String) + 'static
@@ -176,8 +451,23 @@ impl React<ActiveConnection> for Environment {
 
     fn react(&self, conn : &ActiveConnection) {
         let send = self.send.clone();
-        conn.connect_exec_result(move |res : Vec<StatementOutput>| {
-            send.send(EnvironmentAction::Update(res)).unwrap();
+        conn.connect_session_result(move |(session, res, script) : (usize, Vec<StatementOutput>, String)| {
+            send.send(EnvironmentAction::Update(session, res, script)).unwrap();
+        });
+        let send = self.send.clone();
+        conn.connect_session_notices(move |(session, notices)| {
+            send.send(EnvironmentAction::Notices(session, notices)).unwrap();
+        });
+        let send = self.send.clone();
+        conn.connect_session_switched(move |opt_session| {
+            if let Some((id, info, _)) = &opt_session {
+                send.send(EnvironmentAction::SessionInfo(*id, info.clone())).unwrap();
+            }
+            send.send(EnvironmentAction::SwitchSession(opt_session.map(|(id, _, _)| id ))).unwrap();
+        });
+        let send = self.send.clone();
+        conn.connect_session_closed(move |(session, _)| {
+            send.send(EnvironmentAction::CloseSession(session)).unwrap();
         });
     }
 
@@ -212,16 +502,73 @@ impl React<QueriesWorkspace> for Environment {
 
 }
 
+impl React<OpenTableDialog> for Environment {
+
+    fn react(&self, dialog : &OpenTableDialog) {
+        let send = self.send.clone();
+        dialog.dialog.connect_response(move |dialog, resp| {
+            if resp == ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path() ) {
+                    if let Some(p) = path.to_str() {
+                        send.send(EnvironmentAction::OpenTable(p.to_string())).unwrap();
+                    } else {
+                        eprintln!("Path is not valid UTF-8")
+                    }
+                }
+            }
+        });
+    }
+
+}
+
 impl React<ExportDialog> for Environment {
 
     fn react(&self, dialog : &ExportDialog) {
         let send = self.send.clone();
+        let target_entry = dialog.target_entry.clone();
+        let (width_spin, height_spin) = (dialog.plot_width.clone(), dialog.plot_height.clone());
+        let tex_box = dialog.tex.clone();
+        dialog.dialog.connect_show({
+            let tex_box = dialog.tex.clone();
+            let user_state = self.user_state.clone();
+            move |_| {
+                tex_box.set(&user_state.borrow().tex);
+            }
+        });
+        let user_state = self.user_state.clone();
         dialog.dialog.connect_response(move |dialog, resp| {
             match resp {
                 ResponseType::Accept => {
                     if let Some(path) = dialog.file().and_then(|f| f.path() ) {
                         if let Some(p) = path.to_str() {
-                            send.send(EnvironmentAction::ExportRequest(p.to_string())).unwrap();
+                            if dialog.choice("tables").map(|c| c.as_str() == "all" ).unwrap_or(false) {
+                                send.send(EnvironmentAction::ExportWorkbookRequest(p.to_string())).unwrap();
+                            } else {
+                                let target = target_entry.text().trim().to_string();
+                                let page = match dialog.choice("page").as_ref().map(|c| c.as_str() ) {
+                                    Some("a4") => PageSize::A4,
+                                    Some("letter") => PageSize::Letter,
+                                    _ => PageSize::Plot
+                                };
+                                let dpi = dialog.choice("dpi").and_then(|c| c.parse::<f64>().ok() ).unwrap_or(150.0);
+
+                                // The LaTeX layout is remembered for the next exports.
+                                let tex = match tex_box.options() {
+                                    Ok(tex) => tex,
+                                    Err(e) => {
+                                        send.send(EnvironmentAction::ExportError(e)).unwrap();
+                                        return;
+                                    }
+                                };
+                                user_state.borrow_mut().tex = tex.clone();
+                                let opts = ExportOptions {
+                                    sql_target : if target.is_empty() { None } else { Some(target) },
+                                    csv : user_state.borrow().csv.clone(),
+                                    plot : PlotExportOptions { page, width : width_spin.value(), height : height_spin.value(), dpi },
+                                    tex
+                                };
+                                send.send(EnvironmentAction::ExportRequest(p.to_string(), opts)).unwrap();
+                            }
                         } else {
                             eprintln!("Path is not valid UTF-8")
                         }
@@ -234,18 +581,84 @@ impl React<ExportDialog> for Environment {
 
 }
 
-fn export_to_path(item : ExportItem, path : &Path) -> Result<(), String> {
+impl React<ExportAllDialog> for Environment {
+
+    fn react(&self, dialog : &ExportAllDialog) {
+        let send = self.send.clone();
+        let user_state = self.user_state.clone();
+        dialog.dialog.connect_response(move |dialog, resp| {
+            if resp == ResponseType::Accept {
+                if let Some(path) = dialog.file().and_then(|f| f.path() ) {
+                    if let Some(p) = path.to_str() {
+                        let tbl_ext = dialog.choice("table_format").map(|c| c.to_string() ).unwrap_or(String::from("csv"));
+                        let plot_ext = dialog.choice("plot_format").map(|c| c.to_string() ).unwrap_or(String::from("svg"));
+                        let opts = ExportOptions {
+                            sql_target : None,
+                            csv : user_state.borrow().csv.clone(),
+                            plot : PlotExportOptions::default(),
+                            tex : user_state.borrow().tex.clone()
+                        };
+                        send.send(EnvironmentAction::ExportAllRequest(p.to_string(), tbl_ext, plot_ext, opts)).unwrap();
+                    } else {
+                        eprintln!("Path is not valid UTF-8")
+                    }
+                }
+            }
+        });
+    }
+
+}
+
+// Lowercase file name stem with only alphanumeric characters and underscores.
+fn export_file_stem(name : &str) -> String {
+    let stem : String = name.trim().to_lowercase().chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' } )
+        .collect();
+    let stem = stem.trim_matches('_').to_string();
+    if stem.is_empty() {
+        String::from("result")
+    } else {
+        stem
+    }
+}
+
+fn export_all_to_dir(
+    items : Vec<(String, ExportItem, String)>,
+    mut manifest : ExportManifest,
+    dir : &Path,
+    opts : &ExportOptions
+) -> Result<(), String> {
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    for (file, item, query) in items {
+        let (kind, rows) = match &item {
+            ExportItem::Table(tbl, _) => ("table", Some(tbl.nrows())),
+            ExportItem::Panel(_) => ("plot", None),
+            ExportItem::Workbook(_) => ("workbook", None)
+        };
+        export_to_path(item, &dir.join(&file), opts).map_err(|e| format!("Error exporting {}: {}", file, e) )?;
+        manifest.files.push(ManifestFile { file, kind : kind.to_string(), query, rows });
+    }
+    let content = serde_json::to_string_pretty(&manifest).map_err(|e| format!("{}", e) )?;
+    let mut f = File::create(dir.join("manifest.json")).map_err(|e| format!("Error creating manifest file: {}", e) )?;
+    f.write_all(content.as_bytes()).map_err(|e| format!("Error writing to manifest file: {}", e) )
+}
+
+fn export_to_path(item : ExportItem, path : &Path, opts : &ExportOptions) -> Result<(), String> {
     let ext = path.extension().map(|ext| ext.to_str().unwrap_or("") );
+    let stem = path.file_stem().and_then(|s| s.to_str() ).unwrap_or("exported").to_string();
     match item {
-        ExportItem::Table(mut tbl) => {
+        ExportItem::Table(mut tbl, query) => {
             let mut export_format = TableSettings::default();
             export_format.prec = None;
+            export_format.tex = opts.tex.clone();
             tbl.update_format(export_format);
             match ext {
                 Some("csv") => {
+                    let content = tbl.to_csv_with(&opts.csv)?;
                     let mut f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
-                    let s = tbl.to_csv();
-                    f.write_all(s.as_bytes()).map_err(|e| format!("Error writing to export file: {}", e) )
+                    f.write_all(&content[..]).map_err(|e| format!("Error writing to export file: {}", e) )
                 },
                 Some("md") => {
                     let mut f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
@@ -257,16 +670,57 @@ fn export_to_path(item : ExportItem, path : &Path) -> Result<(), String> {
                     let s = tbl.to_tex();
                     f.write_all(s.as_bytes()).map_err(|e| format!("Error writing to export file: {}", e) )
                 },
-                _ => Err(format!("Invalid file extension for table export (expected .csv, .md or .tex)"))
+                Some("json") => {
+                    let mut f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
+                    let s = tbl.to_json();
+                    f.write_all(s.as_bytes()).map_err(|e| format!("Error writing to export file: {}", e) )
+                },
+                Some("ndjson") | Some("jsonl") => {
+                    let f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
+                    let mut writer = BufWriter::new(f);
+                    tbl.write_ndjson(&mut writer).map_err(|e| format!("Error writing to export file: {}", e) )
+                },
+                Some("xlsx") => spreadsheet::write_xlsx(&[tbl], path),
+                Some("ods") => spreadsheet::write_ods(&[tbl], path),
+                Some("parquet") => tbl.write_parquet(path),
+                Some("arrow") => tbl.write_arrow(path),
+                Some("sql") => {
+                    let target = opts.sql_target.clone().or(tbl.source().relation).unwrap_or(stem);
+                    let s = tbl.to_sql_script(&target, SQL_INSERT_BATCH)?;
+                    let mut f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
+                    f.write_all(s.as_bytes()).map_err(|e| format!("Error writing to export file: {}", e) )
+                },
+                Some("html") => {
+                    let source = tbl.source();
+                    let title = source.name.or(source.relation).unwrap_or(stem);
+                    let s = tbl.to_html_page(&title, &query);
+                    let mut f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
+                    f.write_all(s.as_bytes()).map_err(|e| format!("Error writing to export file: {}", e) )
+                },
+                _ => Err(format!("Invalid file extension for table export (expected .csv, .md, .tex, .json, .ndjson, .jsonl, .xlsx, .ods, .parquet, .arrow, .sql or .html)"))
+            }
+        },
+        ExportItem::Workbook(mut tbls) => {
+            for tbl in tbls.iter_mut() {
+                let mut export_format = TableSettings::default();
+                export_format.prec = None;
+                tbl.update_format(export_format);
+            }
+            match ext {
+                Some("xlsx") => spreadsheet::write_xlsx(&tbls[..], path),
+                Some("ods") => spreadsheet::write_ods(&tbls[..], path),
+                _ => Err(format!("Invalid file extension for exporting all results (expected .xlsx or .ods)"))
             }
         },
         ExportItem::Panel(mut panel) => {
             match ext {
-                Some("png") | Some("eps") | Some("svg") => {
+                Some("png") => crate::ui::write_panel_png(&mut panel, path, &opts.plot),
+                Some("pdf") => crate::ui::write_panel_pdf(&mut panel, path, &opts.plot),
+                Some("eps") | Some("svg") => {
                     panel.draw_to_file(path.to_str().unwrap()).map_err(|e| format!("{e}") )
                 },
                 _ => {
-                    Err(format!("Invalid file extension for plot export (expected .png, .eps or .svg)"))
+                    Err(format!("Invalid file extension for plot export (expected .png, .pdf, .eps or .svg)"))
                 }
             }
         }
@@ -362,6 +816,9 @@ pub struct Tables {
 
     last_update : Option<String>,
 
+    /// Full script executed to produce the current tables, including its non-query statements.
+    script : String,
+
     history : Vec<EnvironmentUpdate>,
 
 }
@@ -381,6 +838,7 @@ impl Tables {
         Self{
             tables : Vec::new(),
             last_update : None,
+            script : String::new(),
             queries : Vec::new(),
             history : vec![EnvironmentUpdate::Clear],
             exec_results : Vec::new(),
@@ -399,6 +857,7 @@ impl Tables {
         self.tables.clear();
         self.queries.clear();
         self.exec_results.clear();
+        self.last_update = Some(chrono::Local::now().to_rfc3339());
         if results.len() == 0 {
             self.history.push(EnvironmentUpdate::Clear);
             return Some(Ok(EnvironmentUpdate::Clear));
@@ -419,6 +878,11 @@ impl Tables {
                     self.history.push(EnvironmentUpdate::Clear);
                     opt_err = Some(ExecutionError { msg : msg.clone(), is_server });
                 },
+                StatementOutput::Rejected(e) => {
+                    self.tables.clear();
+                    self.history.push(EnvironmentUpdate::Clear);
+                    opt_err = Some(ExecutionError { msg : e.describe(), is_server : true });
+                },
                 StatementOutput::Statement(_) | 
                     StatementOutput::Modification(_) | 
                     StatementOutput::Empty | 
@@ -477,6 +941,9 @@ impl Tables {
                 StatementOutput::Invalid(e, is_server) => {
                     return Some(Err(ExecutionError{ msg : e.clone(), is_server : *is_server}));
                 },
+                StatementOutput::Rejected(e) => {
+                    return Some(Err(ExecutionError{ msg : e.describe(), is_server : true }));
+                },
                 _ => { }
             }
         }
@@ -488,6 +955,9 @@ impl Tables {
                 StatementOutput::Invalid(e, is_server) => {
                     Some(Err(ExecutionError { msg : e.clone(), is_server : *is_server }))
                 },
+                StatementOutput::Rejected(e) => {
+                    Some(Err(ExecutionError { msg : e.describe(), is_server : true }))
+                },
                 StatementOutput::Modification(m) => Some(Ok(m.clone())),
                 StatementOutput::Valid(_, _) => None,
                 StatementOutput::Empty => Some(Ok(format!("No results to show")))
@@ -830,7 +1300,9 @@ impl Tables {
         &mut self,
         tbl : Table
     ) -> Result<(), &'static str> {
+        // External tables were not produced by a query, but are kept paired with an empty one.
         self.tables.push(tbl);
+        self.queries.push(String::new());
         self.history.push(EnvironmentUpdate::NewExternal);
         Ok(())
     }
@@ -4,16 +4,27 @@ This work is licensed under the terms of the GPL v3.0 License.
 For a copy, see http://www.gnu.org/licenses.*/
 
 use std::thread::{self, JoinHandle};
-use crate::sql::{StatementOutput};
+use crate::sql::{StatementOutput, ServerNotice};
 use std::sync::{Arc, Mutex, mpsc::{self, Sender, Receiver}};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::collections::HashMap;
-use crate::server::Connection;
+use crate::server::{Connection, TransactionCommand};
 use crate::sql::object::{DBObject, DBInfo};
 use crate::sql::{SafetyLock};
-use std::fs::File;
-use std::io::Read;
+use std::path::Path;
 use crate::sql::copy::*;
 use crate::tables::table::*;
+use crate::tables::dialect::CsvDialect;
+use crate::tables::stream::TableWriter;
+use crate::tables::dialect::read_csv_records;
+use crate::tables::csv_import::{ImportPlan, RejectedRow};
+use crate::sql::parsing::AnyStatement;
+
+// Rows fetched from the server cursor at each step of a query export.
+const EXPORT_CHUNK_SIZE : usize = 10_000;
+
+// Rows sent by each INSERT statement of a mapped CSV import.
+const IMPORT_BATCH_SIZE : usize = 1000;
 
 #[derive(Debug, Clone)]
 pub struct ExecutionRequest {
@@ -21,6 +32,11 @@ pub struct ExecutionRequest {
     subs : HashMap<String, String>,
     safety : SafetyLock,
     is_schedule : bool,
+
+    // Whether a transaction should be started before executing the statements
+    // (set for the first execution of sessions in manual transaction mode).
+    begin : bool,
+
     mode : ExecMode
 }
 
@@ -107,7 +123,7 @@ impl SqlListener {
 
     pub fn launch<F>(result_cb : F) -> Self
     where
-        F : Fn(Vec<StatementOutput>, ExecMode) + 'static + Send
+        F : Fn(Vec<StatementOutput>, Vec<ServerNotice>, ExecMode) + 'static + Send
     {
         let (cmd_tx, cmd_rx) = mpsc::channel::<ExecutionRequest>();
         let engine : Arc<Mutex<Option<Box<dyn Connection>>>> = Arc::new(Mutex::new(None));
@@ -126,7 +142,7 @@ impl SqlListener {
     }
 
     pub fn send_single_command(&self, sql : String, safety : SafetyLock) -> Result<(), String> {
-        match self.cmd_sender.send(ExecutionRequest { sql : sql.clone(), subs : HashMap::new(), safety, is_schedule : false, mode : ExecMode::Single }) {
+        match self.cmd_sender.send(ExecutionRequest { sql : sql.clone(), subs : HashMap::new(), safety, is_schedule : false, begin : false, mode : ExecMode::Single }) {
             Ok(_) => {
 
             },
@@ -142,7 +158,7 @@ impl SqlListener {
     /// are correctly parsed, send the SQL to the server. If sequence is not
     /// correctly parsed, do not send anything to the server, and return the
     /// error to the user.
-    pub fn send_commands(&self, sql : String, subs : HashMap<String, String>, safety : SafetyLock, is_schedule : bool) -> Result<(), String> {
+    pub fn send_commands(&self, sql : String, subs : HashMap<String, String>, safety : SafetyLock, is_schedule : bool, begin : bool) -> Result<(), String> {
 
         // Before sending a command, it might be interesting to check if self.handle.is_running()
         // when this stabilizes at the stdlib. If it is not running (i.e. there is a panic at the
@@ -159,6 +175,7 @@ impl SqlListener {
             subs, 
             safety, 
             is_schedule,
+            begin,
             mode : ExecMode::Multiple 
         };
         match self.cmd_sender.send(request) {
@@ -174,6 +191,21 @@ impl SqlListener {
         Ok(())
     }
 
+    pub fn send_transaction_command(&self, cmd : TransactionCommand) -> Result<(), String> {
+        let request = ExecutionRequest {
+            sql : String::new(),
+            subs : HashMap::new(),
+            safety : SafetyLock::default(),
+            is_schedule : false,
+            begin : false,
+            mode : ExecMode::Transaction(cmd)
+        };
+        match self.cmd_sender.send(request) {
+            Ok(_) => Ok(()),
+            Err(_e) => Err(format!("Database connection thread is down.\nPlease restart the application."))
+        }
+    }
+
     /*/// Gets all results which might have been queued at the receiver.
     pub fn maybe_get_result(&self) -> Option<Vec<StatementOutput>> {
         let mut full_ans = Vec::new();
@@ -266,13 +298,14 @@ impl SqlListener {
         &self,
         path : String,
         action : crate::sql::copy::Copy,
+        dialect : CsvDialect,
         f : impl Fn(Result<usize, String>)->() + Send + 'static
     ) {
         let engine = self.engine.clone();
         thread::spawn(move|| {
             if let Ok(mut opt_engine) = engine.lock() {
                 if let Some(engine) = opt_engine.as_mut() {
-                    let ans = copy_table_from_csv(path, engine.as_mut(), action);
+                    let ans = copy_table_from_file(path, engine.as_mut(), action, &dialect);
                     f(ans);
                 } else {
                     f(Err(String::from("No active connection to complete import action")));
@@ -283,14 +316,86 @@ impl SqlListener {
         });
     }
 
+    /// Imports the CSV file as the plan maps its columns, returning the number of rows inserted and
+    /// the records left out. Errors carry whether they were raised by a statement sent to the server
+    /// (which leaves an open transaction failed). With ImportTransaction::Begin, the transaction
+    /// was started unless the error was not raised by the server.
+    pub fn spawn_mapped_import_and_then(
+        &self,
+        path : String,
+        plan : ImportPlan,
+        dialect : CsvDialect,
+        lock : SafetyLock,
+        tx : ImportTransaction,
+        f : impl Fn(Result<(usize, Vec<RejectedRow>), (String, bool)>)->() + Send + 'static
+    ) {
+        let engine = self.engine.clone();
+        thread::spawn(move|| {
+            if let Ok(mut opt_engine) = engine.lock() {
+                if let Some(engine) = opt_engine.as_mut() {
+                    f(import_mapped_csv(&path, engine.as_mut(), &plan, &dialect, &lock, tx));
+                } else {
+                    f(Err((String::from("No active connection to complete import action"), false)));
+                }
+            } else {
+                eprintln!("Unable to acquire lock over engine");
+            }
+        });
+    }
+
+    /// Streams the query result into the file at path, calling on_progress with the number of rows
+    /// written after each chunk. The partial file is removed if the export fails or is cancelled.
+    pub fn spawn_export_and_then(
+        &self,
+        query : String,
+        path : String,
+        dialect : CsvDialect,
+        cancel : Arc<AtomicBool>,
+        on_progress : impl Fn(usize)->() + Send + 'static,
+        f : impl Fn(Result<usize, String>)->() + Send + 'static
+    ) {
+        let engine = self.engine.clone();
+        thread::spawn(move|| {
+            if let Ok(mut opt_engine) = engine.lock() {
+                if let Some(engine) = opt_engine.as_mut() {
+                    let ans = stream_query_to_file(&query, &path, engine.as_mut(), &dialect, &cancel, &on_progress);
+                    if ans.is_err() {
+                        let _ = std::fs::remove_file(&path);
+                    }
+                    f(ans);
+                } else {
+                    f(Err(String::from("No active connection to complete export action")));
+                }
+            } else {
+                eprintln!("Unable to acquire lock over engine");
+            }
+        });
+    }
+
+}
+
+/// How the statements of a mapped import relate to the session transaction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportTransaction {
+
+    // The rows (and the created table) are only committed if every statement succeeds.
+    Atomic,
+
+    // The session transaction is started for the import, and left for the user to commit (manual mode).
+    Begin,
+
+    // The rows are inserted within the transaction the user already opened.
+    Open
 }
 
 /// The queries table environment only listens to "multiple" mode. Use
 /// "single" mode to query information that wont't be displayed as tables.
-#[derive(Debug, Clone, Copy)]
+/// "Transaction" mode carries the transaction command that was executed.
+#[derive(Debug, Clone)]
 pub enum ExecMode {
     Single,
-    Multiple
+    Multiple,
+    Transaction(TransactionCommand)
 }
 
 fn spawn_listener_thread<F>(
@@ -299,27 +404,54 @@ fn spawn_listener_thread<F>(
     cmd_rx : Receiver<ExecutionRequest>
 ) -> JoinHandle<()>
 where
-    F : Fn(Vec<StatementOutput>, ExecMode) + 'static + Send
+    F : Fn(Vec<StatementOutput>, Vec<ServerNotice>, ExecMode) + 'static + Send
 {
     thread::spawn(move ||  {
         loop {
             match cmd_rx.recv() {
             
-                Ok(ExecutionRequest { sql, subs, safety, is_schedule, mode }) => {
+                Ok(ExecutionRequest { sql, subs, safety, is_schedule, begin, mode }) => {
                 
                     let result;
+                    let mut notices = Vec::new();
+
+                    // Reported separately, so the transaction is only marked open after BEGIN succeeded.
+                    let mut began = None;
                     
                     match engine.lock() {
                         Ok(mut opt_eng) => match &mut *opt_eng {
                             Some(ref mut eng) => {
-                                result = match eng.try_run(sql, &subs, safety, is_schedule) {
-                                    Ok(stmt_results) => {
-                                        stmt_results
-                                    },
-                                    Err(e) => {
-                                        vec![StatementOutput::Invalid(e.to_string(), false )]
-                                    }
-                                };
+                                if let ExecMode::Transaction(cmd) = &mode {
+                                    result = vec![eng.transaction(cmd)];
+                                    notices = eng.take_notices();
+                                } else {
+
+                                    // Statements are never executed if the transaction could not be started, since
+                                    // the user expects them not to be committed.
+                                    let begin_err = if begin {
+                                        let out = eng.transaction(&TransactionCommand::Begin);
+                                        let err = out.error_message()
+                                            .map(|e| format!("Could not start transaction:\n{}", e) );
+                                        if err.is_none() {
+                                            began = Some(out);
+                                        }
+                                        err
+                                    } else {
+                                        None
+                                    };
+                                    result = match begin_err {
+                                        Some(e) => vec![StatementOutput::Invalid(e, false)],
+                                        None => match eng.try_run(sql, &subs, safety, is_schedule) {
+                                            Ok((stmt_results, stmt_notices)) => {
+                                                notices = stmt_notices;
+                                                stmt_results
+                                            },
+                                            Err(e) => {
+                                                vec![StatementOutput::Invalid(e.to_string(), false )]
+                                            }
+                                        }
+                                    };
+                                }
                             },
                             None => {
                                 result = vec![StatementOutput::Invalid(format!("Database connection is down. Please restart the connection"), false)];
@@ -337,7 +469,10 @@ where
                     /* It is important to call the result callback only after the engine mutex
                     is unlocked, so that new statements can be promptly sent after results arrive
                     (used during testing, but a good practice for ordinary use nevertheless). */
-                    result_cb(result, mode);
+                    if let Some(out) = began {
+                        result_cb(vec![out], Vec::new(), ExecMode::Transaction(TransactionCommand::Begin));
+                    }
+                    result_cb(result, notices, mode);
                     
                 },
                 Err(_e) => {
@@ -351,35 +486,98 @@ where
     })
 }
 
-fn copy_table_from_csv(
+fn copy_table_from_file(
     path : String,
     conn : &mut dyn Connection,
-    action : crate::sql::copy::Copy
+    action : crate::sql::copy::Copy,
+    dialect : &CsvDialect
 ) -> Result<usize, String> {
     assert!(action.target == CopyTarget::From);
-    match File::open(&path) {
-        Ok(mut f) => {
-            let mut txt = String::new();
-            if let Err(e) = f.read_to_string(&mut txt) {
-                return Err(format!("{}", e));
-            }
-            match Table::new_from_text(txt) {
-                Ok(mut tbl) => {
-                    conn.import(
-                        &mut tbl,
-                        &action.table[..]
-                    )
-                },
-                Err(e) => {
-                    Err(format!("Error parsing table: {}", e))
-                }
-            }
-        },
-        Err(e) => {
-            Err(format!("Error opening file to import data: {}", e))
-        }
+    let mut tbl = Table::new_from_file(Path::new(&path), dialect)?;
+    conn.import(
+        &mut tbl,
+        &action.table[..]
+    )
+}
+
+
+
+
+fn stream_query_to_file(
+    query : &str,
+    path : &str,
+    conn : &mut dyn Connection,
+    dialect : &CsvDialect,
+    cancel : &Arc<AtomicBool>,
+    on_progress : &dyn Fn(usize)
+) -> Result<usize, String> {
+    let mut writer = TableWriter::create(Path::new(path), dialect)?;
+    let ans = conn.stream_query(query, EXPORT_CHUNK_SIZE, cancel, &mut |chunk| {
+        writer.write(chunk)?;
+        on_progress(writer.rows());
+        Ok(!cancel.load(Ordering::Relaxed))
+    });
+
+    // A statement cancelled while running fails with an error, which is reported as the cancellation.
+    if cancel.load(Ordering::Relaxed) {
+        return Err(format!("Query export cancelled"));
     }
+    ans?;
+    writer.finish()
 }
 
+fn exec_import_statement(conn : &mut dyn Connection, sql : &str) -> Result<(), String> {
+    let stmt = AnyStatement::from_sql(sql).ok_or(String::from("Invalid import SQL"))?;
+    match conn.exec(&stmt, &HashMap::new()) {
+        StatementOutput::Statement(_) | StatementOutput::Modification(_) => Ok(()),
+        StatementOutput::Invalid(err, _) => Err(err),
+        StatementOutput::Rejected(err) => Err(err.describe()),
+        _ => Err(String::from("Invalid import output"))
+    }
+}
 
+fn import_mapped_csv(
+    path : &str,
+    conn : &mut dyn Connection,
+    plan : &ImportPlan,
+    dialect : &CsvDialect,
+    lock : &SafetyLock,
+    tx : ImportTransaction
+) -> Result<(usize, Vec<RejectedRow>), (String, bool)> {
+    let unsent = |e : String| (e, false);
+    plan.validate().map_err(unsent)?;
+    let bytes = std::fs::read(path).map_err(|e| format!("Error reading file: {}", e) ).map_err(unsent)?;
+    let records = read_csv_records(&bytes[..], dialect, None).map_err(unsent)?;
+    let (stmts, n, rejected) = plan.insert_statements(&records, dialect, IMPORT_BATCH_SIZE);
+
+    // The statements go through the connection safety policies before any of them is sent. Statements
+    // requiring confirmation were already confirmed by the user at this point.
+    let creation = if plan.create { Some(plan.creation_sql()) } else { None };
+    for sql in creation.iter().chain(stmts.iter()) {
+        lock.accepts_sql(sql).map_err(unsent)?;
+    }
 
+    let atomic = tx == ImportTransaction::Atomic;
+    if tx != ImportTransaction::Open {
+        if let Some(e) = conn.transaction(&TransactionCommand::Begin).error_message() {
+            return Err((format!("Could not start transaction:\n{}", e), false));
+        }
+    }
+    let mut ans = Ok(());
+    if let Some(creation) = &creation {
+        ans = exec_import_statement(conn, creation);
+    }
+    for stmt in stmts.iter() {
+        if ans.is_err() {
+            break;
+        }
+        ans = exec_import_statement(conn, stmt);
+    }
+    if atomic {
+        let end = if ans.is_ok() { TransactionCommand::Commit } else { TransactionCommand::Rollback };
+        if let (Ok(_), Some(e)) = (&ans, conn.transaction(&end).error_message()) {
+            return Err((format!("Could not commit imported rows:\n{}", e), true));
+        }
+    }
+    ans.map(|_| (n, rejected) ).map_err(|e| (e, true) )
+}
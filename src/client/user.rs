@@ -20,6 +20,9 @@ use stateful::PersistentState;
 use std::thread::JoinHandle;
 
 use crate::sql::SafetyLock;
+use crate::sql::format::{KeywordCase, FormatOptions};
+use crate::tables::dialect::{CsvDialect, Quoting, LineTerminator};
+use crate::tables::tex::TexOptions;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnSettings {
@@ -39,12 +42,17 @@ impl Default for ConnSettings {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct EditorSettings {
     pub scheme : String,
     pub font_family : String,
     pub font_size : i32,
     pub show_line_numbers : bool,
-    pub highlight_current_line : bool
+    pub highlight_current_line : bool,
+
+    // Used when formatting scripts.
+    pub keyword_case : KeywordCase,
+    pub indent_width : i32
 }
 
 impl Default for EditorSettings {
@@ -55,7 +63,21 @@ impl Default for EditorSettings {
             font_family : String::from("Source Code Pro"),
             font_size : 16,
             show_line_numbers : true,
-            highlight_current_line : false
+            highlight_current_line : false,
+            keyword_case : KeywordCase::Upper,
+            indent_width : 4
+        }
+    }
+
+}
+
+impl EditorSettings {
+
+    pub fn format_options(&self) -> FormatOptions {
+        FormatOptions {
+            keyword_case : self.keyword_case,
+            indent : self.indent_width.max(0) as usize,
+            ..FormatOptions::default()
         }
     }
 
@@ -86,12 +108,6 @@ pub struct ExecutionSettings {
     // Statement execution timeout, in seconds
     pub statement_timeout : i32,
     
-    // Whether to execute destructive ddl statements
-    pub accept_ddl  : bool,
-    
-    // Whether to execute destructive dml statements
-    pub accept_dml : bool,
-    
     pub enable_async : bool
 }
 
@@ -102,8 +118,6 @@ impl Default for ExecutionSettings {
             row_limit : 500,
             execution_interval : 5,
             statement_timeout : 5,
-            accept_ddl : false,
-            accept_dml : false,
             enable_async : false
         }
     }
@@ -129,17 +143,27 @@ pub struct UserState {
 
     pub execution : ExecutionSettings,
 
-    pub security : SecuritySettings
+    pub security : SecuritySettings,
+
+    // Dialect of exported and imported CSV files.
+    #[serde(default)]
+    pub csv : CsvDialect,
+
+    // Layout of the last LaTeX export.
+    #[serde(default)]
+    pub tex : TexOptions
 
 }
 
 impl UserState {
 
-    pub fn safety(&self) -> SafetyLock {
+    // Safety policies are set per connection, while the remaining execution
+    // settings are shared by all connections.
+    pub fn safety(&self, info : &ConnectionInfo) -> SafetyLock {
         SafetyLock {
-            accept_dml : self.execution.accept_dml,
-            accept_ddl : self.execution.accept_ddl,
-            enable_async : self.execution.enable_async
+            policies : info.policies,
+            enable_async : self.execution.enable_async,
+            confirmed : false
         }
     }
     
@@ -289,20 +313,6 @@ impl React<crate::ui::QueriesWindow> for SharedUserState {
                 state.borrow_mut().execution.statement_timeout = adj.value() as i32;
             }
         });
-        win.settings.exec_bx.ddl_switch.connect_state_set({
-            let state = self.clone();
-            move|switch, _| {
-                state.borrow_mut().execution.accept_ddl = switch.is_active();
-                Inhibit(false)
-            }
-        });
-        win.settings.exec_bx.dml_switch.connect_state_set({
-            let state = self.clone();
-            move|switch, _| {
-                state.borrow_mut().execution.accept_dml = switch.is_active();
-                Inhibit(false)
-            }
-        });
         win.settings.exec_bx.async_switch.connect_state_set({
             let state = self.clone();
             move|switch, _| {
@@ -350,6 +360,110 @@ impl React<crate::ui::QueriesWindow> for SharedUserState {
                 Inhibit(false)
             }
         });
+        win.settings.editor_bx.keyword_case_combo.connect_changed({
+            let state = self.clone();
+            move |combo| {
+                let case = match combo.active_id().as_ref().map(|id| id.as_str() ) {
+                    Some("lower") => KeywordCase::Lower,
+                    Some("preserve") => KeywordCase::Preserve,
+                    _ => KeywordCase::Upper
+                };
+                state.borrow_mut().editor.keyword_case = case;
+            }
+        });
+        win.settings.editor_bx.indent_spin.adjustment().connect_value_changed({
+            let state = self.clone();
+            move |adj| {
+                state.borrow_mut().editor.indent_width = adj.value() as i32;
+            }
+        });
+
+        // CSV
+        let csv_bx = &win.settings.csv_bx;
+        csv_bx.delimiter_combo.connect_changed({
+            let state = self.clone();
+            move |combo| {
+                if let Some(c) = combo.active_id().and_then(|id| id.chars().next() ) {
+                    let mut state = state.borrow_mut();
+                    state.csv.delimiter = c;
+                    state.csv.delimiter_set = true;
+                }
+            }
+        });
+        csv_bx.quote_combo.connect_changed({
+            let state = self.clone();
+            move |combo| {
+                if let Some(c) = combo.active_id().and_then(|id| id.chars().next() ) {
+                    state.borrow_mut().csv.quote = c;
+                }
+            }
+        });
+        csv_bx.quoting_combo.connect_changed({
+            let state = self.clone();
+            move |combo| {
+                let quoting = match combo.active_id().as_ref().map(|id| id.as_str() ) {
+                    Some("always") => Quoting::Always,
+                    Some("non_numeric") => Quoting::NonNumeric,
+                    Some("never") => Quoting::Never,
+                    _ => Quoting::Necessary
+                };
+                state.borrow_mut().csv.quoting = quoting;
+            }
+        });
+        csv_bx.header_switch.connect_state_set({
+            let state = self.clone();
+            move|switch, _| {
+                state.borrow_mut().csv.header = switch.is_active();
+                Inhibit(false)
+            }
+        });
+        csv_bx.null_entry.connect_changed({
+            let state = self.clone();
+            move|entry| {
+                state.borrow_mut().csv.null_text = entry.text().as_str().to_string();
+            }
+        });
+        csv_bx.terminator_combo.connect_changed({
+            let state = self.clone();
+            move |combo| {
+                let terminator = match combo.active_id().as_ref().map(|id| id.as_str() ) {
+                    Some("crlf") => LineTerminator::CrLf,
+                    _ => LineTerminator::Lf
+                };
+                state.borrow_mut().csv.terminator = terminator;
+            }
+        });
+        csv_bx.encoding_combo.connect_changed({
+            let state = self.clone();
+            move |combo| {
+                if let Some(id) = combo.active_id() {
+                    state.borrow_mut().csv.encoding = id.to_string();
+                }
+            }
+        });
+        csv_bx.decimal_combo.connect_changed({
+            let state = self.clone();
+            move |combo| {
+                if let Some(c) = combo.active_id().and_then(|id| id.chars().next() ) {
+                    state.borrow_mut().csv.decimal_sep = c;
+                }
+            }
+        });
+        csv_bx.thousands_combo.connect_changed({
+            let state = self.clone();
+            move |combo| {
+                if let Some(id) = combo.active_id() {
+                    state.borrow_mut().csv.thousands_sep = if id.as_str() == "none" { None } else { id.chars().next() };
+                }
+            }
+        });
+        csv_bx.detect_switch.connect_state_set({
+            let state = self.clone();
+            move|switch, _| {
+                state.borrow_mut().csv.auto_detect = switch.is_active();
+                Inhibit(false)
+            }
+        });
 
         // Security
         win.settings.security_bx.save_switch.connect_state_set({
@@ -472,8 +586,6 @@ impl PersistentState<QueriesWindow> for SharedUserState {
         queries_win.settings.exec_bx.row_limit_spin.adjustment().set_value(state.execution.row_limit as f64);
         queries_win.settings.exec_bx.schedule_scale.adjustment().set_value(state.execution.execution_interval as f64);
         queries_win.settings.exec_bx.timeout_scale.adjustment().set_value(state.execution.statement_timeout as f64);
-        queries_win.settings.exec_bx.dml_switch.set_active(state.execution.accept_dml);
-        queries_win.settings.exec_bx.ddl_switch.set_active(state.execution.accept_ddl);
         queries_win.settings.exec_bx.async_switch.set_active(state.execution.enable_async);
 
         let font = format!("{} {}", state.editor.font_family, state.editor.font_size);
@@ -481,7 +593,37 @@ impl PersistentState<QueriesWindow> for SharedUserState {
         queries_win.settings.editor_bx.font_btn.set_font(&font);
         queries_win.settings.editor_bx.line_num_switch.set_active(state.editor.show_line_numbers);
         queries_win.settings.editor_bx.line_highlight_switch.set_active(state.editor.highlight_current_line);
+        let case_id = match state.editor.keyword_case {
+            KeywordCase::Upper => "upper",
+            KeywordCase::Lower => "lower",
+            KeywordCase::Preserve => "preserve"
+        };
+        queries_win.settings.editor_bx.keyword_case_combo.set_active_id(Some(case_id));
+        queries_win.settings.editor_bx.indent_spin.adjustment().set_value(state.editor.indent_width as f64);
         
+        let csv_bx = &queries_win.settings.csv_bx;
+        csv_bx.delimiter_combo.set_active_id(Some(&state.csv.delimiter.to_string()));
+        csv_bx.quote_combo.set_active_id(Some(&state.csv.quote.to_string()));
+        let quoting_id = match state.csv.quoting {
+            Quoting::Necessary => "necessary",
+            Quoting::Always => "always",
+            Quoting::NonNumeric => "non_numeric",
+            Quoting::Never => "never"
+        };
+        csv_bx.quoting_combo.set_active_id(Some(quoting_id));
+        csv_bx.header_switch.set_active(state.csv.header);
+        csv_bx.null_entry.set_text(&state.csv.null_text);
+        let terminator_id = match state.csv.terminator {
+            LineTerminator::Lf => "lf",
+            LineTerminator::CrLf => "crlf"
+        };
+        csv_bx.terminator_combo.set_active_id(Some(terminator_id));
+        csv_bx.encoding_combo.set_active_id(Some(&state.csv.encoding));
+        csv_bx.decimal_combo.set_active_id(Some(&state.csv.decimal_sep.to_string()));
+        let thousands_id = state.csv.thousands_sep.map(|c| c.to_string() ).unwrap_or(String::from("none"));
+        csv_bx.thousands_combo.set_active_id(Some(&thousands_id));
+        csv_bx.detect_switch.set_active(state.csv.auto_detect);
+
         queries_win.settings.security_bx.save_switch.set_active(state.security.save_conns);
     }
 
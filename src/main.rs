@@ -58,8 +58,10 @@ fn main() {
     application.set_accels_for_action("win.new_file", &["<Ctrl>N"]);
     application.set_accels_for_action("win.save_as_file", &["<Ctrl><Shift>S"]);
     application.set_accels_for_action("win.find_replace", &["<Ctrl>F"]);
+    application.set_accels_for_action("win.format_sql", &["<Ctrl><Shift>F"]);
     
     application.set_accels_for_action("win.queue_execution", &["F7"]);
+    application.set_accels_for_action("win.queue_current_execution", &["<Shift>F7"]);
     application.set_accels_for_action("win.clear", &["F8"]);
     application.set_accels_for_action("win.restore", &["F5"]);
     
@@ -95,10 +97,19 @@ fn main() {
             client.active_conn.react(&queries_win.content.results.overview.conn_bx);
             client.active_conn.react(&queries_win.titlebar.exec_btn);
             client.active_conn.react(&queries_win.sidebar.schema_tree);
+            client.active_conn.react(&client.scripts);
+            client.active_conn.react(&queries_win.titlebar);
+            client.active_conn.react(&queries_win.safety_dialog);
+            client.active_conn.react(&queries_win.disconnect_dialog);
+            client.active_conn.react(&queries_win.query_export_dialog);
+            client.active_conn.react(&queries_win.params_dialog);
+            client.active_conn.react(&queries_win.content.results.workspace);
 
             client.env.react(&client.active_conn);
             client.env.react(&queries_win.content.results.workspace);
             client.env.react(&queries_win.content.editor.export_dialog);
+            client.env.react(&queries_win.content.editor.export_all_dialog);
+            client.env.react(&queries_win.content.editor.open_table_dialog);
             // client.env.react(&queries_win.settings);
             client.env.react(&queries_win.titlebar.exec_btn);
 
@@ -108,6 +119,7 @@ fn main() {
             queries_win.content.results.overview.conn_list.react(&client.active_conn);
             queries_win.content.results.overview.conn_bx.react(&client.active_conn);
             queries_win.content.results.workspace.react(&client.env);
+            queries_win.content.results.workspace.react(&client.active_conn);
 
             queries_win.sidebar.schema_tree.react(&client.active_conn);
             queries_win.sidebar.file_list.react(&client.scripts);
@@ -127,12 +139,17 @@ fn main() {
             queries_win.content.react(&client.scripts);
             queries_win.titlebar.exec_btn.react(&client.scripts);
             queries_win.titlebar.exec_btn.react(&client.active_conn);
+            queries_win.titlebar.react(&client.active_conn);
             queries_win.titlebar.exec_btn.react(&queries_win.content);
             queries_win.titlebar.main_menu.react(&client.scripts);
             queries_win.content.react(&client.env);
             queries_win.sidebar.file_list.react(&client.active_conn);
 
             queries_win.content.results.overview.detail_bx.react(&client.active_conn);
+            queries_win.safety_dialog.react(&client.active_conn);
+            queries_win.disconnect_dialog.react(&client.active_conn);
+            queries_win.query_export_dialog.react(&client.active_conn);
+            queries_win.params_dialog.react(&client.active_conn);
 
             queries_win.react(&queries_win.titlebar);
             queries_win.react(&client.scripts);
@@ -145,6 +162,7 @@ fn main() {
             queries_win.window.add_action(&queries_win.find_dialog.replace_all_action);
 
             queries_win.content.editor.react(&queries_win.settings);
+            queries_win.content.editor.react(&client.active_conn);
             user_state.react(&client.conn_set);
             user_state.react(&client.scripts);
 
@@ -13,6 +13,8 @@ use crate::client::ConnectionInfo;
 use crate::client::ConnConfig;
 use crate::sql::SafetyLock;
 use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
 /*
 
@@ -51,9 +53,40 @@ pub use pg::*;
 
 // pub use sqlite::*;
 
-// mod arrow;
+/// Transaction control issued by the user when the session is in manual transaction
+/// mode (i.e. a single transaction is kept open across executions).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionCommand {
+    Begin,
+    Commit,
+    Rollback,
+    Savepoint(String),
+    RollbackTo(String)
+}
+
+impl TransactionCommand {
 
-// pub use arrow::*;
+    pub fn sql(&self) -> String {
+        match self {
+            TransactionCommand::Begin => String::from("begin;"),
+            TransactionCommand::Commit => String::from("commit;"),
+            TransactionCommand::Rollback => String::from("rollback;"),
+            TransactionCommand::Savepoint(name) => format!("savepoint {};", name),
+            TransactionCommand::RollbackTo(name) => format!("rollback to savepoint {};", name)
+        }
+    }
+
+}
+
+/// Savepoint names are interpolated into the statement, so only plain identifiers are accepted.
+pub fn validate_savepoint_name(name : &str) -> Result<(), String> {
+    let starts_with_letter = name.chars().next().map(|c| c.is_alphabetic() || c == '_' ).unwrap_or(false);
+    if starts_with_letter && name.chars().all(|c| c.is_alphanumeric() || c == '_' ) {
+        Ok(())
+    } else {
+        Err(format!("Invalid savepoint name: {}\n(use only letters, digits and underscores)", name))
+    }
+}
 
 pub trait Connection
 where
@@ -68,12 +101,22 @@ where
     
     fn query_async(&mut self, stmts : &[AnyStatement]) -> Vec<StatementOutput>;
     
-    fn exec_transaction(&mut self, stmt : &AnyStatement) -> StatementOutput;
+    fn exec_transaction(&mut self, stmt : &AnyStatement, subs : &HashMap<String, String>) -> StatementOutput;
+
+    fn transaction(&mut self, cmd : &TransactionCommand) -> StatementOutput;
 
     fn listen_at_channel(&mut self, channel : String);
 
     fn conn_info(&self) -> ConnectionInfo;
 
+    /// For read-only sessions, verifies at the server that the session and the current transaction
+    /// are still read-only. If any of them was made writable (e.g. by a function calling set_config),
+    /// the current transaction is rolled back and the session setting is restored, returning an error.
+    fn guard_read_only(&mut self) -> Result<(), String>;
+
+    /// Returns the notices received since the last call.
+    fn take_notices(&mut self) -> Vec<ServerNotice>;
+
     fn db_info(&mut self) -> Result<DBInfo, Box<dyn Error>>;
 
     fn import(
@@ -82,6 +125,18 @@ where
         dst : &str
     ) -> Result<usize, String>;
 
+    /// Runs the query through a server-side cursor, handing the result to the sink in chunks of at
+    /// most chunk_size rows (the first chunk is always given, even when empty). Fetching stops when
+    /// the sink returns false, and the running statement is cancelled when the cancel flag is set.
+    /// Returns the number of rows fetched.
+    fn stream_query(
+        &mut self,
+        query : &str,
+        chunk_size : usize,
+        cancel : &Arc<AtomicBool>,
+        sink : &mut dyn FnMut(Table) -> Result<bool, String>
+    ) -> Result<usize, String>;
+
     /// It is important that every time this method is called,
     /// at least one query result is pushed into the queue, or else
     /// the GUI will be insensitive waiting for a response.
@@ -91,7 +146,10 @@ where
         subs : &HashMap<String, String>,
         lock : SafetyLock,
         is_schedule : bool
-    ) -> Result<Vec<StatementOutput>, String> {
+    ) -> Result<(Vec<StatementOutput>, Vec<ServerNotice>), String> {
+
+        // Discards notices not raised by this sequence.
+        self.take_notices();
 
         match crate::sql::parsing::fully_parse_sql(&query_seq) {
             Ok(stmts) => {
@@ -99,6 +157,22 @@ where
                 if stmts.len() == 0 {
                     return Err(String::from("Empty statement sequence"));
                 }
+
+                // The server enforces read-only sessions. Statements that change the setting are rejected
+                // early here, and changes made by any other means are undone before the next statement.
+                let read_only = self.conn_info().read_only;
+                if read_only {
+                    for stmt in &stmts {
+                        let sql = match stmt {
+                            AnyStatement::Parsed(_, s) | AnyStatement::Raw(_, s, _) => &s[..],
+                            AnyStatement::ParsedTransaction { raw, .. } => &raw[..],
+                            AnyStatement::Local(_) => continue
+                        };
+                        if crate::sql::lifts_read_only(sql) {
+                            return Err(String::from("Cannot change the transaction mode of a read-only session"));
+                        }
+                    }
+                }
                 
                 let all_queries = stmts.iter().all(|stmt| {
                     match stmt { 
@@ -112,12 +186,20 @@ where
                 }
                 
                 // If sequence is exclusively composed of query statements, perform asysnchronous execution.
-                if all_queries && lock.enable_async {
-                    return Ok(self.query_async(&stmts[..]));
+                // Notices of queries executed concurrently cannot be matched to
+                // a single statement, so they are attached to the first one.
+                // Parameters are bound only for statements executed one at a time.
+                if all_queries && lock.enable_async && !read_only && !crate::sql::parsing::has_placeholders(&query_seq) {
+                    let results = self.query_async(&stmts[..]);
+                    return Ok((results, self.take_notices()));
                 }
                 
                 // If sequence has at least one non-query statement, default to synchronous exection.
                 let mut results = Vec::new();
+                let mut notices = Vec::new();
+                if read_only {
+                    self.guard_read_only()?;
+                }
                 
                 for any_stmt in stmts {
                     match any_stmt {
@@ -139,13 +221,14 @@ where
                                 end : end.clone(), 
                                 middle : middle.clone(), 
                                 raw : raw.clone() 
-                            }));
+                            }, &subs));
                         },
                         AnyStatement::Local(_local) => {
                             // Self::run_local_statement(&local, conn, exec, &mut results)?;
                             return Err(String::from("Unsupported statement"));
                         },
                         AnyStatement::Raw(stmt_tokens, stmt_string, is_select) => {
+                            lock.accepts_sql(&stmt_string)?;
                             if is_select {
                                 results.push(self.query(&format!("{}", stmt_string), &subs));
                             } else {
@@ -153,9 +236,19 @@ where
                             }
                         }
                     }
+                    for mut notice in self.take_notices() {
+                        notice.stmt = results.len().saturating_sub(1);
+                        notices.push(notice);
+                    }
+                    if read_only {
+                        if let Err(e) = self.guard_read_only() {
+                            results.push(StatementOutput::Invalid(e, true));
+                            break;
+                        }
+                    }
                 }
                 
-                Ok(results)
+                Ok((results, notices))
             },
             Err(SQLError::Lexing(err)) | Err(SQLError::Parsing(err)) | Err(SQLError::Unsupported(err)) => {
                 Err(err)
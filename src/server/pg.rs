@@ -8,18 +8,27 @@ use std::error::Error;
 use crate::tables::table::{Table};
 use crate::sql::object::{DBObject, DBType, DBInfo};
 use crate::sql::parsing::AnyStatement;
-use super::Connection;
+use super::{Connection, TransactionCommand};
 use std::collections::HashMap;
 use std::fs::{self};
 use std::convert::{TryFrom, TryInto};
 use std::str::FromStr;
-use tokio_postgres::Client;
+use tokio_postgres::{Client, GenericClient};
 use crate::client::ConnectionInfo;
 use crate::client::{ConnURI, ConnConfig};
 use sqlparser::ast::Statement;
 use futures::future;
 use std::ops::Range;
 use crate::ui::TlsVersion;
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_postgres::AsyncMessage;
+use tokio_postgres::error::ErrorPosition;
+use futures::StreamExt;
+use tokio_postgres::types::{Type, ToSql};
 
 pub struct PostgresConnection {
 
@@ -29,6 +38,11 @@ pub struct PostgresConnection {
     
     rt : Option<tokio::runtime::Runtime>,
 
+    tls : Option<postgres_native_tls::MakeTlsConnector>,
+
+    // Notices forwarded by the connection task, waiting to be matched to the statements.
+    notices : Receiver<ServerNotice>
+
 }
 
 const CERT_ERR : &'static str = r#"
@@ -36,53 +50,110 @@ const CERT_ERR : &'static str = r#"
 Inform a certificate file path for this host at the security settings."
 "#;
 
+/* Drives the connection, forwarding the notices sent by the server (which would
+otherwise be dropped if the connection future were just spawned). */
+fn spawn_connection<S, T>(
+    rt : &tokio::runtime::Runtime,
+    mut conn : tokio_postgres::Connection<S, T>,
+    notices : Sender<ServerNotice>
+)
+where
+    S : AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    T : AsyncRead + AsyncWrite + Unpin + Send + 'static
+{
+    rt.spawn(async move {
+        let mut messages = futures::stream::poll_fn(move |cx| conn.poll_message(cx) );
+        while let Some(msg) = messages.next().await {
+            match msg {
+                Ok(AsyncMessage::Notice(db_err)) => {
+                    let _ = notices.send(ServerNotice {
+                        stmt : 0,
+                        severity : db_err.severity().to_string(),
+                        code : db_err.code().code().to_string(),
+                        message : db_err.message().to_string(),
+                        detail : db_err.detail().map(|d| d.to_string() ),
+                        hint : db_err.hint().map(|h| h.to_string() )
+                    });
+                },
+                Ok(_) => { },
+                Err(e) => {
+                    eprintln!("{}", e);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+/* Read-only sessions are started with default_transaction_read_only = on as a startup
+option, so the setting is in place before any statement is sent. */
+fn session_config(uri : &ConnURI) -> Result<tokio_postgres::Config, String> {
+    let mut config = tokio_postgres::Config::from_str(&uri.uri[..])
+        .map_err(|e| format!("{}", e) )?;
+    if uri.info.read_only {
+        config.options("-c default_transaction_read_only=on");
+    }
+    Ok(config)
+}
+
+/* Connector for connections with an associated certificate. It is kept by the connection,
+since requests to cancel a running statement use a new connection to the server. */
+fn tls_connector(uri : &ConnURI) -> Result<Option<postgres_native_tls::MakeTlsConnector>, String> {
+
+    use native_tls::{Certificate, TlsConnector};
+    use postgres_native_tls::MakeTlsConnector;
+
+    let cert = match uri.info.cert.as_ref() {
+        Some(cert) => cert,
+        None => return Ok(None)
+    };
+    let min_version = match uri.info.min_tls_version {
+        Some(TlsVersion { major : 1, minor : 0 }) => {
+            native_tls::Protocol::Tlsv10
+        },
+        Some(TlsVersion { major : 1, minor : 1 }) => {
+            native_tls::Protocol::Tlsv11
+        },
+        Some(TlsVersion { major : 1, minor : 2 }) => {
+            native_tls::Protocol::Tlsv12
+        },
+        Some(TlsVersion { major, minor }) => {
+            return Err(format!("Unrecognized TLS version: {}.{}", major, minor));
+        },
+        None => {
+            return Err(format!("Unspecified TLS minimum version"));
+        }
+    };
+    let cert_content = fs::read(cert)
+        .map_err(|e| format!("Could not read certificate:\n{}", e) )?;
+    let cert = Certificate::from_pem(&cert_content)
+        .map_err(|e| format!("{}", e) )?;
+    let connector = TlsConnector::builder()
+        .add_root_certificate(cert)
+        .use_sni(true)
+        .disable_built_in_roots(false)
+        .min_protocol_version(Some(min_version))
+        .build()
+        .map_err(|e| format!("Error establishing TLS connector:\n{}", e) )?;
+    Ok(Some(MakeTlsConnector::new(connector)))
+}
+
 async fn connect(
     rt : &tokio::runtime::Runtime, 
-    uri : &ConnURI
+    uri : &ConnURI,
+    tls : Option<postgres_native_tls::MakeTlsConnector>,
+    notices : Sender<ServerNotice>
 ) -> Result<tokio_postgres::Client, String> {
 
-    if let Some(cert) = uri.info.cert.as_ref() {
+    if let Some(connector) = tls {
 
-        use native_tls::{Certificate, TlsConnector};
-        use postgres_native_tls::MakeTlsConnector;
-        
-        let min_version = match uri.info.min_tls_version {
-            Some(TlsVersion { major : 1, minor : 0 }) => {
-                native_tls::Protocol::Tlsv10
-            },
-            Some(TlsVersion { major : 1, minor : 1 }) => {
-                native_tls::Protocol::Tlsv11
-            },
-            Some(TlsVersion { major : 1, minor : 2 }) => {
-                native_tls::Protocol::Tlsv12
-            },
-            Some(TlsVersion { major, minor }) => {
-                return Err(format!("Unrecognized TLS version: {}.{}", major, minor));
-            },
-            None => {
-                return Err(format!("Unspecified TLS minimum version"));
-            }
-        };
-        let cert_content = fs::read(cert)
-            .map_err(|e| format!("Could not read certificate:\n{}", e) )?;
-        let cert = Certificate::from_pem(&cert_content)
-            .map_err(|e| format!("{}", e) )?;
-        let connector = TlsConnector::builder()
-            .add_root_certificate(cert)
-            .use_sni(true)
-            .disable_built_in_roots(false)
-            .min_protocol_version(Some(min_version))
-            .build()
-            .map_err(|e| format!("Error establishing TLS connector:\n{}", e) )?;
-        
-        let connector = MakeTlsConnector::new(connector);
         if !uri.uri.ends_with("sslmode=require") {
             return Err(format!("Tried to connect without TLS mode 'require'"));
         }
         
-        match tokio_postgres::connect(&uri.uri[..], connector).await {
+        match session_config(uri)?.connect(connector).await {
             Ok((cli, conn)) => {
-                rt.spawn(conn);
+                spawn_connection(rt, conn, notices);
                 Ok(cli)
             },
             Err(e) => {
@@ -101,9 +172,9 @@ async fn connect(
                 return Err(format!("Tried to connect without TLS, but connection requires it."));
             }
             
-            match tokio_postgres::connect(&uri.uri[..], tokio_postgres::NoTls{ }).await {
+            match session_config(uri)?.connect(tokio_postgres::NoTls{ }).await {
                 Ok((cli, conn)) => {
-                    rt.spawn(conn);
+                    spawn_connection(rt, conn, notices);
                     Ok(cli)
                 },
                 Err(e) => {
@@ -118,7 +189,11 @@ async fn connect(
     }
 }
 
-async fn run_transaction(client : &mut tokio_postgres::Client, any_stmt : &AnyStatement) -> StatementOutput {
+async fn run_transaction(
+    client : &mut tokio_postgres::Client,
+    any_stmt : &AnyStatement,
+    subs : &HashMap<String, String>
+) -> StatementOutput {
 
     /* There is an early return for any queries or executions that fail from within
     the transactiton. We rely on the implicit rollback issued when the transaction goes out
@@ -154,28 +229,42 @@ async fn run_transaction(client : &mut tokio_postgres::Client, any_stmt : &AnySt
                             },
                             
                             Statement::Query(_) => {
-                                match tr.query(&format!("{}", stmt), &[]).await {
+                                let sql = format!("{}", stmt);
+                                let ans = if crate::sql::parsing::has_placeholders(&sql) {
+                                    match prepare_with_parameters(&tr, &sql, subs).await {
+                                        Ok((_, prepared, params)) => tr.query(&prepared, &param_refs(&params)[..]).await,
+                                        Err(out) => return out
+                                    }
+                                } else {
+                                    tr.query(&sql[..], &[]).await
+                                };
+                                match ans {
                                     Ok(_) => {
                                         // Queries inside transactions are not shown for now. But they
                                         // might rollback the transaction when they fail.
                                     },
                                     Err(e) => {
-                                        let mut e = e.to_string();
-                                        format_pg_string(&mut e);
-                                        return StatementOutput::Invalid(e, true);
+                                        return build_error(e, &format!("{}", stmt));
                                     }
                                 }
                             },
                             
                             _other_stmt => {
-                                match tr.execute(&format!("{}", stmt), &[]).await {
+                                let sql = format!("{}", stmt);
+                                let ans = if crate::sql::parsing::has_placeholders(&sql) {
+                                    match prepare_with_parameters(&tr, &sql, subs).await {
+                                        Ok((_, prepared, params)) => tr.execute(&prepared, &param_refs(&params)[..]).await,
+                                        Err(out) => return out
+                                    }
+                                } else {
+                                    tr.execute(&sql[..], &[]).await
+                                };
+                                match ans {
                                     Ok(n) => {
                                         total_changed += n;
                                     },
                                     Err(e) => {
-                                        let mut e = e.to_string();
-                                        format_pg_string(&mut e);
-                                        return StatementOutput::Invalid(e, true);
+                                        return build_error(e, &format!("{}", stmt));
                                     }
                                 }
                             }
@@ -193,11 +282,7 @@ async fn run_transaction(client : &mut tokio_postgres::Client, any_stmt : &AnySt
                                         middle.len()
                                     )
                                 },
-                                Err(e) => {
-                                    let mut e = e.to_string();
-                                    format_pg_string(&mut e);
-                                    StatementOutput::Invalid(e, true)
-                                }
+                                Err(e) => build_error(e, "commit")
                             }
                         },
                         other => {
@@ -241,40 +326,134 @@ impl PostgresConnection {
     does not hold in memory any security-sensitive information. */
     pub fn try_new(uri : ConnURI) -> Result<Self, String> {
         let rt = tokio::runtime::Runtime::new().unwrap();
+        let (notice_send, notices) = mpsc::channel();
+        let tls = tls_connector(&uri)?;
         let client = rt.block_on(async {
-            connect(&rt, &uri).await
+            connect(&rt, &uri, tls.clone(), notice_send).await
         })?;
+
         Ok(Self {
             info : uri.info,
             rt : Some(rt),
             client,
+            tls,
+            notices
         })
     }
 
 }
 
+/* Keeps the structured error response when the error was reported by the server. */
+fn build_error(e : tokio_postgres::Error, stmt : &str) -> StatementOutput {
+    if let Some(db_err) = e.as_db_error() {
+        let position = match db_err.position() {
+            Some(ErrorPosition::Original(pos)) => Some(*pos as usize),
+            _ => None
+        };
+        StatementOutput::Rejected(DatabaseError {
+            severity : db_err.severity().to_string(),
+            code : db_err.code().code().to_string(),
+            message : db_err.message().to_string(),
+            detail : db_err.detail().map(|d| d.to_string() ),
+            hint : db_err.hint().map(|h| h.to_string() ),
+            position,
+            stmt : stmt.to_string()
+        })
+    } else {
+        let mut e = e.to_string();
+        format_pg_string(&mut e);
+        StatementOutput::Invalid(e, true)
+    }
+}
+
+/* Converts the parameter values (kept as text) to the types the server inferred for the
+placeholders of the prepared statement. Missing values are bound to NULL. */
+fn bind_values(types : &[Type], values : &[Option<&String>]) -> Result<Vec<Box<dyn ToSql + Sync>>, String> {
+    let mut params : Vec<Box<dyn ToSql + Sync>> = Vec::new();
+    for (ix, (ty, val)) in types.iter().zip(values.iter()).enumerate() {
+        let err = format!("Invalid {} value for parameter ${}", ty, ix + 1);
+        let trimmed = val.map(|v| v.trim() );
+        let param : Box<dyn ToSql + Sync> = match ty {
+            t if t == &Type::BOOL => Box::new(trimmed.map(|v| match &v.to_lowercase()[..] {
+                "true" | "t" | "yes" | "y" | "on" | "1" => Ok(true),
+                "false" | "f" | "no" | "n" | "off" | "0" => Ok(false),
+                _ => Err(())
+            }).transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::INT2 => Box::new(trimmed.map(i16::from_str).transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::INT4 => Box::new(trimmed.map(i32::from_str).transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::INT8 => Box::new(trimmed.map(i64::from_str).transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::FLOAT4 => Box::new(trimmed.map(f32::from_str).transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::FLOAT8 => Box::new(trimmed.map(f64::from_str).transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::NUMERIC => Box::new(trimmed.map(rust_decimal::Decimal::from_str).transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::DATE => Box::new(trimmed.map(chrono::NaiveDate::from_str).transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::TIMESTAMP => Box::new(trimmed.map(|v| chrono::NaiveDateTime::from_str(&v.replacen(' ', "T", 1)) )
+                .transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::JSON || t == &Type::JSONB => Box::new(val.map(|v| serde_json::from_str::<serde_json::Value>(v) )
+                .transpose().map_err(|_| err.clone() )?),
+            t if t == &Type::TEXT || t == &Type::VARCHAR || t == &Type::BPCHAR || t == &Type::NAME || t == &Type::UNKNOWN => Box::new(val.cloned()),
+            _ => {
+                return Err(format!("Parameter ${} has type {}, which cannot be bound.\nCast it to text or a numeric type (e.g. :name::text)", ix + 1, ty));
+            }
+        };
+        params.push(param);
+    }
+    Ok(params)
+}
+
+fn param_refs(params : &[Box<dyn ToSql + Sync>]) -> Vec<&(dyn ToSql + Sync)> {
+    params.iter().map(|p| p.as_ref() ).collect()
+}
+
+/* Numbers the placeholders of the statement, and prepares it so the values can be
+converted to the types the server inferred for them. */
+async fn prepare_with_parameters<C : GenericClient>(
+    client : &C,
+    stmt : &str,
+    subs : &HashMap<String, String>
+) -> Result<(String, tokio_postgres::Statement, Vec<Box<dyn ToSql + Sync>>), StatementOutput> {
+    let (sql, names) = crate::sql::parsing::renumber_parameters(stmt);
+    let prepared = client.prepare(&sql[..]).await.map_err(|e| build_error(e, &sql) )?;
+    let values : Vec<Option<&String>> = names.iter().map(|name| subs.get(name) ).collect();
+    let params = bind_values(prepared.params(), &values[..])
+        .map_err(|e| StatementOutput::Invalid(crate::sql::build_error_with_stmt(&e, &sql), false) )?;
+    Ok((sql, prepared, params))
+}
+
+fn set_table_source(tbl : &mut Table, query : &str) {
+    if let Some((name, relation)) = crate::sql::table_name_from_sql(query) {
+        tbl.set_name(Some(name));
+        if !relation.is_empty() {
+            tbl.set_relation(Some(relation));
+        }
+    }
+}
+
 fn build_table(rows : &[tokio_postgres::Row], query : &str) -> StatementOutput {
     if rows.len() == 0 {
         if let Ok(cols) = crate::sql::parsing::parse_query_cols(query) {
-            return StatementOutput::Valid(query.to_string(), Table::empty(cols));
+            // Named even when empty, so rows can be inserted from the result table.
+            let mut tbl = Table::empty(cols);
+            set_table_source(&mut tbl, query);
+            return StatementOutput::Valid(query.to_string(), tbl);
         }
     }
     match Table::from_rows(rows) {
         Ok(mut tbl) => {
-            if let Some((name, relation)) = crate::sql::table_name_from_sql(query) {
-                tbl.set_name(Some(name));
-                if !relation.is_empty() {
-                    tbl.set_relation(Some(relation));
-                }
-            }
-            
+            set_table_source(&mut tbl, query);
             StatementOutput::Valid(query.to_string(), tbl)
-            
         },
         Err(e) => StatementOutput::Invalid(crate::sql::build_error_with_stmt(&e, &query[..]), false)
     }
 }
 
+fn stream_error(e : tokio_postgres::Error, query : &str) -> String {
+    match build_error(e, query) {
+        StatementOutput::Rejected(err) => err.describe(),
+        StatementOutput::Invalid(e, _) => e,
+        _ => String::from("Unknown error")
+    }
+}
+
 async fn query_multiple(
     client : &mut Client, 
     stmts : &[AnyStatement]
@@ -315,30 +494,54 @@ impl Connection for PostgresConnection {
 
     }
 
-    fn query(&mut self, query : &str, _subs : &HashMap<String, String>) -> StatementOutput {
+    fn query(&mut self, query : &str, subs : &HashMap<String, String>) -> StatementOutput {
+        if crate::sql::parsing::has_placeholders(query) {
+            let client = &self.client;
+            return self.rt.as_ref().unwrap().block_on(async {
+                let (sql, stmt, params) = match prepare_with_parameters(client, query, subs).await {
+                    Ok(prepared) => prepared,
+                    Err(out) => return out
+                };
+                match client.query(&stmt, &param_refs(&params)[..]).await {
+                    Ok(rows) => build_table(&rows[..], &sql),
+                    Err(e) => build_error(e, &sql)
+                }
+            });
+        }
         self.rt.as_ref().unwrap().block_on(async {
             match self.client.query(&query[..], &[]).await {
                 Ok(rows) => {
                     build_table(&rows[..], query)
                 },
-                Err(e) => {
-                    let mut e = e.to_string();
-                    format_pg_string(&mut e);
-                    StatementOutput::Invalid(e, true)
-                }
+                Err(e) => build_error(e, query)
             }
         })
     }
 
-    fn exec_transaction(&mut self, any_stmt : &AnyStatement) -> StatementOutput {
+    fn exec_transaction(&mut self, any_stmt : &AnyStatement, subs : &HashMap<String, String>) -> StatementOutput {
         let rt = self.rt.take().unwrap();
         let out = rt.block_on(async {
-            run_transaction(&mut self.client, any_stmt).await
+            run_transaction(&mut self.client, any_stmt, subs).await
         });
         self.rt = Some(rt);
         out
     }
     
+    fn transaction(&mut self, cmd : &TransactionCommand) -> StatementOutput {
+        self.rt.as_ref().unwrap().block_on(async {
+            match self.client.batch_execute(&cmd.sql()[..]).await {
+                Ok(_) => match cmd {
+                    TransactionCommand::Begin => StatementOutput::Statement(format!("Transaction started")),
+                    TransactionCommand::Commit => StatementOutput::Statement(format!("Transaction committed")),
+                    TransactionCommand::Rollback => StatementOutput::RolledBack(format!("Transaction rolled back")),
+                    TransactionCommand::Savepoint(name) => StatementOutput::Statement(format!("Savepoint {} created", name)),
+                    TransactionCommand::RollbackTo(name) => StatementOutput::RolledBack(format!("Rolled back to savepoint {}", name))
+                },
+                Err(e) => build_error(e, &cmd.sql())
+            }
+        })
+    }
+    
     fn query_async(&mut self, stmts : &[AnyStatement]) -> Vec<StatementOutput> {
         let rt = self.rt.take().unwrap();
         let res = rt.block_on(async {
@@ -355,25 +558,30 @@ impl Connection for PostgresConnection {
                 out
             },
             Err(e) => {
-                let mut e = format!("{}", e);
-                format_pg_string(&mut e);
-                vec![StatementOutput::Invalid(e, false)]
+                // The statement that failed is unknown when the queries run concurrently.
+                vec![build_error(e, "")]
             }
         }
     }
     
-    fn exec(&mut self, stmt : &AnyStatement, _subs : &HashMap<String, String>) -> StatementOutput {
+    fn exec(&mut self, stmt : &AnyStatement, subs : &HashMap<String, String>) -> StatementOutput {
         self.rt.as_ref().unwrap().block_on(async {
             match &stmt {
+                AnyStatement::Parsed(_, s) if crate::sql::parsing::has_placeholders(s) => {
+                    let (sql, prepared, params) = match prepare_with_parameters(&self.client, s, subs).await {
+                        Ok(prepared) => prepared,
+                        Err(out) => return out
+                    };
+                    match self.client.execute(&prepared, &param_refs(&params)[..]).await {
+                        Ok(n) => crate::sql::build_statement_result(&stmt, n as usize),
+                        Err(e) => build_error(e, &sql)
+                    }
+                },
                 AnyStatement::Parsed(_, s) => {
                     let ans = self.client.execute(&s[..], &[]).await;
                     match ans {
                         Ok(n) => crate::sql::build_statement_result(&stmt, n as usize),
-                        Err(e) => {
-                            let mut e = e.to_string();
-                            format_pg_string(&mut e);
-                            StatementOutput::Invalid(e, true)
-                        }
+                        Err(e) => build_error(e, s)
                     }
                 },
                 AnyStatement::ParsedTransaction { .. } => {
@@ -393,6 +601,33 @@ impl Connection for PostgresConnection {
         self.info.clone()
     }
 
+    fn guard_read_only(&mut self) -> Result<(), String> {
+        if !self.info.read_only {
+            return Ok(());
+        }
+        let client = &self.client;
+        self.rt.as_ref().unwrap().block_on(async {
+            // Nothing can be written within an aborted transaction, where the settings cannot be read.
+            let row = match client.query_one(READ_ONLY_QUERY, &[]).await {
+                Ok(row) => row,
+                Err(_) => return Ok(())
+            };
+            let (session_ro, tx_ro) : (String, String) = (row.get(0), row.get(1));
+            if &session_ro[..] == "on" && &tx_ro[..] == "on" {
+                return Ok(());
+            }
+            let _ = client.batch_execute("rollback").await;
+            client.batch_execute("set default_transaction_read_only = on").await
+                .map_err(|e| format!("Could not restore the read-only session setting: {}", e) )?;
+            Err(String::from("The read-only setting of the session was changed by the last statement.\n\
+                The current transaction was rolled back and the setting restored."))
+        })
+    }
+
+    fn take_notices(&mut self) -> Vec<ServerNotice> {
+        self.notices.try_iter().collect()
+    }
+
     fn db_info(&mut self) -> Result<DBInfo, Box<dyn Error>> {
         
         let mut col_queries = Vec::new();
@@ -502,6 +737,9 @@ impl Connection for PostgresConnection {
             StatementOutput::Invalid(err, _) => {
                 Err(err)
             },
+            StatementOutput::Rejected(err) => {
+                Err(err.describe())
+            },
             _ => {
                 Err(String::from("Invalid insertion output"))
             }
@@ -527,7 +765,7 @@ impl Connection for PostgresConnection {
 
             // TODO filter cols
             /*if !crate::sql::object::schema_has_table(dst, schema) {
-                let create = tbl.sql_table_creation(dst, cols).unwrap();
+                let create = tbl.sql_table_creation(dst).unwrap();
                 client.execute(&create[..], &[])
                     .map_err(|e| format!("{}", e) )?;
             } else {
@@ -545,11 +783,84 @@ impl Connection for PostgresConnection {
         })*/
     }
 
+    fn stream_query(
+        &mut self,
+        query : &str,
+        chunk_size : usize,
+        cancel : &Arc<AtomicBool>,
+        sink : &mut dyn FnMut(Table) -> Result<bool, String>
+    ) -> Result<usize, String> {
+
+        // The running statement is cancelled as soon as the flag is set, instead of only
+        // after the current chunk arrives.
+        let finished = Arc::new(AtomicBool::new(false));
+        let watcher = {
+            let token = self.client.cancel_token();
+            let tls = self.tls.clone();
+            let handle = self.rt.as_ref().unwrap().handle().clone();
+            let (finished, cancel) = (finished.clone(), cancel.clone());
+            std::thread::spawn(move || {
+                while !finished.load(Ordering::Relaxed) {
+                    if cancel.load(Ordering::Relaxed) {
+                        let ans = handle.block_on(async {
+                            match tls {
+                                Some(tls) => token.cancel_query(tls).await,
+                                None => token.cancel_query(tokio_postgres::NoTls).await
+                            }
+                        });
+                        if let Err(e) = ans {
+                            eprintln!("Could not cancel export query: {}", e);
+                        }
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+            })
+        };
+
+        let client = &mut self.client;
+        let ans = self.rt.as_ref().unwrap().block_on(async {
+
+            // Portals only live within a transaction, which is read-only and rolled back at the
+            // end (or when dropped at an error) since the query only reads.
+            let tx = client.build_transaction()
+                .read_only(true)
+                .start()
+                .await
+                .map_err(|e| stream_error(e, query) )?;
+            let stmt = tx.prepare(query).await.map_err(|e| stream_error(e, query) )?;
+            let portal = tx.bind(&stmt, &[]).await.map_err(|e| stream_error(e, query) )?;
+            let names : Vec<String> = stmt.columns().iter().map(|c| c.name().to_string() ).collect();
+            let mut n = 0;
+            loop {
+                let rows = tx.query_portal(&portal, chunk_size as i32).await.map_err(|e| stream_error(e, query) )?;
+                if rows.len() == 0 {
+                    if n == 0 {
+                        sink(Table::empty(names))?;
+                    }
+                    break;
+                }
+                let chunk = Table::from_rows(&rows[..]).map_err(|e| crate::sql::build_error_with_stmt(e, query) )?;
+                n += rows.len();
+                if !sink(chunk)? || rows.len() < chunk_size {
+                    break;
+                }
+            }
+            tx.rollback().await.map_err(|e| stream_error(e, query) )?;
+            Ok(n)
+        });
+        finished.store(true, Ordering::Relaxed);
+        let _ = watcher.join();
+        ans
+    }
+
 
 }
 
 const SERVER_VERSION_QUERY : &'static str = "show server_version";
 
+const READ_ONLY_QUERY : &'static str = "select current_setting('default_transaction_read_only'), current_setting('transaction_read_only')";
+
 const COLLATION_QUERY : &'static str = "show lc_collate";
 
 const SIZE_QUERY : &'static str = r#"
@@ -703,9 +1014,12 @@ fn retrieve_functions(fn_info : &Table, schema : &str) -> Option<Vec<DBObject>>
                     },
                     5 => {
                         // timestamp with time zone | timestamp without time zone will have 4 splits but and a type name
-                        if split_arg[1].trim() == "time" || split_arg[1].trim() == "timestamp" {
+                        if split_arg[1].trim() == "time" {
                             func_arg_names.push(split_arg[0].to_string());
                             args.push(DBType::Time);
+                        } else if split_arg[1].trim() == "timestamp" {
+                            func_arg_names.push(split_arg[0].to_string());
+                            args.push(DBType::Timestamp);
                         }
                     },
                     _n => {
@@ -784,8 +1098,8 @@ fn get_postgres_schemata(conn : &mut PostgresConnection) -> Result<HashMap<Strin
                         StatementOutput::Valid(_, tbl) => {
                             schemata = retrieve_schemata(&tbl).unwrap();
                         },
-                        StatementOutput::Invalid(e, _) => {
-                            return Err(format!("{}", e));
+                        StatementOutput::Invalid(_, _) | StatementOutput::Rejected(_) => {
+                            return Err(tbl_out.error_message().unwrap_or_default());
                         },
                         _ => unimplemented!()
                     }
@@ -804,8 +1118,8 @@ fn get_postgres_schemata(conn : &mut PostgresConnection) -> Result<HashMap<Strin
                 }
                 Ok(schemata)
             },
-            StatementOutput::Invalid(e, _) => {
-                Err(e.to_string())
+            StatementOutput::Invalid(_, _) | StatementOutput::Rejected(_) => {
+                Err(schem_out.error_message().unwrap_or_default())
             },
             _ => {
                 unimplemented!()
@@ -874,46 +1188,4 @@ fn format_pg_string(e : &mut String) {
     break_string(e, 80);
 }
 
-fn break_string(content : &mut String, line_length : usize) {
-    let mut break_next = false;
-    let mut last_break = 0;
-    let mut break_pos = Vec::new();
-    for (i, c) in content.chars().enumerate() {
-        if c == '\n' {
-            last_break = i;
-            break_next = false;
-        }
-        if c == ' ' && break_next {
-            break_pos.push(i);
-            break_next = false;
-            last_break = i;
-        }
-        if i - last_break > line_length && !break_next {
-            break_next = true;
-        }
-    }
-
-    if break_pos.len() == 0 {
-        return;
-    }
-
-    let mut broken = String::with_capacity(content.len() + 4);
-    let mut chars = content.chars();
-    while break_pos.len() > 0 {
-        let mut count = 0;
-        while let Some(c) = chars.next() {
-            broken.push(c);
-            count += 1;
-            if count == break_pos[0] {
-                broken += "\n";
-                break_pos.remove(0);
-                if break_pos.len() == 0 {
-                    break;
-                }
-            }
-        }
-    }
-    broken.extend(chars);
-    *content = broken;
-}
 
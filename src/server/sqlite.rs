@@ -169,6 +169,10 @@ impl Connection for SqliteConnection {
         unimplemented!()
     }
 
+    fn guard_read_only(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
     fn db_info(&mut self) -> Result<DBInfo, Box<dyn Error>> {
         let mut top_objs = Vec::new();
         if let Some(names) = get_sqlite_tbl_names(self) {
@@ -0,0 +1,106 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::object::{DBObject, DBType};
+
+/// Change staged at a result table. Rows of the source table are identified by the
+/// (column, SQL literal) pairs of their primary key. Values are the text of the edited
+/// cells, or None for NULL.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowChange {
+
+    Update { key : Vec<(String, String)>, values : Vec<(String, Option<String>)> },
+
+    Insert { values : Vec<(String, Option<String>)> },
+
+    Delete { key : Vec<(String, String)> }
+
+}
+
+/// Text shown for NULL cells at the result tables.
+pub const NULL_TEXT : &'static str = "NULL";
+
+pub fn quote_identifier(name : &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Values are always sent as string literals, which the server coerces to the column type.
+pub fn quote_literal(value : Option<&str>) -> String {
+    match value {
+        Some(value) => format!("'{}'", value.replace('\'', "''")),
+        None => String::from("NULL")
+    }
+}
+
+fn key_condition(key : &[(String, String)]) -> String {
+    key.iter()
+        .map(|(col, lit)| format!("{} = {}", quote_identifier(col), lit) )
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
+
+/// Column types of a table, searched at the given schema (when the name is qualified)
+/// or at the public schema.
+pub fn find_table_columns(table : &str, schema : &[DBObject]) -> Option<Vec<(String, DBType, bool)>> {
+    let unquote = |s : &str| s.trim_matches('"').to_string();
+    let (schema_name, tbl_name) = match table.split_once('.') {
+        Some((s, t)) => (unquote(s), unquote(t)),
+        None => (String::from("public"), unquote(table))
+    };
+    for obj in schema.iter() {
+        match obj {
+            DBObject::Schema { name, children } if name == &schema_name => {
+                return find_table_columns(&tbl_name, &children[..]);
+            },
+            DBObject::Table { name, cols, .. } if name == &tbl_name => {
+                return Some(cols.clone());
+            },
+            _ => { }
+        }
+    }
+    None
+}
+
+/// Returns the indices of the primary key columns among the result column names, and which
+/// result columns belong to the table (and thus can be edited). Returns None if the table
+/// has no primary key, or if the result does not carry all of its columns.
+pub fn editable_columns(names : &[String], cols : &[(String, DBType, bool)]) -> Option<(Vec<usize>, Vec<bool>)> {
+    let mut pks = Vec::new();
+    for (col, _, _) in cols.iter().filter(|c| c.2 ) {
+        pks.push(names.iter().position(|n| n == col )?);
+    }
+    if pks.len() == 0 {
+        return None;
+    }
+    let editable = names.iter().map(|n| cols.iter().any(|c| &c.0 == n ) ).collect();
+    Some((pks, editable))
+}
+
+/// Builds the statements that apply the changes to the given table. The table name is
+/// expected to be a valid (possibly qualified) SQL identifier.
+pub fn change_statements(table : &str, changes : &[RowChange]) -> String {
+    let mut stmts = String::new();
+    for change in changes.iter() {
+        let stmt = match change {
+            RowChange::Update { key, values } => {
+                let assign = values.iter()
+                    .map(|(col, val)| format!("{} = {}", quote_identifier(col), quote_literal(val.as_deref())) )
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("update {} set {} where {};\n", table, assign, key_condition(&key[..]))
+            },
+            RowChange::Insert { values } => {
+                let cols = values.iter().map(|(col, _)| quote_identifier(col) ).collect::<Vec<_>>().join(", ");
+                let vals = values.iter().map(|(_, val)| quote_literal(val.as_deref()) ).collect::<Vec<_>>().join(", ");
+                format!("insert into {} ({}) values ({});\n", table, cols, vals)
+            },
+            RowChange::Delete { key } => {
+                format!("delete from {} where {};\n", table, key_condition(&key[..]))
+            }
+        };
+        stmts += &stmt;
+    }
+    stmts
+}
@@ -0,0 +1,532 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, Whitespace};
+use serde::{Serialize, Deserialize};
+use super::parsing;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeywordCase {
+    Upper,
+    Lower,
+    Preserve
+}
+
+impl Default for KeywordCase {
+
+    fn default() -> Self {
+        KeywordCase::Upper
+    }
+
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FormatOptions {
+    pub keyword_case : KeywordCase,
+
+    // Number of spaces per indentation level
+    pub indent : usize,
+
+    // Lists separated by commas are wrapped when they would cross this column.
+    pub width : usize
+}
+
+impl Default for FormatOptions {
+
+    fn default() -> Self {
+        Self { keyword_case : KeywordCase::default(), indent : 4, width : 80 }
+    }
+
+}
+
+// Only those words have their case changed. Other words recognized by sqlparser as keywords
+// are frequently used as identifiers (name, data, value), so they are left as they are.
+const FORMAT_KEYWORDS : [&'static str; 95] = [
+    "ADD", "ALL", "ALTER", "AND", "ANY", "AS", "ASC", "BEGIN", "BETWEEN", "BY", "CASCADE", "CASE",
+    "CAST", "CHECK", "COLUMN", "COMMIT", "CONFLICT", "CONSTRAINT", "CREATE", "CROSS", "DEFAULT",
+    "DELETE", "DESC", "DISTINCT", "DO", "DROP", "ELSE", "END", "EXCEPT", "EXISTS", "EXPLAIN",
+    "FALSE", "FETCH", "FILTER", "FOR", "FOREIGN", "FROM", "FULL", "FUNCTION", "GRANT", "GROUP",
+    "HAVING", "IF", "ILIKE", "IN", "INDEX", "INNER", "INSERT", "INTERSECT", "INTO", "IS", "JOIN",
+    "LANGUAGE", "LATERAL", "LEFT", "LIKE", "LIMIT", "NATURAL", "NOT", "NOTHING", "NULL", "NULLS",
+    "OFFSET", "ON", "OR", "ORDER", "OUTER", "OVER", "PARTITION", "PRIMARY", "RECURSIVE", "REFERENCES",
+    "REPLACE", "RETURNING", "RETURNS", "REVOKE", "RIGHT", "ROLLBACK", "SELECT", "SET", "SOME",
+    "TABLE", "THEN", "TRUE", "TRUNCATE", "UNION", "UNIQUE", "UPDATE", "USING", "VALUES", "VIEW",
+    "WHEN", "WHERE", "WINDOW", "WITH"
+];
+
+// Statements starting with those words have their clauses broken into lines. Other
+// statements only have their spacing and keyword case normalized.
+const CLAUSE_STATEMENTS : [&'static str; 8] = [
+    "SELECT", "WITH", "INSERT", "UPDATE", "DELETE", "CREATE", "EXPLAIN", "VALUES"
+];
+
+const JOIN_MODIFIERS : [&'static str; 6] = ["LEFT", "RIGHT", "FULL", "INNER", "CROSS", "NATURAL"];
+
+// Words that end a list of clause elements.
+const CLAUSE_WORDS : [&'static str; 16] = [
+    "EXCEPT", "FROM", "GROUP", "HAVING", "INTERSECT", "JOIN", "LIMIT", "OFFSET", "ON", "ORDER",
+    "RETURNING", "SELECT", "SET", "UNION", "WHERE", "WINDOW"
+];
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Token(Token),
+
+    // Dollar-quoted strings, which are kept as they were written.
+    Raw,
+    LineComment,
+    BlockComment
+}
+
+#[derive(Debug, Clone)]
+struct Item {
+    piece : Piece,
+    text : String,
+
+    // Whether this item was written right after the previous one (without whitespace).
+    glued : bool
+}
+
+impl Item {
+
+    fn upper(&self) -> Option<String> {
+        match &self.piece {
+            Piece::Token(Token::Word(w)) if w.quote_style.is_none() => Some(w.value.to_uppercase()),
+            _ => None
+        }
+    }
+
+    fn is_symbol(&self) -> bool {
+        match &self.piece {
+            Piece::Token(Token::Word(_)) | Piece::Token(Token::Number(_, _)) |
+            Piece::Token(Token::SingleQuotedString(_)) | Piece::Token(Token::NationalStringLiteral(_)) |
+            Piece::Token(Token::EscapedStringLiteral(_)) | Piece::Token(Token::HexStringLiteral(_)) => false,
+            Piece::Token(_) => true,
+            _ => false
+        }
+    }
+
+    // Whether a binary operator might be at the left of this item.
+    fn is_operand(&self) -> bool {
+        match &self.piece {
+            Piece::Token(Token::RParen) | Piece::Token(Token::RBracket) | Piece::Raw => true,
+            Piece::Token(Token::Word(_)) => self.upper().map(|w| !FORMAT_KEYWORDS.contains(&&w[..]) ).unwrap_or(true),
+            _ => !self.is_symbol() && !self.is_comment()
+        }
+    }
+
+    fn is_comment(&self) -> bool {
+        match &self.piece {
+            Piece::LineComment | Piece::BlockComment => true,
+            _ => false
+        }
+    }
+
+    fn is_token(&self, tk : &Token) -> bool {
+        match &self.piece {
+            Piece::Token(this) => this == tk,
+            _ => false
+        }
+    }
+
+}
+
+fn build_items(tokens : Vec<Token>, case : KeywordCase) -> Vec<Item> {
+    let mut items : Vec<Item> = Vec::new();
+    let mut glued = true;
+    let mut dollar_quote : Option<String> = None;
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(tk) = tokens.next() {
+
+        if let Some(mut raw) = dollar_quote.take() {
+            raw += &tk.to_string();
+            if tk == Token::Char('$') && tokens.peek() == Some(&Token::Char('$')) {
+                tokens.next();
+                raw += "$";
+                items.push(Item { piece : Piece::Raw, text : raw, glued });
+                glued = true;
+            } else {
+                dollar_quote = Some(raw);
+            }
+            continue;
+        }
+
+        match tk {
+            Token::Whitespace(Whitespace::Space) | Token::Whitespace(Whitespace::Tab) |
+            Token::Whitespace(Whitespace::Newline) => {
+                glued = false;
+                continue;
+            },
+            Token::Whitespace(Whitespace::SingleLineComment { .. }) => {
+                let text = tk.to_string().trim_end().to_string();
+                items.push(Item { piece : Piece::LineComment, text, glued });
+            },
+            Token::Whitespace(Whitespace::MultiLineComment(_)) => {
+                items.push(Item { piece : Piece::BlockComment, text : tk.to_string(), glued });
+            },
+            Token::Char('$') if tokens.peek() == Some(&Token::Char('$')) => {
+                tokens.next();
+                dollar_quote = Some(String::from("$$"));
+                continue;
+            },
+            Token::Word(ref w) if w.quote_style.is_none() && FORMAT_KEYWORDS.contains(&&w.value.to_uppercase()[..]) => {
+                let text = match case {
+                    KeywordCase::Upper => w.value.to_uppercase(),
+                    KeywordCase::Lower => w.value.to_lowercase(),
+                    KeywordCase::Preserve => w.value.clone()
+                };
+                items.push(Item { text, piece : Piece::Token(tk), glued });
+            },
+            tk => {
+                items.push(Item { text : tk.to_string(), piece : Piece::Token(tk), glued });
+            }
+        }
+        glued = true;
+    }
+
+    // Unterminated dollar quote.
+    if let Some(raw) = dollar_quote {
+        items.push(Item { piece : Piece::Raw, text : raw, glued });
+    }
+    items
+}
+
+fn needs_space(prev : Option<&Item>, before_prev : Option<&Item>, item : &Item) -> bool {
+    let prev = match prev {
+        Some(prev) => prev,
+        None => return false
+    };
+    if prev.is_comment() || item.is_comment() {
+        return true;
+    }
+    match &item.piece {
+        Piece::Token(Token::Comma) | Piece::Token(Token::RParen) | Piece::Token(Token::Period) |
+        Piece::Token(Token::SemiColon) | Piece::Token(Token::DoubleColon) | Piece::Token(Token::RBracket) => {
+            return false;
+        },
+        Piece::Token(Token::LBracket) => {
+            return !prev.is_operand();
+        },
+        Piece::Token(Token::LParen) => {
+            if let Piece::Token(Token::Word(_)) = &prev.piece {
+                return !item.glued || prev.upper().map(|w| CLAUSE_STATEMENTS.contains(&&w[..]) ).unwrap_or(false);
+            }
+        },
+        _ => { }
+    }
+    match &prev.piece {
+        Piece::Token(Token::LParen) | Piece::Token(Token::Period) | Piece::Token(Token::DoubleColon) |
+        Piece::Token(Token::LBracket) | Piece::Token(Token::Char('$')) => {
+            return false;
+        },
+        Piece::Token(Token::Minus) | Piece::Token(Token::Plus) if item.glued => {
+
+            // Unary sign
+            if !before_prev.map(|it| it.is_operand() ).unwrap_or(false) {
+                return false;
+            }
+        },
+        _ => { }
+    }
+
+    // Keeps operators written together (such as ->> or @>) as they were.
+    !(item.glued && prev.is_symbol() && item.is_symbol())
+}
+
+fn is_clause_break(
+    word : &str,
+    prev : Option<&str>,
+    next : Option<&str>,
+    first : &str,
+    after_paren : bool
+) -> bool {
+    match word {
+        "SELECT" => prev != Some("GRANT") && prev != Some("REVOKE"),
+        "FROM" => prev != Some("DISTINCT") && prev != Some("DELETE"),
+        "WHERE" | "HAVING" | "LIMIT" | "OFFSET" | "RETURNING" | "WINDOW" | "UNION" | "EXCEPT" | "INTERSECT" => true,
+        "GROUP" | "ORDER" => next == Some("BY"),
+        "VALUES" => first == "INSERT",
+        "SET" => first == "UPDATE" || prev == Some("UPDATE"),
+        "INSERT" | "UPDATE" | "DELETE" => after_paren,
+        "JOIN" => !prev.map(|p| JOIN_MODIFIERS.contains(&p) || p == "OUTER" ).unwrap_or(false),
+        w if JOIN_MODIFIERS.contains(&w) => {
+            !prev.map(|p| JOIN_MODIFIERS.contains(&p) ).unwrap_or(false) &&
+                next.map(|n| n == "JOIN" || n == "OUTER" || JOIN_MODIFIERS.contains(&n) ).unwrap_or(false)
+        },
+        _ => false
+    }
+}
+
+enum Paren {
+
+    // Holds the indentation level and clause outside the subquery.
+    Subquery(usize, Option<String>),
+
+    Inline
+}
+
+struct Layout {
+    out : String,
+    indent : usize,
+    line_level : usize,
+    at_line_start : bool
+}
+
+impl Layout {
+
+    fn newline(&mut self, level : usize) {
+        let len = self.out.trim_end_matches(' ').len();
+        self.out.truncate(len);
+        self.out.push('\n');
+        self.out += &" ".repeat(self.indent * level);
+        self.line_level = level;
+        self.at_line_start = true;
+    }
+
+    fn push(&mut self, text : &str, space : bool) {
+        if space && !self.at_line_start {
+            self.out.push(' ');
+        }
+        self.out += text;
+        self.at_line_start = false;
+    }
+
+    fn column(&self) -> usize {
+        self.out.rsplit('\n').next().map(|line| line.chars().count() ).unwrap_or(0)
+    }
+
+}
+
+fn next_word(items : &[Item], from : usize) -> Option<String> {
+    items[from..].iter().find(|it| !it.is_comment() ).and_then(|it| it.upper() )
+}
+
+// Width of the list element starting at the given item, up to the next comma, clause,
+// unmatched closing parenthesis or statement end.
+fn element_width(items : &[Item], from : usize) -> usize {
+    let mut depth = 0;
+    let mut width = 0;
+    for it in items[from..].iter() {
+        match &it.piece {
+            Piece::Token(Token::LParen) => depth += 1,
+            Piece::Token(Token::RParen) if depth == 0 => break,
+            Piece::Token(Token::RParen) => depth -= 1,
+            Piece::Token(Token::Comma) | Piece::Token(Token::SemiColon) if depth == 0 => break,
+            Piece::LineComment => break,
+            Piece::Token(Token::Word(_)) if depth == 0 => {
+                if it.upper().map(|w| CLAUSE_WORDS.contains(&&w[..]) || JOIN_MODIFIERS.contains(&&w[..]) ).unwrap_or(false) {
+                    break;
+                }
+            },
+            _ => { }
+        }
+        width += it.text.chars().count() + 1;
+    }
+    width
+}
+
+fn layout_statement(items : &[Item], opts : &FormatOptions) -> String {
+    let first = items.iter().find(|it| !it.is_comment() ).and_then(|it| it.upper() ).unwrap_or_default();
+    let break_clauses = CLAUSE_STATEMENTS.contains(&&first[..]);
+    let mut layout = Layout { out : String::new(), indent : opts.indent, line_level : 0, at_line_start : true };
+    let mut parens : Vec<Paren> = Vec::new();
+    let mut level = 0;
+    let mut clause : Option<String> = None;
+    let mut between = false;
+    let mut closed_subquery = false;
+    let mut prev : Option<&Item> = None;
+    let mut before_prev : Option<&Item> = None;
+    for (ix, item) in items.iter().enumerate() {
+        let inline = match parens.last() {
+            Some(Paren::Inline) => true,
+            _ => false
+        };
+        let after_subquery = closed_subquery;
+        closed_subquery = false;
+
+        match &item.piece {
+            Piece::LineComment => {
+                layout.push(&item.text, true);
+                let line_level = layout.line_level;
+                layout.newline(line_level);
+                before_prev = prev;
+                prev = Some(item);
+                continue;
+            },
+            Piece::Token(Token::Word(_)) if break_clauses && !inline => {
+                let word = item.upper().unwrap_or_default();
+                let prev_word = prev.and_then(|p| p.upper() );
+                let next = next_word(items, ix + 1);
+                let after_paren = prev.map(|p| p.is_token(&Token::RParen) ).unwrap_or(false);
+                if is_clause_break(&word, prev_word.as_deref(), next.as_deref(), &first, after_paren) {
+                    if !layout.at_line_start {
+                        layout.newline(level);
+                    }
+                    clause = Some(word.clone());
+                } else if (word == "AND" || word == "OR") && !between {
+                    match clause.as_deref() {
+                        Some("WHERE") | Some("HAVING") | Some("ON") => {
+                            layout.newline(level + 1);
+                        },
+                        _ => { }
+                    }
+                }
+                match &word[..] {
+                    "BETWEEN" => between = true,
+                    "AND" => between = false,
+                    "ON" => clause = Some(word.clone()),
+                    _ => { }
+                }
+            },
+            _ => { }
+        }
+
+        match &item.piece {
+            Piece::Token(Token::LParen) => {
+                layout.push(&item.text, needs_space(prev, before_prev, item));
+                let next = next_word(items, ix + 1);
+                if break_clauses && (next.as_deref() == Some("SELECT") || next.as_deref() == Some("WITH")) {
+                    parens.push(Paren::Subquery(level, clause.take()));
+                    level += 1;
+                } else {
+                    parens.push(Paren::Inline);
+                }
+            },
+            Piece::Token(Token::RParen) => {
+                match parens.pop() {
+                    Some(Paren::Subquery(outer_level, outer_clause)) => {
+                        level = outer_level;
+                        clause = outer_clause;
+                        layout.newline(level);
+                        layout.push(&item.text, false);
+                        closed_subquery = true;
+                    },
+                    _ => {
+                        layout.push(&item.text, needs_space(prev, before_prev, item));
+                    }
+                }
+            },
+            Piece::Token(Token::Comma) => {
+                layout.push(&item.text, false);
+                if break_clauses && !inline {
+                    if after_subquery {
+                        layout.newline(level);
+                    } else if layout.column() + element_width(items, ix + 1) > opts.width {
+                        layout.newline(level + 1);
+                    }
+                }
+            },
+            _ => {
+                layout.push(&item.text, needs_space(prev, before_prev, item));
+            }
+        }
+        before_prev = prev;
+        prev = Some(item);
+    }
+    let len = layout.out.trim_end().len();
+    layout.out.truncate(len);
+    layout.out
+}
+
+// Statements are compared by their syntax tree (ignoring case, since only keywords and
+// unquoted identifiers have their case changed).
+fn same_statements(a : &str, b : &str) -> bool {
+    let dialect = PostgreSqlDialect{};
+    match (Parser::parse_sql(&dialect, a), Parser::parse_sql(&dialect, b)) {
+        (Ok(a), Ok(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter())
+                .all(|(a, b)| a.to_string().to_lowercase() == b.to_string().to_lowercase() )
+        },
+        _ => false
+    }
+}
+
+/// Formats a single statement. Statements that sqlparser does not accept, or whose layout would
+/// not be parsed back into the same statement, are returned as they were.
+pub fn format_statement(stmt : &str, opts : &FormatOptions) -> String {
+    let stmt = stmt.trim();
+    match Parser::parse_sql(&PostgreSqlDialect{}, stmt) {
+        Ok(parsed) if parsed.len() == 1 => { },
+        _ => return stmt.to_string()
+    }
+    let tokens = match parsing::extract_postgres_tokens(stmt) {
+        Ok(tokens) => tokens,
+        Err(_) => return stmt.to_string()
+    };
+    let formatted = layout_statement(&build_items(tokens, opts.keyword_case), opts);
+    if same_statements(stmt, &formatted) {
+        formatted
+    } else {
+        stmt.to_string()
+    }
+}
+
+fn separator(ws : &str) -> &'static str {
+    if ws.matches('\n').count() >= 2 {
+        "\n\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Formats all statements in the script, preserving comments and the blank lines
+/// between statements.
+pub fn format_sql(script : &str, opts : &FormatOptions) -> Result<String, String> {
+    let chars : Vec<char> = script.chars().collect();
+    let mut out = String::new();
+    let mut last_end = 0;
+    let mut pieces : Vec<String> = Vec::new();
+    for range in parsing::statement_ranges(script)? {
+
+        // Text between statements (e.g. comments followed by an empty statement) is kept as is.
+        let gap : String = chars[last_end..range.start].iter().collect();
+        if !gap.trim().is_empty() {
+            pieces.push(gap);
+        }
+        pieces.push(chars[range.start..range.end].iter().collect());
+        last_end = range.end;
+    }
+    let rest : String = chars[last_end..].iter().collect();
+    if !rest.trim().is_empty() {
+        pieces.push(rest);
+    }
+
+    for piece in pieces {
+        let ws_len = piece.len() - piece.trim_start().len();
+        let (ws, mut txt) = (&piece[..ws_len], &piece[ws_len..]);
+
+        // A comment at the same line as the end of the previous statement stays there.
+        if !out.is_empty() && !ws.contains('\n') && (txt.starts_with("--") || txt.starts_with("/*")) {
+            let comment_end = if txt.starts_with("--") {
+                txt.find('\n').unwrap_or(txt.len())
+            } else {
+                txt.find("*/").map(|pos| pos + 2 ).unwrap_or(txt.len())
+            };
+            out += " ";
+            out += txt[..comment_end].trim_end();
+            txt = &txt[comment_end..];
+            let ws_len = txt.len() - txt.trim_start().len();
+            if txt.trim().is_empty() {
+                continue;
+            }
+            out += separator(&txt[..ws_len]);
+            txt = &txt[ws_len..];
+        } else if !out.is_empty() {
+            out += separator(ws);
+        }
+
+        if parsing::statement_ranges(txt).map(|r| r.is_empty() ).unwrap_or(true) {
+            out += txt.trim_end();
+        } else {
+            out += &format_statement(txt, opts);
+        }
+    }
+    if script.ends_with('\n') {
+        out.push('\n');
+    }
+    Ok(out)
+}
@@ -18,7 +18,8 @@ use sqlparser::dialect::{PostgreSqlDialect};
 use sqlparser::ast::{Statement, SetExpr, TableFactor, JoinOperator, ObjectType, Expr, SelectItem, FunctionArgExpr, FunctionArg};
 use sqlparser::parser::{Parser, ParserError};
 use sqlparser::dialect::keywords::Keyword;
-use sqlparser::tokenizer::{Token};
+use sqlparser::tokenizer::{Token, Tokenizer};
+use serde::{Serialize, Deserialize};
 
 pub fn is_like_query(s : &Statement) -> bool {
     match s {
@@ -45,6 +46,12 @@ pub mod parsing;
 /// Locally-parsed and executed copy statements.
 pub mod copy;
 
+/// Layout of SQL scripts (keyword case, indentation and line breaks).
+pub mod format;
+
+/// Statements that write changes staged at result tables back to their source table.
+pub mod edit;
+
 // PostgreSQL notifications
 // pub mod notify;
 
@@ -55,44 +62,360 @@ use parsing::*;
 
 use copy::*;
 
+/// What happens when a statement matches a safety rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Policy {
+
+    // Statement is sent to the server.
+    Allow,
+
+    // Statement is only sent after the user confirms it.
+    Confirm,
+
+    // Statement is never sent to the server.
+    Deny
+
+}
+
+impl Policy {
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Policy::Allow => "allow",
+            Policy::Confirm => "confirm",
+            Policy::Deny => "deny"
+        }
+    }
+
+}
+
+impl FromStr for Policy {
+
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, String> {
+        match s {
+            "allow" => Ok(Policy::Allow),
+            "confirm" => Ok(Policy::Confirm),
+            "deny" => Ok(Policy::Deny),
+            _ => Err(format!("Invalid policy: {}", s))
+        }
+    }
+
+}
+
+/// Returns whether the statement would let a read-only session write, i.e. SET or RESET
+/// default_transaction_read_only (to anything but on), or a READ WRITE transaction mode
+/// given to BEGIN, START TRANSACTION or SET SESSION CHARACTERISTICS. This only gives an early
+/// message: the setting is verified at the server after each statement of read-only sessions.
+pub fn lifts_read_only(sql : &str) -> bool {
+    let dialect = PostgreSqlDialect {};
+    let tokens = match Tokenizer::new(&dialect, sql).tokenize() {
+        Ok(tokens) => tokens,
+        Err(_) => return false
+    };
+    let words : Vec<String> = tokens.iter()
+        .filter_map(|tk| match tk {
+            Token::Word(w) => Some(w.value.to_uppercase()),
+            Token::SingleQuotedString(s) | Token::Number(s, _) => Some(s.to_uppercase()),
+            _ => None
+        })
+        .collect();
+    let read_write = words.windows(2).any(|w| &w[0][..] == "READ" && &w[1][..] == "WRITE" );
+    let setting = words.iter()
+        .position(|w| &w[..] == "DEFAULT_TRANSACTION_READ_ONLY" || &w[..] == "TRANSACTION_READ_ONLY" );
+    match words.first().map(|w| &w[..] ) {
+        Some("BEGIN") | Some("START") => read_write,
+        Some("SET") => {
+            if read_write {
+                return true;
+            }
+            match setting {
+                Some(ix) => {
+                    let value = words[(ix+1)..].iter().find(|w| &w[..] != "TO" );
+                    match value.map(|v| &v[..] ) {
+                        Some("ON") | Some("TRUE") | Some("YES") | Some("1") => false,
+                        _ => true
+                    }
+                },
+                None => false
+            }
+        },
+        Some("RESET") => setting.is_some() || words.iter().any(|w| &w[..] == "ALL" ),
+        _ => false
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SafetyRule {
+
+    // UPDATE without a WHERE clause.
+    UnqualifiedUpdate,
+
+    // DELETE without a WHERE clause, or TRUNCATE.
+    UnqualifiedDelete,
+
+    Insert,
+
+    // GRANT or REVOKE.
+    Privileges,
+
+    // CREATE of any object kind.
+    Create,
+
+    // DROP of any object kind.
+    Drop,
+
+    // ALTER of any object kind.
+    Alter
+
+}
+
+impl SafetyRule {
+
+    pub const ALL : [SafetyRule; 7] = [
+        SafetyRule::UnqualifiedUpdate,
+        SafetyRule::UnqualifiedDelete,
+        SafetyRule::Insert,
+        SafetyRule::Privileges,
+        SafetyRule::Create,
+        SafetyRule::Drop,
+        SafetyRule::Alter
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SafetyRule::UnqualifiedUpdate => "UPDATE without WHERE",
+            SafetyRule::UnqualifiedDelete => "DELETE without WHERE or TRUNCATE",
+            SafetyRule::Insert => "INSERT",
+            SafetyRule::Privileges => "GRANT or REVOKE",
+            SafetyRule::Create => "CREATE",
+            SafetyRule::Drop => "DROP",
+            SafetyRule::Alter => "ALTER"
+        }
+    }
+
+    /// Returns the rule a statement falls under, if any.
+    pub fn matching(stmt : &Statement) -> Option<SafetyRule> {
+        match stmt {
+            Statement::Update { selection : None, .. } => Some(SafetyRule::UnqualifiedUpdate),
+            Statement::Delete { selection : None, .. } | Statement::Truncate { .. } => Some(SafetyRule::UnqualifiedDelete),
+            Statement::Insert { .. } => Some(SafetyRule::Insert),
+            Statement::Grant { .. } | Statement::Revoke { .. } => Some(SafetyRule::Privileges),
+            Statement::CreateTable { .. } | Statement::CreateView { .. } | Statement::CreateVirtualTable { .. } |
+            Statement::CreateIndex { .. } | Statement::CreateSchema { .. } | Statement::CreateDatabase { .. } |
+            Statement::CreateFunction { .. } | Statement::CreateRole { .. } => Some(SafetyRule::Create),
+            Statement::Drop { .. } => Some(SafetyRule::Drop),
+            Statement::AlterTable { .. } | Statement::AlterIndex { .. } => Some(SafetyRule::Alter),
+            stmt => SafetyRule::matching_keywords(&stmt.to_string())
+        }
+    }
+
+    /// Returns the rule a statement falls under from its leading keywords. This covers the
+    /// statements the parser cannot represent (e.g. CREATE TYPE or ALTER ROLE). Only a WHERE
+    /// outside parentheses qualifies an UPDATE or DELETE (not the WHERE of a subquery).
+    pub fn matching_keywords(sql : &str) -> Option<SafetyRule> {
+        let dialect = PostgreSqlDialect {};
+
+        // Unquoted words, with the parenthesis depth they appear at.
+        let words : Vec<(String, usize)> = match Tokenizer::new(&dialect, sql).tokenize() {
+            Ok(tokens) => {
+                let mut depth = 0;
+                tokens.iter()
+                    .filter_map(|tk| match tk {
+                        Token::LParen => {
+                            depth += 1;
+                            None
+                        },
+                        Token::RParen => {
+                            depth = depth.saturating_sub(1);
+                            None
+                        },
+                        Token::Word(w) if w.quote_style.is_none() => Some((w.value.to_uppercase(), depth)),
+                        _ => None
+                    })
+                    .collect()
+            },
+            Err(_) => {
+                let mut depth = 0;
+                sql.split_whitespace()
+                    .map(|w| {
+                        let at = depth + w.chars().take_while(|c| *c == '(' ).count();
+                        depth = (depth + w.matches('(').count()).saturating_sub(w.matches(')').count());
+                        (w.trim_matches(|c| c == '(' || c == ')' || c == ';' ).to_uppercase(), at)
+                    })
+                    .collect()
+            }
+        };
+        let has_where = words.iter().any(|(w, depth)| &w[..] == "WHERE" && *depth == 0 );
+        match words.first().map(|(w, _)| &w[..] ) {
+            Some("CREATE") => Some(SafetyRule::Create),
+            Some("ALTER") => Some(SafetyRule::Alter),
+            Some("DROP") => Some(SafetyRule::Drop),
+            Some("GRANT") | Some("REVOKE") => Some(SafetyRule::Privileges),
+            Some("INSERT") => Some(SafetyRule::Insert),
+            Some("UPDATE") if !has_where => Some(SafetyRule::UnqualifiedUpdate),
+            Some("DELETE") if !has_where => Some(SafetyRule::UnqualifiedDelete),
+            Some("TRUNCATE") => Some(SafetyRule::UnqualifiedDelete),
+            _ => None
+        }
+    }
+
+}
+
+/// Policies set for each safety rule. Those are kept per connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SafetyPolicies {
+    pub unqualified_update : Policy,
+    pub unqualified_delete : Policy,
+    pub insert : Policy,
+    pub privileges : Policy,
+    pub create : Policy,
+    pub drop : Policy,
+    pub alter : Policy
+}
+
+impl Default for SafetyPolicies {
+
+    fn default() -> Self {
+        Self {
+            unqualified_update : Policy::Confirm,
+            unqualified_delete : Policy::Confirm,
+            insert : Policy::Allow,
+            privileges : Policy::Confirm,
+            create : Policy::Allow,
+            drop : Policy::Confirm,
+            alter : Policy::Confirm
+        }
+    }
+
+}
+
+impl SafetyPolicies {
+
+    pub fn allow_all() -> Self {
+        Self {
+            unqualified_update : Policy::Allow,
+            unqualified_delete : Policy::Allow,
+            insert : Policy::Allow,
+            privileges : Policy::Allow,
+            create : Policy::Allow,
+            drop : Policy::Allow,
+            alter : Policy::Allow
+        }
+    }
+
+    pub fn get(&self, rule : SafetyRule) -> Policy {
+        match rule {
+            SafetyRule::UnqualifiedUpdate => self.unqualified_update,
+            SafetyRule::UnqualifiedDelete => self.unqualified_delete,
+            SafetyRule::Insert => self.insert,
+            SafetyRule::Privileges => self.privileges,
+            SafetyRule::Create => self.create,
+            SafetyRule::Drop => self.drop,
+            SafetyRule::Alter => self.alter
+        }
+    }
+
+    pub fn set(&mut self, rule : SafetyRule, policy : Policy) {
+        match rule {
+            SafetyRule::UnqualifiedUpdate => self.unqualified_update = policy,
+            SafetyRule::UnqualifiedDelete => self.unqualified_delete = policy,
+            SafetyRule::Insert => self.insert = policy,
+            SafetyRule::Privileges => self.privileges = policy,
+            SafetyRule::Create => self.create = policy,
+            SafetyRule::Drop => self.drop = policy,
+            SafetyRule::Alter => self.alter = policy
+        }
+    }
+
+    /// Returns the matched rule and its policy. Statements not matching
+    /// any rule are always allowed.
+    pub fn evaluate(&self, stmt : &Statement) -> (Option<SafetyRule>, Policy) {
+        self.evaluate_rule(SafetyRule::matching(stmt))
+    }
+
+    /// Evaluates a statement the parser could not handle by its leading keywords.
+    pub fn evaluate_sql(&self, sql : &str) -> (Option<SafetyRule>, Policy) {
+        self.evaluate_rule(SafetyRule::matching_keywords(sql))
+    }
+
+    fn evaluate_rule(&self, rule : Option<SafetyRule>) -> (Option<SafetyRule>, Policy) {
+        match rule {
+            Some(rule) => (Some(rule), self.get(rule)),
+            None => (None, Policy::Allow)
+        }
+    }
+
+}
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct SafetyLock {
-    pub accept_ddl : bool,
-    pub accept_dml : bool,
-    pub enable_async : bool
-}
 
-fn safety_msg(stmt : &str) -> Result<(), String> {
-    Err(format!("Cannot execute {} statement\n(currently disabled at settings)", stmt))
+    pub policies : SafetyPolicies,
+
+    pub enable_async : bool,
+
+    // Whether the user already confirmed the statements that match
+    // rules set to Policy::Confirm.
+    pub confirmed : bool
+
 }
 
 impl SafetyLock {
 
     pub fn accepts(&self, stmt : &Statement) -> Result<(), String> {
-        match (stmt, self.accept_dml) {
-            (Statement::Delete { .. }, false) => {
-                safety_msg("DELETE")
+        self.check(self.policies.evaluate(stmt))
+    }
+
+    pub fn accepts_sql(&self, sql : &str) -> Result<(), String> {
+        self.check(self.policies.evaluate_sql(sql))
+    }
+
+    fn check(&self, evaluated : (Option<SafetyRule>, Policy)) -> Result<(), String> {
+        match evaluated {
+            (Some(rule), Policy::Deny) => {
+                Err(format!("Cannot execute {} statement\n(denied by the connection safety policy)", rule.name()))
             },
-            (Statement::Update { .. }, false) => {
-                safety_msg("UPDATE")
+            (Some(rule), Policy::Confirm) if !self.confirmed => {
+                Err(format!("Cannot execute {} statement\n(statement requires confirmation)", rule.name()))
             },
-            (other, _) => match (other, self.accept_ddl) { 
-                (Statement::Truncate { .. }, false) => {
-                    safety_msg("TRUNCATE")
+            _ => Ok(())
+        }
+    }
+
+    /// Lists the statements that should be confirmed by the user before being
+    /// sent to the server, with the rule each one of them matched.
+    pub fn pending_confirmation(&self, stmts : &[AnyStatement]) -> Vec<(SafetyRule, String)> {
+        if self.confirmed {
+            return Vec::new();
+        }
+        let mut pending = Vec::new();
+        for any_stmt in stmts {
+            match any_stmt {
+                AnyStatement::Parsed(stmt, s) => {
+                    if let (Some(rule), Policy::Confirm) = self.policies.evaluate(stmt) {
+                        pending.push((rule, s.clone()));
+                    }
                 },
-                (Statement::Drop { .. }, false) => {
-                    safety_msg("DROP")
+                AnyStatement::ParsedTransaction { middle, .. } => {
+                    for stmt in middle {
+                        if let (Some(rule), Policy::Confirm) = self.policies.evaluate(stmt) {
+                            pending.push((rule, stmt.to_string()));
+                        }
+                    }
                 },
-                (Statement::AlterTable { .. }, false) => {
-                    safety_msg("ALTER")
+                AnyStatement::Raw(_, s, _) => {
+                    if let (Some(rule), Policy::Confirm) = self.policies.evaluate_sql(s) {
+                        pending.push((rule, s.clone()));
+                    }
                 },
-                _ => {
-                    Ok(())
-                }
+                _ => { }
             }
         }
+        pending
     }
-    
+
 }
 
 // #[cfg(feature="arrowext")]
@@ -210,6 +533,134 @@ impl DecodingError {
 
 }
 
+/// Message sent by the server while a statement was executed (e.g. by RAISE NOTICE
+/// or VACUUM VERBOSE), which does not change the statement output.
+#[derive(Debug, Clone)]
+pub struct ServerNotice {
+
+    // Index of the matching StatementOutput at the execution results.
+    pub stmt : usize,
+
+    // NOTICE, WARNING, INFO, LOG or DEBUG.
+    pub severity : String,
+
+    // SQLSTATE code.
+    pub code : String,
+
+    pub message : String,
+
+    pub detail : Option<String>,
+
+    pub hint : Option<String>
+
+}
+
+impl ServerNotice {
+
+    pub fn describe(&self) -> String {
+        let mut s = format!("{} ({}): {}", self.severity, self.code, self.message);
+        if let Some(detail) = &self.detail {
+            s += &format!("\nDetail: {}", detail);
+        }
+        if let Some(hint) = &self.hint {
+            s += &format!("\nHint: {}", hint);
+        }
+        s
+    }
+
+}
+
+/// Breaks the lines at the first space after every line_length characters.
+pub fn break_string(content : &mut String, line_length : usize) {
+    let mut break_next = false;
+    let mut last_break = 0;
+    let mut break_pos = Vec::new();
+    for (i, c) in content.chars().enumerate() {
+        if c == '\n' {
+            last_break = i;
+            break_next = false;
+        }
+        if c == ' ' && break_next {
+            break_pos.push(i);
+            break_next = false;
+            last_break = i;
+        }
+        if i - last_break > line_length && !break_next {
+            break_next = true;
+        }
+    }
+
+    if break_pos.len() == 0 {
+        return;
+    }
+
+    let mut broken = String::with_capacity(content.len() + 4);
+    let mut chars = content.chars();
+    while break_pos.len() > 0 {
+        let mut count = 0;
+        while let Some(c) = chars.next() {
+            broken.push(c);
+            count += 1;
+            if count == break_pos[0] {
+                broken += "\n";
+                break_pos.remove(0);
+                if break_pos.len() == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    broken.extend(chars);
+    *content = broken;
+}
+
+/// Error reported by the database server for a statement, carrying the fields of
+/// the server error response.
+#[derive(Debug, Clone)]
+pub struct DatabaseError {
+
+    // ERROR, FATAL or PANIC.
+    pub severity : String,
+
+    // SQLSTATE code.
+    pub code : String,
+
+    pub message : String,
+
+    pub detail : Option<String>,
+
+    pub hint : Option<String>,
+
+    // 1-based character position of the error at the statement, if reported.
+    pub position : Option<usize>,
+
+    // Statement as sent to the server (empty if it could not be determined).
+    pub stmt : String
+
+}
+
+impl DatabaseError {
+
+    /// Message shown to the user, followed by the detail and hint (if any), as plain
+    /// text broken at 80 columns.
+    pub fn describe(&self) -> String {
+        let mut s = self.message.clone();
+        if let Some(fst) = s.get(0..1) {
+            let fst = fst.to_uppercase();
+            s.replace_range(0..1, &fst);
+        }
+        if let Some(detail) = &self.detail {
+            s += &format!("\nDetail: {}", detail);
+        }
+        if let Some(hint) = &self.hint {
+            s += &format!("\nHint: {}", hint);
+        }
+        break_string(&mut s, 80);
+        s
+    }
+
+}
+
 // Carries a result (arranged over columns)
 #[derive(Debug, Clone)]
 pub enum StatementOutput {
@@ -232,6 +683,10 @@ pub enum StatementOutput {
     // or client (if false), carrying its error message.
     Invalid(String, bool),
 
+    // Returns a query/statement rejected by the database engine, with the
+    // error response sent by the server.
+    Rejected(DatabaseError),
+
     // Resulting from a local command invocation
     Empty
 
@@ -247,9 +702,18 @@ impl StatementOutput {
     }
     
     pub fn error(&self) -> Result<(), Box<dyn Error>> {
+        match self.error_message() {
+            Some(msg) => Err(msg.into()),
+            None => Ok(())
+        }
+    }
+
+    /// Error message of invalid or rejected statements.
+    pub fn error_message(&self) -> Option<String> {
         match self {
-            StatementOutput::Invalid(msg, _) => Err(msg.clone().into()),
-            _ => Ok(())
+            StatementOutput::Invalid(msg, _) => Some(msg.clone()),
+            StatementOutput::Rejected(e) => Some(e.describe()),
+            _ => None
         }
     }
     
@@ -268,14 +732,7 @@ impl StatementOutput {
 }
 
 pub fn condense_errors(stmts : &[StatementOutput]) -> Option<String> {
-    let mut errs : Vec<String> = stmts.iter().filter_map(|stmt| {
-        match stmt {
-            StatementOutput::Invalid(msg, _) => {
-                Some(msg.clone())
-            },
-            _ => None
-        }
-    }).collect();
+    let mut errs : Vec<String> = stmts.iter().filter_map(|stmt| stmt.error_message() ).collect();
     match errs.len() {
         0 => None,
         1 => Some(errs.remove(0)),
@@ -338,6 +795,23 @@ pub fn require_insert_n_from_sql(stmt : &str, ncols : usize, nrows : usize) -> R
     require_insert_n(&stmt, ncols, nrows)
 }
 
+/// Returns the SQL of the single query (SELECT, VALUES, TABLE or WITH) the text holds,
+/// so its result can be fetched with a cursor.
+pub fn require_single_query(sql : &str) -> Result<String, String> {
+    match crate::sql::parsing::fully_parse_sql(sql) {
+        Ok(stmts) => {
+            if stmts.len() != 1 {
+                return Err(format!("Script must hold a single query to be exported (found {} statements)", stmts.len()));
+            }
+            match &stmts[0] {
+                AnyStatement::Parsed(Statement::Query(_), sql) => Ok(sql.clone()),
+                _ => Err(format!("Only queries can be exported to files"))
+            }
+        },
+        Err(SQLError::Lexing(err)) | Err(SQLError::Parsing(err)) | Err(SQLError::Unsupported(err)) => Err(err)
+    }
+}
+
 pub fn require_single_fn_select_from_sql(sql : &str) -> Result<(), String> {
     let stmt = crate::sql::AnyStatement::from_sql(sql)
         .ok_or(format!("Invalid SELECT statement generated."))?;
@@ -636,7 +1110,7 @@ pub fn build_error_with_stmt(msg : &str, query : &str) -> String {
         &query[..]
     };
     let ellipsis = if query.len() > 60 { "..." } else { "" };
-    format!("<b>Error</b> {}\n<b>Statement</b> {}{}", msg, q, ellipsis)
+    format!("Error: {}\nStatement: {}{}", msg, q, ellipsis)
 }
 
 pub fn parse_sql(sql : &str, subs : &HashMap<String, String>) -> Result<Vec<Statement>, String> {
@@ -652,3 +1126,332 @@ pub fn parse_sql(sql : &str, subs : &HashMap<String, String>) -> Result<Vec<Stat
         })
 }
 
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn parse_one(sql : &str) -> Statement {
+        parse_sql(sql, &HashMap::new()).unwrap().remove(0)
+    }
+
+    #[test]
+    fn safety_rules() {
+        let cases = [
+            ("update t set a = 1;", Some(SafetyRule::UnqualifiedUpdate)),
+            ("update t set a = 1 where b = 2;", None),
+            ("update t set a = (select b from u where u.id = t.id);", Some(SafetyRule::UnqualifiedUpdate)),
+            ("delete from t;", Some(SafetyRule::UnqualifiedDelete)),
+            ("delete from t where b = 2;", None),
+            ("truncate t;", Some(SafetyRule::UnqualifiedDelete)),
+            ("insert into t values (1);", Some(SafetyRule::Insert)),
+            ("grant select on t to bob;", Some(SafetyRule::Privileges)),
+            ("revoke select on t from bob;", Some(SafetyRule::Privileges)),
+            ("create table t (a integer);", Some(SafetyRule::Create)),
+            ("create view v as select * from t;", Some(SafetyRule::Create)),
+            ("create index i on t (a);", Some(SafetyRule::Create)),
+            ("create schema s;", Some(SafetyRule::Create)),
+            ("drop table t;", Some(SafetyRule::Drop)),
+            ("drop view v;", Some(SafetyRule::Drop)),
+            ("drop schema s;", Some(SafetyRule::Drop)),
+            ("alter table t add column b integer;", Some(SafetyRule::Alter)),
+            ("select * from t;", None)
+        ];
+        for (sql, rule) in cases {
+            assert_eq!(SafetyRule::matching(&parse_one(sql)), rule, "{}", sql);
+        }
+
+        // Statements verified by their keywords are only qualified by a WHERE outside subqueries.
+        let keyword_cases = [
+            ("delete from t using (select 1 where true) s;", Some(SafetyRule::UnqualifiedDelete)),
+            ("delete from t using u where t.id = u.id;", None),
+            ("update t set x = (select y from u where u.id = t.id);", Some(SafetyRule::UnqualifiedUpdate)),
+            ("update t set x = (select y from u where u.id = t.id) where t.id > 0;", None),
+            ("create type mood as enum ('sad', 'ok');", Some(SafetyRule::Create))
+        ];
+        for (sql, rule) in keyword_cases {
+            assert_eq!(SafetyRule::matching_keywords(sql), rule, "{}", sql);
+        }
+    }
+
+    #[test]
+    fn safety_policies() {
+        let mut lock = SafetyLock { policies : SafetyPolicies::allow_all(), ..Default::default() };
+        lock.policies.set(SafetyRule::Drop, Policy::Deny);
+        lock.policies.set(SafetyRule::UnqualifiedDelete, Policy::Confirm);
+
+        assert!(lock.accepts(&parse_one("drop table t;")).is_err());
+        assert!(lock.accepts(&parse_one("delete from t;")).is_err());
+        assert!(lock.accepts(&parse_one("delete from t where a = 1;")).is_ok());
+        assert!(lock.accepts(&parse_one("insert into t values (1);")).is_ok());
+        assert_eq!(lock.policies.evaluate(&parse_one("select 1;")), (None, Policy::Allow));
+
+        let stmts = match fully_parse_sql("select 1; delete from t; drop table t;") {
+            Ok(stmts) => stmts,
+            Err(_) => panic!("Failed parsing statements")
+        };
+        let pending = lock.pending_confirmation(&stmts[..]);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].0, SafetyRule::UnqualifiedDelete);
+
+        // Confirmation never overrides a denied statement.
+        lock.confirmed = true;
+        assert!(lock.accepts(&parse_one("delete from t;")).is_ok());
+        assert!(lock.accepts(&parse_one("drop table t;")).is_err());
+        assert!(lock.pending_confirmation(&stmts[..]).is_empty());
+    }
+
+    #[test]
+    fn read_only_changes() {
+        let lifted = [
+            "set default_transaction_read_only = off;",
+            "SET default_transaction_read_only TO 'off';",
+            "set session default_transaction_read_only = 0;",
+            "reset default_transaction_read_only;",
+            "reset all;",
+            "set session characteristics as transaction read write;",
+            "set transaction read write;",
+            "begin read write;",
+            "start transaction isolation level serializable, read write;"
+        ];
+        for sql in lifted {
+            assert!(lifts_read_only(sql), "{}", sql);
+        }
+        let kept = [
+            "set default_transaction_read_only = on;",
+            "begin;",
+            "begin read only;",
+            "select 'read write' from t;",
+            "set search_path to public;"
+        ];
+        for sql in kept {
+            assert!(!lifts_read_only(sql), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn safety_raw_statements() {
+        let cases = [
+            ("create type mood as enum ('sad', 'ok');", Some(SafetyRule::Create)),
+            ("create extension if not exists pgcrypto;", Some(SafetyRule::Create)),
+            ("create policy p on t using (true);", Some(SafetyRule::Create)),
+            ("alter role bob with superuser;", Some(SafetyRule::Alter)),
+            ("alter sequence s restart;", Some(SafetyRule::Alter)),
+            ("drop trigger tr on t;", Some(SafetyRule::Drop)),
+            ("delete from \"where\";", Some(SafetyRule::UnqualifiedDelete)),
+            ("update t set a = 1 where b = 2;", None),
+            ("vacuum t;", None)
+        ];
+        for (sql, rule) in cases {
+            assert_eq!(SafetyRule::matching_keywords(sql), rule, "{}", sql);
+        }
+
+        let mut lock = SafetyLock { policies : SafetyPolicies::allow_all(), ..Default::default() };
+        lock.policies.set(SafetyRule::Create, Policy::Deny);
+        lock.policies.set(SafetyRule::Alter, Policy::Confirm);
+        assert!(lock.accepts_sql("create type mood as enum ('sad', 'ok');").is_err());
+        assert!(lock.accepts_sql("alter role bob with superuser;").is_err());
+
+        // Statements the parser rejects are kept as raw statements, and still require confirmation.
+        let stmts = match fully_parse_sql("select 1; alter role bob with superuser;") {
+            Ok(stmts) => stmts,
+            Err(_) => panic!("Failed parsing statements")
+        };
+        let pending = lock.pending_confirmation(&stmts[..]);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].0, SafetyRule::Alter);
+        lock.confirmed = true;
+        assert!(lock.accepts_sql("alter role bob with superuser;").is_ok());
+    }
+
+    #[test]
+    fn error_locations() {
+        let script = "select 1;\n\nselect a, missing from t;\n";
+
+        // The server reports positions relative to the re-formatted statement.
+        let stmt = "SELECT a, missing FROM t";
+        let pos = stmt.find("missing").unwrap() + 1;
+        let (offset, len) = parsing::error_location(script, 1, stmt, pos).unwrap();
+        let located : String = script.chars().skip(offset).take(len).collect();
+        assert_eq!(located, "missing");
+        assert_eq!(offset, script.find("missing").unwrap());
+
+        // Repeated words are located by the number of previous occurrences.
+        let script = "select t.a from t join t2 on t.a = t2.t;";
+        let stmt = "SELECT t.a FROM t JOIN t2 ON t.a = t2.t";
+        let pos = stmt.rfind("t.a").unwrap() + 1;
+        let (offset, len) = parsing::error_location(script, 0, stmt, pos).unwrap();
+        assert_eq!((offset, len), (script.rfind("t.a").unwrap(), 1));
+
+        // Escaped quotes at previous statements do not move the underline.
+        let script = "update t set a = 'it''s', b = 'o''neil';\nselect missing;";
+        let (offset, len) = parsing::error_location(script, 1, "SELECT missing", 8).unwrap();
+        assert_eq!((offset, len), (script.rfind("missing").unwrap(), 7));
+
+        // Locations at the bound script are mapped back to the parameters typed by the user.
+        let script = "select :value, missing from t where id = ${id};";
+        let (sql, _) = parsing::bind_parameters(script, &HashMap::new());
+        let stmt = "SELECT $1, missing FROM t WHERE id = $2";
+        let loc = parsing::error_location(&sql, 0, stmt, stmt.find("missing").unwrap() + 1).unwrap();
+        assert_eq!(parsing::unbind_location(script, loc), (script.find("missing").unwrap(), 7));
+        assert_eq!(parsing::unbind_location(script, (sql.find("$2").unwrap(), 2)), (script.find("${id}").unwrap(), 5));
+    }
+
+    #[test]
+    fn database_error_description() {
+        let err = DatabaseError {
+            severity : String::from("ERROR"),
+            code : String::from("23505"),
+            message : String::from("duplicate key value violates unique constraint \"t_pkey\""),
+            detail : Some(String::from("Key (id)=(1) already exists.")),
+            hint : Some(String::from("Use <on conflict> to update the existing row")),
+            position : None,
+            stmt : String::new()
+        };
+        assert_eq!(
+            err.describe(),
+            "Duplicate key value violates unique constraint \"t_pkey\"\nDetail: Key (id)=(1) already exists.\nHint: Use <on conflict> to update the existing row"
+        );
+        let long = DatabaseError { message : "word ".repeat(40), detail : None, hint : None, ..err };
+        assert!(long.describe().lines().count() > 1);
+    }
+
+    #[test]
+    fn statement_diagnostics() {
+        let script = "select 1;\nselec 2;\nbegin;\ndelete from t;\ncommit;\ncopy t to stdout;";
+        let diags = parsing::diagnose_sql(script).unwrap();
+        assert_eq!(diags.len(), 6);
+        let errors : Vec<bool> = diags.iter().map(|d| d.error.is_some() ).collect();
+        assert_eq!(errors, vec![false, true, false, false, false, true]);
+        let snd : String = script.chars().skip(diags[1].range.start).take(diags[1].range.len()).collect();
+        assert_eq!(snd.trim(), "selec 2;");
+        assert!(matches!(diags[3].stmt, Some(Statement::Delete { .. })));
+
+        // Errors that depend on the whole sequence are reported at the last statement.
+        let diags = parsing::diagnose_sql("begin;\ndelete from t;").unwrap();
+        assert!(diags[0].error.is_none() && diags[1].error.is_some());
+    }
+
+    #[test]
+    fn statement_at_cursor() {
+        let script = "select 1;\n\ncreate function f() returns int as $$ select 1; $$ language sql;\nselect 3;\n";
+        let stmt_at = |offset : usize| -> String {
+            let range = parsing::statement_range_at(script, offset).unwrap().unwrap();
+            script.chars().skip(range.start).take(range.len()).collect()
+        };
+        assert_eq!(stmt_at(0), "select 1;");
+        assert_eq!(stmt_at(9), "select 1;");
+        assert!(stmt_at(10).starts_with("create function") && stmt_at(10).ends_with("language sql;"));
+        assert!(stmt_at(50).starts_with("create function"));
+        assert_eq!(stmt_at(script.chars().count()), "select 3;");
+        assert!(parsing::statement_range_at(" \n ", 1).unwrap().is_none());
+
+        // Escaped quotes are shorter when rendered, and should not shift the next statements.
+        let script = "select 'it''s', E'a\\'b', \"a\"\"b\";\nselect 2;";
+        let snd = script.find("select 2").unwrap();
+        assert_eq!(parsing::statement_ranges(script).unwrap()[1].end, script.chars().count());
+        let range = parsing::statement_range_at(script, snd).unwrap().unwrap();
+        assert_eq!(range.start, snd);
+        let stmt : String = script.chars().skip(range.start).take(range.len()).collect();
+        assert_eq!(stmt, "select 2;");
+    }
+
+    #[test]
+    fn format_scripts() {
+        let opts = format::FormatOptions::default();
+        let formatted = format::format_sql(
+            "select a, b from t join u on t.id = u.id and u.x between 1 and 2 where a > 1 order by b;",
+            &opts
+        ).unwrap();
+        assert_eq!(
+            formatted,
+            "SELECT a, b\nFROM t\nJOIN u ON t.id = u.id\n    AND u.x BETWEEN 1 AND 2\nWHERE a > 1\nORDER BY b;"
+        );
+
+        // Comments are preserved and statements sqlparser does not accept are kept as they are.
+        let formatted = format::format_sql(
+            "-- totals\nselect x from (select 1 as x) s; -- done\nnot sql at all;\n",
+            &opts
+        ).unwrap();
+        assert_eq!(formatted, "-- totals\nSELECT x\nFROM (\n    SELECT 1 AS x\n) s; -- done\nnot sql at all;\n");
+
+        let opts = format::FormatOptions { keyword_case : format::KeywordCase::Lower, indent : 2, ..opts };
+        let formatted = format::format_sql("SELECT \"Name\" FROM (SELECT 1 AS \"Name\") s;", &opts).unwrap();
+        assert_eq!(formatted, "select \"Name\"\nfrom (\n  select 1 as \"Name\"\n) s;");
+    }
+
+    #[test]
+    fn script_parameters() {
+        let script = "select * from t where id = :id and d > ${date}::date and s = ':no' and x::int = $1; -- :no\nselect :id;";
+        let params = parsing::find_parameters(script);
+        let names : Vec<&str> = params.iter().map(|p| &p.name[..] ).collect();
+        assert_eq!(names, vec!["id", "date", "$1"]);
+        assert_eq!(params[0].ty, None);
+        assert_eq!(params[1].ty, Some(DBType::Date));
+
+        let mut values = HashMap::new();
+        values.insert(String::from("id"), String::from("10"));
+        let (sql, subs) = parsing::bind_parameters(script, &values);
+        assert_eq!(sql, "select * from t where id = $1 and d > $2::date and s = ':no' and x::int = $3; -- :no\nselect $1;");
+        assert_eq!(subs.len(), 1);
+        assert_eq!(subs.get("$1").map(|s| &s[..] ), Some("10"));
+
+        // Each statement is sent with its placeholders numbered from $1.
+        let (stmt, names) = parsing::renumber_parameters("select $3, $1, $3;");
+        assert_eq!(stmt, "select $1, $2, $1;");
+        assert_eq!(names, vec![String::from("$3"), String::from("$1")]);
+
+        // Placeholders are bound (to NULL) even without any informed values, including the
+        // statements inside transaction blocks.
+        let (sql, subs) = parsing::bind_parameters("begin; insert into t values (:a); commit;", &HashMap::new());
+        assert!(subs.is_empty());
+        assert!(parsing::has_placeholders(&sql));
+        match parsing::fully_parse_sql(&sql).ok().as_ref().and_then(|stmts| stmts.first() ) {
+            Some(parsing::AnyStatement::ParsedTransaction { middle, .. }) => {
+                assert!(parsing::has_placeholders(&middle[0].to_string()));
+            },
+            _ => panic!("Expected transaction block")
+        }
+        assert!(!parsing::has_placeholders("select '$1', \"$2\" from t; -- $3"));
+
+        // Array slices and escape strings do not hold parameters.
+        let params = parsing::find_parameters("select arr[lo:hi], x[1:n], f(a)[1:n] from t where id = :lo;");
+        let names : Vec<&str> = params.iter().map(|p| &p.name[..] ).collect();
+        assert_eq!(names, vec!["lo"]);
+        let params = parsing::find_parameters("select E'it\\'s :no', e'\\\\' || :yes;");
+        let names : Vec<&str> = params.iter().map(|p| &p.name[..] ).collect();
+        assert_eq!(names, vec!["yes"]);
+    }
+
+    #[test]
+    fn table_edits() {
+        let cols = vec![
+            (String::from("id"), DBType::I32, true),
+            (String::from("name"), DBType::Text, false)
+        ];
+        let names = vec![String::from("name"), String::from("id"), String::from("upper")];
+        assert_eq!(edit::editable_columns(&names[..], &cols[..]), Some((vec![1], vec![true, true, false])));
+        assert_eq!(edit::editable_columns(&names[0..1], &cols[..]), None);
+
+        let key = vec![(String::from("id"), String::from("1"))];
+        let changes = [
+            edit::RowChange::Delete { key : key.clone() },
+            edit::RowChange::Update { key, values : vec![(String::from("name"), Some(String::from("O'Brien")))] },
+            edit::RowChange::Insert { values : vec![(String::from("name"), None), (String::from("id"), Some(String::from("NULL")))] }
+        ];
+        assert_eq!(
+            edit::change_statements("public.people", &changes),
+            "delete from public.people where \"id\" = 1;\n\
+            update public.people set \"name\" = 'O''Brien' where \"id\" = 1;\n\
+            insert into public.people (\"name\", \"id\") values (NULL, 'NULL');\n"
+        );
+
+        // Keys are built from the typed values, not from the displayed text.
+        let col = crate::tables::column::Column::F64(vec![0.123456789012]);
+        assert_eq!(crate::tables::sql_script::sql_literal(&col, 0), "0.123456789012");
+        let col = crate::tables::column::Column::Str(vec![String::from("it's")]);
+        assert_eq!(crate::tables::sql_script::sql_literal(&col, 0), "'it''s'");
+    }
+
+}
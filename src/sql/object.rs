@@ -34,6 +34,7 @@ pub enum DBType {
     Text,
     Date,
     Time,
+    Timestamp,
     Bytes,
     Json,
     Xml,
@@ -64,8 +65,8 @@ impl FromStr for DBType {
             "real" | "REAL" | "float4" => Ok(Self::F32),
             "dp" | "double precision" | "float8" => Ok(Self::F64),
             "blob" | "BLOB" | "bytea" => Ok(Self::Bytes),
-            "time" | "time with time zone" | "time without time zone" |
-            "timestamp with time zone" | "timestamp without time zone" => Ok(Self::Time),
+            "time" | "time with time zone" | "time without time zone" => Ok(Self::Time),
+            "timestamp" | "timestamptz" | "timestamp with time zone" | "timestamp without time zone" => Ok(Self::Timestamp),
             "xml" => Ok(Self::Xml),
             "anyarray" | "array" | "ARRAY" => Ok(Self::Array),
             "trigger" => Ok(Self::Trigger),
@@ -89,6 +90,7 @@ impl fmt::Display for DBType {
             Self::Text => "text",
             Self::Date => "date",
             Self::Time => "time",
+            Self::Timestamp => "timestamp",
             Self::Bytes => "bytea",
             Self::Json => "json",
             Self::Xml => "xml",
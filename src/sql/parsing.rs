@@ -11,6 +11,7 @@ use sqlparser::dialect::keywords::Keyword;
 use sqlparser::dialect;
 use sqlparser::tokenizer::{Tokenizer, Token, Word, Whitespace};
 use either::Either;
+use std::ops::Range;
 
 /// The Parsed variant represents a server-side syntatically-valid SQL statement (although not
 /// guaranteed to be semantically valid); Raw represents an unparsed statement with no
@@ -951,3 +952,480 @@ impl FromStr for Declare {
     }
 }
 
+
+/* Number of characters of a quoted literal at the start of the text, counting the opening
+quote after the prefix (e.g. E or N), the escaped quotes and the closing quote. */
+fn quoted_literal_len(txt : &[char], prefix : usize, backslash_escapes : bool) -> usize {
+    let mut i = prefix + 1;
+    while i < txt.len() {
+        match txt[i] {
+            '\\' if backslash_escapes => i += 2,
+            '\'' if txt.get(i + 1) == Some(&'\'') => i += 2,
+            '\'' => return i + 1,
+            _ => i += 1
+        }
+    }
+    txt.len()
+}
+
+/* Number of characters the token spans at the text starting at it. Quoted tokens are measured
+at the text, since their rendering does not preserve escaped quotes (e.g. 'it''s'). */
+fn token_source_len(tk : &Token, txt : &[char]) -> usize {
+    match tk {
+        Token::SingleQuotedString(_) => quoted_literal_len(txt, 0, false),
+        Token::NationalStringLiteral(_) | Token::HexStringLiteral(_) => quoted_literal_len(txt, 1, false),
+        Token::EscapedStringLiteral(_) => quoted_literal_len(txt, 1, true),
+        Token::Word(w) if w.quote_style.is_some() => {
+            let quote = w.quote_style.unwrap();
+            let n_escaped = w.value.chars().filter(|c| *c == quote ).count();
+            w.value.chars().count() + n_escaped + 2
+        },
+        tk => tk.to_string().chars().count()
+    }
+}
+
+/* Character ranges of the statements in the script, skipping statements with only whitespace
+(as split_statement_tokens does). Positions are found by walking the script alongside the tokens. */
+pub fn statement_ranges(script : &str) -> Result<Vec<Range<usize>>, String> {
+    let tokens = extract_postgres_tokens(script)?;
+    let chars : Vec<char> = script.chars().collect();
+    let mut ranges = Vec::new();
+    let (mut start, mut pos) = (0, 0);
+    let mut only_ws = true;
+    let mut last_tk_is_dollar = false;
+    let mut inside_dollar_quote = false;
+    for tk in tokens.iter() {
+        pos = (pos + token_source_len(tk, &chars[pos.min(chars.len())..])).min(chars.len());
+        match tk {
+            Token::SemiColon if !inside_dollar_quote => {
+                if !only_ws {
+                    ranges.push(Range { start, end : pos });
+                }
+                start = pos;
+                only_ws = true;
+                last_tk_is_dollar = false;
+                continue;
+            },
+            Token::Char('$') => {
+                if last_tk_is_dollar {
+                    inside_dollar_quote = !inside_dollar_quote;
+                }
+                last_tk_is_dollar = true;
+            },
+            _ => {
+                last_tk_is_dollar = false;
+            }
+        }
+        if !is_token_whitespace(tk) {
+            only_ws = false;
+        }
+    }
+    if !only_ws {
+        ranges.push(Range { start, end : pos });
+    }
+    Ok(ranges)
+}
+
+/// Character range of the statement around the offset, without the surrounding whitespace. Offsets
+/// at whitespace between statements select the next statement, and offsets after the last statement
+/// select the last one. Statements are split as in split_statement_tokens (i.e. semicolons inside
+/// dollar-quoted strings and comments do not end statements).
+pub fn statement_range_at(script : &str, offset : usize) -> Result<Option<Range<usize>>, String> {
+    let ranges = statement_ranges(script)?;
+    let range = match ranges.iter().find(|r| offset <= r.end ).or(ranges.last()) {
+        Some(range) => range.clone(),
+        None => return Ok(None)
+    };
+    let chars : Vec<char> = script.chars().skip(range.start).take(range.end - range.start).collect();
+    let start = chars.iter().position(|c| !c.is_whitespace() ).unwrap_or(0);
+    let end = chars.iter().rposition(|c| !c.is_whitespace() ).map(|p| p + 1 ).unwrap_or(chars.len());
+    Ok(Some(Range { start : range.start + start, end : range.start + end }))
+}
+
+fn is_word_char(c : char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Character offsets where the word starts within the text (ignoring case).
+fn word_offsets(txt : &[char], word : &[char]) -> Vec<usize> {
+    let mut offsets = Vec::new();
+    if word.len() == 0 || word.len() > txt.len() {
+        return offsets;
+    }
+    let is_word = word.iter().all(|c| is_word_char(*c) );
+    for start in 0..=(txt.len() - word.len()) {
+        let matches = txt[start..(start+word.len())].iter()
+            .zip(word.iter())
+            .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()) );
+        if !matches {
+            continue;
+        }
+        if is_word {
+            let starts_word = start == 0 || !is_word_char(txt[start-1]);
+            let ends_word = start + word.len() == txt.len() || !is_word_char(txt[start+word.len()]);
+            if !starts_word || !ends_word {
+                continue;
+            }
+        }
+        offsets.push(start);
+    }
+    offsets
+}
+
+/// Maps the 1-based position of an error reported by the server for the statement sent as
+/// stmt (the statement at index stmt_ix of the sequence parsed from the script) to the character
+/// offset and length of the offending token at the script. Statements are re-formatted
+/// before being sent, so the token is located by its text and the number of times it
+/// appeared before the error position.
+pub fn error_location(script : &str, stmt_ix : usize, stmt : &str, position : usize) -> Option<(usize, usize)> {
+    let ranges = statement_ranges(script).ok()?;
+    let any_stmts = fully_parse_sql(script).ok()?;
+    let n_groups = |s : &AnyStatement| -> usize {
+        match s {
+            AnyStatement::ParsedTransaction { middle, .. } => middle.len() + 2,
+            _ => 1
+        }
+    };
+    let fst_group : usize = any_stmts.iter().take(stmt_ix).map(|s| n_groups(s) ).sum();
+    let lst_group = fst_group + n_groups(any_stmts.get(stmt_ix)?) - 1;
+    let region = Range { start : ranges.get(fst_group)?.start, end : ranges.get(lst_group)?.end };
+
+    let stmt_chars : Vec<char> = stmt.chars().collect();
+    let err_ix = position.checked_sub(1)?;
+    let err_char = *stmt_chars.get(err_ix)?;
+    let word_range = if is_word_char(err_char) {
+        let start = stmt_chars[..err_ix].iter().rposition(|c| !is_word_char(*c) ).map(|p| p + 1 ).unwrap_or(0);
+        let end = stmt_chars[err_ix..].iter().position(|c| !is_word_char(*c) ).map(|p| err_ix + p ).unwrap_or(stmt_chars.len());
+        Range { start, end }
+    } else {
+        Range { start : err_ix, end : err_ix + 1 }
+    };
+    let word = &stmt_chars[word_range.clone()];
+    let nth = word_offsets(&stmt_chars[..], word).iter().position(|off| *off == word_range.start )?;
+
+    let region_chars : Vec<char> = script.chars().skip(region.start).take(region.end - region.start).collect();
+    let offsets = word_offsets(&region_chars[..], word);
+    let offset = offsets.get(nth).or(offsets.first())?;
+    Some((region.start + offset, word.len()))
+}
+
+/// Result of parsing a single statement of a script, used to annotate the
+/// editor before the script is executed.
+#[derive(Debug, Clone)]
+pub struct StatementDiagnostic {
+
+    // Character range of the statement at the script.
+    pub range : Range<usize>,
+
+    pub stmt : Option<Statement>,
+
+    pub error : Option<String>
+
+}
+
+/// Parses each statement of the script separately, so that errors are reported for all of the
+/// statements that would be rejected by fully_parse_sql. Fails only if the script could not be lexed.
+pub fn diagnose_sql(script : &str) -> Result<Vec<StatementDiagnostic>, String> {
+
+    // Parameters are filled in only at execution.
+    let masked = mask_parameters(script);
+    let script = &masked[..];
+
+    let ranges = statement_ranges(script)?;
+    let dialect = PostgreSqlDialect{};
+    let mut diagnostics = Vec::new();
+    for range in ranges {
+        let txt : String = script.chars().skip(range.start).take(range.end - range.start).collect();
+        let mut diag = StatementDiagnostic { range, stmt : None, error : None };
+        match Parser::parse_sql(&dialect, &txt).map(|mut stmts| if stmts.len() == 1 { stmts.pop() } else { None } ) {
+
+            // Transaction blocks are only valid as a whole, so their statements are
+            // accepted here and the block is verified below.
+            Ok(Some(stmt @ Statement::StartTransaction { .. })) |
+            Ok(Some(stmt @ Statement::Commit { .. })) |
+            Ok(Some(stmt @ Statement::Rollback { .. })) => {
+                diag.stmt = Some(stmt);
+            },
+            _ => match fully_parse_sql(&txt) {
+                Ok(mut any_stmts) => {
+                    if let Some(AnyStatement::Parsed(stmt, _)) = any_stmts.pop() {
+                        diag.stmt = Some(stmt);
+                    }
+                },
+                Err(SQLError::Lexing(e)) | Err(SQLError::Parsing(e)) | Err(SQLError::Unsupported(e)) => {
+                    diag.error = Some(e);
+                }
+            }
+        }
+        diagnostics.push(diag);
+    }
+
+    // Errors that depend on the full sequence (e.g. unfinished transaction blocks).
+    if diagnostics.iter().all(|d| d.error.is_none() ) {
+        if let Err(SQLError::Lexing(e)) | Err(SQLError::Parsing(e)) | Err(SQLError::Unsupported(e)) = fully_parse_sql(script) {
+            if let Some(last) = diagnostics.last_mut() {
+                last.error = Some(e);
+            }
+        }
+    }
+    Ok(diagnostics)
+}
+
+/// Parameter of a script, written as :name, ${name} or $1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptParameter {
+
+    // Name without the prefix (positional parameters keep the $).
+    pub name : String,
+
+    // Type set by an explicit cast of the first occurrence (:name::int or cast(:name as int)).
+    pub ty : Option<crate::sql::object::DBType>
+
+}
+
+fn take_word_chars(chars : &[char], from : usize) -> usize {
+    let mut end = from;
+    while end < chars.len() && is_word_char(chars[end]) {
+        end += 1;
+    }
+    end
+}
+
+/* Whether the character at ix follows an identifier, literal or closed bracket, where a colon
+is the bound separator of an array slice instead of the start of a placeholder. */
+fn follows_operand(chars : &[char], ix : usize) -> bool {
+    match ix.checked_sub(1).map(|prev| chars[prev] ) {
+        Some(c) => is_word_char(c) || c == ']' || c == ')' || c == ':',
+        None => false
+    }
+}
+
+/* Character ranges and names of the parameter placeholders of the script. Placeholders
+inside strings (including E'' strings with backslash escapes), quoted identifiers, comments,
+dollar-quoted strings and array slices (e.g. arr[lo:hi]) are ignored. */
+fn parameter_occurrences(script : &str) -> Vec<(Range<usize>, String)> {
+    let chars : Vec<char> = script.chars().collect();
+    let mut found = Vec::new();
+    let mut ix = 0;
+    while ix < chars.len() {
+        let next = chars.get(ix + 1).cloned();
+        match (chars[ix], next) {
+            ('-', Some('-')) => {
+                while ix < chars.len() && chars[ix] != '\n' {
+                    ix += 1;
+                }
+            },
+            ('/', Some('*')) => {
+                ix += 2;
+                while ix < chars.len() && !(chars[ix] == '*' && chars.get(ix + 1) == Some(&'/')) {
+                    ix += 1;
+                }
+                ix += 2;
+            },
+            ('E', Some('\'')) | ('e', Some('\'')) => {
+                ix += 2;
+                while ix < chars.len() && chars[ix] != '\'' {
+                    ix += if chars[ix] == '\\' { 2 } else { 1 };
+                }
+                ix += 1;
+            },
+            (quote @ '\'', _) | (quote @ '"', _) => {
+                ix += 1;
+                while ix < chars.len() && chars[ix] != quote {
+                    ix += 1;
+                }
+                ix += 1;
+            },
+            (':', Some(':')) => {
+                ix += 2;
+            },
+            (':', Some(c)) if (c.is_alphabetic() || c == '_') && !follows_operand(&chars, ix) => {
+                let end = take_word_chars(&chars, ix + 1);
+                found.push((Range { start : ix, end }, chars[ix+1..end].iter().collect()));
+                ix = end;
+            },
+            ('$', Some('{')) => {
+                let end = take_word_chars(&chars, ix + 2);
+                if end > ix + 2 && chars.get(end) == Some(&'}') {
+                    found.push((Range { start : ix, end : end + 1 }, chars[ix+2..end].iter().collect()));
+                }
+                ix = end + 1;
+            },
+            ('$', Some(c)) if c.is_ascii_digit() && (ix == 0 || !is_word_char(chars[ix-1])) => {
+                let mut end = ix + 1;
+                while end < chars.len() && chars[end].is_ascii_digit() {
+                    end += 1;
+                }
+                found.push((Range { start : ix, end }, chars[ix..end].iter().collect()));
+                ix = end;
+            },
+            ('$', _) => {
+
+                // Dollar-quoted string ($$ or $tag$), skipped up to the matching delimiter.
+                let tag_end = take_word_chars(&chars, ix + 1);
+                if chars.get(tag_end) == Some(&'$') {
+                    let tag = &chars[ix..tag_end+1];
+                    ix = tag_end + 1;
+                    while ix < chars.len() && !chars[ix..].starts_with(tag) {
+                        ix += 1;
+                    }
+                    ix += tag.len();
+                } else {
+                    ix = tag_end;
+                }
+            },
+            (c, _) if is_word_char(c) => {
+
+                // Avoids taking a $ at the middle of identifiers as a placeholder.
+                ix = take_word_chars(&chars, ix).max(ix + 1);
+                while ix < chars.len() && chars[ix] == '$' {
+                    ix = take_word_chars(&chars, ix + 1);
+                }
+            },
+            _ => {
+                ix += 1;
+            }
+        }
+    }
+    found
+}
+
+// Type of an explicit cast around the placeholder at the given range.
+fn parameter_cast(chars : &[char], range : &Range<usize>) -> Option<crate::sql::object::DBType> {
+    let after : String = chars[range.end..].iter().take(64).collect();
+    let ty = if let Some(rest) = after.strip_prefix("::") {
+        rest.split(|c : char| !c.is_alphanumeric() && c != '_' ).next().map(|s| s.to_string() )
+    } else {
+        let before : String = chars[..range.start].iter().collect();
+        let before = before.trim_end().to_lowercase();
+        if before.ends_with('(') && before[..before.len()-1].trim_end().ends_with("cast") {
+            let after = after.trim_start();
+            if after.get(..2).map(|s| s.eq_ignore_ascii_case("as") ).unwrap_or(false) {
+                after[2..].trim_start().split(|c : char| !c.is_alphanumeric() && c != '_' ).next().map(|s| s.to_string() )
+            } else {
+                None
+            }
+        } else {
+            None
+        }
+    };
+    ty.filter(|ty| !ty.is_empty() )
+        .map(|ty| crate::sql::object::DBType::from_str(&ty.to_lowercase()[..]).unwrap() )
+}
+
+/// Distinct parameters of the script, in the order they first appear.
+pub fn find_parameters(script : &str) -> Vec<ScriptParameter> {
+    let chars : Vec<char> = script.chars().collect();
+    let mut params : Vec<ScriptParameter> = Vec::new();
+    for (range, name) in parameter_occurrences(script) {
+        if !params.iter().any(|p| p.name == name ) {
+            let ty = parameter_cast(&chars, &range);
+            params.push(ScriptParameter { name, ty });
+        }
+    }
+    params
+}
+
+/// Replaces the parameters of the script by numbered placeholders ($1, $2...), numbered in
+/// the order the parameters first appear. Returns the modified script and the values for
+/// each placeholder. Parameters without values are bound to NULL.
+pub fn bind_parameters(script : &str, values : &HashMap<String, String>) -> (String, HashMap<String, String>) {
+    let mut chars : Vec<char> = script.chars().collect();
+    let names : Vec<String> = find_parameters(script).into_iter().map(|p| p.name ).collect();
+    for (range, placeholder) in parameter_bindings(script).into_iter().rev() {
+        chars.splice(range, placeholder.chars());
+    }
+    let mut subs = HashMap::new();
+    for (ix, name) in names.iter().enumerate() {
+        if let Some(val) = values.get(name) {
+            subs.insert(format!("${}", ix + 1), val.clone());
+        }
+    }
+    (chars.into_iter().collect(), subs)
+}
+
+// Character range of each parameter occurrence at the script, with the placeholder bind_parameters replaces it by.
+fn parameter_bindings(script : &str) -> Vec<(Range<usize>, String)> {
+    let names : Vec<String> = find_parameters(script).into_iter().map(|p| p.name ).collect();
+    parameter_occurrences(script).into_iter()
+        .map(|(range, name)| {
+            let pos = names.iter().position(|n| n == &name ).unwrap();
+            (range, format!("${}", pos + 1))
+        })
+        .collect()
+}
+
+// Maps a character offset of the script returned by bind_parameters to the offset at the
+// original script. Offsets within a placeholder map to the first (or, if last is set, the
+// last) character of the parameter.
+fn unbound_offset(bindings : &[(Range<usize>, String)], offset : usize, last : bool) -> usize {
+    let mut shift : isize = 0;
+    for (range, placeholder) in bindings.iter() {
+        let bound_start = (range.start as isize - shift) as usize;
+        let bound_len = placeholder.chars().count();
+        if offset < bound_start {
+            break;
+        }
+        if offset < bound_start + bound_len {
+            return if last { range.end - 1 } else { range.start };
+        }
+        shift += range.len() as isize - bound_len as isize;
+    }
+    (offset as isize + shift) as usize
+}
+
+/// Maps a character range (start offset and length) of the script returned by bind_parameters
+/// back to the original script, so it can be shown at the editor. Ranges within a placeholder
+/// cover the whole parameter.
+pub fn unbind_location(script : &str, loc : (usize, usize)) -> (usize, usize) {
+    let bindings = parameter_bindings(script);
+    let start = unbound_offset(&bindings[..], loc.0, false);
+    if loc.1 == 0 {
+        return (start, 0);
+    }
+    let end = unbound_offset(&bindings[..], loc.0 + loc.1 - 1, true) + 1;
+    (start, end.max(start) - start)
+}
+
+/// Whether the statement has numbered placeholders ($1, $2...), which must be bound
+/// even when the user did not inform any values (in which case they are NULL).
+pub fn has_placeholders(stmt : &str) -> bool {
+    parameter_occurrences(stmt).iter().any(|(_, name)| name.starts_with('$') )
+}
+
+/// Numbers the placeholders of a single statement from $1, returning the placeholders
+/// they replaced (in the order of the new numbers). The server requires the parameters of
+/// each statement to be numbered without gaps.
+pub fn renumber_parameters(stmt : &str) -> (String, Vec<String>) {
+    let mut chars : Vec<char> = stmt.chars().collect();
+    let occurrences : Vec<_> = parameter_occurrences(stmt).into_iter()
+        .filter(|(_, name)| name.starts_with('$') )
+        .collect();
+    let mut names : Vec<String> = Vec::new();
+    for (_, name) in occurrences.iter() {
+        if !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+    for (range, name) in occurrences.into_iter().rev() {
+        let pos = names.iter().position(|n| n == &name ).unwrap();
+        chars.splice(range, format!("${}", pos + 1).chars());
+    }
+    (chars.into_iter().collect(), names)
+}
+
+// Replaces the parameters by $1, padded to the original length, so the script
+// can be parsed while keeping the character positions.
+fn mask_parameters(script : &str) -> String {
+    let mut chars : Vec<char> = script.chars().collect();
+    for (range, _) in parameter_occurrences(script) {
+        for (ix, pos) in range.enumerate() {
+            chars[pos] = match ix {
+                0 => '$',
+                1 => '1',
+                _ => ' '
+            };
+        }
+    }
+    chars.into_iter().collect()
+}
@@ -0,0 +1,335 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::Table;
+use super::column::Column;
+use super::nullable_column::NullableColumn;
+use super::field::Field;
+use arrow::array::*;
+use arrow::datatypes::{DataType, Field as ArrowField, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow::util::display::array_value_to_string;
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use rust_decimal::Decimal;
+use tokio_postgres::types::ToSql;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// Precision of decimal columns written to Arrow (rust_decimal holds at most 28 digits).
+const DECIMAL_PRECISION : u8 = 38;
+
+fn downcast<T : 'static>(arr : &ArrayRef) -> Result<&T, String> {
+    arr.as_any().downcast_ref::<T>().ok_or(format!("Error downcasting column of type {}", arr.data_type()))
+}
+
+/// Copies the array into a column, which is nullable only if the array has null values.
+fn array_to_column<T, F>(arr : &ArrayRef, value : F) -> Column
+where
+    T : ToSql + Sync + Clone,
+    Column : From<Vec<T>>,
+    F : Fn(usize) -> T
+{
+    if arr.null_count() > 0 {
+        let opt_values : Vec<Option<T>> = (0..arr.len()).map(|ix| if arr.is_null(ix) { None } else { Some(value(ix)) } ).collect();
+        Column::Nullable(Box::new(NullableColumn::from(opt_values)))
+    } else {
+        Column::from((0..arr.len()).map(value).collect::<Vec<T>>())
+    }
+}
+
+/* Decimals that do not fit into rust_decimal are kept as text. */
+fn decimal_column(arr : &ArrayRef) -> Column {
+    let parsed : Option<Vec<Decimal>> = (0..arr.len())
+        .map(|ix| if arr.is_null(ix) { Some(Decimal::ZERO) } else { Decimal::from_str(&array_value_to_string(arr, ix).ok()?).ok() } )
+        .collect();
+    match parsed {
+        Some(decs) => array_to_column(arr, |ix| decs[ix] ),
+        None => array_to_column(arr, |ix| array_value_to_string(arr, ix).unwrap_or_default() )
+    }
+}
+
+/// Converts an Arrow record batch into a table. Dates, times and nested values are kept as text.
+pub fn table_from_batch(batch : &RecordBatch) -> Result<Table, String> {
+    let schema = batch.schema();
+    let mut names = Vec::new();
+    let mut cols = Vec::new();
+    for (field, arr) in schema.fields().iter().zip(batch.columns().iter()) {
+        let col = match field.data_type() {
+            DataType::Boolean => {
+                let a = downcast::<BooleanArray>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) )
+            },
+            DataType::Int8 => {
+                let a = downcast::<Int8Array>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) )
+            },
+            DataType::Int16 => {
+                let a = downcast::<Int16Array>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) )
+            },
+            DataType::Int32 => {
+                let a = downcast::<Int32Array>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) )
+            },
+            DataType::Int64 => {
+                let a = downcast::<Int64Array>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) )
+            },
+            DataType::UInt8 => {
+                let a = downcast::<UInt8Array>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) as i16 )
+            },
+            DataType::UInt16 => {
+                let a = downcast::<UInt16Array>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) as i32 )
+            },
+            DataType::UInt32 => {
+                let a = downcast::<UInt32Array>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) )
+            },
+            DataType::UInt64 => {
+                let a = downcast::<UInt64Array>(arr)?;
+                array_to_column(arr, |ix| Decimal::from(a.value(ix)) )
+            },
+            DataType::Float32 => {
+                let a = downcast::<Float32Array>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) )
+            },
+            DataType::Float64 => {
+                let a = downcast::<Float64Array>(arr)?;
+                array_to_column(arr, |ix| a.value(ix) )
+            },
+            DataType::Decimal128(_, _) => decimal_column(arr),
+            DataType::Utf8 => {
+                let a = downcast::<StringArray>(arr)?;
+                array_to_column(arr, |ix| a.value(ix).to_string() )
+            },
+            DataType::LargeUtf8 => {
+                let a = downcast::<LargeStringArray>(arr)?;
+                array_to_column(arr, |ix| a.value(ix).to_string() )
+            },
+            DataType::Binary => {
+                let a = downcast::<BinaryArray>(arr)?;
+                array_to_column(arr, |ix| a.value(ix).to_vec() )
+            },
+            DataType::LargeBinary => {
+                let a = downcast::<LargeBinaryArray>(arr)?;
+                array_to_column(arr, |ix| a.value(ix).to_vec() )
+            },
+            _ => array_to_column(arr, |ix| array_value_to_string(arr, ix).unwrap_or_default() )
+        };
+        cols.push(col);
+        names.push(field.name().clone());
+    }
+    Table::new(None, names, cols).map_err(|e| format!("{}", e))
+}
+
+/* Values of the column, with None at null rows. */
+fn column_fields(col : &Column) -> Vec<Option<Field>> {
+    (0..col.len()).map(|ix| if col.is_null_at(ix) { None } else { col.at(ix, None) } ).collect()
+}
+
+fn values<T, F>(fields : &[Option<Field>], f : F) -> Vec<Option<T>>
+where
+    F : Fn(&Field) -> Option<T>
+{
+    fields.iter().map(|field| field.as_ref().and_then(|field| f(field) ) ).collect()
+}
+
+/* Decimals are written with the informed scale, or with the largest scale found in the column.
+Values with more decimal digits than the informed scale are rejected instead of rounded. */
+fn decimal_array(fields : &[Option<Field>], fixed_scale : Option<u32>) -> Result<(DataType, ArrayRef), String> {
+    let decs = values(fields, |f| if let Field::Numeric(d) = f { Some(d.clone()) } else { None } );
+    let scale = match fixed_scale {
+        Some(scale) => scale,
+        None => decs.iter().filter_map(|d| d.map(|d| d.scale() ) ).max().unwrap_or(0)
+    };
+    let max = 10i128.pow(DECIMAL_PRECISION as u32);
+    let mut mantissas : Vec<Option<i128>> = Vec::with_capacity(decs.len());
+    for d in decs.iter() {
+        match d {
+            Some(d) => {
+                if d.scale() > scale {
+                    return Err(format!("Numeric value {} has more than {} decimal digits", d, scale));
+                }
+                let m = d.mantissa().checked_mul(10i128.pow(scale - d.scale()))
+                    .filter(|m| m.abs() < max )
+                    .ok_or(format!("Numeric value {} does not fit {} digits with scale {}", d, DECIMAL_PRECISION, scale))?;
+                mantissas.push(Some(m));
+            },
+            None => mantissas.push(None)
+        }
+    }
+    let arr = Decimal128Array::from(mantissas)
+        .with_precision_and_scale(DECIMAL_PRECISION, scale as _)
+        .map_err(|e| format!("Invalid decimal column: {}", e) )?;
+    Ok((DataType::Decimal128(DECIMAL_PRECISION, scale as _), Arc::new(arr)))
+}
+
+/* Arrow type of a column without values, which follows the column variant. */
+fn empty_data_type(col : &Column, decimal_scale : Option<u32>) -> DataType {
+    match col {
+        Column::Bool(_) => DataType::Boolean,
+        Column::I8(_) => DataType::Int8,
+        Column::I16(_) => DataType::Int16,
+        Column::I32(_) => DataType::Int32,
+        Column::U32(_) => DataType::UInt32,
+        Column::I64(_) => DataType::Int64,
+        Column::F32(_) => DataType::Float32,
+        Column::F64(_) => DataType::Float64,
+        Column::Numeric(_) => DataType::Decimal128(DECIMAL_PRECISION, decimal_scale.unwrap_or(0) as _),
+        Column::Bytes(_) => DataType::Binary,
+        Column::Str(_) | Column::Json(_) => DataType::Utf8,
+        Column::Nullable(col) => empty_data_type(col.dense(), decimal_scale)
+    }
+}
+
+/* The Arrow type follows the variant of the first non-null value (or the column variant, when
+all values are null). JSON values are written as text. */
+fn column_to_array(col : &Column, decimal_scale : Option<u32>) -> Result<(DataType, ArrayRef), String> {
+    let fields = column_fields(col);
+    let first = fields.iter().find_map(|f| f.as_ref() );
+    let typed : (DataType, ArrayRef) = match first {
+        Some(Field::Bool(_)) => {
+            (DataType::Boolean, Arc::new(BooleanArray::from(values(&fields, |f| if let Field::Bool(v) = f { Some(*v) } else { None } ))))
+        },
+        Some(Field::I8(_)) => {
+            (DataType::Int8, Arc::new(Int8Array::from(values(&fields, |f| if let Field::I8(v) = f { Some(*v) } else { None } ))))
+        },
+        Some(Field::I16(_)) => {
+            (DataType::Int16, Arc::new(Int16Array::from(values(&fields, |f| if let Field::I16(v) = f { Some(*v) } else { None } ))))
+        },
+        Some(Field::I32(_)) => {
+            (DataType::Int32, Arc::new(Int32Array::from(values(&fields, |f| if let Field::I32(v) = f { Some(*v) } else { None } ))))
+        },
+        Some(Field::U32(_)) => {
+            (DataType::UInt32, Arc::new(UInt32Array::from(values(&fields, |f| if let Field::U32(v) = f { Some(*v) } else { None } ))))
+        },
+        Some(Field::I64(_)) => {
+            (DataType::Int64, Arc::new(Int64Array::from(values(&fields, |f| if let Field::I64(v) = f { Some(*v) } else { None } ))))
+        },
+        Some(Field::F32(_)) => {
+            (DataType::Float32, Arc::new(Float32Array::from(values(&fields, |f| if let Field::F32(v) = f { Some(*v) } else { None } ))))
+        },
+        Some(Field::F64(_)) => {
+            (DataType::Float64, Arc::new(Float64Array::from(values(&fields, |f| if let Field::F64(v) = f { Some(*v) } else { None } ))))
+        },
+        Some(Field::Numeric(_)) => decimal_array(&fields, decimal_scale)?,
+        Some(Field::Bytes(_)) => {
+            let bytes = values(&fields, |f| if let Field::Bytes(v) = f { Some(v.clone()) } else { None } );
+            let refs : Vec<Option<&[u8]>> = bytes.iter().map(|b| b.as_deref() ).collect();
+            (DataType::Binary, Arc::new(BinaryArray::from(refs)))
+        },
+        None => {
+            let dtype = empty_data_type(col, decimal_scale);
+            let arr = new_null_array(&dtype, col.len());
+            (dtype, arr)
+        },
+        Some(Field::Str(_)) | Some(Field::Json(_)) => {
+            let text = values(&fields, |f| match f {
+                Field::Str(s) => Some(s.clone()),
+                Field::Json(v) => Some(v.to_string()),
+                _ => None
+            });
+            (DataType::Utf8, Arc::new(StringArray::from(text)))
+        }
+    };
+    Ok(typed)
+}
+
+/// Converts a table into a single Arrow record batch. Columns are nullable when the table
+/// column is nullable.
+pub fn batch_from_table(tbl : &Table) -> Result<RecordBatch, String> {
+    table_batch(tbl, None)
+}
+
+/// Converts a table into a single Arrow record batch, with all decimal columns at the given scale,
+/// so batches built from different parts of a result share the same types.
+pub fn batch_with_decimal_scale(tbl : &Table, scale : u32) -> Result<RecordBatch, String> {
+    table_batch(tbl, Some(scale))
+}
+
+fn table_batch(tbl : &Table, decimal_scale : Option<u32>) -> Result<RecordBatch, String> {
+    let mut fields = Vec::new();
+    let mut arrays = Vec::new();
+    for (ix, name) in tbl.names().iter().enumerate() {
+        let col = tbl.get_column(ix).ok_or(format!("Invalid column index: {}", ix))?;
+        let (dtype, arr) = column_to_array(col, decimal_scale)?;
+        fields.push(ArrowField::new(name, dtype, matches!(col, Column::Nullable(_))));
+        arrays.push(arr);
+    }
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays).map_err(|e| format!("{}", e) )
+}
+
+fn table_from_batches(schema : &SchemaRef, batches : &[RecordBatch]) -> Result<Table, String> {
+    if batches.len() == 0 {
+        return Err(format!("No rows to import"));
+    }
+    let batch = arrow::compute::concat_batches(schema, batches).map_err(|e| format!("{}", e) )?;
+    table_from_batch(&batch)
+}
+
+impl Table {
+
+    /// Writes the table as an Apache Parquet file.
+    pub fn write_parquet(&self, path : &Path) -> Result<(), String> {
+        let batch = batch_from_table(self)?;
+        let f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
+        let mut writer = ArrowWriter::try_new(f, batch.schema(), None).map_err(|e| format!("{}", e) )?;
+        writer.write(&batch).map_err(|e| format!("Error writing to export file: {}", e) )?;
+        writer.close().map_err(|e| format!("Error writing to export file: {}", e) )?;
+        Ok(())
+    }
+
+    /// Writes the table as an Arrow IPC file.
+    pub fn write_arrow(&self, path : &Path) -> Result<(), String> {
+        let batch = batch_from_table(self)?;
+        let f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
+        let mut writer = FileWriter::try_new(f, &batch.schema()).map_err(|e| format!("{}", e) )?;
+        writer.write(&batch).map_err(|e| format!("Error writing to export file: {}", e) )?;
+        writer.finish().map_err(|e| format!("Error writing to export file: {}", e) )?;
+        Ok(())
+    }
+
+    pub fn new_from_parquet(path : &Path) -> Result<Self, String> {
+        let f = File::open(path).map_err(|e| format!("Error opening file: {}", e) )?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(f).map_err(|e| format!("Invalid Parquet file: {}", e) )?;
+        let schema = builder.schema().clone();
+        let reader = builder.build().map_err(|e| format!("Invalid Parquet file: {}", e) )?;
+        let batches = reader.collect::<Result<Vec<_>, _>>().map_err(|e| format!("Invalid Parquet file: {}", e) )?;
+        table_from_batches(&schema, &batches[..])
+    }
+
+    pub fn new_from_arrow(path : &Path) -> Result<Self, String> {
+        let f = File::open(path).map_err(|e| format!("Error opening file: {}", e) )?;
+        let reader = FileReader::try_new(f, None).map_err(|e| format!("Invalid Arrow file: {}", e) )?;
+        let schema = reader.schema();
+        let batches = reader.collect::<Result<Vec<_>, _>>().map_err(|e| format!("Invalid Arrow file: {}", e) )?;
+        table_from_batches(&schema, &batches[..])
+    }
+
+}
+
+#[test]
+fn arrow_batches() {
+    let ids = Column::from(vec![1i64, 2, 3]);
+    let prices = Column::from(vec![Decimal::new(150, 2), Decimal::new(2, 0), Decimal::new(3125, 3)]);
+    let names = Column::Nullable(Box::new(NullableColumn::from(vec![Some(String::from("pen")), None, Some(String::from("ink"))])));
+    let tbl = Table::new(None, vec![String::from("id"), String::from("price"), String::from("name")], vec![ids, prices, names]).unwrap();
+    let batch = batch_from_table(&tbl).unwrap();
+    assert_eq!(batch.schema().field(1).data_type(), &DataType::Decimal128(DECIMAL_PRECISION, 3));
+    assert!(!batch.schema().field(0).is_nullable());
+    assert!(batch.schema().field(2).is_nullable());
+    let tbl = table_from_batch(&batch).unwrap();
+    assert!(matches!(tbl.get_column(0), Some(Column::I64(_))));
+    assert!(matches!(tbl.get_column(1).unwrap().at(2, None), Some(Field::Numeric(d)) if d == Decimal::new(3125, 3)));
+    assert!(tbl.get_column(2).unwrap().is_null_at(1));
+    assert_eq!(tbl.text_at(2, 2).unwrap(), "ink");
+}
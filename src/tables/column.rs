@@ -4,10 +4,12 @@ This work is licensed under the terms of the GPL v3.0 License.
 For a copy, see http://www.gnu.org/licenses.*/
 
 use rust_decimal::Decimal;
+use num_traits::cast::ToPrimitive;
 use super::nullable_column::*;
 use super::field::Field;
 use serde_json::Value;
 use std::borrow::Cow;
+use std::cmp::Ordering;
 use serde_json;
 
 /// Densely packed column, where each variant is a vector of some
@@ -104,6 +106,90 @@ impl<'a> Column {
         v.iter().map(|e| e as &'b (dyn ToSql + Sync)).collect()
     }*/
 
+    pub fn is_null_at(&self, ix : usize) -> bool {
+        match self {
+            Column::Nullable(col) => col.is_null_at(ix),
+            _ => false
+        }
+    }
+
+    /// Orders the values at two rows according to the column type. Null values are ordered
+    /// before all other values if nulls_first is true, or after them otherwise.
+    pub fn compare_rows(&self, a : usize, b : usize, nulls_first : bool) -> Ordering {
+        match self {
+            Column::Bool(v) => v[a].cmp(&v[b]),
+            Column::I8(v) => v[a].cmp(&v[b]),
+            Column::I16(v) => v[a].cmp(&v[b]),
+            Column::I32(v) => v[a].cmp(&v[b]),
+            Column::U32(v) => v[a].cmp(&v[b]),
+            Column::I64(v) => v[a].cmp(&v[b]),
+            Column::F32(v) => v[a].partial_cmp(&v[b]).unwrap_or(Ordering::Equal),
+            Column::F64(v) => v[a].partial_cmp(&v[b]).unwrap_or(Ordering::Equal),
+            Column::Numeric(v) => v[a].cmp(&v[b]),
+            Column::Str(v) => compare_text(&v[a], &v[b]),
+            Column::Bytes(v) => v[a].len().cmp(&v[b].len()),
+            Column::Json(v) => json_to_string(&v[a]).cmp(&json_to_string(&v[b])),
+            Column::Nullable(col) => col.compare_rows(a, b, nulls_first)
+        }
+    }
+
+    /// Orders the value at the given row relative to a value informed as text, interpreted
+    /// according to the column type. Returns None for null values or if the text cannot be
+    /// interpreted as a value of the column type.
+    pub fn compare_with(&self, ix : usize, value : &str) -> Option<Ordering> {
+        let value = value.trim();
+        match self {
+            Column::Bool(v) => Some(v[ix].cmp(&value.parse::<bool>().ok()?)),
+            Column::I8(v) => (v[ix] as f64).partial_cmp(&value.parse::<f64>().ok()?),
+            Column::I16(v) => (v[ix] as f64).partial_cmp(&value.parse::<f64>().ok()?),
+            Column::I32(v) => (v[ix] as f64).partial_cmp(&value.parse::<f64>().ok()?),
+            Column::U32(v) => (v[ix] as f64).partial_cmp(&value.parse::<f64>().ok()?),
+            Column::I64(v) => (v[ix] as f64).partial_cmp(&value.parse::<f64>().ok()?),
+            Column::F32(v) => (v[ix] as f64).partial_cmp(&value.parse::<f64>().ok()?),
+            Column::F64(v) => v[ix].partial_cmp(&value.parse::<f64>().ok()?),
+            Column::Numeric(v) => Some(v[ix].cmp(&value.parse::<Decimal>().ok()?)),
+            Column::Str(v) => Some(compare_text(&v[ix], value)),
+            Column::Bytes(_) => None,
+            Column::Json(v) => Some(json_to_string(&v[ix]).as_str().cmp(value)),
+            Column::Nullable(col) => col.compare_with(ix, value)
+        }
+    }
+
+    /// Returns the value at the given row as a real number, if the column is numeric and the value is not null.
+    pub fn real_at(&self, ix : usize) -> Option<f64> {
+        match self {
+            Column::I8(v) => v.get(ix).map(|f| *f as f64 ),
+            Column::I16(v) => v.get(ix).map(|f| *f as f64 ),
+            Column::I32(v) => v.get(ix).map(|f| *f as f64 ),
+            Column::U32(v) => v.get(ix).map(|f| *f as f64 ),
+            Column::I64(v) => v.get(ix).map(|f| *f as f64 ),
+            Column::F32(v) => v.get(ix).map(|f| *f as f64 ),
+            Column::F64(v) => v.get(ix).cloned(),
+            Column::Numeric(v) => v.get(ix).and_then(|f| f.to_f64() ),
+            Column::Nullable(col) => col.real_at(ix),
+            _ => None
+        }
+    }
+
+    /// Builds a new column with the values at the given rows, in the order they are informed.
+    pub fn select_rows(&self, ixs : &[usize]) -> Column {
+        match self {
+            Column::Bool(v) => Column::Bool(ixs.iter().map(|ix| v[*ix] ).collect()),
+            Column::I8(v) => Column::I8(ixs.iter().map(|ix| v[*ix] ).collect()),
+            Column::I16(v) => Column::I16(ixs.iter().map(|ix| v[*ix] ).collect()),
+            Column::I32(v) => Column::I32(ixs.iter().map(|ix| v[*ix] ).collect()),
+            Column::U32(v) => Column::U32(ixs.iter().map(|ix| v[*ix] ).collect()),
+            Column::I64(v) => Column::I64(ixs.iter().map(|ix| v[*ix] ).collect()),
+            Column::F32(v) => Column::F32(ixs.iter().map(|ix| v[*ix] ).collect()),
+            Column::F64(v) => Column::F64(ixs.iter().map(|ix| v[*ix] ).collect()),
+            Column::Numeric(v) => Column::Numeric(ixs.iter().map(|ix| v[*ix].clone() ).collect()),
+            Column::Str(v) => Column::Str(ixs.iter().map(|ix| v[*ix].clone() ).collect()),
+            Column::Bytes(v) => Column::Bytes(ixs.iter().map(|ix| v[*ix].clone() ).collect()),
+            Column::Json(v) => Column::Json(ixs.iter().map(|ix| v[*ix].clone() ).collect()),
+            Column::Nullable(col) => col.select_rows(ixs)
+        }
+    }
+
     /// Returns a string field carrying Field::String(missing.unwrap_or("null")) if value is not present;
     /// or None if ix is outside the column range.
     pub fn at(&self, ix : usize, missing : Option<&str>) -> Option<Field> {
@@ -242,6 +328,23 @@ impl<'a> Column {
         }
     }
 
+    /// PostgreSQL type used to create a table holding the column.
+    pub fn pg_type(&self) -> &'static str {
+        match self {
+            Column::Bool(_) => "boolean",
+            Column::I8(_) | Column::I16(_) => "smallint",
+            Column::I32(_) => "integer",
+            Column::U32(_) | Column::I64(_) => "bigint",
+            Column::F32(_) => "real",
+            Column::F64(_) => "double precision",
+            Column::Numeric(_) => "numeric",
+            Column::Str(_) => "text",
+            Column::Bytes(_) => "bytea",
+            Column::Json(_) => "jsonb",
+            Column::Nullable(col) => col.pg_type()
+        }
+    }
+
     pub fn sqlite3_type(&self) -> String {
         match self {
             Column::I32(_) | Column::I64(_) => String::from("INT"),
@@ -620,4 +723,18 @@ pub mod try_into {
 
 }
 
-
+/* Text columns also hold dates and times (formatted by chrono), which are compared by their values
+when both sides can be parsed. */
+fn compare_text(a : &str, b : &str) -> Ordering {
+    use chrono::{NaiveDateTime, NaiveDate, NaiveTime};
+    if let (Ok(a), Ok(b)) = (NaiveDateTime::parse_from_str(a, "%Y-%m-%d %H:%M:%S%.f"), NaiveDateTime::parse_from_str(b, "%Y-%m-%d %H:%M:%S%.f")) {
+        return a.cmp(&b);
+    }
+    if let (Ok(a), Ok(b)) = (NaiveDate::parse_from_str(a, "%Y-%m-%d"), NaiveDate::parse_from_str(b, "%Y-%m-%d")) {
+        return a.cmp(&b);
+    }
+    if let (Ok(a), Ok(b)) = (NaiveTime::parse_from_str(a, "%H:%M:%S%.f"), NaiveTime::parse_from_str(b, "%H:%M:%S%.f")) {
+        return a.cmp(&b);
+    }
+    a.cmp(b)
+}
@@ -0,0 +1,386 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::Table;
+use super::column::Column;
+use super::dialect::{CsvDialect, CsvRecords, read_csv_records};
+use super::sql_script::quote_target;
+use crate::sql::object::DBType;
+use crate::sql::edit::quote_identifier;
+use chrono::{NaiveDate, NaiveDateTime, DateTime};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Records shown by the import wizard, from which the column types are inferred.
+pub const PREVIEW_ROWS : usize = 100;
+
+// Bytes read from the start of the file to build the preview.
+const PREVIEW_BYTES : u64 = 256 * 1024;
+
+/// Column types offered by the import wizard, as (label, type) pairs.
+pub const IMPORT_TYPES : [(&'static str, DBType); 11] = [
+    ("Text", DBType::Text),
+    ("Smallint", DBType::I16),
+    ("Integer", DBType::I32),
+    ("Bigint", DBType::I64),
+    ("Real", DBType::F32),
+    ("Double precision", DBType::F64),
+    ("Numeric", DBType::Numeric),
+    ("Boolean", DBType::Bool),
+    ("Date", DBType::Date),
+    ("Timestamp", DBType::Timestamp),
+    ("JSON", DBType::Json)
+];
+
+// Numbers with more significant digits than a double holds are inferred as numeric.
+const MAX_DOUBLE_DIGITS : usize = 15;
+
+const TIMESTAMP_FORMATS : [&'static str; 4] = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"];
+
+/// Where a CSV column is imported to. Columns without a target are skipped.
+#[derive(Debug, Clone)]
+pub struct ColumnMapping {
+    pub source : usize,
+    pub target : Option<String>,
+    pub ty : DBType
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportPlan {
+
+    // Possibly schema-qualified table name.
+    pub table : String,
+
+    // Whether the table is created before the rows are inserted.
+    pub create : bool,
+
+    pub columns : Vec<ColumnMapping>
+
+}
+
+/// A CSV record that could not be converted to the types of the mapped columns.
+#[derive(Debug, Clone)]
+pub struct RejectedRow {
+    pub line : usize,
+    pub column : String,
+    pub value : String,
+    pub reason : String
+}
+
+fn parse_bool(s : &str) -> Option<bool> {
+    match &s.to_lowercase()[..] {
+        "true" | "t" | "yes" | "y" => Some(true),
+        "false" | "f" | "no" | "n" => Some(false),
+        _ => None
+    }
+}
+
+fn parse_date(s : &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()
+}
+
+/* Timestamps with an offset are kept in RFC 3339 form, which the server reads as timestamptz. */
+fn parse_timestamp(s : &str) -> Option<String> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Some(dt.to_rfc3339());
+    }
+    TIMESTAMP_FORMATS.iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok() )
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S%.f").to_string() )
+}
+
+fn significant_digits(s : &str) -> usize {
+    s.trim_start_matches(|c : char| c == '-' || c == '+' || c == '0' || c == '.')
+        .chars()
+        .filter(|c| c.is_ascii_digit() )
+        .count()
+}
+
+fn text_literal(s : &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// SQL literal for the CSV field, converted to the given type.
+pub fn field_literal(field : &str, ty : &DBType, dialect : &CsvDialect) -> Result<String, String> {
+    let number = || dialect.normalize_number(field).ok_or(format!("Invalid number (misplaced separators)"));
+    match ty {
+        DBType::Bool => parse_bool(field).map(|b| b.to_string() ).ok_or(format!("Invalid boolean")),
+        DBType::I16 => i16::from_str(&number()?).map(|i| i.to_string() ).map_err(|e| format!("Invalid smallint ({})", e) ),
+        DBType::I32 => i32::from_str(&number()?).map(|i| i.to_string() ).map_err(|e| format!("Invalid integer ({})", e) ),
+        DBType::I64 => i64::from_str(&number()?).map(|i| i.to_string() ).map_err(|e| format!("Invalid bigint ({})", e) ),
+        DBType::F32 | DBType::F64 => {
+            let n = number()?;
+            let f = f64::from_str(&n).map_err(|_| format!("Invalid real number") )?;
+            if f.is_finite() {
+                Ok(n)
+            } else {
+                Ok(text_literal(&f.to_string().replace("inf", "Infinity")))
+            }
+        },
+        DBType::Numeric => {
+            let n = number()?;
+            Decimal::from_str(&n)
+                .or_else(|_| Decimal::from_scientific(&n) )
+                .map(|d| d.to_string() )
+                .map_err(|_| format!("Invalid numeric value") )
+        },
+        DBType::Date => parse_date(field).map(|d| text_literal(&d.to_string()) ).ok_or(format!("Invalid date (expected YYYY-MM-DD)")),
+        DBType::Timestamp => parse_timestamp(field).map(|t| text_literal(&t) ).ok_or(format!("Invalid timestamp (expected YYYY-MM-DD HH:MM:SS)")),
+        DBType::Json => Value::from_str(field).map(|_| text_literal(field) ).map_err(|e| format!("Invalid JSON ({})", e) ),
+        _ => Ok(text_literal(field))
+    }
+}
+
+/// Name of the PostgreSQL type a new table column is created with.
+pub fn pg_type_name(ty : &DBType) -> &'static str {
+    match ty {
+        DBType::Bool => "boolean",
+        DBType::I16 => "smallint",
+        DBType::I32 => "integer",
+        DBType::I64 => "bigint",
+        DBType::F32 => "real",
+        DBType::F64 => "double precision",
+        DBType::Numeric => "numeric",
+        DBType::Date => "date",
+        DBType::Time => "time",
+        DBType::Timestamp => "timestamp",
+        DBType::Json => "jsonb",
+        DBType::Bytes => "bytea",
+        DBType::Xml => "xml",
+        _ => "text"
+    }
+}
+
+/// Most specific type all non-null values of the column can be converted to.
+pub fn infer_type(values : &[Option<&str>], dialect : &CsvDialect) -> DBType {
+    let values : Vec<&str> = values.iter().filter_map(|v| *v ).filter(|v| !v.is_empty() ).collect();
+    if values.len() == 0 {
+        return DBType::Text;
+    }
+    let all = |ty : DBType| values.iter().all(|v| field_literal(v, &ty, dialect).is_ok() );
+    if all(DBType::I32) {
+        DBType::I32
+    } else if all(DBType::I64) {
+        DBType::I64
+    } else if all(DBType::Numeric) {
+        if values.iter().any(|v| significant_digits(&dialect.normalize_number(v).unwrap_or_default()) > MAX_DOUBLE_DIGITS ) {
+            DBType::Numeric
+        } else {
+            DBType::F64
+        }
+    } else if all(DBType::F64) {
+        DBType::F64
+    } else if all(DBType::Bool) {
+        DBType::Bool
+    } else if all(DBType::Date) {
+        DBType::Date
+    } else if all(DBType::Timestamp) {
+        DBType::Timestamp
+    } else if values.iter().all(|v| Value::from_str(v).map(|v| v.is_object() || v.is_array() ).unwrap_or(false) ) {
+        DBType::Json
+    } else {
+        DBType::Text
+    }
+}
+
+/// Dialect the file is read with, if it is a CSV (or TSV) file.
+pub fn csv_file_dialect(path : &Path, dialect : &CsvDialect) -> Option<CsvDialect> {
+    match path.extension().and_then(|ext| ext.to_str() ) {
+        Some("csv") | Some("txt") => Some(dialect.clone()),
+        Some("tsv") => Some(CsvDialect { delimiter : '\t', delimiter_set : true, ..dialect.clone() }),
+        _ => None
+    }
+}
+
+/// Reads the first records of the file, to be shown by the import wizard.
+pub fn preview_csv_file(path : &Path, dialect : &CsvDialect) -> Result<CsvRecords, String> {
+    let f = File::open(path).map_err(|e| format!("Error opening file: {}", e) )?;
+    let mut bytes = Vec::new();
+    f.take(PREVIEW_BYTES).read_to_end(&mut bytes).map_err(|e| format!("Error reading file: {}", e) )?;
+
+    // Discards the last (possibly partial) line when the file was not read up to its end.
+    if bytes.len() as u64 == PREVIEW_BYTES {
+        if let Some(pos) = bytes.iter().rposition(|b| *b == b'\n' ) {
+            bytes.truncate(pos + 1);
+        }
+    }
+    read_csv_records(&bytes, dialect, Some(PREVIEW_ROWS))
+}
+
+impl CsvRecords {
+
+    pub fn column_values(&self, col : usize) -> Vec<Option<&str>> {
+        self.rows.iter().map(|row| row.get(col).and_then(|v| v.as_deref() ) ).collect()
+    }
+
+    pub fn infer_types(&self, dialect : &CsvDialect) -> Vec<DBType> {
+        (0..self.names.len()).map(|col| infer_type(&self.column_values(col)[..], dialect) ).collect()
+    }
+
+}
+
+impl ImportPlan {
+
+    fn mapped(&self) -> impl Iterator<Item=(&ColumnMapping, &String)> {
+        self.columns.iter().filter_map(|col| col.target.as_ref().map(|tgt| (col, tgt) ) )
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.table.trim().is_empty() {
+            return Err(format!("Missing target table name"));
+        }
+        let targets : Vec<&String> = self.mapped().map(|(_, tgt)| tgt ).collect();
+        if targets.len() == 0 {
+            return Err(format!("No columns selected for import"));
+        }
+        for (ix, tgt) in targets.iter().enumerate() {
+            if tgt.trim().is_empty() {
+                return Err(format!("Empty target column name"));
+            }
+            if targets[(ix+1)..].contains(tgt) {
+                return Err(format!("Column {} is the target of more than one imported column", tgt));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn creation_sql(&self) -> String {
+        let cols : Vec<String> = self.mapped()
+            .map(|(col, tgt)| format!("    {} {}", quote_identifier(tgt), pg_type_name(&col.ty)) )
+            .collect();
+        format!("CREATE TABLE {} (\n{}\n);", quote_target(&self.table), cols.join(",\n"))
+    }
+
+    fn insertion_header(&self) -> String {
+        let col_list = self.mapped().map(|(_, tgt)| quote_identifier(tgt) ).collect::<Vec<_>>().join(", ");
+        format!("INSERT INTO {} ({}) VALUES\n", quote_target(&self.table), col_list)
+    }
+
+    /// INSERT statement with a single row of default values, representing the statements generated
+    /// by insert_statements when they are verified against the connection safety policies.
+    pub fn insertion_sql(&self) -> String {
+        let values = self.mapped().map(|_| "DEFAULT" ).collect::<Vec<_>>().join(", ");
+        format!("{}    ({});", self.insertion_header(), values)
+    }
+
+    /// Converts the records to INSERT statements with at most batch_size rows each. Records
+    /// with any field that cannot be converted to the type of its column are left out and reported.
+    pub fn insert_statements(
+        &self,
+        records : &CsvRecords,
+        dialect : &CsvDialect,
+        batch_size : usize
+    ) -> (Vec<String>, usize, Vec<RejectedRow>) {
+        let header = self.insertion_header();
+        let mut stmts = Vec::new();
+        let mut tuples = Vec::new();
+        let mut rejected = Vec::new();
+        let mut accepted = 0;
+        for (row, line) in records.rows.iter().zip(records.lines.iter()) {
+            let mut values = Vec::new();
+            let mut error = None;
+            if row.len() != records.names.len() {
+                error = Some(RejectedRow {
+                    line : *line,
+                    column : String::new(),
+                    value : String::new(),
+                    reason : format!("Record has {} fields (expected {})", row.len(), records.names.len())
+                });
+            } else {
+                for (col, _) in self.mapped() {
+                    match row.get(col.source).and_then(|v| v.as_ref() ) {
+
+                        // Empty fields are only kept as values by text columns.
+                        Some(field) if field.is_empty() && col.ty != DBType::Text => values.push(String::from("NULL")),
+                        Some(field) => match field_literal(field, &col.ty, dialect) {
+                            Ok(lit) => values.push(lit),
+                            Err(reason) => {
+                                error = Some(RejectedRow {
+                                    line : *line,
+                                    column : records.names.get(col.source).cloned().unwrap_or_default(),
+                                    value : field.clone(),
+                                    reason
+                                });
+                                break;
+                            }
+                        },
+                        None => values.push(String::from("NULL"))
+                    }
+                }
+            }
+            match error {
+                Some(err) => rejected.push(err),
+                None => {
+                    tuples.push(format!("    ({})", values.join(", ")));
+                    accepted += 1;
+                }
+            }
+            if tuples.len() == batch_size.max(1) {
+                stmts.push(format!("{}{};", header, tuples.join(",\n")));
+                tuples.clear();
+            }
+        }
+        if tuples.len() > 0 {
+            stmts.push(format!("{}{};", header, tuples.join(",\n")));
+        }
+        (stmts, accepted, rejected)
+    }
+
+}
+
+/// Table listing the rejected records, shown after the import.
+pub fn rejected_table(rows : &[RejectedRow]) -> Result<Table, String> {
+    let names = vec![String::from("line"), String::from("column"), String::from("value"), String::from("reason")];
+    let cols = vec![
+        Column::from(rows.iter().map(|r| r.line as i64 ).collect::<Vec<_>>()),
+        Column::from(rows.iter().map(|r| r.column.clone() ).collect::<Vec<_>>()),
+        Column::from(rows.iter().map(|r| r.value.clone() ).collect::<Vec<_>>()),
+        Column::from(rows.iter().map(|r| r.reason.clone() ).collect::<Vec<_>>())
+    ];
+    Table::new(None, names, cols).map_err(|e| e.to_string() )
+}
+
+#[test]
+fn csv_import() {
+    let dialect = CsvDialect::default();
+    let records = read_csv_records(b"id,price,paid,day,note\n1,2.5,true,2022-01-03,\"{\"\"a\"\": 1}\"\n2,x,f,2022-01-04,\n3,1.25,no,2022-01-05\n", &dialect, None).unwrap();
+    let types = records.infer_types(&dialect);
+    assert_eq!(types[0], DBType::I32);
+    assert_eq!(types[2], DBType::Bool);
+    assert_eq!(types[3], DBType::Date);
+    assert_eq!(types[4], DBType::Json);
+    assert_eq!(infer_type(&[Some("2022-01-03 10:00:00"), None], &dialect), DBType::Timestamp);
+    assert_eq!(infer_type(&[Some("1.5"), Some("3")], &dialect), DBType::F64);
+    assert_eq!(infer_type(&[Some("12345678901234567.5")], &dialect), DBType::Numeric);
+    assert_eq!(infer_type(&[Some("01.02.2020"), Some("10.0.0.1")], &dialect), DBType::Text);
+    let grouped = CsvDialect { decimal_sep : ',', thousands_sep : Some('.'), ..CsvDialect::default() };
+    assert_eq!(infer_type(&[Some("1.234"), Some("12")], &grouped), DBType::I32);
+    assert_eq!(infer_type(&[Some("1.234"), Some("01.02.2020")], &grouped), DBType::Text);
+    assert!(field_literal("10.0.0.1", &DBType::I64, &grouped).is_err());
+
+    let plan = ImportPlan {
+        table : String::from("public.sales"),
+        create : true,
+        columns : vec![
+            ColumnMapping { source : 0, target : Some(String::from("id")), ty : DBType::I32 },
+            ColumnMapping { source : 1, target : Some(String::from("price")), ty : DBType::F64 },
+            ColumnMapping { source : 2, target : None, ty : DBType::Bool }
+        ]
+    };
+    assert!(plan.validate().is_ok());
+    assert_eq!(plan.creation_sql(), "CREATE TABLE \"public\".\"sales\" (\n    \"id\" integer,\n    \"price\" double precision\n);");
+    let (stmts, accepted, rejected) = plan.insert_statements(&records, &dialect, 1000);
+    assert_eq!(accepted, 1);
+    assert_eq!(stmts, vec![String::from("INSERT INTO \"public\".\"sales\" (\"id\", \"price\") VALUES\n    (1, 2.5);")]);
+    assert_eq!(plan.insertion_sql(), "INSERT INTO \"public\".\"sales\" (\"id\", \"price\") VALUES\n    (DEFAULT, DEFAULT);");
+    assert_eq!(rejected.len(), 2);
+    assert_eq!((rejected[0].line, &rejected[0].column[..], &rejected[0].value[..]), (3, "price", "x"));
+    assert_eq!(rejected[1].line, 4);
+    assert_eq!(rejected_table(&rejected[..]).unwrap().nrows(), 2);
+}
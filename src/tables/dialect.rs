@@ -0,0 +1,453 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::Table;
+use super::column::Column;
+use super::nullable_column::NullableColumn;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use tokio_postgres::types::ToSql;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+
+/// Delimiters tried (in order of preference) when the delimiter is detected from the file content.
+pub const DELIMITERS : [char; 4] = [',', ';', '\t', '|'];
+
+/// Encodings offered for CSV files, as (label, description) pairs.
+pub const ENCODINGS : [(&'static str, &'static str); 4] = [
+    ("utf-8", "UTF-8"),
+    ("windows-1252", "Western (Latin-1)"),
+    ("iso-8859-15", "Western (ISO-8859-15)"),
+    ("windows-1250", "Central European")
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Quoting {
+
+    // Only fields holding delimiters, quotes or line breaks.
+    Necessary,
+
+    Always,
+
+    NonNumeric,
+
+    Never
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineTerminator {
+    Lf,
+    CrLf
+}
+
+/// How CSV files are written by exports, and read by imports.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvDialect {
+
+    pub delimiter : char,
+
+    pub quote : char,
+
+    pub quoting : Quoting,
+
+    pub header : bool,
+
+    // Text written for null values. Fields holding it are read as null.
+    pub null_text : String,
+
+    pub terminator : LineTerminator,
+
+    // Label of the text encoding (as accepted by encoding_rs).
+    pub encoding : String,
+
+    pub decimal_sep : char,
+
+    pub thousands_sep : Option<char>,
+
+    // Whether the delimiter and encoding of imported files are detected from their content.
+    pub auto_detect : bool,
+
+    // Whether the delimiter was chosen by the user, in which case it is not detected.
+    pub delimiter_set : bool
+
+}
+
+impl Default for CsvDialect {
+
+    fn default() -> Self {
+        Self {
+            delimiter : ',',
+            quote : '"',
+            quoting : Quoting::Necessary,
+            header : true,
+            null_text : String::new(),
+            terminator : LineTerminator::Lf,
+            encoding : String::from("utf-8"),
+            decimal_sep : '.',
+            thousands_sep : None,
+            auto_detect : true,
+            delimiter_set : false
+        }
+    }
+
+}
+
+impl CsvDialect {
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.delimiter.is_ascii() || self.delimiter == '\n' || self.delimiter == '\r' {
+            return Err(format!("Invalid CSV delimiter: {:?}", self.delimiter));
+        }
+        if !self.quote.is_ascii() || self.quote == self.delimiter {
+            return Err(format!("Invalid CSV quote character: {:?}", self.quote));
+        }
+        if Some(self.decimal_sep) == self.thousands_sep {
+            return Err(format!("Decimal and thousands separators should be different"));
+        }
+        self.text_encoding().map(|_| () )
+    }
+
+    fn text_encoding(&self) -> Result<&'static Encoding, String> {
+        Encoding::for_label(self.encoding.as_bytes()).ok_or(format!("Unknown text encoding: {}", self.encoding))
+    }
+
+    /// Decodes the file content, detecting the encoding from a byte order mark or from whether
+    /// the content is valid UTF-8 when auto-detection is enabled. Other content is read with the
+    /// configured encoding (or Latin-1, if UTF-8 is configured).
+    pub fn decode(&self, bytes : &[u8]) -> Result<String, String> {
+        let configured = self.text_encoding()?;
+        let enc = if self.auto_detect {
+            if let Some((enc, _)) = Encoding::for_bom(bytes) {
+                enc
+            } else if std::str::from_utf8(bytes).is_ok() {
+                UTF_8
+            } else if configured == UTF_8 {
+                WINDOWS_1252
+            } else {
+                configured
+            }
+        } else {
+            configured
+        };
+        let (txt, _, had_errors) = enc.decode(bytes);
+        if had_errors {
+            Err(format!("File content is not valid {} text", enc.name()))
+        } else {
+            Ok(txt.into_owned())
+        }
+    }
+
+    pub fn encode(&self, txt : &str) -> Result<Vec<u8>, String> {
+        let enc = self.text_encoding()?;
+        let (bytes, out_enc, had_errors) = enc.encode(txt);
+        if had_errors || out_enc != enc {
+            Err(format!("Table holds characters that cannot be written as {} text", enc.name()))
+        } else {
+            Ok(bytes.into_owned())
+        }
+    }
+
+    /// Writes a number formatted by the table (with '.' as the decimal separator) with the separators of this dialect.
+    pub fn localize_number(&self, s : &str) -> String {
+        let (sign, digits) = match s.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", s)
+        };
+        let (int_part, frac_part) = match digits.split_once('.') {
+            Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+            None => (digits, None)
+        };
+        if int_part.is_empty() || !int_part.chars().all(|c| c.is_ascii_digit() ) {
+            return s.to_string();
+        }
+        let mut localized = String::from(sign);
+        for (i, c) in int_part.chars().enumerate() {
+            if i > 0 && (int_part.len() - i) % 3 == 0 {
+                if let Some(sep) = self.thousands_sep {
+                    localized.push(sep);
+                }
+            }
+            localized.push(c);
+        }
+        if let Some(frac_part) = frac_part {
+            localized.push(self.decimal_sep);
+            localized += frac_part;
+        }
+        localized
+    }
+
+    /// Inverse of localize_number: removes thousands separators and uses '.' as the decimal separator.
+    /// Returns None when the separators are misplaced: there should be at most one decimal separator,
+    /// and thousands separators are only accepted between groups of three digits before it.
+    pub fn normalize_number(&self, s : &str) -> Option<String> {
+        let (int_part, frac_part) = match s.split_once(self.decimal_sep) {
+            Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+            None => (s, None)
+        };
+        if let Some(frac_part) = frac_part {
+            if frac_part.contains(self.decimal_sep) || self.thousands_sep.map(|sep| frac_part.contains(sep) ).unwrap_or(false) {
+                return None;
+            }
+        }
+        let mut normalized = String::new();
+        match self.thousands_sep {
+            Some(sep) if int_part.contains(sep) => {
+                let digits = int_part.strip_prefix('-').or_else(|| int_part.strip_prefix('+') ).unwrap_or(int_part);
+                let mut groups = digits.split(sep);
+                let first = groups.next().unwrap_or("");
+                let valid_first = first.len() >= 1 && first.len() <= 3 && !first.starts_with('0') &&
+                    first.chars().all(|c| c.is_ascii_digit() );
+                if !valid_first || !groups.all(|g| g.len() == 3 && g.chars().all(|c| c.is_ascii_digit() ) ) {
+                    return None;
+                }
+                normalized += &int_part.replace(sep, "");
+            },
+            _ => normalized += int_part
+        }
+        if let Some(frac_part) = frac_part {
+            normalized.push('.');
+            normalized += frac_part;
+        }
+        Some(normalized)
+    }
+
+}
+
+/// Guesses the delimiter as the candidate found the largest number of times at every line
+/// (outside quoted fields) among the first lines of the content.
+pub fn detect_delimiter(txt : &str, quote : char) -> Option<char> {
+    let lines : Vec<&str> = txt.lines().filter(|l| !l.trim().is_empty() ).take(20).collect();
+    let mut best : Option<(char, usize)> = None;
+    for delim in DELIMITERS.iter() {
+        let min_count = lines.iter().map(|line| {
+            let mut quoted = false;
+            line.chars().filter(|c| {
+                if *c == quote {
+                    quoted = !quoted;
+                }
+                !quoted && c == delim
+            }).count()
+        }).min().unwrap_or(0);
+        if min_count > 0 && best.map(|(_, n)| min_count > n ).unwrap_or(true) {
+            best = Some((*delim, min_count));
+        }
+    }
+    best.map(|(delim, _)| delim )
+}
+
+fn typed_column<T>(values : &[Option<String>], parse : impl Fn(&str) -> Option<T>) -> Option<Column>
+where
+    T : ToSql + Sync + Clone,
+    Column : From<Vec<T>>
+{
+    let mut parsed : Vec<Option<T>> = Vec::with_capacity(values.len());
+    for v in values.iter() {
+        match v {
+            Some(v) => parsed.push(Some(parse(v)?)),
+            None => parsed.push(None)
+        }
+    }
+    if parsed.iter().all(|v| v.is_some() ) {
+        Some(Column::from(parsed.into_iter().map(|v| v.unwrap() ).collect::<Vec<T>>()))
+    } else {
+        Some(Column::Nullable(Box::new(NullableColumn::from(parsed))))
+    }
+}
+
+/* Columns are integer, real or JSON if all their non-null values can be parsed as such, and text otherwise. */
+fn infer_column(values : &[Option<String>], dialect : &CsvDialect) -> Column {
+    if values.iter().all(|v| v.is_none() ) {
+        return typed_column(values, |s| Some(s.to_string()) ).unwrap();
+    }
+    typed_column(values, |s| dialect.normalize_number(s)?.parse::<i64>().ok() )
+        .or_else(|| typed_column(values, |s| dialect.normalize_number(s)?.parse::<f64>().ok() ) )
+        .or_else(|| typed_column(values, |s| s.parse::<Value>().ok().filter(|v| v.is_object() || v.is_array() ) ) )
+        .or_else(|| typed_column(values, |s| Some(s.to_string()) ) )
+        .unwrap()
+}
+
+impl Table {
+
+    /// Writes the table as CSV with the given dialect, encoded as the dialect requires.
+    pub fn to_csv_with(&self, dialect : &CsvDialect) -> Result<Vec<u8>, String> {
+        dialect.validate()?;
+        let quote_style = match dialect.quoting {
+            Quoting::Necessary => csv::QuoteStyle::Necessary,
+            Quoting::Always => csv::QuoteStyle::Always,
+            Quoting::NonNumeric => csv::QuoteStyle::NonNumeric,
+            Quoting::Never => csv::QuoteStyle::Never
+        };
+        let terminator = match dialect.terminator {
+            LineTerminator::Lf => csv::Terminator::Any(b'\n'),
+            LineTerminator::CrLf => csv::Terminator::CRLF
+        };
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(dialect.delimiter as u8)
+            .quote(dialect.quote as u8)
+            .quote_style(quote_style)
+            .terminator(terminator)
+            .from_writer(Vec::new());
+        let err = |e : csv::Error| format!("Error writing CSV: {}", e);
+        if dialect.header {
+            writer.write_record(self.names().iter()).map_err(err)?;
+        }
+        for row in 0..self.nrows() {
+            let mut record = Vec::with_capacity(self.ncols());
+            for ix in 0..self.ncols() {
+                let col = self.get_column(ix).ok_or(format!("Invalid column index: {}", ix))?;
+                if col.is_null_at(row) {
+                    record.push(dialect.null_text.clone());
+                } else {
+                    let text = self.text_at(row, ix).map(|t| t.to_string() ).unwrap_or_default();
+                    if col.real_at(row).is_some() {
+                        record.push(dialect.localize_number(&text));
+                    } else {
+                        record.push(text);
+                    }
+                }
+            }
+            writer.write_record(record.iter()).map_err(err)?;
+        }
+        let content = writer.into_inner().map_err(|e| format!("Error writing CSV: {}", e) )?;
+        dialect.encode(&String::from_utf8(content).map_err(|e| format!("{}", e) )?)
+    }
+
+    /// Reads a table from CSV content with the given dialect. Fields holding the dialect null
+    /// text are read as nulls, and columns are named "Column i" when the file has no header.
+    pub fn new_from_csv(bytes : &[u8], dialect : &CsvDialect) -> Result<Self, String> {
+        let records = read_csv_records(bytes, dialect, None)?;
+        let mut values : Vec<Vec<Option<String>>> = vec![Vec::new(); records.names.len()];
+        for (row, line) in records.rows.iter().zip(records.lines.iter()) {
+            if row.len() != records.names.len() {
+                return Err(format!(
+                    "Error parsing CSV record (Line {}): found record with {} fields, but the previous record has {} fields",
+                    line,
+                    row.len(),
+                    records.names.len()
+                ));
+            }
+            for (col, field) in values.iter_mut().zip(row.iter()) {
+                col.push(field.clone());
+            }
+        }
+        if values.len() == 0 || values[0].len() == 0 {
+            return Err(format!("No records available."));
+        }
+        let cols = values.iter().map(|v| infer_column(&v[..], dialect) ).collect();
+        Table::new(None, records.names, cols).map_err(|e| e.to_string() )
+    }
+
+}
+
+/// Fields of a CSV file as text, with None for fields holding the dialect null text.
+#[derive(Debug, Clone, Default)]
+pub struct CsvRecords {
+
+    pub names : Vec<String>,
+
+    // Records might have a different number of fields than the header.
+    pub rows : Vec<Vec<Option<String>>>,
+
+    // Line of the file each record starts at.
+    pub lines : Vec<usize>
+
+}
+
+/// Reads the header and (at most max_rows) records of the CSV content, without converting the fields.
+/// Columns are named "Column i" when the file has no header.
+pub fn read_csv_records(bytes : &[u8], dialect : &CsvDialect, max_rows : Option<usize>) -> Result<CsvRecords, String> {
+    dialect.validate()?;
+    let txt = dialect.decode(bytes)?;
+    let delimiter = if dialect.auto_detect && !dialect.delimiter_set {
+        detect_delimiter(&txt, dialect.quote).unwrap_or(dialect.delimiter)
+    } else {
+        dialect.delimiter
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .delimiter(delimiter as u8)
+        .quote(dialect.quote as u8)
+        .from_reader(txt.as_bytes());
+    let mut records = reader.records();
+    let mut out = CsvRecords::default();
+    if dialect.header {
+        match records.next() {
+            Some(Ok(header)) => out.names.extend(header.iter().map(|name| name.to_string() )),
+            Some(Err(e)) => return Err(format!("Error parsing CSV header: {}", e)),
+            None => return Err(format!("No CSV header at informed file"))
+        }
+    }
+    for (ix, record) in records.enumerate() {
+        if max_rows.map(|max| out.rows.len() >= max ).unwrap_or(false) {
+            break;
+        }
+        let record = record.map_err(|e| format!("Error parsing CSV record (Line {}): {}", ix + 1, e) )?;
+        if out.names.len() == 0 {
+            out.names.extend((0..record.len()).map(|i| format!("Column {}", i + 1) ));
+        }
+        let line = record.position().map(|pos| pos.line() as usize ).unwrap_or(ix + 1);
+        let row = record.iter()
+            .map(|field| if field == dialect.null_text { None } else { Some(field.to_string()) } )
+            .collect();
+        out.rows.push(row);
+        out.lines.push(line);
+    }
+    Ok(out)
+}
+
+#[test]
+fn csv_dialect() {
+    let dialect = CsvDialect {
+        delimiter : ';',
+        null_text : String::from("NA"),
+        encoding : String::from("windows-1252"),
+        decimal_sep : ',',
+        thousands_sep : Some('.'),
+        terminator : LineTerminator::CrLf,
+        ..CsvDialect::default()
+    };
+    assert_eq!(dialect.localize_number("-1234567.25"), "-1.234.567,25");
+    assert_eq!(dialect.localize_number("123"), "123");
+    assert_eq!(dialect.localize_number("NaN"), "NaN");
+    assert_eq!(dialect.normalize_number("-1.234.567,25"), Some(String::from("-1234567.25")));
+    assert_eq!(dialect.normalize_number("01.02.2020"), None);
+    assert_eq!(dialect.normalize_number("10.0.0.1"), None);
+    assert_eq!(dialect.normalize_number("1,2,3"), None);
+    assert_eq!(CsvDialect::default().normalize_number("10.0.0.1"), None);
+    assert_eq!(CsvDialect::default().normalize_number("2.5"), Some(String::from("2.5")));
+    assert_eq!(detect_delimiter("nome;preço\n\"a;b\";1,5\nc;2\n", '"'), Some(';'));
+    assert_eq!(detect_delimiter("abc\n", '"'), None);
+
+    let content = dialect.encode("nome;preço;qtd\r\ncafé;1.234,5;NA\r\nchá;2;3\r\n").unwrap();
+    assert_eq!(content[8], 0xE7);
+    let tbl = Table::new_from_csv(&content, &dialect).unwrap();
+    assert_eq!(tbl.names(), vec![String::from("nome"), String::from("preço"), String::from("qtd")]);
+    assert_eq!(tbl.get_column(1).unwrap().real_at(0), Some(1234.5));
+    assert!(tbl.get_column(2).unwrap().is_null_at(0));
+    assert_eq!(tbl.text_at(0, 0).unwrap(), "café");
+
+    let mut tbl = tbl;
+    let mut fmt = super::table::TableSettings::default();
+    fmt.prec = None;
+    tbl.update_format(fmt);
+    assert_eq!(tbl.to_csv_with(&dialect).unwrap(), content);
+
+    let detected = CsvDialect { thousands_sep : Some(','), ..CsvDialect::default() };
+    let tbl = Table::new_from_csv(b"ip;day;n\n10.0.0.1;01.02.2020;1,234\n", &detected).unwrap();
+    assert_eq!(tbl.get_column(0).unwrap().real_at(0), None);
+    assert_eq!(tbl.get_column(1).unwrap().real_at(0), None);
+    assert_eq!(tbl.get_column(2).unwrap().real_at(0), Some(1234.0));
+    let explicit = CsvDialect { delimiter_set : true, ..detected.clone() };
+    assert_eq!(read_csv_records(b"a;b,c\n", &explicit, None).unwrap().names, vec![String::from("a;b"), String::from("c")]);
+    let latin = CsvDialect { encoding : String::from("windows-1250"), ..CsvDialect::default() };
+    assert_eq!(latin.decode(&[0xA5]).unwrap(), "Ą");
+
+    let no_header = CsvDialect { header : false, auto_detect : false, ..CsvDialect::default() };
+    let tbl = Table::new_from_csv(b"1,x\n2,y\n", &no_header).unwrap();
+    assert_eq!(tbl.names(), vec![String::from("Column 1"), String::from("Column 2")]);
+}
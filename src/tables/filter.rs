@@ -0,0 +1,201 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::Table;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq
+}
+
+// Two-character operators come first, so that "<=" is not taken as "<".
+const OPERATORS : [(&'static str, CompareOp); 7] = [
+    ("<=", CompareOp::LtEq),
+    (">=", CompareOp::GtEq),
+    ("<>", CompareOp::NotEq),
+    ("!=", CompareOp::NotEq),
+    ("=", CompareOp::Eq),
+    ("<", CompareOp::Lt),
+    (">", CompareOp::Gt)
+];
+
+impl CompareOp {
+
+    fn accepts(&self, ord : Ordering) -> bool {
+        match self {
+            CompareOp::Eq => ord == Ordering::Equal,
+            CompareOp::NotEq => ord != Ordering::Equal,
+            CompareOp::Lt => ord == Ordering::Less,
+            CompareOp::LtEq => ord != Ordering::Greater,
+            CompareOp::Gt => ord == Ordering::Greater,
+            CompareOp::GtEq => ord != Ordering::Less
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+
+    // Column value compared to a value typed by the user.
+    Compare(usize, CompareOp, String),
+
+    // Column value is null (if true) or not null (if false).
+    IsNull(usize, bool),
+
+    // Text of the given column (or of any column) contains the (lowercase) text.
+    Contains(Option<usize>, String)
+
+}
+
+/// Filter over the rows of a table, satisfied when all of its conditions are. Written as
+/// conditions separated by "and", such as "price > 10 and name contains pen and notes is null".
+/// Text without a column name or operator matches rows where any column contains it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowFilter {
+    pub conds : Vec<Condition>
+}
+
+fn unquote(s : &str) -> &str {
+    let s = s.trim();
+    for q in ['\'', '"'] {
+        if s.len() >= 2 && s.starts_with(q) && s.ends_with(q) {
+            return &s[1..s.len()-1];
+        }
+    }
+    s
+}
+
+fn column_index(name : &str, names : &[String]) -> Result<usize, String> {
+    let name = unquote(name);
+    names.iter().position(|n| n == name )
+        .or_else(|| names.iter().position(|n| n.eq_ignore_ascii_case(name) ) )
+        .ok_or_else(|| format!("Unknown column: {}", name) )
+}
+
+/* Splits at the case-insensitive separator, returning pieces of the original text. */
+fn split_keyword<'a>(text : &'a str, sep : &str) -> Vec<&'a str> {
+    let lower = text.to_lowercase();
+
+    // Lowercasing might change byte offsets for non-ASCII text, in which case the text is not split.
+    if lower.len() != text.len() {
+        return vec![text];
+    }
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (pos, _) in lower.match_indices(sep) {
+        if pos >= start {
+            pieces.push(&text[start..pos]);
+            start = pos + sep.len();
+        }
+    }
+    pieces.push(&text[start..]);
+    pieces
+}
+
+fn parse_condition(cond : &str, names : &[String]) -> Result<Condition, String> {
+    let lower = cond.to_lowercase();
+    if lower.len() == cond.len() {
+        if let Some(col) = lower.strip_suffix(" is not null") {
+            return Ok(Condition::IsNull(column_index(&cond[..col.len()], names)?, false));
+        }
+        if let Some(col) = lower.strip_suffix(" is null") {
+            return Ok(Condition::IsNull(column_index(&cond[..col.len()], names)?, true));
+        }
+        if let Some(pos) = lower.find(" contains ") {
+            let col = column_index(&cond[..pos], names)?;
+            let txt = unquote(&cond[(pos + " contains ".len())..]).to_lowercase();
+            return Ok(Condition::Contains(Some(col), txt));
+        }
+    }
+    let op_pos = OPERATORS.iter()
+        .filter_map(|(s, op)| cond.find(s).map(|pos| (pos, *s, *op)) )
+        .min_by_key(|(pos, _, _)| *pos );
+    match op_pos {
+        Some((pos, s, op)) if pos > 0 => {
+            let col = column_index(&cond[..pos], names)?;
+            Ok(Condition::Compare(col, op, unquote(&cond[(pos + s.len())..]).to_string()))
+        },
+        _ => Ok(Condition::Contains(None, unquote(cond).to_lowercase()))
+    }
+}
+
+impl RowFilter {
+
+    /// Parses a filter over a table with the given column names. Returns None for an empty filter.
+    pub fn parse(expr : &str, names : &[String]) -> Result<Option<Self>, String> {
+        if expr.trim().is_empty() {
+            return Ok(None);
+        }
+        let mut conds = Vec::new();
+        for cond in split_keyword(expr, " and ") {
+            if cond.trim().is_empty() {
+                return Err(format!("Empty condition"));
+            }
+            conds.push(parse_condition(cond.trim(), names)?);
+        }
+        Ok(Some(Self { conds }))
+    }
+
+    pub fn matches(&self, tbl : &Table, row : usize) -> bool {
+        self.conds.iter().all(|cond| {
+            match cond {
+                Condition::Compare(col, op, value) => {
+                    tbl.get_column(*col)
+                        .and_then(|c| c.compare_with(row, value) )
+                        .map(|ord| op.accepts(ord) )
+                        .unwrap_or(false)
+                },
+                Condition::IsNull(col, is_null) => {
+                    tbl.get_column(*col).map(|c| c.is_null_at(row) == *is_null ).unwrap_or(false)
+                },
+                Condition::Contains(Some(col), txt) => {
+                    tbl.text_at(row, *col).map(|t| t.to_lowercase().contains(&txt[..]) ).unwrap_or(false)
+                },
+                Condition::Contains(None, txt) => {
+                    (0..tbl.ncols()).any(|col| {
+                        tbl.text_at(row, col).map(|t| t.to_lowercase().contains(&txt[..]) ).unwrap_or(false)
+                    })
+                }
+            }
+        })
+    }
+
+}
+
+#[test]
+fn row_filters() {
+    use super::column::Column;
+    use super::nullable_column::NullableColumn;
+    let names = vec![String::from("id"), String::from("name"), String::from("born")];
+    let born : NullableColumn = vec![Some(String::from("1990-01-05")), None, Some(String::from("2001-12-01"))].into();
+    let tbl = Table::new(None, names.clone(), vec![
+        Column::from(vec![1i32, 10, 11]),
+        Column::from(vec![String::from("Ann"), String::from("Bob"), String::from("Ben")]),
+        Column::Nullable(Box::new(born))
+    ]).unwrap();
+    let rows = |expr : &str| -> Vec<usize> {
+        let filter = RowFilter::parse(expr, &names[..]).unwrap().unwrap();
+        (0..3).filter(|row| filter.matches(&tbl, *row) ).collect()
+    };
+    assert_eq!(rows("id > 9"), vec![1, 2]);
+    assert_eq!(rows("id >= 10 and name contains b"), vec![1, 2]);
+    assert_eq!(rows("born is null"), vec![1]);
+    assert_eq!(rows("born < '2000-01-01'"), vec![0]);
+    assert_eq!(rows("ann"), vec![0]);
+    assert_eq!(RowFilter::parse(" ", &names[..]), Ok(None));
+    assert!(RowFilter::parse("age > 1", &names[..]).is_err());
+
+    let col = tbl.get_column(2).unwrap();
+    assert_eq!(col.compare_rows(0, 1, true), Ordering::Greater);
+    assert_eq!(col.compare_rows(0, 1, false), Ordering::Less);
+    assert_eq!(col.compare_rows(2, 0, false), Ordering::Greater);
+}
@@ -0,0 +1,85 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::Table;
+use super::spreadsheet::escape_xml;
+
+const PAGE_STYLE : &'static str = "
+body { font-family: -apple-system, 'Segoe UI', Cantarell, Roboto, sans-serif; margin: 2em; color: #241f31; }
+h1 { font-size: 1.4em; }
+pre { background: #f6f5f4; border: 1px solid #deddda; border-radius: 6px; padding: 1em; overflow-x: auto; }
+table { border-collapse: collapse; font-size: 0.9em; }
+th, td { border: 1px solid #deddda; padding: 0.3em 0.6em; text-align: left; vertical-align: top; }
+th { background: #f6f5f4; position: sticky; top: 0; }
+tr:nth-child(even) td { background: #fafafa; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+td.null { color: #9a9996; font-style: italic; }
+footer { margin-top: 1em; color: #77767b; font-size: 0.85em; }
+";
+
+impl Table {
+
+    /// HTML table with escaped cell contents. NULL cells and numeric cells are marked by the
+    /// null and num classes.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from("<table>\n<thead>\n<tr>");
+        for name in self.names().iter() {
+            html += &format!("<th>{}</th>", escape_xml(name));
+        }
+        html += "</tr>\n</thead>\n<tbody>\n";
+        for row in 0..self.nrows() {
+            html += "<tr>";
+            for ix in 0..self.ncols() {
+                let col = match self.get_column(ix) {
+                    Some(col) => col,
+                    None => continue
+                };
+                if col.is_null_at(row) {
+                    html += "<td class=\"null\">NULL</td>";
+                } else {
+                    let text = self.text_at(row, ix).unwrap_or_default();
+                    let class = if col.real_at(row).is_some() { " class=\"num\"" } else { "" };
+                    html += &format!("<td{}>{}</td>", class, escape_xml(&text));
+                }
+            }
+            html += "</tr>\n";
+        }
+        html += "</tbody>\n</table>";
+        html
+    }
+
+    /// Self-contained HTML page (with inline styles) showing the query that produced the
+    /// table (if any) followed by the table itself.
+    pub fn to_html_page(&self, title : &str, query : &str) -> String {
+        let mut html = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html += &format!("<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n", escape_xml(title), PAGE_STYLE);
+        html += &format!("<h1>{}</h1>\n", escape_xml(title));
+        if !query.trim().is_empty() {
+            html += &format!("<pre><code>{}</code></pre>\n", escape_xml(query.trim()));
+        }
+        html += &self.to_html();
+        html += "\n";
+        html += &format!(
+            "<footer>{} row(s), exported at {}</footer>\n</body>\n</html>\n",
+            self.nrows(),
+            chrono::Local::now().format("%Y-%m-%d %H:%M")
+        );
+        html
+    }
+
+}
+
+#[test]
+fn html_escaping() {
+    use super::column::Column;
+    use super::nullable_column::NullableColumn;
+    let notes = Column::Nullable(Box::new(NullableColumn::from(vec![Some(String::from("<b>&</b>")), None])));
+    let tbl = Table::new(None, vec![String::from("a<b")], vec![notes]).unwrap();
+    assert_eq!(
+        tbl.to_html(),
+        "<table>\n<thead>\n<tr><th>a&lt;b</th></tr>\n</thead>\n<tbody>\n\
+        <tr><td>&lt;b&gt;&amp;&lt;/b&gt;</td></tr>\n<tr><td class=\"null\">NULL</td></tr>\n</tbody>\n</table>"
+    );
+}
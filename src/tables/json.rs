@@ -0,0 +1,234 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::Table;
+use super::column::Column;
+use super::nullable_column::NullableColumn;
+use super::field::Field;
+use serde::de::{Deserialize, Deserializer, Visitor, MapAccess};
+use serde_json::{Value, Number};
+use num_traits::cast::ToPrimitive;
+use tokio_postgres::types::ToSql;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+/* JSON object with its keys in the order they were written (serde_json::Map sorts them), so the
+columns of imported tables follow the order of the file. */
+struct Record(Vec<(String, Value)>);
+
+struct RecordVisitor;
+
+impl<'de> Visitor<'de> for RecordVisitor {
+
+    type Value = Record;
+
+    fn expecting(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a JSON object")
+    }
+
+    fn visit_map<A>(self, mut map : A) -> Result<Record, A::Error>
+    where
+        A : MapAccess<'de>
+    {
+        let mut fields = Vec::new();
+        while let Some((key, value)) = map.next_entry::<String, Value>()? {
+            fields.push((key, value));
+        }
+        Ok(Record(fields))
+    }
+
+}
+
+impl<'de> Deserialize<'de> for Record {
+
+    fn deserialize<D>(deserializer : D) -> Result<Self, D::Error>
+    where
+        D : Deserializer<'de>
+    {
+        deserializer.deserialize_map(RecordVisitor)
+    }
+
+}
+
+/// JSON value of a field. JSON columns are kept as nested values, and binary values are
+/// written as base64 strings.
+pub fn field_to_json(field : Field) -> Value {
+    match field {
+        Field::Bool(b) => Value::Bool(b),
+        Field::I8(i) => Value::from(i),
+        Field::I16(i) => Value::from(i),
+        Field::I32(i) => Value::from(i),
+        Field::U32(u) => Value::from(u),
+        Field::I64(i) => Value::from(i),
+        Field::F32(f) => Number::from_f64(f as f64).map(Value::Number).unwrap_or(Value::Null),
+        Field::F64(f) => Number::from_f64(f).map(Value::Number).unwrap_or(Value::Null),
+        Field::Numeric(d) => {
+            d.to_f64()
+                .and_then(Number::from_f64)
+                .map(Value::Number)
+                .unwrap_or_else(|| Value::String(d.to_string()) )
+        },
+        Field::Str(s) => Value::String(s),
+        Field::Json(v) => v,
+        Field::Bytes(b) => Value::String(base64::encode(&b))
+    }
+}
+
+/// JSON object holding the values of the given row, keyed by the column names.
+pub fn row_object(tbl : &Table, names : &[String], row : usize) -> String {
+    let mut obj = String::from("{");
+    for (ix, name) in names.iter().enumerate() {
+        if ix >= 1 {
+            obj += ",";
+        }
+        obj += &Value::String(name.clone()).to_string();
+        obj += ":";
+        let value = match tbl.get_column(ix) {
+            Some(col) if !col.is_null_at(row) => col.at(row, None).map(field_to_json).unwrap_or(Value::Null),
+            _ => Value::Null
+        };
+        obj += &value.to_string();
+    }
+    obj += "}";
+    obj
+}
+
+fn typed_column<T, F>(values : &[Value], f : F) -> Column
+where
+    T : ToSql + Sync + Clone,
+    Column : From<Vec<T>>,
+    F : Fn(&Value) -> Option<T>
+{
+    if values.iter().any(|v| v.is_null() ) {
+        let opt_values : Vec<Option<T>> = values.iter().map(|v| if v.is_null() { None } else { f(v) } ).collect();
+        Column::Nullable(Box::new(NullableColumn::from(opt_values)))
+    } else {
+        Column::from(values.iter().filter_map(|v| f(v) ).collect::<Vec<T>>())
+    }
+}
+
+/* Columns are boolean, integer, real or text when all their non-null values are of that type, and JSON otherwise. */
+fn infer_column(values : &[Value]) -> Column {
+    let valid : Vec<&Value> = values.iter().filter(|v| !v.is_null() ).collect();
+    if valid.len() > 0 && valid.iter().all(|v| v.is_boolean() ) {
+        typed_column(values, |v| v.as_bool() )
+    } else if valid.len() > 0 && valid.iter().all(|v| v.is_i64() ) {
+        typed_column(values, |v| v.as_i64() )
+    } else if valid.len() > 0 && valid.iter().all(|v| v.is_number() ) {
+        typed_column(values, |v| v.as_f64() )
+    } else if valid.iter().all(|v| v.is_string() ) {
+        typed_column(values, |v| v.as_str().map(|s| s.to_string() ) )
+    } else {
+        typed_column(values, |v| Some(v.clone()) )
+    }
+}
+
+/* Keys missing from an object are taken as null values at its row. */
+fn table_from_records(records : Vec<Record>) -> Result<Table, String> {
+    if records.len() == 0 {
+        return Err(format!("No rows to import"));
+    }
+    let mut names : Vec<String> = Vec::new();
+    let mut col_ixs : HashMap<String, usize> = HashMap::new();
+    let mut values : Vec<Vec<Value>> = Vec::new();
+    for (row, record) in records.into_iter().enumerate() {
+        for (key, value) in record.0 {
+            let ix = match col_ixs.get(&key) {
+                Some(ix) => *ix,
+                None => {
+                    col_ixs.insert(key.clone(), names.len());
+                    names.push(key);
+                    values.push(vec![Value::Null; row]);
+                    names.len() - 1
+                }
+            };
+
+            // Repeated keys keep the last value.
+            if values[ix].len() > row {
+                values[ix][row] = value;
+            } else {
+                values[ix].push(value);
+            }
+        }
+        for col in values.iter_mut() {
+            col.resize(row + 1, Value::Null);
+        }
+    }
+    if names.len() == 0 {
+        return Err(format!("No columns to import"));
+    }
+    let cols = values.iter().map(|col| infer_column(&col[..]) ).collect();
+    Table::new(None, names, cols).map_err(|e| e.to_string() )
+}
+
+impl Table {
+
+    /// Array holding one object per row, with keys in the column order.
+    pub fn to_json(&self) -> String {
+        let names = self.names();
+        let rows : Vec<String> = (0..self.nrows()).map(|row| format!("  {}", row_object(self, &names[..], row)) ).collect();
+        if rows.len() == 0 {
+            String::from("[]\n")
+        } else {
+            format!("[\n{}\n]\n", rows.join(",\n"))
+        }
+    }
+
+    /// Newline-delimited JSON, with one object per row.
+    pub fn to_ndjson(&self) -> String {
+        let mut content = Vec::new();
+        self.write_ndjson(&mut content).unwrap();
+        String::from_utf8(content).unwrap()
+    }
+
+    /// Writes each row as soon as it is formatted, so large tables are not held in memory as text.
+    pub fn write_ndjson(&self, w : &mut impl Write) -> io::Result<()> {
+        let names = self.names();
+        for row in 0..self.nrows() {
+            writeln!(w, "{}", row_object(self, &names[..], row))?;
+        }
+        w.flush()
+    }
+
+    /// Reads a table from an array of objects, with one column per key.
+    pub fn new_from_json(txt : &str) -> Result<Self, String> {
+        let records : Vec<Record> = serde_json::from_str(txt).map_err(|e| format!("Invalid JSON: {}", e) )?;
+        table_from_records(records)
+    }
+
+    /// Reads a table from newline-delimited JSON, with one object per line.
+    pub fn new_from_ndjson(txt : &str) -> Result<Self, String> {
+        let mut records = Vec::new();
+        for (ix, line) in txt.lines().enumerate() {
+            if !line.trim().is_empty() {
+                let record = serde_json::from_str::<Record>(line)
+                    .map_err(|e| format!("Invalid JSON at line {}: {}", ix + 1, e) )?;
+                records.push(record);
+            }
+        }
+        table_from_records(records)
+    }
+
+}
+
+#[test]
+fn json_tables() {
+    let ndjson = "{\"id\":1,\"name\":\"pen\",\"price\":1.5,\"tags\":[\"a\"]}\n\n{\"id\":2,\"price\":2,\"name\":null,\"tags\":{\"b\":1}}\n";
+    let tbl = Table::new_from_ndjson(ndjson).unwrap();
+    assert_eq!(tbl.names(), vec![String::from("id"), String::from("name"), String::from("price"), String::from("tags")]);
+    assert!(matches!(tbl.get_column(0), Some(Column::I64(_))));
+    assert!(tbl.get_column(1).unwrap().is_null_at(1));
+    assert!(matches!(tbl.get_column(2), Some(Column::F64(_))));
+    assert!(matches!(tbl.get_column(3), Some(Column::Json(_))));
+    assert_eq!(
+        tbl.to_ndjson(),
+        "{\"id\":1,\"name\":\"pen\",\"price\":1.5,\"tags\":[\"a\"]}\n{\"id\":2,\"name\":null,\"price\":2.0,\"tags\":{\"b\":1}}\n"
+    );
+    let tbl = Table::new_from_json(&tbl.to_json()).unwrap();
+    assert_eq!(tbl.nrows(), 2);
+    assert!(Table::new_from_json("[]").is_err());
+    assert!(Table::new_from_ndjson("{\"id\":1}\n[1]").is_err());
+}
@@ -13,5 +13,24 @@ pub mod field;
 
 pub mod nullable_column;
 
+pub mod filter;
+
 pub mod report;
 
+pub mod json;
+
+pub mod spreadsheet;
+
+pub mod arrow;
+
+pub mod sql_script;
+
+pub mod html;
+
+pub mod dialect;
+
+pub mod stream;
+
+pub mod csv_import;
+
+pub mod tex;
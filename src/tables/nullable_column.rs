@@ -8,6 +8,7 @@ use tokio_postgres::types::{ToSql   };
 use std::marker::Sync;
 use std::convert::{TryFrom, TryInto};
 use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use crate::tables::field::Field;
 
@@ -64,6 +65,50 @@ impl<'a> NullableColumn {
         }
     }
 
+    pub fn is_null_at(&self, row_ix : usize) -> bool {
+        !self.valid_ixs.contains_key(&row_ix)
+    }
+
+    pub fn compare_rows(&self, a : usize, b : usize, nulls_first : bool) -> Ordering {
+        match (self.valid_ixs.get(&a), self.valid_ixs.get(&b)) {
+            (Some(a), Some(b)) => self.col.compare_rows(*a, *b, nulls_first),
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => if nulls_first { Ordering::Less } else { Ordering::Greater },
+            (Some(_), None) => if nulls_first { Ordering::Greater } else { Ordering::Less }
+        }
+    }
+
+    pub fn compare_with(&self, row_ix : usize, value : &str) -> Option<Ordering> {
+        self.col.compare_with(*self.valid_ixs.get(&row_ix)?, value)
+    }
+
+    pub fn pg_type(&self) -> &'static str {
+        self.col.pg_type()
+    }
+
+    /// Column holding the non-null values, which keeps the column type even when all values are null.
+    pub fn dense(&self) -> &Column {
+        &self.col
+    }
+
+    pub fn real_at(&self, row_ix : usize) -> Option<f64> {
+        self.col.real_at(*self.valid_ixs.get(&row_ix)?)
+    }
+
+    /// Builds a column with the values at the given rows, which is nullable only if
+    /// any of the selected values is null.
+    pub fn select_rows(&self, ixs : &[usize]) -> Column {
+        let mut dense_ixs = Vec::new();
+        let mut valid_ixs = HashMap::new();
+        for (new_ix, ix) in ixs.iter().enumerate() {
+            if let Some(dense_ix) = self.valid_ixs.get(ix) {
+                valid_ixs.insert(new_ix, dense_ixs.len());
+                dense_ixs.push(*dense_ix);
+            }
+        }
+        Self { col : self.col.select_rows(&dense_ixs[..]), valid_ixs, n : ixs.len() }.to_column()
+    }
+
     pub fn display_content(&self, prec : Option<usize>) -> Vec<String> {
         let mut content = Vec::new();
         for ix in 0..self.n {
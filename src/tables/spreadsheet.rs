@@ -0,0 +1,361 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::Table;
+use super::column::Column;
+use super::field::Field;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use zip::ZipWriter;
+use zip::write::FileOptions;
+use zip::CompressionMethod;
+
+/* Spreadsheet export (Office Open XML and OpenDocument), with one sheet per table. Both formats are
+zip archives of XML parts. Cells are written with their types, so numbers, dates and booleans are not
+taken as text by the spreadsheet application. */
+
+#[derive(Debug, Clone, PartialEq)]
+enum SheetCell {
+
+    Empty,
+
+    Bool(bool),
+
+    Number(f64),
+
+    // Date and whether it carries a time of the day.
+    Date(NaiveDateTime, bool),
+
+    Text(String)
+
+}
+
+const ODS_MIMETYPE : &'static str = "application/vnd.oasis.opendocument.spreadsheet";
+
+fn parse_date(s : &str) -> Option<SheetCell> {
+    for fmt in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(s, fmt) {
+            return Some(SheetCell::Date(dt, true));
+        }
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(|d| SheetCell::Date(d.and_hms(0, 0, 0), false) )
+}
+
+fn sheet_cell(tbl : &Table, col : &Column, row : usize, col_ix : usize) -> SheetCell {
+    if col.is_null_at(row) {
+        return SheetCell::Empty;
+    }
+    if let Some(Field::Bool(b)) = col.at(row, None) {
+        return SheetCell::Bool(b);
+    }
+    if let Some(v) = col.real_at(row) {
+        return if v.is_finite() { SheetCell::Number(v) } else { SheetCell::Empty };
+    }
+    let text = tbl.text_at(row, col_ix).map(|t| t.to_string() ).unwrap_or_default();
+    parse_date(&text).unwrap_or(SheetCell::Text(text))
+}
+
+fn sheet_rows(tbl : &Table) -> Vec<Vec<SheetCell>> {
+    (0..tbl.nrows()).map(|row| {
+        (0..tbl.ncols())
+            .map(|ix| tbl.get_column(ix).map(|col| sheet_cell(tbl, col, row, ix) ).unwrap_or(SheetCell::Empty) )
+            .collect()
+    }).collect()
+}
+
+/// Escapes text for XML content and attributes, removing control characters XML does not accept.
+pub fn escape_xml(s : &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped += "&amp;",
+            '<' => escaped += "&lt;",
+            '>' => escaped += "&gt;",
+            '"' => escaped += "&quot;",
+            '\'' => escaped += "&apos;",
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => { },
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// Spreadsheet column name (A, B, ..., Z, AA, AB, ...) for a zero-based index.
+fn column_letters(mut ix : usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push((b'A' + (ix % 26) as u8) as char);
+        if ix < 26 {
+            break;
+        }
+        ix = ix / 26 - 1;
+    }
+    letters.iter().rev().collect()
+}
+
+/* Days since 1899-12-30, with the time as a fraction of the day. */
+fn excel_serial(dt : &NaiveDateTime) -> f64 {
+    let epoch = NaiveDate::from_ymd(1899, 12, 30).and_hms(0, 0, 0);
+    let elapsed = *dt - epoch;
+    elapsed.num_seconds() as f64 / 86400.0
+}
+
+/// Sheet names, taken from the table name (or relation), without the characters spreadsheet
+/// applications reject, up to 31 characters, and unique within the workbook.
+pub fn sheet_names(tables : &[Table]) -> Vec<String> {
+    let mut names : Vec<String> = Vec::new();
+    for (ix, tbl) in tables.iter().enumerate() {
+        let source = tbl.source();
+        let base : String = source.name.or(source.relation)
+            .unwrap_or_else(|| format!("Result {}", ix + 1) )
+            .chars()
+            .map(|c| if "[]:*?/\\".contains(c) { '_' } else { c } )
+            .take(31)
+            .collect();
+        let base = if base.trim().is_empty() { format!("Result {}", ix + 1) } else { base };
+        let mut name = base.clone();
+        let mut n = 2;
+        while names.iter().any(|other| other.eq_ignore_ascii_case(&name) ) {
+            let suffix = format!(" ({})", n);
+            name = base.chars().take(31 - suffix.len()).collect::<String>() + &suffix;
+            n += 1;
+        }
+        names.push(name);
+    }
+    names
+}
+
+fn write_zip(path : &Path, entries : &[(&str, String)]) -> Result<(), String> {
+    let f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
+    let mut zip = ZipWriter::new(f);
+    for (name, content) in entries.iter() {
+
+        // The ODS mimetype entry must be stored uncompressed.
+        let method = if *name == "mimetype" { CompressionMethod::Stored } else { CompressionMethod::Deflated };
+        zip.start_file(*name, FileOptions::default().compression_method(method))
+            .map_err(|e| format!("Error writing to export file: {}", e) )?;
+        zip.write_all(content.as_bytes()).map_err(|e| format!("Error writing to export file: {}", e) )?;
+    }
+    zip.finish().map_err(|e| format!("Error writing to export file: {}", e) )?;
+    Ok(())
+}
+
+fn xlsx_sheet(tbl : &Table) -> String {
+    let mut xml = String::from(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+    xml += r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">"#;
+    xml += r#"<sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/></sheetView></sheetViews>"#;
+    xml += "<sheetData>";
+
+    // Style 1 is the bold header; styles 2 and 3 are dates without and with time.
+    xml += r#"<row r="1">"#;
+    for (j, name) in tbl.names().iter().enumerate() {
+        xml += &format!(r#"<c r="{}1" t="inlineStr" s="1"><is><t>{}</t></is></c>"#, column_letters(j), escape_xml(name));
+    }
+    xml += "</row>";
+    for (i, row) in sheet_rows(tbl).iter().enumerate() {
+        xml += &format!(r#"<row r="{}">"#, i + 2);
+        for (j, cell) in row.iter().enumerate() {
+            let r = format!("{}{}", column_letters(j), i + 2);
+            match cell {
+                SheetCell::Empty => { },
+                SheetCell::Bool(b) => xml += &format!(r#"<c r="{}" t="b"><v>{}</v></c>"#, r, *b as u8),
+                SheetCell::Number(v) => xml += &format!(r#"<c r="{}"><v>{}</v></c>"#, r, v),
+                SheetCell::Date(dt, has_time) => {
+                    let style = if *has_time { 3 } else { 2 };
+                    xml += &format!(r#"<c r="{}" s="{}"><v>{}</v></c>"#, r, style, excel_serial(dt));
+                },
+                SheetCell::Text(s) => {
+                    xml += &format!(r#"<c r="{}" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#, r, escape_xml(s));
+                }
+            }
+        }
+        xml += "</row>";
+    }
+    xml += "</sheetData></worksheet>";
+    xml
+}
+
+const XLSX_STYLES : &'static str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+<numFmts count="1"><numFmt numFmtId="164" formatCode="yyyy\-mm\-dd\ hh:mm:ss"/></numFmts>
+<fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts>
+<fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills>
+<borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders>
+<cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs>
+<cellXfs count="4">
+<xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/>
+<xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/>
+<xf numFmtId="14" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/>
+<xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/>
+</cellXfs>
+<cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles>
+</styleSheet>"#;
+
+/// Writes the tables as the sheets of an Office Open XML (.xlsx) workbook.
+pub fn write_xlsx(tables : &[Table], path : &Path) -> Result<(), String> {
+    if tables.len() == 0 {
+        return Err(format!("No tables to export"));
+    }
+    let names = sheet_names(tables);
+    let mut content_types = String::from(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+<Default Extension="xml" ContentType="application/xml"/>
+<Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>
+<Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/>
+"#);
+    let mut workbook = String::from(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>"#);
+    let mut workbook_rels = String::from(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#);
+    let mut sheets = Vec::new();
+    for (ix, (tbl, name)) in tables.iter().zip(names.iter()).enumerate() {
+        let n = ix + 1;
+        content_types += &format!(
+            "<Override PartName=\"/xl/worksheets/sheet{}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>\n",
+            n
+        );
+        workbook += &format!(r#"<sheet name="{}" sheetId="{}" r:id="rId{}"/>"#, escape_xml(name), n, n);
+        workbook_rels += &format!(
+            r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{}.xml"/>"#,
+            n,
+            n
+        );
+        sheets.push((format!("xl/worksheets/sheet{}.xml", n), xlsx_sheet(tbl)));
+    }
+    content_types += "</Types>";
+    workbook += "</sheets></workbook>";
+    workbook_rels += &format!(
+        r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>"#,
+        tables.len() + 1
+    );
+    workbook_rels += "</Relationships>";
+    let root_rels = String::from(r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#);
+
+    let mut entries : Vec<(&str, String)> = vec![
+        ("[Content_Types].xml", content_types),
+        ("_rels/.rels", root_rels),
+        ("xl/workbook.xml", workbook),
+        ("xl/_rels/workbook.xml.rels", workbook_rels),
+        ("xl/styles.xml", String::from(XLSX_STYLES))
+    ];
+    entries.extend(sheets.iter().map(|(name, content)| (&name[..], content.clone()) ));
+    write_zip(path, &entries[..])
+}
+
+fn ods_table(tbl : &Table, name : &str) -> String {
+    let mut xml = format!(r#"<table:table table:name="{}">"#, escape_xml(name));
+    xml += &format!(r#"<table:table-column table:number-columns-repeated="{}"/>"#, tbl.ncols().max(1));
+    xml += "<table:table-header-rows><table:table-row>";
+    for name in tbl.names().iter() {
+        xml += &format!(
+            r#"<table:table-cell table:style-name="ceHeader" office:value-type="string"><text:p>{}</text:p></table:table-cell>"#,
+            escape_xml(name)
+        );
+    }
+    xml += "</table:table-row></table:table-header-rows>";
+    for row in sheet_rows(tbl).iter() {
+        xml += "<table:table-row>";
+        for cell in row.iter() {
+            match cell {
+                SheetCell::Empty => xml += "<table:table-cell/>",
+                SheetCell::Bool(b) => {
+                    xml += &format!(
+                        r#"<table:table-cell office:value-type="boolean" office:boolean-value="{}"><text:p>{}</text:p></table:table-cell>"#,
+                        b,
+                        if *b { "TRUE" } else { "FALSE" }
+                    );
+                },
+                SheetCell::Number(v) => {
+                    xml += &format!(
+                        r#"<table:table-cell office:value-type="float" office:value="{}"><text:p>{}</text:p></table:table-cell>"#,
+                        v,
+                        v
+                    );
+                },
+                SheetCell::Date(dt, has_time) => {
+                    let (style, value, shown) = if *has_time {
+                        ("ceDateTime", dt.format("%Y-%m-%dT%H:%M:%S%.f").to_string(), dt.format("%Y-%m-%d %H:%M:%S").to_string())
+                    } else {
+                        ("ceDate", dt.format("%Y-%m-%d").to_string(), dt.format("%Y-%m-%d").to_string())
+                    };
+                    xml += &format!(
+                        r#"<table:table-cell table:style-name="{}" office:value-type="date" office:date-value="{}"><text:p>{}</text:p></table:table-cell>"#,
+                        style,
+                        value,
+                        shown
+                    );
+                },
+                SheetCell::Text(s) => {
+                    xml += &format!(
+                        r#"<table:table-cell office:value-type="string"><text:p>{}</text:p></table:table-cell>"#,
+                        escape_xml(s)
+                    );
+                }
+            }
+        }
+        xml += "</table:table-row>";
+    }
+    xml += "</table:table>";
+    xml
+}
+
+const ODS_STYLES : &'static str = r#"<office:automatic-styles>
+<number:date-style style:name="NDate"><number:year number:style="long"/><number:text>-</number:text><number:month number:style="long"/><number:text>-</number:text><number:day number:style="long"/></number:date-style>
+<number:date-style style:name="NDateTime"><number:year number:style="long"/><number:text>-</number:text><number:month number:style="long"/><number:text>-</number:text><number:day number:style="long"/><number:text> </number:text><number:hours number:style="long"/><number:text>:</number:text><number:minutes number:style="long"/><number:text>:</number:text><number:seconds number:style="long"/></number:date-style>
+<style:style style:name="ceHeader" style:family="table-cell"><style:text-properties fo:font-weight="bold" style:font-weight-asian="bold" style:font-weight-complex="bold"/></style:style>
+<style:style style:name="ceDate" style:family="table-cell" style:data-style-name="NDate"/>
+<style:style style:name="ceDateTime" style:family="table-cell" style:data-style-name="NDateTime"/>
+</office:automatic-styles>"#;
+
+/// Writes the tables as the sheets of an OpenDocument spreadsheet (.ods).
+pub fn write_ods(tables : &[Table], path : &Path) -> Result<(), String> {
+    if tables.len() == 0 {
+        return Err(format!("No tables to export"));
+    }
+    let names = sheet_names(tables);
+    let mut content = String::from(r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:number="urn:oasis:names:tc:opendocument:xmlns:datastyle:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" office:version="1.2">
+"#);
+    content += ODS_STYLES;
+    content += "<office:body><office:spreadsheet>";
+    for (tbl, name) in tables.iter().zip(names.iter()) {
+        content += &ods_table(tbl, name);
+    }
+    content += "</office:spreadsheet></office:body></office:document-content>";
+    let manifest = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2">
+<manifest:file-entry manifest:full-path="/" manifest:version="1.2" manifest:media-type="{}"/>
+<manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>"#, ODS_MIMETYPE);
+    write_zip(path, &[
+        ("mimetype", String::from(ODS_MIMETYPE)),
+        ("META-INF/manifest.xml", manifest),
+        ("content.xml", content)
+    ])
+}
+
+#[test]
+fn spreadsheet_cells() {
+    assert_eq!(column_letters(0), "A");
+    assert_eq!(column_letters(25), "Z");
+    assert_eq!(column_letters(26), "AA");
+    assert_eq!(column_letters(27 * 26), "AAA");
+    let dt = NaiveDate::from_ymd(2000, 1, 1).and_hms(12, 0, 0);
+    assert_eq!(excel_serial(&dt), 36526.5);
+    assert_eq!(parse_date("2000-01-01"), Some(SheetCell::Date(NaiveDate::from_ymd(2000, 1, 1).and_hms(0, 0, 0), false)));
+    assert_eq!(parse_date("2000-01-01 12:00:00"), Some(SheetCell::Date(dt, true)));
+    assert_eq!(parse_date("pen"), None);
+    assert_eq!(escape_xml("a<b & \"c\"\u{1}"), "a&lt;b &amp; &quot;c&quot;");
+
+    let mut tbls : Vec<Table> = (0..3).map(|_| Table::new(None, vec![String::from("a")], vec![Column::from(vec![1i64])]).unwrap() ).collect();
+    tbls[0].set_name(Some(String::from("sales/2022")));
+    tbls[1].set_name(Some(String::from("Sales_2022")));
+    assert_eq!(sheet_names(&tbls[..]), vec![String::from("sales_2022"), String::from("Sales_2022 (2)"), String::from("Result 3")]);
+}
@@ -0,0 +1,203 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::Table;
+use super::column::Column;
+use super::field::Field;
+use crate::sql::edit::quote_identifier;
+
+/* PostgreSQL spells non-finite floats as quoted literals. */
+fn float_literal(f : f64, repr : String) -> String {
+    if f.is_nan() {
+        String::from("'NaN'")
+    } else if f.is_infinite() {
+        if f > 0.0 { String::from("'Infinity'") } else { String::from("'-Infinity'") }
+    } else {
+        repr
+    }
+}
+
+fn text_literal(s : &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// SQL literal for the value at the given row, built from the typed column value.
+pub fn sql_literal(col : &Column, row : usize) -> String {
+    if col.is_null_at(row) {
+        return String::from("NULL");
+    }
+    match col.at(row, None) {
+        Some(Field::Bool(b)) => b.to_string(),
+        Some(Field::I8(i)) => i.to_string(),
+        Some(Field::I16(i)) => i.to_string(),
+        Some(Field::I32(i)) => i.to_string(),
+        Some(Field::U32(u)) => u.to_string(),
+        Some(Field::I64(i)) => i.to_string(),
+        Some(Field::F32(f)) => float_literal(f as f64, f.to_string()),
+        Some(Field::F64(f)) => float_literal(f, f.to_string()),
+        Some(Field::Numeric(d)) => d.to_string(),
+        Some(Field::Str(s)) => text_literal(&s),
+        Some(Field::Json(v)) => text_literal(&v.to_string()),
+        Some(Field::Bytes(b)) => {
+            let hex : String = b.iter().map(|byte| format!("{:02x}", byte) ).collect();
+            format!("'\\x{}'", hex)
+        },
+        None => String::from("NULL")
+    }
+}
+
+/// Quotes each part of a (possibly schema-qualified) table name, unless it is already quoted.
+/// Dots within quoted parts do not separate the name.
+pub fn quote_target(target : &str) -> String {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut in_quotes = false;
+    for c in target.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                part.push(c);
+            },
+            '.' if !in_quotes => {
+                parts.push(part.clone());
+                part.clear();
+            },
+            c => part.push(c)
+        }
+    }
+    parts.push(part);
+    parts.iter()
+        .map(|part| {
+            let part = part.trim();
+            if part.starts_with('"') && part.ends_with('"') && part.len() >= 2 {
+                part.to_string()
+            } else {
+                quote_identifier(part)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+impl Table {
+
+    /// CREATE TABLE statement (with PostgreSQL column types) for the table columns. The name
+    /// is used verbatim. Returns None if the table has no columns.
+    pub fn sql_table_creation(&self, name : &str) -> Option<String> {
+        let names = self.names();
+        if names.len() == 0 {
+            return None;
+        }
+        let col_defs : Vec<String> = names.iter()
+            .enumerate()
+            .filter_map(|(ix, col_name)| {
+                self.get_column(ix).map(|col| format!("    {} {}", quote_identifier(col_name), col.pg_type()) )
+            })
+            .collect();
+        Some(format!("CREATE TABLE {} (\n{}\n);\n", name, col_defs.join(",\n")))
+    }
+
+    /// INSERT statement for the given columns (or all columns, if none are informed), built from
+    /// the typed column values. The table and column names are used verbatim, so that they
+    /// are resolved as any unquoted identifier (e.g. CSV headers matched against the columns of
+    /// the import target). The statement is empty when the table has no rows.
+    pub fn sql_table_insertion(&self, name : &str, cols : &[String]) -> Result<String, String> {
+        self.sql_rows_insertion(name, cols, 0, self.nrows(), false)
+    }
+
+    fn sql_rows_insertion(
+        &self,
+        name : &str,
+        cols : &[String],
+        start : usize,
+        end : usize,
+        quote_cols : bool
+    ) -> Result<String, String> {
+        for (i, col) in cols.iter().enumerate() {
+            if cols[(i+1)..].iter().any(|other| other == col ) {
+                return Err(format!("Duplicated column {}", col));
+            }
+        }
+        let names = self.names();
+        let order : Vec<usize> = if cols.len() == 0 {
+            (0..names.len()).collect()
+        } else {
+            cols.iter()
+                .map(|c| names.iter().position(|n| n == c ).ok_or(format!("Unknown column {}", c)) )
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        if order.len() == 0 {
+            return Err(format!("Table has no columns"));
+        }
+        let end = end.min(self.nrows());
+        if start >= end {
+            return Ok(String::new());
+        }
+        let columns : Vec<&Column> = order.iter().filter_map(|ix| self.get_column(*ix) ).collect();
+        let col_list = order.iter()
+            .map(|ix| if quote_cols { quote_identifier(&names[*ix]) } else { names[*ix].clone() } )
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut stmt = format!("INSERT INTO {} ({}) VALUES\n", name, col_list);
+        for row in start..end {
+            let values : Vec<String> = columns.iter().map(|col| sql_literal(col, row) ).collect();
+            stmt += &format!("    ({}){}\n", values.join(", "), if row + 1 < end { "," } else { ";" });
+        }
+        Ok(stmt)
+    }
+
+    /// Script creating the target table (with PostgreSQL column types) and inserting the table
+    /// rows into it, with at most batch_size rows per INSERT statement.
+    pub fn to_sql_script(&self, target : &str, batch_size : usize) -> Result<String, String> {
+        if target.trim().is_empty() {
+            return Err(format!("Missing target table name"));
+        }
+        let target = quote_target(target);
+        let mut script = self.sql_table_creation(&target).ok_or(format!("Table has no columns"))?;
+        let batch_size = batch_size.max(1);
+        let mut start = 0;
+        while start < self.nrows() {
+            let end = (start + batch_size).min(self.nrows());
+            script += "\n";
+            script += &self.sql_rows_insertion(&target, &[], start, end, true)?;
+            start = end;
+        }
+        Ok(script)
+    }
+
+}
+
+#[test]
+fn sql_script() {
+    use super::nullable_column::NullableColumn;
+    let names = Column::Nullable(Box::new(NullableColumn::from(vec![Some(String::from("pen's")), None, Some(String::from("ink"))])));
+    let prices = Column::from(vec![1.5f64, f64::NAN, 3.0]);
+    let tbl = Table::new(None, vec![String::from("name"), String::from("price")], vec![names, prices]).unwrap();
+    assert_eq!(
+        tbl.to_sql_script("sales.Items", 2).unwrap(),
+        "CREATE TABLE \"sales\".\"Items\" (\n    \"name\" text,\n    \"price\" double precision\n);\n\n\
+        INSERT INTO \"sales\".\"Items\" (\"name\", \"price\") VALUES\n    ('pen''s', 1.5),\n    (NULL, 'NaN');\n\n\
+        INSERT INTO \"sales\".\"Items\" (\"name\", \"price\") VALUES\n    ('ink', 3);\n"
+    );
+    assert_eq!(quote_target("\"My Schema\".items"), "\"My Schema\".\"items\"");
+    assert_eq!(quote_target("\"a.b\".c"), "\"a.b\".\"c\"");
+    assert_eq!(
+        tbl.sql_table_insertion("t", &[String::from("price"), String::from("name")]).unwrap(),
+        "INSERT INTO t (price, name) VALUES\n    (1.5, 'pen''s'),\n    ('NaN', NULL),\n    (3, 'ink');\n"
+    );
+    assert!(tbl.sql_table_insertion("t", &[String::from("name"), String::from("name")]).is_err());
+    assert_eq!(tbl.select(&[], &[0, 1]).sql_table_insertion("t", &[]).unwrap(), "");
+    assert!(tbl.to_sql_script(" ", 10).is_err());
+}
+
+#[test]
+fn import_insertion() {
+    use super::dialect::CsvDialect;
+    let tbl = Table::new_from_csv(b"Name,Price\npen,1.5\n", &CsvDialect::default()).unwrap();
+    assert_eq!(
+        tbl.sql_table_insertion("public.items", &tbl.names()).unwrap(),
+        "INSERT INTO public.items (Name, Price) VALUES\n    ('pen', 1.5);\n"
+    );
+}
@@ -0,0 +1,268 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::{Table, TableSettings};
+use super::dialect::CsvDialect;
+use super::json::row_object;
+use super::arrow::batch_with_decimal_scale;
+use arrow::datatypes::{Field as ArrowField, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use arrow::ipc::writer::FileWriter;
+use parquet::arrow::ArrowWriter;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    Csv,
+    Json,
+    NdJson,
+    Parquet,
+    Arrow
+}
+
+impl StreamFormat {
+
+    pub fn from_path(path : &Path) -> Result<Self, String> {
+        match path.extension().and_then(|ext| ext.to_str() ) {
+            Some("csv") | Some("tsv") | Some("txt") => Ok(StreamFormat::Csv),
+            Some("json") => Ok(StreamFormat::Json),
+            Some("ndjson") | Some("jsonl") => Ok(StreamFormat::NdJson),
+            Some("parquet") => Ok(StreamFormat::Parquet),
+            Some("arrow") => Ok(StreamFormat::Arrow),
+            _ => Err(format!("Invalid file extension for query export (expected .csv, .tsv, .json, .ndjson, .jsonl, .parquet or .arrow)"))
+        }
+    }
+
+}
+
+enum Sink {
+    Text(BufWriter<File>),
+    Parquet(ArrowWriter<File>),
+    Arrow(FileWriter<File>),
+
+    // Binary formats are only started at the first chunk, when the schema is known.
+    Pending(File)
+}
+
+// Decimal columns of binary formats are written with a fixed scale, since the first chunk might hold
+// fewer decimal digits than the later ones. Values with more digits are rejected.
+const DECIMAL_SCALE : u32 = 12;
+
+/// Writes a query result to a file one chunk at a time, so the full result is never held in memory.
+/// Columns of binary formats take the types of the first chunk (or of the query columns, for columns
+/// holding only nulls there), and later chunks are cast to them.
+pub struct TableWriter {
+    format : StreamFormat,
+    dialect : CsvDialect,
+    sink : Option<Sink>,
+    schema : Option<SchemaRef>,
+    chunks : usize,
+    rows : usize
+}
+
+fn write_err(e : impl std::fmt::Display) -> String {
+    format!("Error writing to export file: {}", e)
+}
+
+/* All columns are nullable, since a column without nulls at the first chunk might have them later. */
+fn nullable_schema(batch : &RecordBatch) -> SchemaRef {
+    let fields : Vec<ArrowField> = batch.schema().fields().iter()
+        .map(|f| ArrowField::new(f.name(), f.data_type().clone(), true) )
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+fn cast_batch(batch : &RecordBatch, schema : &SchemaRef) -> Result<RecordBatch, String> {
+    if batch.num_columns() != schema.fields().len() {
+        return Err(format!("Query result changed its number of columns"));
+    }
+    let mut cols = Vec::new();
+    for (col, field) in batch.columns().iter().zip(schema.fields().iter()) {
+        let col = arrow::compute::cast(col, field.data_type())
+            .map_err(|e| format!("Column {} does not match the type of its first rows: {}", field.name(), e) )?;
+        cols.push(col);
+    }
+    RecordBatch::try_new(schema.clone(), cols).map_err(|e| format!("{}", e) )
+}
+
+impl TableWriter {
+
+    pub fn create(path : &Path, dialect : &CsvDialect) -> Result<Self, String> {
+        let format = StreamFormat::from_path(path)?;
+        let mut dialect = dialect.clone();
+        if path.extension().and_then(|ext| ext.to_str() ) == Some("tsv") {
+            dialect.delimiter = '\t';
+        }
+        if format == StreamFormat::Csv {
+            dialect.validate()?;
+        }
+        let f = File::create(path).map_err(|e| format!("Error creating export file: {}", e) )?;
+        let sink = match format {
+            StreamFormat::Csv | StreamFormat::Json | StreamFormat::NdJson => Sink::Text(BufWriter::new(f)),
+            StreamFormat::Parquet | StreamFormat::Arrow => Sink::Pending(f)
+        };
+        Ok(Self { format, dialect, sink : Some(sink), schema : None, chunks : 0, rows : 0 })
+    }
+
+    /// Number of rows written so far.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn write(&mut self, mut chunk : Table) -> Result<(), String> {
+        let mut export_format = TableSettings::default();
+        export_format.prec = None;
+        chunk.update_format(export_format);
+        let is_first = self.chunks == 0;
+        let sink = match self.sink.take() {
+            Some(sink) => sink,
+            None => return Err(format!("Export file was already closed"))
+        };
+        let sink = match sink {
+            Sink::Text(mut w) => {
+                match self.format {
+                    StreamFormat::Csv => {
+
+                        // The header is written only once, before the first chunk.
+                        let mut dialect = self.dialect.clone();
+                        dialect.header = dialect.header && is_first;
+                        let content = chunk.to_csv_with(&dialect)?;
+                        w.write_all(&content[..]).map_err(write_err)?;
+                    },
+                    StreamFormat::Json => {
+                        if is_first {
+                            w.write_all(b"[").map_err(write_err)?;
+                        }
+                        let names = chunk.names();
+                        for row in 0..chunk.nrows() {
+                            let sep = if self.rows + row == 0 { "\n" } else { ",\n" };
+                            write!(w, "{}  {}", sep, row_object(&chunk, &names[..], row)).map_err(write_err)?;
+                        }
+                    },
+                    _ => {
+                        chunk.write_ndjson(&mut w).map_err(write_err)?;
+                    }
+                }
+                Sink::Text(w)
+            },
+            Sink::Pending(f) => {
+                let batch = batch_with_decimal_scale(&chunk, DECIMAL_SCALE)?;
+                let schema = nullable_schema(&batch);
+                let batch = cast_batch(&batch, &schema)?;
+                self.schema = Some(schema.clone());
+                if self.format == StreamFormat::Parquet {
+                    let mut writer = ArrowWriter::try_new(f, schema, None).map_err(write_err)?;
+                    writer.write(&batch).map_err(write_err)?;
+                    Sink::Parquet(writer)
+                } else {
+                    let mut writer = FileWriter::try_new(f, &schema).map_err(write_err)?;
+                    writer.write(&batch).map_err(write_err)?;
+                    Sink::Arrow(writer)
+                }
+            },
+            Sink::Parquet(mut writer) => {
+                let batch = cast_batch(&batch_with_decimal_scale(&chunk, DECIMAL_SCALE)?, self.schema.as_ref().unwrap())?;
+                writer.write(&batch).map_err(write_err)?;
+                Sink::Parquet(writer)
+            },
+            Sink::Arrow(mut writer) => {
+                let batch = cast_batch(&batch_with_decimal_scale(&chunk, DECIMAL_SCALE)?, self.schema.as_ref().unwrap())?;
+                writer.write(&batch).map_err(write_err)?;
+                Sink::Arrow(writer)
+            }
+        };
+        self.sink = Some(sink);
+        self.chunks += 1;
+        self.rows += chunk.nrows();
+        Ok(())
+    }
+
+    /// Completes the file, returning the number of rows written.
+    pub fn finish(mut self) -> Result<usize, String> {
+        match self.sink.take() {
+            Some(Sink::Text(mut w)) => {
+                if self.format == StreamFormat::Json {
+                    let end = match (self.chunks, self.rows) {
+                        (0, _) => "[]\n",
+                        (_, 0) => "]\n",
+                        _ => "\n]\n"
+                    };
+                    w.write_all(end.as_bytes()).map_err(write_err)?;
+                }
+                w.flush().map_err(write_err)?;
+            },
+            Some(Sink::Parquet(writer)) => {
+                writer.close().map_err(write_err)?;
+            },
+            Some(Sink::Arrow(mut writer)) => {
+                writer.finish().map_err(write_err)?;
+            },
+            Some(Sink::Pending(_)) => {
+                return Err(format!("Query returned no columns to export"));
+            },
+            None => { }
+        }
+        Ok(self.rows)
+    }
+
+}
+
+#[test]
+fn stream_chunks() {
+    use super::column::Column;
+    let dir = std::env::temp_dir();
+    let chunks = || vec![
+        Table::new(None, vec![String::from("id"), String::from("name")], vec![Column::from(vec![1i64, 2]), Column::from(vec![String::from("a"), String::from("b")])]).unwrap(),
+        Table::new(None, vec![String::from("id"), String::from("name")], vec![Column::from(vec![3i64]), Column::from(vec![String::from("c")])]).unwrap()
+    ];
+    let csv_path = dir.join("queries_stream_chunks.csv");
+    let mut writer = TableWriter::create(&csv_path, &CsvDialect::default()).unwrap();
+    chunks().into_iter().for_each(|chunk| writer.write(chunk).unwrap() );
+    assert_eq!(writer.finish().unwrap(), 3);
+    assert_eq!(std::fs::read_to_string(&csv_path).unwrap(), "id,name\n1,a\n2,b\n3,c\n");
+
+    let json_path = dir.join("queries_stream_chunks.json");
+    let mut writer = TableWriter::create(&json_path, &CsvDialect::default()).unwrap();
+    chunks().into_iter().for_each(|chunk| writer.write(chunk).unwrap() );
+    writer.finish().unwrap();
+    let json = std::fs::read_to_string(&json_path).unwrap();
+    assert_eq!(Table::new_from_json(&json).unwrap().nrows(), 3);
+
+    let parquet_path = dir.join("queries_stream_chunks.parquet");
+    let mut writer = TableWriter::create(&parquet_path, &CsvDialect::default()).unwrap();
+    chunks().into_iter().for_each(|chunk| writer.write(chunk).unwrap() );
+    writer.finish().unwrap();
+    assert_eq!(Table::new_from_parquet(&parquet_path).unwrap().nrows(), 3);
+    assert!(TableWriter::create(&dir.join("queries_stream_chunks.xyz"), &CsvDialect::default()).is_err());
+}
+
+#[test]
+fn stream_decimal_chunks() {
+    use super::column::Column;
+    use super::field::Field;
+    use super::nullable_column::NullableColumn;
+    use rust_decimal::Decimal;
+    use std::str::FromStr;
+    let chunk = |prices : &[&str], qty : Vec<Option<i64>>| {
+        let prices = Column::from(prices.iter().map(|p| Decimal::from_str(p).unwrap() ).collect::<Vec<_>>());
+        let qty = Column::Nullable(Box::new(NullableColumn::from(qty)));
+        Table::new(None, vec![String::from("price"), String::from("qty")], vec![prices, qty]).unwrap()
+    };
+    let path = std::env::temp_dir().join("queries_stream_decimals.parquet");
+    let mut writer = TableWriter::create(&path, &CsvDialect::default()).unwrap();
+    writer.write(chunk(&["1.5"], vec![None])).unwrap();
+    writer.write(chunk(&["2.1234"], vec![Some(3)])).unwrap();
+    writer.finish().unwrap();
+    let tbl = Table::new_from_parquet(&path).unwrap();
+    assert!(matches!(tbl.get_column(0).unwrap().at(0, None), Some(Field::Numeric(d)) if d == Decimal::from_str("1.5").unwrap()));
+    assert!(matches!(tbl.get_column(0).unwrap().at(1, None), Some(Field::Numeric(d)) if d == Decimal::from_str("2.1234").unwrap()));
+    assert!(tbl.get_column(1).unwrap().is_null_at(0));
+    assert_eq!(tbl.get_column(1).unwrap().real_at(1), Some(3.0));
+    let mut writer = TableWriter::create(&path, &CsvDialect::default()).unwrap();
+    assert!(writer.write(chunk(&["0.1234567890123"], vec![None])).is_err());
+}
@@ -18,11 +18,14 @@ use serde_json::Value;
 use std::error::Error;
 use itertools::Itertools;
 use std::borrow::Cow;
+use std::path::Path;
 use std::iter::ExactSizeIterator;
 use std::cmp::{Eq, PartialEq};
 use quick_xml::Reader;
 use quick_xml::events::{Event };
 use crate::tables::nullable_column::NullableColumn;
+use crate::tables::dialect::CsvDialect;
+use crate::tables::tex::TexOptions;
 
 
 #[derive(Debug, Clone)]
@@ -136,6 +139,23 @@ fn tbl_from_html() {
     println!("{:?}", Table::from_html(s));
 }
 
+#[test]
+fn tbl_select() {
+    let amount : NullableColumn = vec![Some(1.5f64), None, Some(3.0)].into();
+    let tbl = Table::new(None, vec![String::from("name"), String::from("amount")], vec![
+        Column::from(vec![String::from("a"), String::from("b"), String::from("c")]),
+        Column::Nullable(Box::new(amount))
+    ]).unwrap();
+    let sel = tbl.select(&[2, 0], &[1, 0]);
+    assert_eq!(sel.names(), vec![String::from("amount"), String::from("name")]);
+    assert!(matches!(sel.get_column(0), Some(Column::F64(_))));
+    assert_eq!(sel.get_column(0).and_then(|c| c.real_at(0) ), Some(3.0));
+    assert_eq!(tbl.select(&[2, 0], &[0]).to_tsv(), "name\nc\na\n");
+    let sel = tbl.select(&[1], &[1]);
+    assert_eq!(sel.text_at(0, 0).unwrap(), "NULL");
+    assert_eq!(sel.get_column(0).and_then(|c| c.real_at(0) ), None);
+}
+
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum HTMLTag {
     Table,
@@ -184,10 +204,30 @@ impl Table {
         }
     }
     
+    /// Builds a table with the values at the given rows and columns, in the order they are informed.
+    pub fn select(&self, rows : &[usize], cols : &[usize]) -> Table {
+        Self {
+            name : self.name.clone(),
+            relation : None,
+            names : cols.iter().map(|ix| self.names[*ix].clone() ).collect(),
+            cols : cols.iter().map(|ix| self.cols[*ix].select_rows(rows) ).collect(),
+            nrows : rows.len(),
+            format : self.format.clone()
+        }
+    }
+
     pub fn display_content_at<'a>(&'a self, row_ix : usize, col_ix : usize, precision : Option<usize>) -> Option<Cow<'a, str>> {
         Some(self.cols.get(col_ix)?.display_content_at_index(row_ix, precision))
     }
     
+    /// Shows a single field as text, in the same format as text_rows.
+    pub fn text_at<'a>(&'a self, row_ix : usize, col_ix : usize) -> Option<Cow<'a, str>> {
+        if row_ix >= self.nrows {
+            return None;
+        }
+        self.display_content_at(row_ix, col_ix, self.format.prec)
+    }
+
     /// Joints two tables, as long as they have the same number of rows and column names are unique.
     pub fn join(mut self, other : &Table) -> Result<Table, String> {
         if self.shape().0 != other.shape().0 {
@@ -473,6 +513,26 @@ impl Table {
         self.relation = relation;
     }
 
+    /// Reads a table from a CSV (with the given dialect), JSON (array of objects), NDJSON (one object
+    /// per line), Parquet or Arrow file, according to its extension. The table is named after the file.
+    pub fn new_from_file(path : &Path, dialect : &CsvDialect) -> Result<Self, String> {
+        let read_text = || std::fs::read_to_string(path).map_err(|e| format!("Error opening file: {}", e) );
+        let read_bytes = || std::fs::read(path).map_err(|e| format!("Error opening file: {}", e) );
+        let mut tbl = match path.extension().and_then(|ext| ext.to_str() ) {
+            Some("json") => Table::new_from_json(&read_text()?)?,
+            Some("ndjson") | Some("jsonl") => Table::new_from_ndjson(&read_text()?)?,
+            Some("parquet") => Table::new_from_parquet(path)?,
+            Some("arrow") => Table::new_from_arrow(path)?,
+            Some("tsv") => {
+                let dialect = CsvDialect { delimiter : '\t', delimiter_set : true, ..dialect.clone() };
+                Table::new_from_csv(&read_bytes()?, &dialect)?
+            },
+            _ => Table::new_from_csv(&read_bytes()?, dialect)?
+        };
+        tbl.set_name(path.file_stem().and_then(|s| s.to_str() ).map(|s| s.to_string() ));
+        Ok(tbl)
+    }
+
     pub fn new_from_text(
         source : String
     ) -> Result<Self, &'static str> {
@@ -578,14 +638,10 @@ impl Table {
         })
     }
 
-    /// Returns a SQL string (valid for SQlite3/PostgreSQL subset)
-    /// which will contain both the table creation and data insertion
-    /// commands. Binary columns are created but will hold NULL. Fails
-    /// if table is not named.
-    /// TODO check if SQL is valid (maybe external to the struct). SQL can
-    /// be invalid if there are reserved keywords as column names.
+    /// Returns a SQL string which will contain both the table creation and data insertion
+    /// commands. Fails if the table has no columns.
     pub fn sql_string(&self, name : &str) -> Result<String, String> {
-        if let Some(mut creation) = self.sql_table_creation(name, &[]) {
+        if let Some(mut creation) = self.sql_table_creation(name) {
             creation += &self.sql_table_insertion(name, &[])?;
             /*match crate::sql::parsing::parse_sql(&creation[..], &HashMap::new()) {
                 Ok(_) => Ok(creation),
@@ -601,77 +657,8 @@ impl Table {
         self.cols.iter().map(|c| c.sqlite3_type().to_string()).collect()
     }
 
-    pub fn sql_table_creation(&self, name : &str, _cols : &[String]) -> Option<String> {
-        let mut query = format!("CREATE TABLE {}(", name);
-        for (i, (name, col)) in self.names.iter().zip(self.cols.iter()).enumerate() {
-            let name = match name.chars().find(|c| *c == ' ') {
-                Some(_) => String::from("\"") + &name[..] + "\"",
-                None => name.clone()
-            };
-            query += &format!("{} {}", name, col.sqlite3_type());
-            if i < self.cols.len() - 1 {
-                query += ","
-            } else {
-                query += ");\n"
-            }
-        }
-        Some(query)
-    }
-
-    /// Always successful, but query might be empty if there is no data on the columns.
-    pub fn sql_table_insertion(&self, name : &str, cols : &[String]) -> Result<String, String> {
-        let mut stmt = String::new();
-        let nrows = self.nrows();
-        
-        for i in 0..(cols.len()-1) {
-            for j in (i+1)..cols.len() {
-                if &cols[i][..] == &cols[j][..] {
-                    return Err(String::from("Duplicated columns"));
-                }
-            }
-        }
-        
-        if cols.len() == 0 {
-            stmt += &format!("insert into {} values ", name)[..];
-        } else {
-            let tuple = insertion_tuple(&cols);
-            stmt += &format!("insert into {} {} values ", name, tuple)[..];
-        }
-        
-        let types = self.sql_types();
-        let order : Vec<usize> = if cols.len() == 0 {
-            (0..self.names.len()).collect()
-        } else {
-            cols.iter().filter_map(|c| self.names.iter().position(|n| &n[..] == &c[..] ) ).collect()
-        };
-
-        let mut curr_row = Vec::new();
-        let mut content = self.text_rows(None, None);
-        let ncol = order.len();
-        for (line_n, line) in content.iter_mut().skip(1).enumerate() {
-            stmt += "(";
-            curr_row.clear();
-            curr_row.extend(line);
-            for i in 0..order.len() {
-                let f = &curr_row[order[i]];
-                let t = &types[order[i]];
-                append_field(&mut stmt, f, t, QuoteType::Single);
-                if i < ncol - 1 {
-                    stmt += ","
-                } else {
-                    if line_n < nrows - 1 {
-                        stmt += "),";
-                    } else {
-                        stmt += ");\n";
-                    }
-                }
-            }
-        }
-        Ok(stmt)
-    }
-
     /// Decide if column at ix should be displayed, according to the current display rules.
-    fn show_column(&self, ix : usize) -> bool {
+    pub(crate) fn show_column(&self, ix : usize) -> bool {
         if let Some(show) = self.format.show_only.as_ref() {
             show.iter()
                 .find(|s| &s[..] == &self.names[ix][..] )
@@ -709,37 +696,24 @@ impl Table {
         content
     }
 
-    pub fn to_tex(&self) -> String {
-        let mut rows = self.text_rows(None, None);
-        let mut tex = String::new();
-        tex += r"\begin{tabular}";
-        let ncol = self.cols.len();
-        for (i, row) in rows.iter_mut().enumerate() {
-            if i == 0 {
-                tex += "{ ";
-                for c in 0..ncol {
-                    if self.show_column(c) {
-                        tex += "c ";
-                    }
-                }
-                tex += "}\n";
-            }
-
-            for (j, field) in row.enumerate() {
-                if self.show_column(j) {
-                    tex += &format!("{} ", field);
-                    if j < ncol - 1 {
-                        tex += "& ";
-                    } else {
-                        tex += r"\\";
-                        tex += "\n";
-                    }
-                }
-            }
+    /// Tab-separated values, as pasted by spreadsheet applications. Tabs and line breaks
+    /// within fields are replaced by spaces.
+    pub fn to_tsv(&self) -> String {
+        let mut content = String::new();
+        for row in self.text_rows(None, None).iter_mut() {
+            let fields : Vec<String> = row.enumerate()
+                .filter(|(i, _)| self.show_column(*i) )
+                .map(|(_, field)| field.replace(|c : char| c == '\t' || c == '\n' || c == '\r', " ") )
+                .collect();
+            content += &fields.join("\t");
+            content += "\n";
         }
-        tex += r"\end{tabular}";
-        tex += "\n";
-        tex
+        content
+    }
+
+    /// LaTeX table, with the layout given by the tex options of the table settings.
+    pub fn to_tex(&self) -> String {
+        self.to_tex_with(&self.format.tex)
     }
 
     pub fn to_markdown(&self) -> String {
@@ -769,38 +743,6 @@ impl Table {
         md
     }
 
-    pub fn to_html(&self) -> String {
-        let mut html = String::new();
-
-        html += "<table>\n";
-
-        html += "<thead>\n";
-        html += "<tr>\n";
-        for ref name in &self.names {
-            html += "<th>";
-            html += &name[..];
-            html += "</th>\n";
-        }
-        html += "</tr>\n";
-        html += "</thead>\n";
-
-        html += "<tbody>\n";
-        for ref mut row in self.text_rows(None, None).iter_mut().skip(1) {
-            html += "<tr>\n";
-            for cell in row {
-                html += "<td>\n";
-                html += &cell[..];
-                html += "</td>\n";
-            }
-            html += "</tr>\n";
-        }
-        html += "</tbody>\n";
-
-        html += "</table>";
-
-        html
-    }
-
     pub fn to_ooxml(&self, name : Option<String>, style : Option<String>) -> String {
         let mut ooxml = String::new();
         let name = name.unwrap_or(String::from("Table1"));
@@ -1144,7 +1086,8 @@ pub struct TableSettings {
     pub bool_field : BoolField,
     pub null_field : NullField,
     pub prec : Option<usize>,
-    pub show_only : Option<Vec<String>>
+    pub show_only : Option<Vec<String>>,
+    pub tex : TexOptions
 }
 
 impl Default for TableSettings {
@@ -1156,7 +1099,8 @@ impl Default for TableSettings {
             bool_field : BoolField::Word,
             null_field : NullField::Omit,
             prec : Some(8),
-            show_only : None
+            show_only : None,
+            tex : TexOptions::default()
         }
     }
 
@@ -1174,7 +1118,8 @@ pub fn full_csv_display(tbl : &mut Table, cols : Vec<String>) -> String {
         bool_field : BoolField::Char,
         null_field : NullField::WordUpper,
         prec : None,
-        show_only : show
+        show_only : show,
+        tex : TexOptions::default()
     };
     tbl.update_format(fmt);
     let csv_tbl = format!("{}", tbl);
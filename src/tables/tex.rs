@@ -0,0 +1,240 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use super::table::Table;
+use serde::{Serialize, Deserialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TexAlign {
+    Left,
+    Center,
+    Right,
+
+    // Numbers aligned at the decimal separator, with siunitx S columns. Only valid for numeric columns.
+    Decimal
+}
+
+impl TexAlign {
+
+    fn spec(&self) -> &'static str {
+        match self {
+            TexAlign::Left => "l",
+            TexAlign::Center => "c",
+            TexAlign::Right => "r",
+            TexAlign::Decimal => "S"
+        }
+    }
+
+}
+
+/// Layout of LaTeX table exports. The defaults reproduce the plain tabular layout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TexOptions {
+
+    // Uses \toprule, \midrule and \bottomrule (booktabs package) around the header and the body.
+    pub booktabs : bool,
+
+    pub caption : String,
+
+    pub label : String,
+
+    pub number_align : TexAlign,
+
+    pub text_align : TexAlign,
+
+    // Digits after the decimal separator of the given numeric columns.
+    pub precision : Vec<(String, usize)>,
+
+    // Escapes the LaTeX special characters of column names and text cells.
+    pub escape : bool,
+
+    // Uses a longtable (longtable package), which breaks across pages, instead of a tabular.
+    pub longtable : bool,
+
+    // Wraps the table in a complete article document.
+    pub standalone : bool
+
+}
+
+impl Default for TexOptions {
+
+    fn default() -> Self {
+        Self {
+            booktabs : false,
+            caption : String::new(),
+            label : String::new(),
+            number_align : TexAlign::Center,
+            text_align : TexAlign::Center,
+            precision : Vec::new(),
+            escape : false,
+            longtable : false,
+            standalone : false
+        }
+    }
+
+}
+
+/// Parses per-column precisions written as "price=2, ratio=4".
+pub fn parse_precision(s : &str) -> Result<Vec<(String, usize)>, String> {
+    let mut precision = Vec::new();
+    for item in s.split(',').map(|item| item.trim() ).filter(|item| !item.is_empty() ) {
+        let (name, digits) = item.split_once('=')
+            .ok_or_else(|| format!("Invalid column precision '{}' (expected column=digits)", item) )?;
+        let digits = digits.trim().parse::<usize>()
+            .map_err(|_| format!("Invalid number of digits for column {}", name.trim()) )?;
+        precision.push((name.trim().to_string(), digits));
+    }
+    Ok(precision)
+}
+
+pub fn format_precision(precision : &[(String, usize)]) -> String {
+    precision.iter().map(|(name, digits)| format!("{}={}", name, digits) ).collect::<Vec<_>>().join(", ")
+}
+
+pub fn escape_tex(s : &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped += r"\textbackslash{}",
+            '~' => escaped += r"\textasciitilde{}",
+            '^' => escaped += r"\textasciicircum{}",
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+impl Table {
+
+    fn is_numeric_column(&self, ix : usize) -> bool {
+        match self.get_column(ix) {
+            Some(col) => {
+                (0..self.nrows()).any(|row| col.real_at(row).is_some() ) &&
+                    (0..self.nrows()).all(|row| col.is_null_at(row) || col.real_at(row).is_some() )
+            },
+            None => false
+        }
+    }
+
+    pub fn to_tex_with(&self, opts : &TexOptions) -> String {
+        let names = self.names();
+        let cols : Vec<usize> = (0..self.ncols()).filter(|ix| self.show_column(*ix) ).collect();
+        let aligns : Vec<TexAlign> = cols.iter().map(|ix| {
+            match (self.is_numeric_column(*ix), opts.text_align) {
+                (true, _) => opts.number_align,
+                (false, TexAlign::Decimal) => TexAlign::Left,
+                (false, align) => align
+            }
+        }).collect();
+        let escape = |s : &str| if opts.escape { escape_tex(s) } else { s.to_string() };
+
+        // Non-numeric content of S columns must be braced.
+        let brace = |s : String, align : TexAlign| if align == TexAlign::Decimal { format!("{{{}}}", s) } else { s };
+
+        let spec = format!("{{ {}}}", aligns.iter().map(|a| format!("{} ", a.spec()) ).collect::<String>());
+        let header = cols.iter().zip(aligns.iter())
+            .map(|(ix, align)| brace(escape(&names[*ix]), *align) )
+            .collect::<Vec<_>>()
+            .join(" & ");
+        let (top, mid, bottom) = if opts.booktabs {
+            ("\\toprule\n", "\\midrule\n", "\\bottomrule\n")
+        } else {
+            ("", "", "")
+        };
+
+        let mut body = String::new();
+        for row in 0..self.nrows() {
+            let mut fields = Vec::new();
+            for (ix, align) in cols.iter().zip(aligns.iter()) {
+                let col = self.get_column(*ix).unwrap();
+                let prec = opts.precision.iter().find(|(name, _)| name == &names[*ix] ).map(|(_, p)| *p );
+                let field = match (col.real_at(row), prec) {
+                    (Some(val), Some(prec)) if !col.is_null_at(row) => format!("{:.*}", prec, val),
+                    (Some(_), _) if !col.is_null_at(row) => self.text_at(row, *ix).unwrap_or_default().to_string(),
+                    _ => brace(escape(&self.text_at(row, *ix).unwrap_or_default()), *align)
+                };
+                fields.push(field);
+            }
+            body += &format!("{} \\\\\n", fields.join(" & "));
+        }
+
+        let caption = if opts.caption.is_empty() { String::new() } else { format!("\\caption{{{}}}\n", escape(&opts.caption)) };
+        let label = if opts.label.is_empty() { String::new() } else { format!("\\label{{{}}}\n", opts.label) };
+        let mut tex = String::new();
+        if opts.standalone {
+            tex += "\\documentclass{article}\n";
+            if opts.booktabs {
+                tex += "\\usepackage{booktabs}\n";
+            }
+            if opts.longtable {
+                tex += "\\usepackage{longtable}\n";
+            }
+            if aligns.contains(&TexAlign::Decimal) {
+                tex += "\\usepackage{siunitx}\n";
+            }
+            tex += "\\begin{document}\n";
+        }
+        if opts.longtable {
+            tex += &format!("\\begin{{longtable}}{}\n", spec);
+            if !caption.is_empty() || !label.is_empty() {
+                tex += &format!("{}{}\\\\\n", caption.trim_end(), label.trim_end());
+            }
+            tex += &format!("{}{} \\\\\n{}\\endhead\n{}{}", top, header, mid, body, bottom);
+            tex += "\\end{longtable}\n";
+        } else {
+            let floating = !caption.is_empty() || !label.is_empty();
+            if floating {
+                tex += &format!("\\begin{{table}}[htbp]\n\\centering\n{}{}", caption, label);
+            }
+            tex += &format!("\\begin{{tabular}}{}\n{}{} \\\\\n{}{}{}", spec, top, header, mid, body, bottom);
+            tex += "\\end{tabular}\n";
+            if floating {
+                tex += "\\end{table}\n";
+            }
+        }
+        if opts.standalone {
+            tex += "\\end{document}\n";
+        }
+        tex
+    }
+
+}
+
+#[test]
+fn tex_options() {
+    use super::column::Column;
+    let tbl = Table::new(
+        None,
+        vec![String::from("item_name"), String::from("price")],
+        vec![Column::from(vec![String::from("A & B"), String::from("50%")]), Column::from(vec![1.5f64, 20.26])]
+    ).unwrap();
+    let plain = tbl.to_tex_with(&TexOptions::default());
+    assert!(plain.starts_with("\\begin{tabular}{ c c }\nitem_name & price \\\\\n"));
+
+    let opts = TexOptions {
+        booktabs : true,
+        caption : String::from("Prices"),
+        label : String::from("tab:prices"),
+        number_align : TexAlign::Decimal,
+        text_align : TexAlign::Left,
+        precision : parse_precision("price=1").unwrap(),
+        escape : true,
+        longtable : false,
+        standalone : true
+    };
+    let tex = tbl.to_tex_with(&opts);
+    assert!(tex.contains("\\usepackage{booktabs}\n\\usepackage{siunitx}\n"));
+    assert!(tex.contains("\\caption{Prices}\n\\label{tab:prices}\n\\begin{tabular}{ l S }\n\\toprule\n"));
+    assert!(tex.contains("item\\_name & {price} \\\\\n\\midrule\n"));
+    assert!(tex.contains("A \\& B & 1.5 \\\\\n50\\% & 20.3 \\\\\n\\bottomrule\n"));
+    assert!(tex.ends_with("\\end{table}\n\\end{document}\n"));
+    assert!(parse_precision("price").is_err());
+    assert_eq!(format_precision(&opts.precision), "price=1");
+}
@@ -22,6 +22,14 @@ use crate::client::SharedUserState;
 use crate::ui::QueriesSettings;
 use crate::client::EditorSettings;
 use filecase::MultiArchiverImpl;
+use crate::client::ActiveConnection;
+use crate::sql::{SafetyPolicies, Policy};
+use crate::sql::parsing::StatementDiagnostic;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::tables::tex::{TexOptions, TexAlign, parse_precision, format_precision};
 
 const MAX_VIEWS : usize = 16;
 
@@ -40,7 +48,20 @@ pub struct QueriesEditor {
     pub save_dialog : SaveDialog,
     pub open_dialog : OpenDialog,
     pub export_dialog : ExportDialog,
-    user_state : SharedUserState
+    pub export_all_dialog : ExportAllDialog,
+    pub open_table_dialog : OpenTableDialog,
+    user_state : SharedUserState,
+
+    // Index of the view and character offset of the last executed text (which
+    // might be just the selected part of the script).
+    exec_origin : Rc<RefCell<Option<(usize, i32)>>>,
+
+    // Safety policies of the connection bound to the selected script, used to mark
+    // statements that would be blocked before execution.
+    policies : Rc<RefCell<Option<SafetyPolicies>>>,
+
+    // Tooltips of the diagnostic marks, by mark name.
+    tooltips : Rc<RefCell<HashMap<String, String>>>
 }
 
 impl QueriesEditor {
@@ -51,17 +72,58 @@ impl QueriesEditor {
         let save_dialog = SaveDialog::build();
         let open_dialog = OpenDialog::build();
         let export_dialog = ExportDialog::build();
+        let export_all_dialog = ExportAllDialog::build();
+        let open_table_dialog = OpenTableDialog::build();
         stack.add_named(&script_list.bx, Some("list"));
         let views : [sourceview5::View; MAX_VIEWS]= Default::default();
+        let policies : Rc<RefCell<Option<SafetyPolicies>>> = Default::default();
+        let tooltips : Rc<RefCell<HashMap<String, String>>> = Default::default();
         for ix in 0..MAX_VIEWS {
             configure_view(&views[ix], &EditorSettings::default());
+            configure_diagnostic_marks(&views[ix], &tooltips);
+
+            // Diagnostics are updated only after the user stops typing for a while.
+            let n_changes = Rc::new(Cell::new(0usize));
+            views[ix].buffer().connect_changed({
+                let policies = policies.clone();
+                let tooltips = tooltips.clone();
+                move |buffer| {
+                    clear_error_tag(buffer);
+                    let curr_change = n_changes.get() + 1;
+                    n_changes.set(curr_change);
+                    let n_changes = n_changes.clone();
+                    let buffer = buffer.clone();
+                    let policies = policies.clone();
+                    let tooltips = tooltips.clone();
+                    glib::timeout_add_local_once(Duration::from_millis(DIAGNOSTIC_DELAY), move || {
+                        if n_changes.get() == curr_change {
+                            update_diagnostics(&buffer, policies.borrow().as_ref(), &tooltips);
+                        }
+                    });
+                }
+            });
             let scroll = ScrolledWindow::new();
             scroll.set_child(Some(&views[ix]));
             stack.add_named(&scroll, Some(&format!("editor{}", ix)));
         }
         open_dialog.react(&script_list);
         let ignore_file_save_action = gio::SimpleAction::new("ignore_file_save", Some(&i32::static_variant_type()));
-        Self { views, stack, script_list, save_dialog, open_dialog, ignore_file_save_action, export_dialog, user_state : user_state.clone() }
+        let exec_origin = Rc::new(RefCell::new(None));
+        Self {
+            views,
+            stack,
+            script_list,
+            save_dialog,
+            open_dialog,
+            ignore_file_save_action,
+            export_dialog,
+            export_all_dialog,
+            open_table_dialog,
+            user_state : user_state.clone(),
+            exec_origin,
+            policies,
+            tooltips
+        }
     }
 
     pub fn configure(&self, settings : &EditorSettings) {
@@ -197,12 +259,18 @@ impl React<ExecButton> for QueriesEditor {
     fn react(&self, btn : &ExecButton) {
         let weak_views : [glib::WeakRef<sourceview5::View>; MAX_VIEWS] = self.views.clone().map(|view| view.downgrade() );
         let exec_action = btn.exec_action.clone();
+        let exec_origin = self.exec_origin.clone();
         btn.queue_exec_action.connect_activate(move |_, _| {
             let selected_view = exec_action.state().unwrap().get::<i32>().unwrap();
             if selected_view >= 0 {
                 if let Some(view) = weak_views[selected_view as usize].upgrade() {
                     if let Ok(Some(txt)) = retrieve_statements_from_buffer(&view) {
 
+                        let buffer = view.buffer();
+                        clear_error_tag(&buffer);
+                        let start = buffer.selection_bounds().map(|(from, _)| from.offset() ).unwrap_or(0);
+                        exec_origin.replace(Some((selected_view as usize, start)));
+
                         // Implemented at React<ExecButton> for ActiveConnection
 
                         exec_action.activate(Some(&txt.to_variant()));
@@ -214,8 +282,294 @@ impl React<ExecButton> for QueriesEditor {
                 eprintln!("No selected view");
             }
         });
+
+        let weak_views : [glib::WeakRef<sourceview5::View>; MAX_VIEWS] = self.views.clone().map(|view| view.downgrade() );
+        let exec_action = btn.exec_action.clone();
+        let exec_origin = self.exec_origin.clone();
+        btn.queue_current_action.connect_activate(move |_, _| {
+            let selected_view = exec_action.state().unwrap().get::<i32>().unwrap();
+            if selected_view < 0 {
+                eprintln!("No selected view");
+                return;
+            }
+            if let Some(view) = weak_views[selected_view as usize].upgrade() {
+                let buffer = view.buffer();
+                let txt = buffer.text(&buffer.start_iter(), &buffer.end_iter(), true).to_string();
+                let cursor = buffer.iter_at_mark(&buffer.get_insert()).offset();
+                clear_error_tag(&buffer);
+                match crate::sql::parsing::statement_range_at(&txt, cursor as usize) {
+                    Ok(Some(range)) => {
+                        let stmt : String = txt.chars().skip(range.start).take(range.end - range.start).collect();
+                        let from = buffer.iter_at_offset(range.start as i32);
+                        let to = buffer.iter_at_offset(range.end as i32);
+                        highlight_sent_range(&buffer, &from, &to);
+                        exec_origin.replace(Some((selected_view as usize, range.start as i32)));
+                        exec_action.activate(Some(&stmt.to_variant()));
+                    },
+                    Ok(None) => {
+                        eprintln!("No statement under cursor");
+                    },
+                    Err(_) => {
+
+                        // Let the listener report the lexing error as for a regular execution.
+                        exec_origin.replace(Some((selected_view as usize, 0)));
+                        exec_action.activate(Some(&txt.to_variant()));
+                    }
+                }
+            }
+        });
+
+        let weak_views : [glib::WeakRef<sourceview5::View>; MAX_VIEWS] = self.views.clone().map(|view| view.downgrade() );
+        let exec_action = btn.exec_action.clone();
+        let export_action = btn.export_action.clone();
+        btn.queue_export_action.connect_activate(move |_, _| {
+            let selected_view = exec_action.state().unwrap().get::<i32>().unwrap();
+            if selected_view < 0 {
+                eprintln!("No selected view");
+                return;
+            }
+            if let Some(view) = weak_views[selected_view as usize].upgrade() {
+                if let Ok(Some(txt)) = retrieve_statements_from_buffer(&view) {
+
+                    // Implemented at React<ExecButton> for QueryExportDialog
+                    export_action.activate(Some(&txt.to_variant()));
+                } else {
+                    eprintln!("No text to be retrieved");
+                }
+            }
+        });
+    }
+
+}
+
+impl React<MainMenu> for QueriesEditor {
+
+    fn react(&self, menu : &MainMenu) {
+        let stack = self.stack.clone();
+        let views = self.views.clone();
+        let user_state = self.user_state.clone();
+        menu.action_format.connect_activate(move |_, _| {
+            let ix = match selected_editor_stack_index(&stack) {
+                Some(ix) => ix,
+                None => return
+            };
+            let buffer = views[ix].buffer();
+            let (mut from, mut to) = buffer.selection_bounds()
+                .unwrap_or_else(|| (buffer.start_iter(), buffer.end_iter()) );
+            let txt = buffer.text(&from, &to, true).to_string();
+            let opts = user_state.borrow().editor.format_options();
+            match crate::sql::format::format_sql(&txt, &opts) {
+                Ok(formatted) if formatted != txt => {
+
+                    // Grouped as a single user action, so the whole change is undone at once.
+                    buffer.begin_user_action();
+                    buffer.delete(&mut from, &mut to);
+                    buffer.insert(&mut from, &formatted);
+                    buffer.end_user_action();
+                },
+                Ok(_) => { },
+                Err(e) => {
+                    eprintln!("{}", e);
+                }
+            }
+        });
+    }
+
+}
+
+const SENT_TAG : &'static str = "sent";
+
+const SENT_HIGHLIGHT_DURATION : u64 = 600;
+
+// Briefly highlights the text range that was sent for execution.
+fn highlight_sent_range(buffer : &impl IsA<TextBuffer>, from : &TextIter, to : &TextIter) {
+    let tag = match buffer.tag_table().lookup(SENT_TAG) {
+        Some(tag) => tag,
+        None => {
+            let tag = TextTag::builder().name(SENT_TAG).background("#3584e440").build();
+            buffer.tag_table().add(&tag);
+            tag
+        }
+    };
+    buffer.remove_tag(&tag, &buffer.start_iter(), &buffer.end_iter());
+    buffer.apply_tag(&tag, from, to);
+    let buffer = buffer.clone().upcast::<TextBuffer>();
+    glib::timeout_add_local_once(Duration::from_millis(SENT_HIGHLIGHT_DURATION), move || {
+        buffer.remove_tag(&tag, &buffer.start_iter(), &buffer.end_iter());
+    });
+}
+
+const ERROR_TAG : &'static str = "error";
+
+fn clear_error_tag(buffer : &impl IsA<TextBuffer>) {
+    if let Some(tag) = buffer.tag_table().lookup(ERROR_TAG) {
+        buffer.remove_tag(&tag, &buffer.start_iter(), &buffer.end_iter());
+    }
+}
+
+impl React<ActiveConnection> for QueriesEditor {
+
+    fn react(&self, conn : &ActiveConnection) {
+        let views = self.views.clone();
+        let exec_origin = self.exec_origin.clone();
+        conn.connect_error_location(move |(offset, len)| {
+            if let Some((view_ix, start)) = *exec_origin.borrow() {
+                let view = &views[view_ix];
+                let buffer = view.buffer();
+                let tag = match buffer.tag_table().lookup(ERROR_TAG) {
+                    Some(tag) => tag,
+                    None => {
+                        let tag = TextTag::builder().name(ERROR_TAG).underline(pango::Underline::Error).build();
+                        buffer.tag_table().add(&tag);
+                        tag
+                    }
+                };
+                let mut from = buffer.iter_at_offset(start + offset as i32);
+                let to = buffer.iter_at_offset(start + (offset + len) as i32);
+                buffer.apply_tag(&tag, &from, &to);
+                view.scroll_to_iter(&mut from, 0.0, false, 0.0, 0.0);
+            }
+        });
+        conn.connect_session_switched({
+            let views = self.views.clone();
+            let policies = self.policies.clone();
+            let tooltips = self.tooltips.clone();
+            move |opt_session| {
+                policies.replace(opt_session.map(|(_, info, _)| info.policies ));
+                for view in views.iter() {
+                    let buffer = view.buffer();
+                    if buffer.char_count() > 0 {
+                        update_diagnostics(&buffer, policies.borrow().as_ref(), &tooltips);
+                    }
+                }
+            }
+        });
+    }
+
+}
+
+const DIAGNOSTIC_DELAY : u64 = 500;
+
+const DIAGNOSTIC_TAG : &'static str = "diagnostic";
+
+// Mark categories, from the lowest to the highest priority.
+const STATEMENT_MARK : &'static str = "statement";
+const CONFIRM_MARK : &'static str = "confirm";
+const BLOCKED_MARK : &'static str = "blocked";
+const PARSE_ERROR_MARK : &'static str = "parse-error";
+
+const DIAGNOSTIC_MARKS : [(&'static str, &'static str); 4] = [
+    (STATEMENT_MARK, "pan-end-symbolic"),
+    (CONFIRM_MARK, "dialog-warning-symbolic"),
+    (BLOCKED_MARK, "action-unavailable-symbolic"),
+    (PARSE_ERROR_MARK, "dialog-error-symbolic")
+];
+
+// Mark names must be unique within a buffer, and buffers are moved across views.
+static N_MARKS : AtomicUsize = AtomicUsize::new(0);
+
+fn configure_diagnostic_marks(view : &View, tooltips : &Rc<RefCell<HashMap<String, String>>>) {
+    for (priority, (category, icon)) in DIAGNOSTIC_MARKS.iter().enumerate() {
+        let attrs = sourceview5::MarkAttributes::new();
+        attrs.set_icon_name(icon);
+        attrs.connect_query_tooltip_text({
+            let tooltips = tooltips.clone();
+            move |_, mark| {
+                mark.name()
+                    .and_then(|name| tooltips.borrow().get(name.as_str()).cloned() )
+                    .unwrap_or_default()
+            }
+        });
+        view.set_mark_attributes(category, &attrs, priority as i32);
     }
+}
+
+fn clear_diagnostics(buffer : &sourceview5::Buffer, tooltips : &RefCell<HashMap<String, String>>) {
+    let mut tooltips = tooltips.borrow_mut();
+    for line in 0..buffer.line_count() {
+        for mark in buffer.source_marks_at_line(line, None) {
+            if let Some(name) = mark.name() {
+                tooltips.remove(name.as_str());
+            }
+        }
+    }
+    let (start, end) = (buffer.start_iter(), buffer.end_iter());
+    for (category, _) in DIAGNOSTIC_MARKS.iter() {
+        buffer.remove_source_marks(&start, &end, Some(category));
+    }
+    if let Some(tag) = buffer.tag_table().lookup(DIAGNOSTIC_TAG) {
+        buffer.remove_tag(&tag, &start, &end);
+    }
+}
 
+fn add_diagnostic_mark(
+    buffer : &sourceview5::Buffer,
+    category : &str,
+    offset : usize,
+    tooltip : String,
+    tooltips : &RefCell<HashMap<String, String>>
+) {
+    let name = format!("{}{}", category, N_MARKS.fetch_add(1, Ordering::Relaxed));
+    buffer.create_source_mark(Some(&name), category, &buffer.iter_at_offset(offset as i32));
+    tooltips.borrow_mut().insert(name, tooltip);
+}
+
+// Offset of the first non-whitespace character of the statement.
+fn statement_start(txt : &[char], diag : &StatementDiagnostic) -> usize {
+    let range = diag.range.clone();
+    txt[range.clone()].iter().position(|c| !c.is_whitespace() ).map(|p| range.start + p ).unwrap_or(range.start)
+}
+
+/* Marks the statements of the script at the gutter: Statements that could not be parsed
+(also underlined), statements blocked or requiring confirmation by the safety policies
+of the connection bound to the script, and the start of any other statement. */
+fn update_diagnostics(
+    buffer : &impl IsA<TextBuffer>,
+    policies : Option<&SafetyPolicies>,
+    tooltips : &RefCell<HashMap<String, String>>
+) {
+    let buffer = match buffer.dynamic_cast_ref::<sourceview5::Buffer>() {
+        Some(buffer) => buffer,
+        None => return
+    };
+    clear_diagnostics(&buffer, tooltips);
+    let txt = buffer.text(&buffer.start_iter(), &buffer.end_iter(), true).to_string();
+    let diagnostics = match crate::sql::parsing::diagnose_sql(&txt) {
+        Ok(diagnostics) => diagnostics,
+        Err(e) => {
+            add_diagnostic_mark(&buffer, PARSE_ERROR_MARK, 0, e, tooltips);
+            return;
+        }
+    };
+    let chars : Vec<char> = txt.chars().collect();
+    let tag = match buffer.tag_table().lookup(DIAGNOSTIC_TAG) {
+        Some(tag) => tag,
+        None => {
+            let tag = TextTag::builder().name(DIAGNOSTIC_TAG).underline(pango::Underline::Error).build();
+            buffer.tag_table().add(&tag);
+            tag
+        }
+    };
+    for (ix, diag) in diagnostics.iter().enumerate() {
+        let start = statement_start(&chars[..], &diag);
+        let (category, tooltip) = match (&diag.error, &diag.stmt, policies) {
+            (Some(e), _, _) => {
+                buffer.apply_tag(&tag, &buffer.iter_at_offset(start as i32), &buffer.iter_at_offset(diag.range.end as i32));
+                (PARSE_ERROR_MARK, e.clone())
+            },
+            (None, Some(stmt), Some(policies)) => match policies.evaluate(stmt) {
+                (Some(rule), Policy::Deny) => {
+                    (BLOCKED_MARK, format!("Blocked by the connection policies ({})", rule.name()))
+                },
+                (Some(rule), Policy::Confirm) => {
+                    (CONFIRM_MARK, format!("Requires confirmation ({})", rule.name()))
+                },
+                _ => (STATEMENT_MARK, format!("Statement {}", ix + 1))
+            },
+            _ => (STATEMENT_MARK, format!("Statement {}", ix + 1))
+        };
+        add_diagnostic_mark(&buffer, category, start, tooltip, tooltips);
+    }
 }
 
 pub fn retrieve_statements_from_buffer(view : &sourceview5::View) -> Result<Option<String>, String> {
@@ -395,7 +749,17 @@ fn configure_view(view : &View, settings : &EditorSettings) {
 
 #[derive(Debug, Clone)]
 pub struct ExportDialog {
-    pub dialog : FileChooserDialog
+    pub dialog : FileChooserDialog,
+
+    // Table created by .sql exports.
+    pub target_entry : Entry,
+
+    // Size of exported plots, in points.
+    pub plot_width : SpinButton,
+    pub plot_height : SpinButton,
+
+    // Layout of .tex exports.
+    pub tex : TexOptionsBox
 }
 
 impl ExportDialog {
@@ -407,6 +771,41 @@ impl ExportDialog {
             FileChooserAction::Save,
             &[("Cancel", ResponseType::None), ("Save", ResponseType::Accept)]
         );
+
+        // Exporting all results is only possible to spreadsheets, with one sheet per table.
+        dialog.add_choice("tables", "Tables", &[("selected", "Selected result"), ("all", "All results (.xlsx or .ods)")]);
+        dialog.set_choice("tables", "selected");
+        let target_entry = Entry::new();
+        target_entry.set_placeholder_text(Some("Same as the queried table"));
+        target_entry.set_hexpand(true);
+        let target_lbl = Label::new(Some("Table name (.sql export)"));
+        let target_bx = Box::new(Orientation::Horizontal, 12);
+        super::set_margins(&target_bx, 12, 6);
+        target_bx.append(&target_lbl);
+        target_bx.append(&target_entry);
+        dialog.content_area().append(&target_bx);
+
+        // PDF pages either match the plot size or hold the plot scaled to fit. PNG plots have
+        // width * dpi / 72 by height * dpi / 72 pixels.
+        dialog.add_choice("page", "PDF page", &[("plot", "Plot size"), ("a4", "A4"), ("letter", "Letter")]);
+        dialog.set_choice("page", "plot");
+        dialog.add_choice("dpi", "PNG resolution", &[("72", "72 DPI"), ("150", "150 DPI"), ("300", "300 DPI"), ("600", "600 DPI")]);
+        dialog.set_choice("dpi", "150");
+        let defaults = super::PlotExportOptions::default();
+        let plot_width = SpinButton::with_range(72.0, 4096.0, 12.0);
+        let plot_height = SpinButton::with_range(72.0, 4096.0, 12.0);
+        plot_width.set_value(defaults.width);
+        plot_height.set_value(defaults.height);
+        let size_lbl = Label::new(Some("Plot size (points)"));
+        let size_bx = Box::new(Orientation::Horizontal, 12);
+        super::set_margins(&size_bx, 12, 6);
+        size_bx.append(&size_lbl);
+        size_bx.append(&plot_width);
+        size_bx.append(&Label::new(Some("×")));
+        size_bx.append(&plot_height);
+        dialog.content_area().append(&size_bx);
+        let tex = TexOptionsBox::build();
+        dialog.content_area().append(&tex.expander);
         dialog.connect_response(move |dialog, resp| {
             match resp {
                 ResponseType::Close | ResponseType::Reject | ResponseType::Accept |
@@ -417,7 +816,7 @@ impl ExportDialog {
             }
         });
         super::configure_dialog(&dialog);
-        Self { dialog }
+        Self { dialog, target_entry, plot_width, plot_height, tex }
     }
 
 }
@@ -433,6 +832,350 @@ impl React<MainMenu> for ExportDialog {
 
 }
 
+const TEX_ALIGNS : [(&'static str, &'static str, TexAlign); 4] = [
+    ("left", "Left", TexAlign::Left),
+    ("center", "Center", TexAlign::Center),
+    ("right", "Right", TexAlign::Right),
+    ("decimal", "Decimal point", TexAlign::Decimal)
+];
+
+/// Options of LaTeX table exports, shown at the export dialog.
+#[derive(Debug, Clone)]
+pub struct TexOptionsBox {
+    pub expander : Expander,
+    booktabs_check : CheckButton,
+    escape_check : CheckButton,
+    longtable_check : CheckButton,
+    standalone_check : CheckButton,
+    caption_entry : Entry,
+    label_entry : Entry,
+    number_combo : ComboBoxText,
+    text_combo : ComboBoxText,
+    precision_entry : Entry
+}
+
+impl TexOptionsBox {
+
+    pub fn build() -> Self {
+        let booktabs_check = CheckButton::with_label("Booktabs rules");
+        let escape_check = CheckButton::with_label("Escape special characters");
+        let longtable_check = CheckButton::with_label("Long table (breaks across pages)");
+        let standalone_check = CheckButton::with_label("Standalone document");
+        let caption_entry = Entry::new();
+        caption_entry.set_placeholder_text(Some("Caption"));
+        caption_entry.set_hexpand(true);
+        let label_entry = Entry::new();
+        label_entry.set_placeholder_text(Some("Label (e.g. tab:results)"));
+        label_entry.set_hexpand(true);
+        let number_combo = ComboBoxText::new();
+        let text_combo = ComboBoxText::new();
+        for (id, label, align) in TEX_ALIGNS.iter() {
+            number_combo.append(Some(id), label);
+            if *align != TexAlign::Decimal {
+                text_combo.append(Some(id), label);
+            }
+        }
+        let precision_entry = Entry::new();
+        precision_entry.set_placeholder_text(Some("Digits per column (e.g. price=2, ratio=4)"));
+        precision_entry.set_hexpand(true);
+
+        let grid = Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(12);
+        grid.attach(&booktabs_check, 0, 0, 1, 1);
+        grid.attach(&escape_check, 1, 0, 1, 1);
+        grid.attach(&longtable_check, 0, 1, 1, 1);
+        grid.attach(&standalone_check, 1, 1, 1, 1);
+        grid.attach(&caption_entry, 0, 2, 1, 1);
+        grid.attach(&label_entry, 1, 2, 1, 1);
+        for (col, (lbl, combo)) in [("Numbers", &number_combo), ("Text", &text_combo)].iter().enumerate() {
+            let bx = Box::new(Orientation::Horizontal, 12);
+            bx.append(&Label::new(Some(*lbl)));
+            bx.append(*combo);
+            grid.attach(&bx, col as i32, 3, 1, 1);
+        }
+        grid.attach(&precision_entry, 0, 4, 2, 1);
+        super::set_margins(&grid, 12, 6);
+        let expander = Expander::new(Some("LaTeX options (.tex export)"));
+        expander.set_child(Some(&grid));
+        super::set_margins(&expander, 12, 6);
+        let tex_box = Self {
+            expander,
+            booktabs_check,
+            escape_check,
+            longtable_check,
+            standalone_check,
+            caption_entry,
+            label_entry,
+            number_combo,
+            text_combo,
+            precision_entry
+        };
+        tex_box.set(&TexOptions::default());
+        tex_box
+    }
+
+    pub fn set(&self, opts : &TexOptions) {
+        let align_id = |align : TexAlign| TEX_ALIGNS.iter().find(|(_, _, a)| *a == align ).map(|(id, _, _)| *id );
+        self.booktabs_check.set_active(opts.booktabs);
+        self.escape_check.set_active(opts.escape);
+        self.longtable_check.set_active(opts.longtable);
+        self.standalone_check.set_active(opts.standalone);
+        self.caption_entry.set_text(&opts.caption);
+        self.label_entry.set_text(&opts.label);
+        self.number_combo.set_active_id(align_id(opts.number_align));
+        self.text_combo.set_active_id(align_id(opts.text_align));
+        self.precision_entry.set_text(&format_precision(&opts.precision[..]));
+    }
+
+    pub fn options(&self) -> Result<TexOptions, String> {
+        let align = |combo : &ComboBoxText| {
+            combo.active_id()
+                .and_then(|id| TEX_ALIGNS.iter().find(|(tid, _, _)| *tid == id.as_str() ).map(|(_, _, a)| *a ) )
+                .unwrap_or(TexAlign::Center)
+        };
+        Ok(TexOptions {
+            booktabs : self.booktabs_check.is_active(),
+            caption : self.caption_entry.text().trim().to_string(),
+            label : self.label_entry.text().trim().to_string(),
+            number_align : align(&self.number_combo),
+            text_align : align(&self.text_combo),
+            precision : parse_precision(&self.precision_entry.text())?,
+            escape : self.escape_check.is_active(),
+            longtable : self.longtable_check.is_active(),
+            standalone : self.standalone_check.is_active()
+        })
+    }
+
+}
+
+/// Chooses the directory every result table and plot is written to, together with a manifest.json
+/// describing the queries and the connection that produced them.
+#[derive(Debug, Clone)]
+pub struct ExportAllDialog {
+    pub dialog : FileChooserDialog
+}
+
+impl ExportAllDialog {
+
+    pub fn build() -> Self {
+        let dialog = FileChooserDialog::new(
+            Some("Export all"),
+            None::<&Window>,
+            FileChooserAction::SelectFolder,
+            &[("Cancel", ResponseType::None), ("Export", ResponseType::Accept)]
+        );
+        dialog.add_choice("table_format", "Tables", &[
+            ("csv", "CSV"),
+            ("json", "JSON"),
+            ("ndjson", "NDJSON"),
+            ("parquet", "Parquet"),
+            ("arrow", "Arrow IPC"),
+            ("xlsx", "XLSX"),
+            ("ods", "ODS"),
+            ("sql", "SQL"),
+            ("html", "HTML"),
+            ("md", "Markdown"),
+            ("tex", "LaTeX")
+        ]);
+        dialog.set_choice("table_format", "csv");
+        dialog.add_choice("plot_format", "Plots", &[("svg", "SVG"), ("png", "PNG"), ("pdf", "PDF"), ("eps", "EPS")]);
+        dialog.set_choice("plot_format", "svg");
+        dialog.connect_response(move |dialog, resp| {
+            match resp {
+                ResponseType::Close | ResponseType::Reject | ResponseType::Accept |
+                ResponseType::Yes | ResponseType::No | ResponseType::None => {
+                    dialog.close();
+                },
+                _ => { }
+            }
+        });
+        super::configure_dialog(&dialog);
+        Self { dialog }
+    }
+
+}
+
+impl React<MainMenu> for ExportAllDialog {
+
+    fn react(&self, menu : &MainMenu) {
+        let dialog = self.dialog.clone();
+        menu.action_export_all.connect_activate(move |_,_| {
+            dialog.show();
+        });
+    }
+
+}
+
+/// Chooses the file the result of the current script is streamed into, and shows the
+/// progress of the export while it runs.
+#[derive(Debug, Clone)]
+pub struct QueryExportDialog {
+    pub dialog : FileChooserDialog,
+    pub progress_dialog : Dialog,
+    progress_bar : ProgressBar,
+    progress_lbl : Label,
+    pub cancel_btn : Button,
+
+    // Script waiting for the user to choose the export file.
+    pub pending : Rc<RefCell<Option<String>>>
+}
+
+impl QueryExportDialog {
+
+    pub fn build() -> Self {
+        let dialog = FileChooserDialog::new(
+            Some("Export query to file"),
+            None::<&Window>,
+            FileChooserAction::Save,
+            &[("Cancel", ResponseType::None), ("Export", ResponseType::Accept)]
+        );
+        let filter = FileFilter::new();
+        for pattern in ["*.csv", "*.tsv", "*.json", "*.ndjson", "*.jsonl", "*.parquet", "*.arrow"] {
+            filter.add_pattern(pattern);
+        }
+        filter.set_name(Some("Query export files"));
+        dialog.add_filter(&filter);
+        dialog.connect_response(move |dialog, resp| {
+            match resp {
+                ResponseType::Close | ResponseType::Reject | ResponseType::Accept |
+                ResponseType::Yes | ResponseType::No | ResponseType::None => {
+                    dialog.close();
+                },
+                _ => { }
+            }
+        });
+        super::configure_dialog(&dialog);
+
+        let progress_dialog = Dialog::new();
+        progress_dialog.set_title(Some("Exporting query"));
+        progress_dialog.set_deletable(false);
+        super::configure_dialog(&progress_dialog);
+
+        let progress_lbl = Label::new(None);
+        progress_lbl.set_justify(Justification::Center);
+        let progress_bar = ProgressBar::new();
+        progress_bar.set_pulse_step(0.1);
+        progress_bar.set_margin_top(18);
+        progress_bar.set_width_request(320);
+
+        let cancel_btn = Button::builder().label("Cancel").build();
+        cancel_btn.style_context().add_class("pill");
+        cancel_btn.set_halign(Align::Center);
+        cancel_btn.set_margin_top(18);
+
+        let bx = Box::new(Orientation::Vertical, 0);
+        bx.append(&progress_lbl);
+        bx.append(&progress_bar);
+        bx.append(&cancel_btn);
+        super::set_margins(&bx, 32, 32);
+        progress_dialog.set_child(Some(&bx));
+
+        cancel_btn.connect_clicked({
+            let cancel_btn = cancel_btn.clone();
+            let progress_lbl = progress_lbl.clone();
+            move |_| {
+                cancel_btn.set_sensitive(false);
+                progress_lbl.set_text("Cancelling export…");
+            }
+        });
+        Self { dialog, progress_dialog, progress_bar, progress_lbl, cancel_btn, pending : Default::default() }
+    }
+
+}
+
+impl React<ExecButton> for QueryExportDialog {
+
+    fn react(&self, btn : &ExecButton) {
+        let dialog = self.dialog.clone();
+        let pending = self.pending.clone();
+        btn.export_action.connect_activate(move |_, param| {
+            if let Some(txt) = param.and_then(|p| p.get::<String>() ) {
+                pending.replace(Some(txt));
+                dialog.show();
+            }
+        });
+    }
+
+}
+
+impl React<ActiveConnection> for QueryExportDialog {
+
+    fn react(&self, conn : &ActiveConnection) {
+        conn.connect_export_started({
+            let dialog = self.clone();
+            move |path| {
+                dialog.progress_lbl.set_text(&format!("Exporting to {}", path));
+                dialog.progress_bar.set_fraction(0.0);
+                dialog.cancel_btn.set_sensitive(true);
+                dialog.progress_dialog.show();
+            }
+        });
+        conn.connect_export_progress({
+            let dialog = self.clone();
+            move |n| {
+                if dialog.cancel_btn.is_sensitive() {
+                    dialog.progress_lbl.set_text(&format!("{} row(s) written", n));
+                }
+                dialog.progress_bar.pulse();
+            }
+        });
+        conn.connect_export_finished({
+            let progress_dialog = self.progress_dialog.clone();
+            move |_| {
+                progress_dialog.close();
+            }
+        });
+    }
+
+}
+
+/// Opens a CSV, JSON, NDJSON, Parquet or Arrow file as a result table, without a database connection.
+#[derive(Debug, Clone)]
+pub struct OpenTableDialog {
+    pub dialog : FileChooserDialog
+}
+
+impl OpenTableDialog {
+
+    pub fn build() -> Self {
+        let dialog = FileChooserDialog::new(
+            Some("Open table"),
+            None::<&Window>,
+            FileChooserAction::Open,
+            &[("Cancel", ResponseType::None), ("Open", ResponseType::Accept)]
+        );
+        dialog.connect_response(move |dialog, resp| {
+            match resp {
+                ResponseType::Close | ResponseType::Reject | ResponseType::Accept |
+                ResponseType::Yes | ResponseType::No | ResponseType::None => {
+                    dialog.close();
+                },
+                _ => { }
+            }
+        });
+        super::configure_dialog(&dialog);
+        let filter = FileFilter::new();
+        for pattern in ["*.csv", "*.json", "*.ndjson", "*.jsonl", "*.parquet", "*.arrow"] {
+            filter.add_pattern(pattern);
+        }
+        dialog.set_filter(&filter);
+        Self { dialog }
+    }
+
+}
+
+impl React<MainMenu> for OpenTableDialog {
+
+    fn react(&self, menu : &MainMenu) {
+        let dialog = self.dialog.clone();
+        menu.action_open_table.connect_activate(move |_,_| {
+            dialog.show();
+        });
+    }
+
+}
+
 #[derive(Debug, Clone)]
 pub struct FindDialog {
     pub dialog  : Dialog,
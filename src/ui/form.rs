@@ -6,6 +6,12 @@ For a copy, see http://www.gnu.org/licenses.*/
 use gtk4::prelude::*;
 use gtk4::*;
 use crate::sql::object::{DBObject};
+use crate::sql::parsing::ScriptParameter;
+use crate::client::ActiveConnection;
+use stateful::React;
+use std::rc::Rc;
+use std::cell::RefCell;
+use super::NamedBox;
 
 pub const MAX_ENTRIES : usize = 32;
 
@@ -122,4 +128,122 @@ impl Form {
 
 }
 
+/* Asks for the values of the script parameters before it is executed. Empty
+entries are bound to NULL. */
+#[derive(Debug, Clone)]
+pub struct ParametersDialog {
+    pub dialog : Dialog,
+    pub exec_btn : Button,
+    list : ListBox,
+
+    // Entries for the parameters of the pending script, in the order of the parameter names.
+    pub entries : Rc<RefCell<Vec<Entry>>>,
+
+    // Statement sequence waiting for the parameter values, with the parameter names.
+    pub pending : Rc<RefCell<Option<(String, Vec<String>)>>>
+}
+
+impl ParametersDialog {
+
+    pub fn build() -> Self {
+        let dialog = Dialog::new();
+        dialog.set_title(Some("Script parameters"));
+        super::configure_dialog(&dialog);
+
+        let msg = Label::new(Some("Inform the parameter values\n(leave empty for NULL)"));
+        msg.set_justify(Justification::Center);
+        msg.set_margin_bottom(18);
+
+        let list = ListBox::new();
+        super::configure_list(&list);
+        let scroll = ScrolledWindow::new();
+        scroll.set_child(Some(&list));
+        scroll.set_min_content_height(240);
+        scroll.set_propagate_natural_height(true);
+
+        let cancel_btn = Button::builder().label("Cancel").build();
+        let exec_btn = Button::builder().label("Execute").build();
+        cancel_btn.style_context().add_class("pill");
+        exec_btn.style_context().add_class("pill");
+        exec_btn.style_context().add_class("suggested-action");
+        let btn_bx = Box::new(Orientation::Horizontal, 16);
+        btn_bx.set_halign(Align::Center);
+        btn_bx.append(&cancel_btn);
+        btn_bx.append(&exec_btn);
+        btn_bx.set_margin_top(18);
+
+        let bx = Box::new(Orientation::Vertical, 0);
+        bx.append(&msg);
+        bx.append(&scroll);
+        bx.append(&btn_bx);
+        super::set_margins(&bx, 32, 32);
+        dialog.set_child(Some(&bx));
+
+        let pending : Rc<RefCell<Option<(String, Vec<String>)>>> = Default::default();
+        let entries : Rc<RefCell<Vec<Entry>>> = Default::default();
+        cancel_btn.connect_clicked({
+            let dialog = dialog.clone();
+            let pending = pending.clone();
+            move |_| {
+                pending.replace(None);
+                dialog.close();
+            }
+        });
+        exec_btn.connect_clicked({
+            let dialog = dialog.clone();
+            move |_| {
+                dialog.close();
+            }
+        });
+        Self { dialog, exec_btn, list, entries, pending }
+    }
+
+}
+
+impl React<ActiveConnection> for ParametersDialog {
 
+    fn react(&self, conn : &ActiveConnection) {
+        let dialog = self.clone();
+        conn.connect_parameters_request(move |(stmts, params, last)| {
+            while let Some(row) = dialog.list.row_at_index(0) {
+                dialog.list.remove(&row);
+            }
+            let mut entries = Vec::new();
+            for param in params.iter() {
+                let entry = Entry::new();
+                entry.set_placeholder_text(Some("NULL"));
+                if let Some(val) = last.get(&param.name) {
+                    entry.set_text(val);
+                }
+                let ty = param.ty.as_ref().map(|ty| ty.to_string() );
+                if let Some(ty) = &param.ty {
+                    entry.set_primary_icon_name(Some(super::get_type_icon_name(ty)));
+                }
+
+                // Activating the last entry executes the script.
+                entry.connect_activate({
+                    let exec_btn = dialog.exec_btn.clone();
+                    let n = params.len();
+                    let ix = entries.len();
+                    move |_| {
+                        if ix == n - 1 {
+                            exec_btn.emit_clicked();
+                        }
+                    }
+                });
+                let name = glib::markup_escape_text(&param.name);
+                dialog.list.append(&NamedBox::new(&name, ty.as_deref(), entry.clone()).bx);
+                entries.push(entry);
+            }
+            super::set_all_not_selectable(&dialog.list);
+            if let Some(first) = entries.first() {
+                first.grab_focus();
+            }
+            dialog.entries.replace(entries);
+            let names = params.into_iter().map(|p| p.name ).collect();
+            dialog.pending.replace(Some((stmts, names)));
+            dialog.dialog.show();
+        });
+    }
+
+}
@@ -14,11 +14,14 @@ pub struct MainMenu {
     pub popover : PopoverMenu,
     pub action_new : gio::SimpleAction,
     pub action_open : gio::SimpleAction,
+    pub action_open_table : gio::SimpleAction,
     pub action_save : gio::SimpleAction,
     pub action_save_as : gio::SimpleAction,
     pub action_export : gio::SimpleAction,
+    pub action_export_all : gio::SimpleAction,
     pub action_settings : gio::SimpleAction,
-    pub action_find_replace : gio::SimpleAction
+    pub action_find_replace : gio::SimpleAction,
+    pub action_format : gio::SimpleAction
 }
 
 impl MainMenu {
@@ -27,26 +30,34 @@ impl MainMenu {
         let menu = gio::Menu::new();
         menu.append(Some("New"), Some("win.new_file"));
         menu.append(Some("Open"), Some("win.open_file"));
+        menu.append(Some("Open table"), Some("win.open_table"));
         menu.append(Some("Save"), Some("win.save_file"));
         menu.append(Some("Save as"), Some("win.save_as_file"));
         menu.append(Some("Find and replace"), Some("win.find_replace"));
+        menu.append(Some("Format SQL"), Some("win.format_sql"));
         menu.append(Some("Export"), Some("win.export"));
+        menu.append(Some("Export all"), Some("win.export_all"));
         menu.append(Some("Settings"), Some("win.settings"));
         let popover = PopoverMenu::from_model(Some(&menu));
 
         let action_new = gio::SimpleAction::new("new_file", None);
         let action_open = gio::SimpleAction::new("open_file", None);
+        let action_open_table = gio::SimpleAction::new("open_table", None);
         let action_save = gio::SimpleAction::new("save_file", None);
         let action_save_as = gio::SimpleAction::new("save_as_file", None);
         let action_export = gio::SimpleAction::new("export", None);
+        let action_export_all = gio::SimpleAction::new("export_all", None);
         let action_settings = gio::SimpleAction::new("settings", None);
         let action_find_replace = gio::SimpleAction::new("find_replace", None);
+        let action_format = gio::SimpleAction::new("format_sql", None);
         action_save.set_enabled(false);
         action_save_as.set_enabled(false);
         action_export.set_enabled(false);
+        action_export_all.set_enabled(false);
         action_find_replace.set_enabled(false);
+        action_format.set_enabled(false);
 
-        Self { popover, action_new, action_open, action_save, action_save_as, action_export, action_settings, action_find_replace }
+        Self { popover, action_new, action_open, action_open_table, action_save, action_save_as, action_export, action_export_all, action_settings, action_find_replace, action_format }
     }
 
 }
@@ -55,20 +66,20 @@ impl React<QueriesContent> for MainMenu {
 
     fn react(&self, content : &QueriesContent) {
         let save_actions = [self.action_save.clone(), self.action_save_as.clone()];
-        let export_action = self.action_export.clone();
+        let export_actions = [self.action_export.clone(), self.action_export_all.clone()];
         let results_stack = content.results.stack.clone();
         content.stack.connect_visible_child_notify(move |stack| {
             if let Some(name) = stack.visible_child_name() {
                 match name.as_str() {
                     "editor" => {
                         save_actions.iter().for_each(|action| action.set_enabled(true) );
-                        export_action.set_enabled(false);
+                        export_actions.iter().for_each(|action| action.set_enabled(false) );
                     },
                     "results" => {
                         save_actions.iter().for_each(|action| action.set_enabled(false) );
                         if let Some(name) = results_stack.visible_child_name() {
                             if name.as_str() == "tables" {
-                                export_action.set_enabled(true);
+                                export_actions.iter().for_each(|action| action.set_enabled(true) );
                             }
                         }
                     },
@@ -77,15 +88,15 @@ impl React<QueriesContent> for MainMenu {
             }
         });
         content.results.stack.connect_visible_child_notify({
-            let export_action = self.action_export.clone();
+            let export_actions = [self.action_export.clone(), self.action_export_all.clone()];
             move |stack| {
                 if let Some(name) = stack.visible_child_name() {
                     match name.as_str() {
                         "tables" => {
-                            export_action.set_enabled(true);
+                            export_actions.iter().for_each(|action| action.set_enabled(true) );
                         },
                         "overview" => {
-                            export_action.set_enabled(false);
+                            export_actions.iter().for_each(|action| action.set_enabled(false) );
                         },
                         _ => { }
                     }
@@ -99,11 +110,14 @@ impl React<OpenedScripts> for MainMenu {
 
     fn react(&self, scripts : &OpenedScripts) {
         let action_find_replace = self.action_find_replace.clone();
+        let action_format = self.action_format.clone();
         scripts.connect_selected(move |opt_file| {
             if let Some(_) = opt_file.map(|f| f.index ) {
                 action_find_replace.set_enabled(true);
+                action_format.set_enabled(true);
             } else {
                 action_find_replace.set_enabled(false);
+                action_format.set_enabled(false);
             }
         });
     }
@@ -54,6 +54,10 @@ mod table;
 
 pub use table::*;
 
+mod record;
+
+pub use record::*;
+
 mod plotarea;
 
 pub use plotarea::*;
@@ -109,6 +113,16 @@ impl React<Environment> for QueriesContent {
             content_stack.set_visible_child_name("results");
             results_stack.set_visible_child_name("tables");
         });
+        env.connect_notices_update({
+            let content_stack = self.stack.clone();
+            let results_stack = self.results.stack.clone();
+            move |notices| {
+                if notices.len() > 0 {
+                    content_stack.set_visible_child_name("results");
+                    results_stack.set_visible_child_name("tables");
+                }
+            }
+        });
         env.connect_export_error({
             let overlay = self.overlay.clone();
             let curr_toast = self.curr_toast.clone();
@@ -117,7 +131,7 @@ impl React<Environment> for QueriesContent {
                 if let Some(t) = last_toast.take() {
                     t.dismiss();
                 }
-                let toast = libadwaita::Toast::builder().title(&msg[..]).build();
+                let toast = libadwaita::Toast::builder().title(&error_markup(&msg[..])).build();
                 overlay.add_toast(&toast);
                 connect_toast_dismissed(&toast, &curr_toast);
                 *last_toast = Some(toast);
@@ -131,7 +145,7 @@ impl React<Environment> for QueriesContent {
                 if let Some(t) = last_toast.take() {
                     t.dismiss();
                 }
-                let toast = libadwaita::Toast::builder().title(&msg[..]).build();
+                let toast = libadwaita::Toast::builder().title(&error_markup(&msg[..])).build();
                 overlay.add_toast(&toast);
                 connect_toast_dismissed(&toast, &curr_toast);
                 *last_toast = Some(toast);
@@ -193,7 +207,7 @@ impl React<OpenedScripts> for QueriesContent {
                 if let Some(t) = last_toast.take() {
                     t.dismiss();
                 }
-                let toast = libadwaita::Toast::builder().title(&msg[..]).build();
+                let toast = libadwaita::Toast::builder().title(&error_markup(&msg[..])).build();
                 overlay.add_toast(&toast);
                 *last_toast = Some(toast);
             }
@@ -239,7 +253,7 @@ impl React<ActiveConnection> for QueriesContent {
                 if let Some(t) = last_toast.take() {
                     t.dismiss();
                 }
-                let toast = libadwaita::Toast::builder().title(&err).build();
+                let toast = libadwaita::Toast::builder().title(&error_markup(&err)).build();
                 overlay.add_toast(&toast);
                 connect_toast_dismissed(&toast, &curr_toast);
                 *last_toast = Some(toast);
@@ -261,7 +275,7 @@ impl React<ActiveConnection> for QueriesContent {
                 if let Some(t) = last_toast.take() {
                     t.dismiss();
                 }
-                let toast = libadwaita::Toast::builder().title(&err).build();
+                let toast = libadwaita::Toast::builder().title(&error_markup(&err)).build();
                 overlay.add_toast(&toast);
                 connect_toast_dismissed(&toast, &curr_toast);
                 *last_toast = Some(toast);
@@ -292,7 +306,7 @@ impl React<ActiveConnection> for QueriesContent {
                     if let Some(t) = last_toast.take() {
                         t.dismiss();
                     }
-                    let toast = libadwaita::Toast::builder().title(&msg).build();
+                    let toast = libadwaita::Toast::builder().title(&error_markup(&msg)).build();
                     overlay.add_toast(&toast);
                     connect_toast_dismissed(&toast, &curr_toast);
                     *last_toast = Some(toast);
@@ -350,7 +364,11 @@ pub struct QueriesWindow {
     pub sidebar : QueriesSidebar,
     pub content : QueriesContent,
     pub settings : QueriesSettings,
-    pub find_dialog : FindDialog
+    pub find_dialog : FindDialog,
+    pub safety_dialog : SafetyDialog,
+    pub disconnect_dialog : DisconnectDialog,
+    pub params_dialog : ParametersDialog,
+    pub query_export_dialog : QueryExportDialog
 }
 
 impl QueriesWindow {
@@ -361,16 +379,27 @@ impl QueriesWindow {
         let titlebar = QueriesTitlebar::build();
         let content = QueriesContent::build(state);
         let find_dialog = FindDialog::build();
+        let safety_dialog = SafetyDialog::build();
+        let disconnect_dialog = DisconnectDialog::build();
+        let params_dialog = ParametersDialog::build();
+        let query_export_dialog = QueryExportDialog::build();
 
         content.editor.save_dialog.0.dialog.set_transient_for(Some(&window));
         content.editor.open_dialog.0.dialog.set_transient_for(Some(&window));
         content.editor.export_dialog.dialog.set_transient_for(Some(&window));
+        content.editor.export_all_dialog.dialog.set_transient_for(Some(&window));
+        content.editor.open_table_dialog.dialog.set_transient_for(Some(&window));
         sidebar.schema_tree.form.dialog.set_transient_for(Some(&window));
         sidebar.schema_tree.report_dialog.dialog.set_transient_for(Some(&window));
         sidebar.schema_tree.report_export_dialog.dialog.set_transient_for(Some(&window));
         sidebar.schema_tree.import_dialog.dialog.set_transient_for(Some(&window));
-        sidebar.schema_tree.react(&content.results.overview.conn_bx);
+        sidebar.schema_tree.import_wizard.dialog.set_transient_for(Some(&window));
         find_dialog.dialog.set_transient_for(Some(&window));
+        safety_dialog.dialog.set_transient_for(Some(&window));
+        disconnect_dialog.dialog.set_transient_for(Some(&window));
+        params_dialog.dialog.set_transient_for(Some(&window));
+        query_export_dialog.dialog.set_transient_for(Some(&window));
+        query_export_dialog.progress_dialog.set_transient_for(Some(&window));
 
         titlebar.header.set_title_widget(Some(&content.switcher));
 
@@ -387,16 +416,22 @@ impl QueriesWindow {
         // Add actions to main menu
         window.add_action(&titlebar.main_menu.action_new);
         window.add_action(&titlebar.main_menu.action_open);
+        window.add_action(&titlebar.main_menu.action_open_table);
         window.add_action(&titlebar.main_menu.action_save);
         window.add_action(&titlebar.main_menu.action_find_replace);
+        window.add_action(&titlebar.main_menu.action_format);
         window.add_action(&titlebar.main_menu.action_save_as);
         window.add_action(&titlebar.main_menu.action_export);
+        window.add_action(&titlebar.main_menu.action_export_all);
         window.add_action(&titlebar.main_menu.action_settings);
         window.add_action(&content.editor.ignore_file_save_action);
         window.add_action(&titlebar.sidebar_hide_action);
 
         // Add actions to execution menu
         window.add_action(&titlebar.exec_btn.queue_exec_action);
+        window.add_action(&titlebar.exec_btn.queue_current_action);
+        window.add_action(&titlebar.exec_btn.queue_export_action);
+        window.add_action(&titlebar.exec_btn.export_action);
         window.add_action(&titlebar.exec_btn.exec_action);
         window.add_action(&titlebar.exec_btn.clear_action);
         window.add_action(&titlebar.exec_btn.schedule_action);
@@ -414,10 +449,14 @@ impl QueriesWindow {
 
         content.editor.open_dialog.react(&titlebar.main_menu);
         content.editor.export_dialog.react(&titlebar.main_menu);
+        content.editor.export_all_dialog.react(&titlebar.main_menu);
+        content.editor.open_table_dialog.react(&titlebar.main_menu);
 
         content.react(&sidebar.file_list);
         titlebar.exec_btn.react(&sidebar.file_list);
         content.editor.react(&titlebar.exec_btn);
+        query_export_dialog.react(&titlebar.exec_btn);
+        content.editor.react(&titlebar.main_menu);
         content.react(&titlebar.exec_btn);
         content.react(&content.results.workspace);
         titlebar.main_menu.react(&content);
@@ -428,7 +467,7 @@ impl QueriesWindow {
         settings.react(&titlebar.main_menu);
         window.add_action(&settings.security_bx.cert_removed);
 
-        Self { paned, sidebar, titlebar, content, window, settings, find_dialog }
+        Self { paned, sidebar, titlebar, content, window, settings, find_dialog, safety_dialog, disconnect_dialog, params_dialog, query_export_dialog }
     }
 }
 
@@ -581,6 +620,25 @@ pub fn show_popover_on_toggle(popover : &Popover, toggle : &ToggleButton, alt :
     });
 }
 
+const ERROR_LABELS : [&'static str; 4] = ["Error", "Statement", "Detail", "Hint"];
+
+/// Toast titles are interpreted as markup, so messages are escaped, and the labels
+/// of error lines (e.g. Detail: or Hint:) are highlighted.
+pub fn error_markup(msg : &str) -> String {
+    let escaped = glib::markup_escape_text(msg).to_string();
+    escaped.lines()
+        .map(|line| {
+            let label = ERROR_LABELS.iter()
+                .find_map(|label| line.strip_prefix(label)?.strip_prefix(": ").map(|rest| (label, rest) ) );
+            match label {
+                Some((label, rest)) => format!("<b>{}</b> {}", label, rest),
+                None => line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 pub fn title_label(txt : &str) -> Label {
     let lbl = Label::builder()
         .label(&format!("<span font_weight=\"600\" font_size=\"large\" fgalpha=\"60%\">{}</span>", txt))
@@ -662,7 +720,7 @@ pub fn get_type_icon_name(ty : &DBType) -> &'static str {
         DBType::F32 | DBType::F64 | DBType::Numeric => "type-real-symbolic",
         DBType::Text => "type-text-symbolic",
         DBType::Date => "type-date-symbolic",
-        DBType::Time => "type-time-symbolic",
+        DBType::Time | DBType::Timestamp => "type-time-symbolic",
         DBType::Json => "type-json-symbolic",
         DBType::Xml => "type-xml-symbolic",
         DBType::Bytes => "type-binary-symbolic",
@@ -12,7 +12,11 @@ use crate::ui::PackedImagePasswordEntry;
 use crate::ui::PackedImageLabel;
 use crate::client::ConnectionSet;
 use std::time::Duration;
-use crate::client::ActiveConnection;
+use crate::client::{ActiveConnection, PendingExecution};
+use std::rc::Rc;
+use std::cell::RefCell;
+use crate::sql::{SafetyPolicies, SafetyRule, Policy};
+use super::NamedBox;
 
 #[derive(Debug, Clone)]
 pub struct QueriesOverview {
@@ -134,6 +138,30 @@ impl React<ActiveConnection> for DetailBox {
                 locale_lbl.set_text("");
             }
         });
+
+        conn.connect_session_switched({
+            let (server_lbl, size_lbl, uptime_lbl, locale_lbl) = (
+                self.server_lbl.clone(),
+                self.size_lbl.clone(),
+                self.uptime_lbl.clone(),
+                self.locale_lbl.clone()
+            );
+            move |opt_session| {
+                if let Some((_, _, db_info)) = opt_session {
+                    if let Some(details) = db_info.as_ref().and_then(|info| info.details.as_ref() ) {
+                        server_lbl.set_text(&details.server);
+                        size_lbl.set_text(&details.size);
+                        uptime_lbl.set_text(&details.uptime);
+                        locale_lbl.set_text(&details.locale);
+                    } else {
+                        server_lbl.set_text("Unknown");
+                        size_lbl.set_text("Unknown");
+                        uptime_lbl.set_text("Unknown");
+                        locale_lbl.set_text("Unknown");
+                    }
+                }
+            }
+        });
     }
 }
 
@@ -142,7 +170,8 @@ pub struct ConnectionRow {
     row : ListBoxRow,
     host : PackedImageLabel,
     db : PackedImageLabel,
-    user : PackedImageLabel
+    user : PackedImageLabel,
+    read_only_badge : Label
 }
 
 impl ConnectionRow {
@@ -152,6 +181,7 @@ impl ConnectionRow {
         row.host.change_label(&info.host);
         row.db.change_label(&info.database);
         row.user.change_label(&info.user);
+        row.read_only_badge.set_visible(info.read_only);
         row
     }
 
@@ -165,6 +195,7 @@ impl ConnectionRow {
             host : PackedImageLabel::extract(&bx_host)?,
             db : PackedImageLabel::extract(&bx_db)?,
             user : PackedImageLabel::extract(&bx_user)?,
+            read_only_badge : super::get_child_by_index::<Label>(&bx, 3)
         })
     }
 
@@ -175,6 +206,14 @@ impl ConnectionRow {
         let user = PackedImageLabel::build("avatar-default-symbolic", "User");
         let bx = Box::new(Orientation::Vertical, 0);
         [&host, &db, &user].iter().for_each(|w| bx.append(&w.bx));
+        let read_only_badge = Label::new(Some("Read-only"));
+        read_only_badge.style_context().add_class("caption-heading");
+        read_only_badge.style_context().add_class("warning");
+        read_only_badge.set_halign(Align::End);
+        read_only_badge.set_margin_end(12);
+        read_only_badge.set_margin_bottom(6);
+        read_only_badge.set_visible(false);
+        bx.append(&read_only_badge);
         let row = ListBoxRow::new();
         row.set_activatable(false);
         row.set_selectable(true);
@@ -182,7 +221,7 @@ impl ConnectionRow {
 
         viewp.set_child(Some(&bx));
         row.set_child(Some(&viewp));
-        ConnectionRow { row, host, db, user }
+        ConnectionRow { row, host, db, user, read_only_badge }
     }
 
 }
@@ -300,24 +339,18 @@ impl React<ConnectionSet> for ConnectionList {
 impl React<ActiveConnection> for ConnectionList {
 
     fn react(&self, conn : &ActiveConnection) {
-        let (add_btn, remove_btn) = (self.add_btn.clone(), self.remove_btn.clone());
-        let list = self.list.clone();
+
+        // The list stays sensitive while connected, so that other connections can be
+        // opened at the same time. Only the row of an open connection cannot be removed.
+        let remove_btn = self.remove_btn.clone();
         conn.connect_db_connected(move |_| {
-            add_btn.set_sensitive(false);
             remove_btn.set_sensitive(false);
-            if list.is_sensitive() {
-                list.set_sensitive(false);
-            }
         });
 
-        let (add_btn, remove_btn) = (self.add_btn.clone(), self.remove_btn.clone());
+        let remove_btn = self.remove_btn.clone();
         let list = self.list.clone();
-        conn.connect_db_disconnected(move |_| {
-            add_btn.set_sensitive(true);
-            remove_btn.set_sensitive(true);
-            if !list.is_sensitive() {
-                list.set_sensitive(true);
-            }
+        conn.connect_session_closed(move |_| {
+            remove_btn.set_sensitive(list.selected_row().is_some());
         });
     }
 
@@ -335,6 +368,16 @@ impl React<ConnectionBox> for ConnectionList {
                 }
             });
         }
+        bx.read_only_btn.connect_toggled({
+            let list = self.list.clone();
+            move |btn| {
+                if let Some(row) = list.selected_row() {
+                    let vp = row.child().unwrap().downcast::<Viewport>().unwrap();
+                    let bx = vp.child().unwrap().downcast::<Box>().unwrap();
+                    super::get_child_by_index::<Label>(&bx, 3).set_visible(btn.is_active());
+                }
+            }
+        });
     }
 
 }
@@ -368,8 +411,14 @@ pub struct ConnectionBox {
     pub user : PackedImageEntry,
     pub db : PackedImageEntry,
     pub password : PackedImagePasswordEntry,
+    pub policy_btn : PolicyButton,
+    pub read_only_btn : ToggleButton,
     pub switch : Switch,
-    bx : Box
+    bx : Box,
+
+    // Connections with an open session, used to set the switch state
+    // when the user selects a connection at the list.
+    open : Rc<RefCell<Vec<ConnectionInfo>>>
 }
 
 impl ConnectionBox {
@@ -380,11 +429,19 @@ impl ConnectionBox {
         let cred_bx = Box::new(Orientation::Horizontal, 0);
         let user = PackedImageEntry::build("avatar-default-symbolic", "User");
         let password = PackedImagePasswordEntry::build("dialog-password-symbolic", "Password");
+        let policy_btn = PolicyButton::build();
+        let read_only_btn = ToggleButton::new();
+        read_only_btn.set_icon_name("changes-prevent-symbolic");
+        read_only_btn.set_tooltip_text(Some("Read-only session\n(set before connecting)"));
+        read_only_btn.set_valign(Align::Center);
+        read_only_btn.style_context().add_class("flat");
         let switch = Switch::new();
         switch.set_valign(Align::Center);
         switch.set_vexpand(false);
         cred_bx.append(&user.bx);
         cred_bx.append(&password.bx);
+        cred_bx.append(&read_only_btn);
+        cred_bx.append(&policy_btn.btn);
         cred_bx.append(&switch);
         let title = super::title_label("Authentication");
         let bx = Box::new(Orientation::Vertical, 0);
@@ -404,8 +461,11 @@ impl ConnectionBox {
             user,
             db,
             password,
+            policy_btn,
+            read_only_btn,
             bx,
-            switch
+            switch,
+            open : Default::default()
         };
         conn_bx.set_sensitive(false);
         conn_bx
@@ -419,12 +479,16 @@ impl ConnectionBox {
         &self.password.entry
     }
 
+    // The read-only flag is only applied when the session starts, so it cannot change
+    // while the connection is open.
     fn _set_db_loaded_mode(&self) {
         self.entries().iter().for_each(|entry| entry.set_sensitive(false) );
+        self.read_only_btn.set_sensitive(false);
     }
 
     pub fn set_non_db_mode(&self) {
         self.entries().iter().for_each(|entry| entry.set_sensitive(true) );
+        self.read_only_btn.set_sensitive(true);
     }
 
     pub fn update_info(&self, info : &ConnectionInfo) {
@@ -449,6 +513,8 @@ impl ConnectionBox {
         self.db.entry.set_sensitive(sensitive);
         self.user.entry.set_sensitive(sensitive);
         self.password.entry.set_sensitive(sensitive);
+        self.policy_btn.btn.set_sensitive(sensitive);
+        self.read_only_btn.set_sensitive(sensitive);
         self.switch.set_sensitive(sensitive);
     }
 
@@ -461,8 +527,16 @@ impl React<ConnectionSet> for ConnectionBox {
             let conn_bx = self.clone();
             move |opt_sel| {
                 if let Some((_sel_ix, sel_info)) = opt_sel {
+                    let is_open = conn_bx.open.borrow().iter().any(|info| info.is_like(&sel_info) );
                     conn_bx.set_sensitive(true);
-                    if sel_info.is_default() {
+                    if is_open {
+                        conn_bx.host.entry.set_text(&sel_info.host);
+                        conn_bx.db.entry.set_text(&sel_info.database);
+                        conn_bx.user.entry.set_text(&sel_info.user);
+                        conn_bx.password.entry.set_text("");
+                        conn_bx._set_db_loaded_mode();
+                        conn_bx.password.entry.set_sensitive(false);
+                    } else if sel_info.is_default() {
                         conn_bx.host.entry.set_text("");
                         conn_bx.db.entry.set_text("");
                         conn_bx.user.entry.set_text("");
@@ -474,6 +548,11 @@ impl React<ConnectionSet> for ConnectionBox {
                         conn_bx.password.entry.set_text("");
                         conn_bx.password.entry.grab_focus();
                     }
+                    conn_bx.policy_btn.set_policies(&sel_info.policies);
+                    conn_bx.read_only_btn.set_active(sel_info.read_only);
+
+                    // Set only after the entries, which identify the connection toggled by the switch.
+                    conn_bx.switch.set_active(is_open);
                 } else {
                     conn_bx.host.entry.set_text("");
                     conn_bx.db.entry.set_text("");
@@ -500,6 +579,39 @@ impl React<ActiveConnection> for ConnectionBox {
         conn.connect_db_conn_failure(move |_| {
             disconnect_with_delay(switch.clone());
         });
+        conn.connect_db_connected({
+            let conn_bx = self.clone();
+            move |(info, _)| {
+                conn_bx.open.borrow_mut().push(info);
+                conn_bx._set_db_loaded_mode();
+                conn_bx.password.entry.set_text("");
+                conn_bx.password.entry.set_sensitive(false);
+            }
+        });
+        conn.connect_session_closed({
+            let conn_bx = self.clone();
+            move |(_, info)| {
+                conn_bx.open.borrow_mut().retain(|open| !open.is_like(&info) );
+                conn_bx.set_non_db_mode();
+                conn_bx.password.entry.set_sensitive(true);
+
+                // Sessions closed after the user confirmed discarding changes keep the switch on.
+                let same_conn = conn_bx.host.entry.text().as_str() == &info.host[..] &&
+                    conn_bx.db.entry.text().as_str() == &info.database[..] &&
+                    conn_bx.user.entry.text().as_str() == &info.user[..];
+                if same_conn && conn_bx.switch.is_active() {
+                    conn_bx.switch.set_active(false);
+                }
+            }
+        });
+
+        // The session was kept open, so the switch goes back on.
+        conn.connect_disconnect_warning({
+            let switch = self.switch.clone();
+            move |_| {
+                switch.set_active(true);
+            }
+        });
     }
 
 }
@@ -510,3 +622,232 @@ pub fn disconnect_with_delay(switch : Switch) {
         glib::Continue(false)
     });
 }
+
+/* Lets the user choose what happens to statements matching each safety rule,
+for the connection selected at the list. */
+#[derive(Debug, Clone)]
+pub struct PolicyButton {
+    pub btn : MenuButton,
+    pub combos : Vec<(SafetyRule, ComboBoxText)>
+}
+
+impl PolicyButton {
+
+    pub fn build() -> Self {
+        let btn = MenuButton::new();
+        btn.set_icon_name("security-high-symbolic");
+        btn.set_tooltip_text(Some("Safety policies"));
+        btn.set_valign(Align::Center);
+        btn.style_context().add_class("flat");
+
+        let list = ListBox::new();
+        list.style_context().add_class("boxed-list");
+        let defaults = SafetyPolicies::default();
+        let mut combos = Vec::new();
+        for rule in SafetyRule::ALL {
+            let combo = ComboBoxText::new();
+            combo.append(Some(Policy::Allow.id()), "Allow");
+            combo.append(Some(Policy::Confirm.id()), "Confirm");
+            combo.append(Some(Policy::Deny.id()), "Deny");
+            combo.set_active_id(Some(defaults.get(rule).id()));
+            combo.set_margin_start(12);
+            list.append(&NamedBox::new(rule.name(), None, combo.clone()).bx);
+            combos.push((rule, combo));
+        }
+        super::set_all_not_selectable(&list);
+        super::set_margins(&list, 6, 6);
+
+        let popover = Popover::new();
+        popover.set_child(Some(&list));
+        btn.set_popover(Some(&popover));
+        Self { btn, combos }
+    }
+
+    pub fn set_policies(&self, policies : &SafetyPolicies) {
+        for (rule, combo) in &self.combos {
+            combo.set_active_id(Some(policies.get(*rule).id()));
+        }
+    }
+
+    pub fn policies(&self) -> SafetyPolicies {
+        let mut policies = SafetyPolicies::default();
+        for (rule, combo) in &self.combos {
+            if let Some(policy) = combo.active_id().and_then(|id| id.parse::<Policy>().ok() ) {
+                policies.set(*rule, policy);
+            }
+        }
+        policies
+    }
+
+}
+
+/* Lists the statements matching rules set to Policy::Confirm, which are only
+sent to the server if the user clicks the execute button. */
+#[derive(Debug, Clone)]
+pub struct SafetyDialog {
+    pub dialog : Dialog,
+    pub exec_btn : Button,
+    list : ListBox,
+
+    // Statement sequence (or import) waiting for confirmation.
+    pub pending : Rc<RefCell<Option<PendingExecution>>>
+}
+
+impl SafetyDialog {
+
+    pub fn build() -> Self {
+        let dialog = Dialog::new();
+        dialog.set_title(Some("Confirm execution"));
+        super::configure_dialog(&dialog);
+
+        let msg = Label::new(Some("The following statements require confirmation\nbefore being sent to the database"));
+        msg.set_justify(Justification::Center);
+        msg.set_margin_bottom(18);
+
+        let list = ListBox::new();
+        super::configure_list(&list);
+        let scroll = ScrolledWindow::new();
+        scroll.set_child(Some(&list));
+        scroll.set_min_content_height(240);
+        scroll.set_propagate_natural_height(true);
+
+        let cancel_btn = Button::builder().label("Cancel").build();
+        let exec_btn = Button::builder().label("Execute").build();
+        cancel_btn.style_context().add_class("pill");
+        exec_btn.style_context().add_class("pill");
+        exec_btn.style_context().add_class("destructive-action");
+        let btn_bx = Box::new(Orientation::Horizontal, 16);
+        btn_bx.set_halign(Align::Center);
+        btn_bx.append(&cancel_btn);
+        btn_bx.append(&exec_btn);
+        btn_bx.set_margin_top(18);
+
+        let bx = Box::new(Orientation::Vertical, 0);
+        bx.append(&msg);
+        bx.append(&scroll);
+        bx.append(&btn_bx);
+        super::set_margins(&bx, 32, 32);
+        dialog.set_child(Some(&bx));
+
+        let pending : Rc<RefCell<Option<PendingExecution>>> = Default::default();
+        cancel_btn.connect_clicked({
+            let dialog = dialog.clone();
+            let pending = pending.clone();
+            move |_| {
+                pending.replace(None);
+                dialog.close();
+            }
+        });
+        exec_btn.connect_clicked({
+            let dialog = dialog.clone();
+            move |_| {
+                dialog.close();
+            }
+        });
+        Self { dialog, exec_btn, list, pending }
+    }
+
+}
+
+impl React<ActiveConnection> for SafetyDialog {
+
+    fn react(&self, conn : &ActiveConnection) {
+        let dialog = self.clone();
+        conn.connect_confirm_request(move |(pending, matched)| {
+            while let Some(row) = dialog.list.row_at_index(0) {
+                dialog.list.remove(&row);
+            }
+            for (rule, stmt) in matched {
+                let lbl = Label::new(Some(&format!(
+                    "<b>{}</b>\n<tt>{}</tt>",
+                    rule.name(),
+                    glib::markup_escape_text(stmt.trim())
+                )));
+                lbl.set_use_markup(true);
+                lbl.set_wrap(true);
+                lbl.set_xalign(0.0);
+                super::set_margins(&lbl, 12, 6);
+                dialog.list.append(&lbl);
+            }
+            super::set_all_not_selectable(&dialog.list);
+            dialog.pending.replace(Some(pending));
+            dialog.dialog.show();
+        });
+    }
+
+}
+
+/* Shown when the user closes a session with uncommitted changes within a
+manual transaction. The changes are rolled back if the user proceeds. */
+#[derive(Debug, Clone)]
+pub struct DisconnectDialog {
+    pub dialog : Dialog,
+    pub disconnect_btn : Button,
+    msg : Label,
+
+    // Connection waiting for confirmation.
+    pub pending : Rc<RefCell<Option<ConnectionInfo>>>
+}
+
+impl DisconnectDialog {
+
+    pub fn build() -> Self {
+        let dialog = Dialog::new();
+        dialog.set_title(Some("Uncommitted changes"));
+        super::configure_dialog(&dialog);
+
+        let msg = Label::new(None);
+        msg.set_justify(Justification::Center);
+
+        let cancel_btn = Button::builder().label("Cancel").build();
+        let disconnect_btn = Button::builder().label("Disconnect").build();
+        cancel_btn.style_context().add_class("pill");
+        disconnect_btn.style_context().add_class("pill");
+        disconnect_btn.style_context().add_class("destructive-action");
+        let btn_bx = Box::new(Orientation::Horizontal, 16);
+        btn_bx.set_halign(Align::Center);
+        btn_bx.append(&cancel_btn);
+        btn_bx.append(&disconnect_btn);
+        btn_bx.set_margin_top(18);
+
+        let bx = Box::new(Orientation::Vertical, 0);
+        bx.append(&msg);
+        bx.append(&btn_bx);
+        super::set_margins(&bx, 32, 32);
+        dialog.set_child(Some(&bx));
+
+        let pending : Rc<RefCell<Option<ConnectionInfo>>> = Default::default();
+        cancel_btn.connect_clicked({
+            let dialog = dialog.clone();
+            let pending = pending.clone();
+            move |_| {
+                pending.replace(None);
+                dialog.close();
+            }
+        });
+        disconnect_btn.connect_clicked({
+            let dialog = dialog.clone();
+            move |_| {
+                dialog.close();
+            }
+        });
+        Self { dialog, disconnect_btn, msg, pending }
+    }
+
+}
+
+impl React<ActiveConnection> for DisconnectDialog {
+
+    fn react(&self, conn : &ActiveConnection) {
+        let dialog = self.clone();
+        conn.connect_disconnect_warning(move |info| {
+            dialog.msg.set_text(&format!(
+                "The open transaction at {} has uncommitted changes.\nDisconnecting will roll them back.",
+                crate::client::session_label(&info)
+            ));
+            dialog.pending.replace(Some(info));
+            dialog.dialog.show();
+        });
+    }
+
+}
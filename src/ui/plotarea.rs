@@ -5,13 +5,118 @@ For a copy, see http://www.gnu.org/licenses.*/
 
 use gtk4::*;
 use gtk4::prelude::*;
-use cairo::Context;
+use cairo::{Context, Format, ImageSurface, PdfSurface};
 use papyri::render::{Panel};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::path::Path;
+
+// Pixels per point of the images copied to the clipboard.
+const COPY_SCALE : f64 = 2.0;
+
+// Space left around plots placed at A4 or Letter pages, in points.
+const PAGE_MARGIN : f64 = 36.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageSize {
+
+    // The page has the size of the plot.
+    Plot,
+
+    A4,
+
+    Letter
+
+}
+
+/// Size of exported plots. Width and height are in points (1/72 inch), so raster
+/// output has width * dpi / 72 pixels.
+#[derive(Debug, Clone, Copy)]
+pub struct PlotExportOptions {
+    pub page : PageSize,
+    pub width : f64,
+    pub height : f64,
+    pub dpi : f64
+}
+
+impl Default for PlotExportOptions {
+
+    fn default() -> Self {
+        Self { page : PageSize::Plot, width : 576.0, height : 432.0, dpi : 150.0 }
+    }
+
+}
+
+fn draw_err(e : impl std::fmt::Display) -> String {
+    format!("Error drawing plot: {}", e)
+}
+
+fn draw_panel(panel : &mut Panel, ctx : &Context, width : f64, height : f64) -> Result<(), String> {
+    ctx.set_source_rgb(1.0, 1.0, 1.0);
+    ctx.rectangle(0.0, 0.0, width, height);
+    ctx.fill().map_err(draw_err)?;
+    panel.draw_to_context(ctx, 0, 0, width as i32, height as i32).map_err(draw_err)
+}
+
+/// Renders the panel with the given size (in points) as PNG, with scale pixels per point.
+pub fn panel_to_png(panel : &mut Panel, width : f64, height : f64, scale : f64) -> Result<Vec<u8>, String> {
+    let surface = ImageSurface::create(Format::ARgb32, (width * scale).round() as i32, (height * scale).round() as i32)
+        .map_err(draw_err)?;
+    let ctx = Context::new(&surface).map_err(draw_err)?;
+    ctx.scale(scale, scale);
+    draw_panel(panel, &ctx, width, height)?;
+    drop(ctx);
+    let mut png = Vec::new();
+    surface.write_to_png(&mut png).map_err(draw_err)?;
+    Ok(png)
+}
+
+pub fn write_panel_png(panel : &mut Panel, path : &Path, opts : &PlotExportOptions) -> Result<(), String> {
+    let png = panel_to_png(panel, opts.width, opts.height, opts.dpi / 72.0)?;
+    std::fs::write(path, &png[..]).map_err(|e| format!("Error writing to export file: {}", e) )
+}
+
+/// Writes the panel as a single-page PDF. At A4 and Letter pages, the plot keeps its
+/// aspect ratio and is scaled to fit within the page margins.
+pub fn write_panel_pdf(panel : &mut Panel, path : &Path, opts : &PlotExportOptions) -> Result<(), String> {
+    let (page_w, page_h) = match opts.page {
+        PageSize::Plot => (opts.width, opts.height),
+        PageSize::A4 => (595.0, 842.0),
+        PageSize::Letter => (612.0, 792.0)
+    };
+    let surface = PdfSurface::new(page_w, page_h, path).map_err(draw_err)?;
+    let ctx = Context::new(&surface).map_err(draw_err)?;
+    if opts.page != PageSize::Plot {
+        let scale = ((page_w - 2. * PAGE_MARGIN) / opts.width).min((page_h - 2. * PAGE_MARGIN) / opts.height);
+        ctx.translate((page_w - opts.width * scale) / 2., (page_h - opts.height * scale) / 2.);
+        ctx.scale(scale, scale);
+    }
+    draw_panel(panel, &ctx, opts.width, opts.height)?;
+    ctx.show_page().map_err(draw_err)?;
+    drop(ctx);
+    surface.finish();
+    Ok(())
+}
+
+/// Puts a PNG render of the panel, at the given size (in points), on the clipboard.
+pub fn copy_panel(panel : &mut Panel, width : f64, height : f64) -> Result<(), String> {
+    let png = panel_to_png(panel, width, height, COPY_SCALE)?;
+    let loader = gdk_pixbuf::PixbufLoader::new();
+    loader.write(&png[..]).map_err(draw_err)?;
+    loader.close().map_err(draw_err)?;
+    let pixbuf = loader.pixbuf().ok_or_else(|| format!("Error loading plot image") )?;
+    let display = gdk::Display::default().ok_or_else(|| format!("No display to copy the plot to") )?;
+    display.clipboard().set_texture(&gdk::Texture::for_pixbuf(&pixbuf));
+    Ok(())
+}
 
 #[derive(Clone)]
 pub struct PlotView {
 
-    pub parent : gtk4::DrawingArea
+    pub parent : gtk4::DrawingArea,
+
+    // Holds the drawing area and the copy button.
+    pub overlay : Overlay
 
 }
 
@@ -21,17 +126,55 @@ impl PlotView {
         self.parent.queue_draw();
     }
 
-    pub fn new_from_panel(mut panel : Panel) -> Self {
+    pub fn new_from_panel(panel : Panel) -> Self {
         let parent = gtk4::DrawingArea::new();
-        parent.set_draw_func(move |da, ctx, _, _| {
-            let allocation = da.allocation();
-            let w = allocation.width();
-            let h = allocation.height();
-            if let Err(e) = panel.draw_to_context(&ctx, 0, 0, w, h) {
-                eprintln!("{}", e);
+        let panel = Rc::new(RefCell::new(panel));
+        parent.set_draw_func({
+            let panel = panel.clone();
+            move |da, ctx, _, _| {
+                let allocation = da.allocation();
+                let w = allocation.width();
+                let h = allocation.height();
+                if let Err(e) = panel.borrow_mut().draw_to_context(&ctx, 0, 0, w, h) {
+                    eprintln!("{}", e);
+                }
+            }
+        });
+
+        // The copied image has the size the plot is currently shown with.
+        let group = gio::SimpleActionGroup::new();
+        let copy_action = gio::SimpleAction::new("copy", None);
+        copy_action.connect_activate({
+            let panel = panel.clone();
+            let da = parent.downgrade();
+            move |_, _| {
+                if let Some(da) = da.upgrade() {
+                    let allocation = da.allocation();
+                    if let Err(e) = copy_panel(&mut panel.borrow_mut(), allocation.width() as f64, allocation.height() as f64) {
+                        eprintln!("{}", e);
+                    }
+                }
             }
         });
-        Self { parent }
+        group.add_action(&copy_action);
+
+        let copy_btn = Button::builder().icon_name("edit-copy-symbolic").build();
+        copy_btn.set_tooltip_text(Some("Copy plot"));
+        copy_btn.set_action_name(Some("plot.copy"));
+        copy_btn.set_halign(Align::End);
+        copy_btn.set_valign(Align::Start);
+        copy_btn.style_context().add_class("flat");
+        super::set_margins(&copy_btn, 6, 6);
+
+        let overlay = Overlay::new();
+        overlay.set_child(Some(&parent));
+        overlay.add_overlay(&copy_btn);
+        overlay.insert_action_group("plot", Some(&group));
+        overlay.set_focusable(true);
+        let shortcuts = ShortcutController::new();
+        shortcuts.add_shortcut(&Shortcut::new(ShortcutTrigger::parse_string("<Control>c").as_ref(), Some(&NamedAction::new("plot.copy"))));
+        overlay.add_controller(&shortcuts);
+        Self { parent, overlay }
     }
 
     pub fn new_from_json(json : &str) -> Result<Self, String> {
@@ -49,4 +192,3 @@ fn _draw_plot(da : &gtk4::DrawingArea, ctx : &Context, group : &mut Panel) {
         eprintln!("{}", e);
     }
 }
-
@@ -0,0 +1,326 @@
+/*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
+
+This work is licensed under the terms of the GPL v3.0 License.
+For a copy, see http://www.gnu.org/licenses.*/
+
+use gtk4::*;
+use gtk4::prelude::*;
+use crate::tables::table::Table;
+use crate::tables::field::Field;
+use quick_xml::{Reader, Writer};
+use quick_xml::events::Event;
+use serde_json::Value;
+use std::io::Cursor;
+
+// Binary values longer than this are shown only up to it (but saved in full).
+const MAX_SHOWN_BYTES : usize = 64 * 1024;
+
+// Nested JSON values are shown expanded only up to this depth.
+const JSON_EXPANDED_DEPTH : usize = 2;
+
+/// Panel showing all the fields of a single result row as a vertical form, opened by double-clicking
+/// a cell. JSON values are shown as a tree, XML is indented and binary values are shown as hex dumps,
+/// base64 or (for PNG and JPEG images) a picture. Each value can be saved to a file.
+#[derive(Clone, Debug)]
+pub struct RecordView {
+
+    pub bx : Box,
+
+    title : Label,
+
+    fields : Box
+
+}
+
+impl RecordView {
+
+    pub fn build() -> Self {
+        let title = Label::new(None);
+        title.set_hexpand(true);
+        title.set_xalign(0.0);
+        title.style_context().add_class("heading");
+        let close_btn = Button::builder().icon_name("window-close-symbolic").build();
+        close_btn.set_has_frame(false);
+        close_btn.set_tooltip_text(Some("Close"));
+        let header = Box::new(Orientation::Horizontal, 6);
+        super::set_margins(&header, 12, 6);
+        header.append(&title);
+        header.append(&close_btn);
+
+        let fields = Box::new(Orientation::Vertical, 12);
+        super::set_margins(&fields, 12, 12);
+        let scroll = ScrolledWindow::new();
+        scroll.set_vexpand(true);
+        scroll.set_hscrollbar_policy(PolicyType::Never);
+        scroll.set_child(Some(&fields));
+
+        let bx = Box::new(Orientation::Vertical, 0);
+        bx.set_width_request(320);
+        bx.append(&header);
+        bx.append(&Separator::new(Orientation::Horizontal));
+        bx.append(&scroll);
+        bx.set_visible(false);
+        close_btn.connect_clicked({
+            let bx = bx.clone();
+            move |_| bx.set_visible(false)
+        });
+        Self { bx, title, fields }
+    }
+
+    pub fn show_row(&self, tbl : &Table, row : usize) {
+        if row >= tbl.nrows() {
+            return;
+        }
+        while let Some(child) = self.fields.first_child() {
+            self.fields.remove(&child);
+        }
+        self.title.set_text(&format!("Row {}", row + 1));
+        for (col, name) in tbl.names().iter().enumerate() {
+            let column = match tbl.get_column(col) {
+                Some(column) => column,
+                None => continue
+            };
+            let value = if column.is_null_at(row) {
+                None
+            } else {
+                column.at(row, None)
+            };
+            let text = tbl.text_at(row, col).map(|t| t.to_string() ).unwrap_or_default();
+            self.fields.append(&field_widget(name, value, text));
+        }
+        self.bx.set_visible(true);
+    }
+
+}
+
+/* Content written when a value is saved to a file. */
+fn saved_content(value : &Field, text : &str) -> Vec<u8> {
+    match value {
+        Field::Bytes(bytes) => bytes.clone(),
+        Field::Json(json) => serde_json::to_string_pretty(json).unwrap_or_default().into_bytes(),
+        _ => text.as_bytes().to_vec()
+    }
+}
+
+fn field_widget(name : &str, value : Option<Field>, text : String) -> Box {
+    let name_lbl = Label::new(Some(name));
+    name_lbl.set_xalign(0.0);
+    name_lbl.set_hexpand(true);
+    name_lbl.set_ellipsize(pango::EllipsizeMode::End);
+    name_lbl.style_context().add_class("heading");
+    let header = Box::new(Orientation::Horizontal, 6);
+    header.append(&name_lbl);
+
+    let bx = Box::new(Orientation::Vertical, 6);
+    bx.append(&header);
+    let value = match value {
+        Some(value) => value,
+        None => {
+            let null_lbl = Label::new(Some("NULL"));
+            null_lbl.set_xalign(0.0);
+            null_lbl.style_context().add_class("dim-label");
+            bx.append(&null_lbl);
+            return bx;
+        }
+    };
+
+    let save_btn = Button::builder().icon_name("document-save-symbolic").build();
+    save_btn.set_has_frame(false);
+    save_btn.set_tooltip_text(Some("Save value to file"));
+    header.append(&save_btn);
+    let content = saved_content(&value, &text);
+    save_btn.connect_clicked(move |btn| save_to_file(btn, content.clone()) );
+
+    let value_wid : Widget = match value {
+        Field::Bytes(bytes) => bytes_widget(&bytes),
+        Field::Json(json) => json_tree(None, &json, 0),
+        Field::Str(s) => {
+            if let Some(xml) = indent_xml(&s) {
+                text_widget(&xml, true)
+            } else if let Some(json) = parse_json_container(&s) {
+                json_tree(None, &json, 0)
+            } else if s.contains('\n') || s.chars().count() > 80 {
+                text_widget(&s, false)
+            } else {
+                value_label(&s)
+            }
+        },
+        _ => value_label(&text)
+    };
+    bx.append(&value_wid);
+    bx
+}
+
+fn value_label(text : &str) -> Widget {
+    let lbl = Label::new(Some(text));
+    lbl.set_xalign(0.0);
+    lbl.set_selectable(true);
+    lbl.set_wrap(true);
+    lbl.set_wrap_mode(pango::WrapMode::WordChar);
+    lbl.upcast()
+}
+
+fn text_widget(text : &str, monospace : bool) -> Widget {
+    let view = TextView::new();
+    view.set_editable(false);
+    view.set_monospace(monospace);
+    view.set_wrap_mode(if monospace { WrapMode::None } else { WrapMode::WordChar });
+    view.buffer().set_text(text);
+    let frame = Frame::new(None);
+    if monospace {
+        let scroll = ScrolledWindow::new();
+        scroll.set_vscrollbar_policy(PolicyType::Never);
+        scroll.set_child(Some(&view));
+        frame.set_child(Some(&scroll));
+    } else {
+        frame.set_child(Some(&view));
+    }
+    frame.upcast()
+}
+
+fn parse_json_container(s : &str) -> Option<Value> {
+    let s = s.trim();
+    if !(s.starts_with('{') || s.starts_with('[')) {
+        return None;
+    }
+    serde_json::from_str::<Value>(s).ok()
+}
+
+/* Objects and arrays are shown as expanders holding their entries; other values as labels. */
+fn json_tree(key : Option<&str>, value : &Value, depth : usize) -> Widget {
+    let prefix = key.map(|k| format!("{}: ", k) ).unwrap_or_default();
+    let (summary, entries) : (String, Vec<(String, &Value)>) = match value {
+        Value::Object(map) => {
+            (format!("{}{{{}}}", prefix, map.len()), map.iter().map(|(k, v)| (k.clone(), v) ).collect())
+        },
+        Value::Array(arr) => {
+            (format!("{}[{}]", prefix, arr.len()), arr.iter().enumerate().map(|(i, v)| (i.to_string(), v) ).collect())
+        },
+        other => {
+            return value_label(&format!("{}{}", prefix, other));
+        }
+    };
+    let children = Box::new(Orientation::Vertical, 2);
+    children.set_margin_start(18);
+    for (k, v) in entries.iter() {
+        children.append(&json_tree(Some(k.as_str()), v, depth + 1));
+    }
+    let expander = Expander::new(Some(&summary));
+    expander.set_expanded(depth < JSON_EXPANDED_DEPTH);
+    expander.set_child(Some(&children));
+    expander.upcast()
+}
+
+/// Indents the text if it is a well-formed XML document or fragment. Returns None otherwise.
+pub fn indent_xml(txt : &str) -> Option<String> {
+    if !txt.trim_start().starts_with('<') {
+        return None;
+    }
+    let mut reader = Reader::from_str(txt);
+    reader.trim_text(true);
+    let mut writer = Writer::new_with_indent(Cursor::new(Vec::new()), b' ', 2);
+    let mut buf = Vec::new();
+    let mut nelems = 0;
+    loop {
+        match reader.read_event(&mut buf).ok()? {
+            Event::Eof => break,
+            ev => {
+                if let Event::Start(_) | Event::Empty(_) = ev {
+                    nelems += 1;
+                }
+                writer.write_event(ev).ok()?;
+            }
+        }
+        buf.clear();
+    }
+    if nelems == 0 {
+        return None;
+    }
+    String::from_utf8(writer.into_inner().into_inner()).ok()
+}
+
+/// Lines of 16 bytes, with the offset, hexadecimal values and printable ASCII characters.
+pub fn hex_dump(bytes : &[u8]) -> String {
+    let mut dump = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        dump += &format!("{:08x}  ", i * 16);
+        for j in 0..16 {
+            match chunk.get(j) {
+                Some(b) => dump += &format!("{:02x} ", b),
+                None => dump += "   "
+            }
+            if j == 7 {
+                dump += " ";
+            }
+        }
+        dump += " |";
+        dump.extend(chunk.iter().map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' } ));
+        dump += "|\n";
+    }
+    dump
+}
+
+fn is_image(bytes : &[u8]) -> bool {
+    bytes.starts_with(&[0x89, b'P', b'N', b'G']) || bytes.starts_with(&[0xFF, 0xD8, 0xFF])
+}
+
+fn image_widget(bytes : &[u8]) -> Option<Picture> {
+    let loader = gdk_pixbuf::PixbufLoader::new();
+    loader.write(bytes).ok()?;
+    loader.close().ok()?;
+    let pixbuf = loader.pixbuf()?;
+    let picture = Picture::for_pixbuf(Some(&pixbuf));
+    picture.set_can_shrink(true);
+    picture.set_height_request(pixbuf.height().min(240));
+    Some(picture)
+}
+
+fn bytes_widget(bytes : &[u8]) -> Widget {
+    let shown = &bytes[..bytes.len().min(MAX_SHOWN_BYTES)];
+    let stack = Stack::new();
+    if is_image(bytes) {
+        if let Some(picture) = image_widget(bytes) {
+            stack.add_titled(&picture, Some("image"), "Image");
+        }
+    }
+    stack.add_titled(&text_widget(&hex_dump(shown), true), Some("hex"), "Hex");
+    stack.add_titled(&text_widget(&base64::encode(shown), false), Some("base64"), "Base64");
+    let switcher = StackSwitcher::new();
+    switcher.set_stack(Some(&stack));
+    switcher.set_halign(Align::Start);
+
+    let bx = Box::new(Orientation::Vertical, 6);
+    bx.append(&switcher);
+    bx.append(&stack);
+    let size_lbl = if bytes.len() > shown.len() {
+        Label::new(Some(&format!("{} bytes (showing the first {})", bytes.len(), shown.len())))
+    } else {
+        Label::new(Some(&format!("{} bytes", bytes.len())))
+    };
+    size_lbl.set_xalign(0.0);
+    size_lbl.style_context().add_class("dim-label");
+    bx.append(&size_lbl);
+    bx.upcast()
+}
+
+fn save_to_file(btn : &Button, content : Vec<u8>) {
+    let parent = btn.root().and_then(|root| root.downcast::<Window>().ok() );
+    let dialog = FileChooserDialog::new(
+        Some("Save value"),
+        parent.as_ref(),
+        FileChooserAction::Save,
+        &[("Cancel", ResponseType::None), ("Save", ResponseType::Accept)]
+    );
+    dialog.set_modal(true);
+    dialog.connect_response(move |dialog, resp| {
+        if resp == ResponseType::Accept {
+            if let Some(path) = dialog.file().and_then(|f| f.path() ) {
+                if let Err(e) = std::fs::write(&path, &content[..]) {
+                    eprintln!("{}", e);
+                }
+            }
+        }
+        dialog.destroy();
+    });
+    dialog.show();
+}
@@ -10,6 +10,8 @@ use std::cell::{RefCell};
 use std::fs::File;
 use std::io::Read;
 use crate::sql::object::{DBObject, DBType};
+use crate::tables::dialect::{CsvDialect, CsvRecords};
+use crate::tables::csv_import::{IMPORT_TYPES, PREVIEW_ROWS, ColumnMapping, ImportPlan};
 use std::path::{Path};
 use glib::{types::Type, value::{ToValue}};
 use gdk_pixbuf::Pixbuf;
@@ -23,7 +25,6 @@ use serde_json;
 use crate::ui::NamedBox;
 use std::io::Write;
 use crate::client::ActiveConnectionAction;
-use crate::ui::ConnectionBox;
 
 // The TreeView to the bottom left region that shows the database schema and allows 
 // interactions with tables, views and functions.
@@ -48,13 +49,14 @@ pub struct SchemaTree {
     pub report_action : gio::SimpleAction,
     pub form : super::Form,
     pub import_dialog : ImportDialog,
+    pub import_wizard : ImportWizard,
     pub report_dialog : ReportDialog,
     pub report_export_dialog : filecase::SaveDialog
 }
 
 // TODO views with a homonimous table are not being shown at the schema tree.
 
-const ALL_TYPES : [DBType; 15] = [
+const ALL_TYPES : [DBType; 16] = [
     DBType::Bool,
     DBType::I16,
     DBType::I32,
@@ -65,6 +67,7 @@ const ALL_TYPES : [DBType; 15] = [
     DBType::Text,
     DBType::Date,
     DBType::Time,
+    DBType::Timestamp,
     DBType::Bytes,
     DBType::Json,
     DBType::Xml,
@@ -201,6 +204,7 @@ impl SchemaTree {
                 import_dialog.dialog.show();
             }
         });
+        let import_wizard = ImportWizard::build();
         let report_dialog = ReportDialog::build();
         report_action.connect_activate({
             let report_dialog = report_dialog.clone();
@@ -257,6 +261,7 @@ impl SchemaTree {
             call_action,
             form,
             import_dialog,
+            import_wizard,
             report_dialog,
             report_export_dialog,
         }
@@ -329,25 +334,6 @@ impl SchemaTree {
 
 }
 
-impl React<ConnectionBox> for SchemaTree {
-
-    fn react(&self, conn_bx : &ConnectionBox) {
-        let schema_tree = self.clone();
-        conn_bx.switch.connect_state_set(move |switch, _| {
-            if switch.is_active() {
-                schema_tree.repopulate(vec![DBObject::Schema { 
-                    name : String::from("Connecting..."), 
-                    children : Vec::new() 
-                }]);
-            } else {
-                schema_tree.clear();
-            }
-            glib::signal::Inhibit(false)
-        });
-    }
-    
-}
-
 impl React<ActiveConnection> for SchemaTree {
 
     fn react(&self, conn : &ActiveConnection) {
@@ -365,6 +351,33 @@ impl React<ActiveConnection> for SchemaTree {
                 schema_tree.clear();
             }
         });
+        conn.connect_import_preview({
+            let import_wizard = self.import_wizard.clone();
+            move |(path, table, records, types, dialect)| {
+                import_wizard.show_preview(path, table, records, types, dialect);
+            }
+        });
+
+        // The schema of the newly focused session arrives via connect_schema_update.
+        // Whether the focused session is read-only, in which case the actions
+        // that write to tables are never enabled.
+        let read_only = Rc::new(RefCell::new(false));
+        conn.connect_session_switched({
+            let schema_tree = self.clone();
+            let read_only = read_only.clone();
+            move |opt_session| {
+                read_only.replace(opt_session.as_ref().map(|(_, info, _)| info.read_only ).unwrap_or(false));
+                match opt_session {
+                    Some((_, _, None)) => {
+                        schema_tree.repopulate(vec![DBObject::Schema { name : format!("Catalog unavailable"), children : Vec::new() }]);
+                    },
+                    Some(_) => { },
+                    None => {
+                        schema_tree.clear();
+                    }
+                }
+            }
+        });
         conn.connect_schema_update({
             let schema_tree = self.clone();
             move |info| {
@@ -387,6 +400,12 @@ impl React<ActiveConnection> for SchemaTree {
                             action.set_enabled(true);
                             action.set_state(&s);
                         }
+                        if *read_only.borrow() {
+                            for action in [&insert_action, &import_action] {
+                                action.set_enabled(false);
+                                action.set_state(&String::new().to_variant());
+                            }
+                        }
                         call_action.set_enabled(false);
                         call_action.set_state(&String::new().to_variant());
                     },
@@ -540,13 +559,375 @@ impl ImportDialog {
         });
         super::configure_dialog(&dialog);
         let filter = FileFilter::new();
-        filter.add_pattern("*.csv");
+        for pattern in ["*.csv", "*.json", "*.ndjson", "*.jsonl", "*.parquet", "*.arrow"] {
+            filter.add_pattern(pattern);
+        }
         dialog.set_filter(&filter);
         Self { dialog }
     }
 
 }
 
+// CSV file waiting for its columns to be mapped at the import wizard.
+#[derive(Debug, Clone)]
+struct PendingImport {
+    path : String,
+    table : DBObject,
+    records : CsvRecords,
+    dialect : CsvDialect
+}
+
+// Rows of the preview grid shown at the import wizard.
+const WIZARD_PREVIEW_ROWS : usize = 10;
+
+/// Maps the columns of a CSV file to a new or existing table before importing it.
+#[derive(Debug, Clone)]
+pub struct ImportWizard {
+    pub dialog : Dialog,
+    file_lbl : Label,
+    existing_check : CheckButton,
+    new_check : CheckButton,
+    name_entry : Entry,
+    column_list : ListBox,
+    preview_scroll : ScrolledWindow,
+    status_lbl : Label,
+    pub btn_cancel : Button,
+    pub import_btn : Button,
+
+    // Type and target combos of each CSV column.
+    columns : Rc<RefCell<Vec<(ComboBoxText, ComboBoxText)>>>,
+
+    pending : Rc<RefCell<Option<PendingImport>>>
+}
+
+fn sanitize_identifier(name : &str) -> String {
+    let mut ident : String = name.trim().to_lowercase().chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' } )
+        .collect();
+    if ident.is_empty() || ident.starts_with(|c : char| c.is_numeric() ) {
+        ident.insert(0, '_');
+    }
+    ident
+}
+
+fn import_type_label(ty : &DBType) -> &'static str {
+    IMPORT_TYPES.iter().find(|(_, t)| t == ty ).map(|(label, _)| *label ).unwrap_or("Text")
+}
+
+fn set_combo_entry_text(combo : &ComboBoxText, text : &str) {
+    if let Some(entry) = combo.child().and_then(|w| w.downcast::<Entry>().ok() ) {
+        entry.set_text(text);
+    }
+}
+
+impl ImportWizard {
+
+    pub fn build() -> Self {
+        let dialog = Dialog::new();
+        dialog.set_title(Some("Import CSV"));
+        super::configure_dialog(&dialog);
+        dialog.set_default_size(720, 640);
+
+        let bx = Box::new(Orientation::Vertical, 12);
+        let file_lbl = Label::new(None);
+        file_lbl.set_halign(Align::Start);
+        file_lbl.set_ellipsize(pango::EllipsizeMode::Middle);
+        bx.append(&file_lbl);
+
+        let existing_check = CheckButton::with_label("Existing table");
+        let new_check = CheckButton::with_label("New table");
+        new_check.set_group(Some(&existing_check));
+        existing_check.set_active(true);
+        let name_entry = Entry::new();
+        name_entry.set_placeholder_text(Some("schema.table"));
+        name_entry.set_sensitive(false);
+        name_entry.set_hexpand(true);
+        let target_bx = Box::new(Orientation::Horizontal, 12);
+        target_bx.append(&existing_check);
+        target_bx.append(&new_check);
+        target_bx.append(&name_entry);
+        bx.append(&target_bx);
+
+        let column_list = ListBox::new();
+        column_list.set_selection_mode(SelectionMode::None);
+        column_list.style_context().add_class("boxed-list");
+        let column_scroll = ScrolledWindow::new();
+        column_scroll.set_child(Some(&column_list));
+        column_scroll.set_min_content_height(240);
+        column_scroll.set_vexpand(true);
+        bx.append(&column_scroll);
+
+        let preview_scroll = ScrolledWindow::new();
+        preview_scroll.set_min_content_height(160);
+        preview_scroll.set_vexpand(true);
+        bx.append(&preview_scroll);
+
+        let status_lbl = Label::new(None);
+        status_lbl.set_halign(Align::Start);
+        status_lbl.set_wrap(true);
+        bx.append(&status_lbl);
+
+        let btn_bx = Box::new(Orientation::Horizontal, 32);
+        btn_bx.set_hexpand(true);
+        btn_bx.set_halign(Align::Center);
+        let btn_cancel = Button::builder().label("Cancel").build();
+        let import_btn = Button::builder().label("Import").build();
+        btn_cancel.style_context().add_class("pill");
+        import_btn.style_context().add_class("pill");
+        import_btn.style_context().add_class("suggested-action");
+        btn_bx.append(&btn_cancel);
+        btn_bx.append(&import_btn);
+        bx.append(&btn_bx);
+        super::set_margins(&bx, 32,  32);
+        dialog.set_child(Some(&bx));
+
+        btn_cancel.connect_clicked({
+            let dialog = dialog.clone();
+            move |_| {
+                dialog.close();
+            }
+        });
+
+        let wizard = Self {
+            dialog,
+            file_lbl,
+            existing_check,
+            new_check,
+            name_entry,
+            column_list,
+            preview_scroll,
+            status_lbl,
+            btn_cancel,
+            import_btn,
+            columns : Rc::new(RefCell::new(Vec::new())),
+            pending : Rc::new(RefCell::new(None))
+        };
+        wizard.new_check.connect_toggled({
+            let wizard = wizard.clone();
+            move |check| {
+                wizard.name_entry.set_sensitive(check.is_active());
+                wizard.fill_targets();
+                wizard.update_status();
+            }
+        });
+        wizard.name_entry.connect_changed({
+            let wizard = wizard.clone();
+            move |_| {
+                wizard.update_status();
+            }
+        });
+        wizard.dialog.connect_close({
+            let pending = wizard.pending.clone();
+            move |_| {
+                pending.replace(None);
+            }
+        });
+        wizard
+    }
+
+    /// Shows the first records of a CSV file and the types inferred for its columns.
+    pub fn show_preview(&self, path : String, table : DBObject, records : CsvRecords, types : Vec<DBType>, dialect : CsvDialect) {
+        let (schema, name) = match &table {
+            DBObject::Table { schema, name, .. } => (schema.clone(), name.clone()),
+            _ => return
+        };
+        let stem = Path::new(&path).file_stem().and_then(|s| s.to_str() ).unwrap_or("imported").to_string();
+        self.file_lbl.set_text(&format!("{} ({} preview row(s))", path, records.rows.len()));
+        self.existing_check.set_label(Some(&format!("Existing table ({}.{})", schema, name)));
+        self.name_entry.set_text(&format!("{}.{}", schema, sanitize_identifier(&stem)));
+
+        while let Some(row) = self.column_list.row_at_index(0) {
+            self.column_list.remove(&row);
+        }
+        let mut columns = Vec::new();
+        for (ix, col_name) in records.names.iter().enumerate() {
+            let sample = records.rows.iter()
+                .filter_map(|row| row.get(ix).and_then(|v| v.clone() ) )
+                .find(|v| !v.is_empty() )
+                .unwrap_or_default();
+            let lbl_bx = Box::new(Orientation::Vertical, 0);
+            let name_lbl = Label::new(Some(col_name));
+            name_lbl.set_halign(Align::Start);
+            let sample_lbl = Label::new(Some(&sample));
+            sample_lbl.style_context().add_class("dim-label");
+            sample_lbl.set_halign(Align::Start);
+            sample_lbl.set_ellipsize(pango::EllipsizeMode::End);
+            sample_lbl.set_max_width_chars(24);
+            lbl_bx.append(&name_lbl);
+            lbl_bx.append(&sample_lbl);
+            lbl_bx.set_hexpand(true);
+
+            let ty_combo = ComboBoxText::new();
+            for (label, _) in IMPORT_TYPES.iter() {
+                ty_combo.append(Some(label), label);
+            }
+            ty_combo.set_active_id(Some(import_type_label(types.get(ix).unwrap_or(&DBType::Text))));
+            let target_combo = ComboBoxText::with_entry();
+
+            let bx = Box::new(Orientation::Horizontal, 12);
+            super::set_margins(&bx, 12, 6);
+            bx.append(&lbl_bx);
+            bx.append(&ty_combo);
+            bx.append(&target_combo);
+            self.column_list.append(&bx);
+            columns.push((ty_combo, target_combo));
+        }
+        self.columns.replace(columns.clone());
+        self.pending.replace(Some(PendingImport { path, table, records, dialect }));
+        self.fill_targets();
+
+        for (ty_combo, target_combo) in columns.iter() {
+            ty_combo.connect_changed({
+                let wizard = self.clone();
+                move |_| {
+                    wizard.update_status();
+                }
+            });
+            target_combo.connect_changed({
+                let wizard = self.clone();
+                let ty_combo = ty_combo.clone();
+                move |combo| {
+
+                    // Columns imported into an existing table take the type of their target.
+                    if !wizard.new_check.is_active() {
+                        let target = combo.active_text().map(|t| t.to_string() ).unwrap_or_default();
+                        if let Some(DBObject::Table { cols, .. }) = wizard.pending.borrow().as_ref().map(|p| &p.table ) {
+                            if let Some(col) = cols.iter().find(|c| c.0 == target ) {
+                                ty_combo.set_active_id(Some(import_type_label(&col.1)));
+                            }
+                        }
+                    }
+                    wizard.update_status();
+                }
+            });
+        }
+        self.update_preview_grid();
+        self.update_status();
+        self.dialog.show();
+    }
+
+    // Offers the columns of the existing table as targets, or the sanitized CSV names for a new table.
+    fn fill_targets(&self) {
+        let pending = self.pending.borrow();
+        let pending = match pending.as_ref() {
+            Some(pending) => pending,
+            None => return
+        };
+        let table_cols = match &pending.table {
+            DBObject::Table { cols, .. } => cols.clone(),
+            _ => Vec::new()
+        };
+        let create = self.new_check.is_active();
+        for (ix, (_, target_combo)) in self.columns.borrow().iter().enumerate() {
+            target_combo.remove_all();
+            target_combo.append(Some(""), "");
+            let src_name = pending.records.names.get(ix).cloned().unwrap_or_default();
+            if create {
+                set_combo_entry_text(target_combo, &sanitize_identifier(&src_name));
+            } else {
+                for col in table_cols.iter() {
+                    target_combo.append(Some(&col.0), &col.0);
+                }
+                match table_cols.iter().find(|col| col.0.to_lowercase() == src_name.trim().to_lowercase() ) {
+                    Some(col) => { target_combo.set_active_id(Some(&col.0)); },
+                    None => { target_combo.set_active_id(Some("")); }
+                }
+            }
+        }
+    }
+
+    fn update_preview_grid(&self) {
+        let grid = Grid::new();
+        grid.set_column_spacing(18);
+        grid.set_row_spacing(6);
+        super::set_margins(&grid, 12, 12);
+        if let Some(pending) = self.pending.borrow().as_ref() {
+            for (col, name) in pending.records.names.iter().enumerate() {
+                let lbl = Label::new(None);
+                lbl.set_markup(&format!("<b>{}</b>", glib::markup_escape_text(name)));
+                lbl.set_halign(Align::Start);
+                grid.attach(&lbl, col as i32, 0, 1, 1);
+            }
+            for (row_ix, row) in pending.records.rows.iter().take(WIZARD_PREVIEW_ROWS).enumerate() {
+                for (col, value) in row.iter().enumerate() {
+                    let lbl = Label::new(Some(value.as_deref().unwrap_or("")));
+                    lbl.set_halign(Align::Start);
+                    lbl.set_ellipsize(pango::EllipsizeMode::End);
+                    lbl.set_max_width_chars(32);
+                    grid.attach(&lbl, col as i32, row_ix as i32 + 1, 1, 1);
+                }
+            }
+        }
+        self.preview_scroll.set_child(Some(&grid));
+    }
+
+    // Validates the mapping against the preview records, reporting how many of them would be rejected.
+    fn update_status(&self) {
+        let pending = self.pending.borrow();
+        let pending = match pending.as_ref() {
+            Some(pending) => pending,
+            None => return
+        };
+        let plan = match self.build_plan(pending) {
+            Ok(plan) => plan,
+            Err(e) => {
+                self.status_lbl.set_text(&e);
+                self.import_btn.set_sensitive(false);
+                return;
+            }
+        };
+        let (_, accepted, rejected) = plan.insert_statements(&pending.records, &pending.dialect, PREVIEW_ROWS);
+        match rejected.first() {
+            Some(first) => {
+                self.status_lbl.set_text(&format!(
+                    "{} of {} preview row(s) would be rejected (line {}: {})",
+                    rejected.len(),
+                    accepted + rejected.len(),
+                    first.line,
+                    first.reason
+                ));
+            },
+            None => {
+                self.status_lbl.set_text(&format!("All {} preview row(s) can be imported", accepted));
+            }
+        }
+        self.import_btn.set_sensitive(true);
+    }
+
+    fn build_plan(&self, pending : &PendingImport) -> Result<ImportPlan, String> {
+        let create = self.new_check.is_active();
+        let table = if create {
+            self.name_entry.text().trim().to_string()
+        } else {
+            match &pending.table {
+                DBObject::Table { schema, name, .. } => format!("{}.{}", schema, name),
+                _ => return Err(format!("Import target is not a table"))
+            }
+        };
+        let mut columns = Vec::new();
+        for (source, (ty_combo, target_combo)) in self.columns.borrow().iter().enumerate() {
+            let ty = ty_combo.active_id()
+                .and_then(|id| IMPORT_TYPES.iter().find(|(label, _)| *label == id.as_str() ).map(|(_, ty)| *ty ) )
+                .unwrap_or(DBType::Text);
+            let target = target_combo.active_text()
+                .map(|t| t.trim().to_string() )
+                .filter(|t| !t.is_empty() );
+            columns.push(ColumnMapping { source, target, ty });
+        }
+        let plan = ImportPlan { table, create, columns };
+        plan.validate()?;
+        Ok(plan)
+    }
+
+    /// The file path and the import plan chosen by the user.
+    pub fn plan(&self) -> Result<(String, ImportPlan), String> {
+        let pending = self.pending.borrow();
+        let pending = pending.as_ref().ok_or_else(|| format!("No file to import") )?;
+        Ok((pending.path.clone(), self.build_plan(pending)?))
+    }
+
+}
+
 #[derive(Debug, Clone)]
 pub struct ReportDialog {
     pub dialog : Dialog,
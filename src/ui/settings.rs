@@ -153,6 +153,8 @@ pub struct EditorBox {
     pub font_btn : FontButton,
     pub line_num_switch : Switch,
     pub line_highlight_switch : Switch,
+    pub keyword_case_combo : ComboBoxText,
+    pub indent_spin : SpinButton
 }
 
 pub fn configure_list(list : &ListBox) {
@@ -188,9 +190,22 @@ impl EditorBox {
         list.append(&NamedBox::new("Show line numbers", None, line_num_switch.clone()).bx);
         list.append(&NamedBox::new("Highlight current line", None, line_highlight_switch.clone()).bx);
 
+        let keyword_case_combo = ComboBoxText::new();
+        keyword_case_combo.append(Some("upper"), "Upper case");
+        keyword_case_combo.append(Some("lower"), "Lower case");
+        keyword_case_combo.append(Some("preserve"), "As written");
+        keyword_case_combo.set_active_id(Some("upper"));
+
+        let indent_spin = SpinButton::with_range(1.0, 8.0, 1.0);
+        indent_spin.set_digits(0);
+        indent_spin.set_value(4.);
+
+        list.append(&NamedBox::new("Keyword case", Some("Case of SQL keywords\nin formatted scripts"), keyword_case_combo.clone()).bx);
+        list.append(&NamedBox::new("Indentation width", Some("Number of spaces per indentation\nlevel in formatted scripts"), indent_spin.clone()).bx);
+
         set_all_not_selectable(&list);
         
-        Self { list, scheme_combo, font_btn, line_num_switch, line_highlight_switch }
+        Self { list, scheme_combo, font_btn, line_num_switch, line_highlight_switch, keyword_case_combo, indent_spin }
     }
 
 }
@@ -201,8 +216,6 @@ pub struct ExecutionBox {
     pub row_limit_spin : SpinButton,
     pub schedule_scale : Scale,
     pub timeout_scale : Scale,
-    pub dml_switch : Switch,
-    pub ddl_switch : Switch,
     pub async_switch : Switch
 }
 
@@ -211,7 +224,7 @@ impl ExecutionBox {
     pub fn build() -> Self {
         let list = ListBox::new();
         configure_list(&list);
-        let row_limit_spin = SpinButton::with_range(0.0, 10_000.0, 1.0);
+        let row_limit_spin = SpinButton::with_range(0.0, 1_000_000.0, 1.0);
         row_limit_spin.set_digits(0);
         row_limit_spin.set_value(500.);
 
@@ -240,17 +253,108 @@ impl ExecutionBox {
         list.append(&NamedBox::new("Schedule interval", Some("Interval (in seconds)\nbetween scheduled executions"), schedule_scale.clone()).bx);
         list.append(&NamedBox::new("Statement timeout", Some("Maximum time (in seconds)\nto wait for database response"), timeout_scale.clone()).bx);
         
-        let dml_switch = Switch::new();
-        let ddl_switch = Switch::new();
+        // Which statements are accepted is set per connection, at the overview.
         let async_switch = Switch::new();
 
-        list.append(&NamedBox::new("Enable UPDATE and DELETE", Some("Allow execution of potentially destructive \ndata modification statements\n"), dml_switch.clone()).bx);
-        list.append(&NamedBox::new("Enable ALTER, DROP and TRUNCATE", Some("Allow execution of potentially destructive \ndata definition statements\n"), ddl_switch.clone()).bx);
         list.append(&NamedBox::new("Enable asynchronous queries", Some("Execute SELECT statements asynchronously when possible"), async_switch.clone()).bx);
 
         set_all_not_selectable(&list);
         
-        Self { list, row_limit_spin, /*col_limit_spin*/ schedule_scale, timeout_scale, dml_switch, ddl_switch, async_switch }
+        Self { list, row_limit_spin, /*col_limit_spin*/ schedule_scale, timeout_scale, async_switch }
+    }
+
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvBox {
+    pub scrolled : ScrolledWindow,
+    pub delimiter_combo : ComboBoxText,
+    pub quote_combo : ComboBoxText,
+    pub quoting_combo : ComboBoxText,
+    pub header_switch : Switch,
+    pub null_entry : Entry,
+    pub terminator_combo : ComboBoxText,
+    pub encoding_combo : ComboBoxText,
+    pub decimal_combo : ComboBoxText,
+    pub thousands_combo : ComboBoxText,
+    pub detect_switch : Switch
+}
+
+impl CsvBox {
+
+    pub fn build() -> Self {
+        let list = ListBox::new();
+        configure_list(&list);
+
+        let delimiter_combo = ComboBoxText::new();
+        for (id, name) in [(",", "Comma"), (";", "Semicolon"), ("\t", "Tab"), ("|", "Vertical bar")] {
+            delimiter_combo.append(Some(id), name);
+        }
+        delimiter_combo.set_active_id(Some(","));
+        let quote_combo = ComboBoxText::new();
+        quote_combo.append(Some("\""), "Double quote (\")");
+        quote_combo.append(Some("'"), "Single quote (')");
+        quote_combo.set_active_id(Some("\""));
+        let quoting_combo = ComboBoxText::new();
+        quoting_combo.append(Some("necessary"), "When necessary");
+        quoting_combo.append(Some("always"), "All fields");
+        quoting_combo.append(Some("non_numeric"), "Non-numeric fields");
+        quoting_combo.append(Some("never"), "Never");
+        quoting_combo.set_active_id(Some("necessary"));
+        let header_switch = Switch::new();
+        header_switch.set_active(true);
+        let null_entry = Entry::new();
+        null_entry.set_placeholder_text(Some("Empty field"));
+        let terminator_combo = ComboBoxText::new();
+        terminator_combo.append(Some("lf"), "LF (Linux, Mac)");
+        terminator_combo.append(Some("crlf"), "CRLF (Windows)");
+        terminator_combo.set_active_id(Some("lf"));
+        let encoding_combo = ComboBoxText::new();
+        for (label, name) in crate::tables::dialect::ENCODINGS.iter() {
+            encoding_combo.append(Some(label), name);
+        }
+        encoding_combo.set_active_id(Some("utf-8"));
+        let decimal_combo = ComboBoxText::new();
+        decimal_combo.append(Some("."), "Point (1.5)");
+        decimal_combo.append(Some(","), "Comma (1,5)");
+        decimal_combo.set_active_id(Some("."));
+        let thousands_combo = ComboBoxText::new();
+        thousands_combo.append(Some("none"), "None");
+        thousands_combo.append(Some(","), "Comma (1,000)");
+        thousands_combo.append(Some("."), "Point (1.000)");
+        thousands_combo.append(Some(" "), "Space (1 000)");
+        thousands_combo.append(Some("'"), "Apostrophe (1'000)");
+        thousands_combo.set_active_id(Some("none"));
+        let detect_switch = Switch::new();
+        detect_switch.set_active(true);
+
+        list.append(&NamedBox::new("Delimiter", None, delimiter_combo.clone()).bx);
+        list.append(&NamedBox::new("Quote character", None, quote_combo.clone()).bx);
+        list.append(&NamedBox::new("Quoting", Some("Which fields are quoted\nat exported files"), quoting_combo.clone()).bx);
+        list.append(&NamedBox::new("Header", Some("First line holds the column names"), header_switch.clone()).bx);
+        list.append(&NamedBox::new("Null text", Some("Text written for null values\n(and read as null at imports)"), null_entry.clone()).bx);
+        list.append(&NamedBox::new("Line terminator", None, terminator_combo.clone()).bx);
+        list.append(&NamedBox::new("Encoding", None, encoding_combo.clone()).bx);
+        list.append(&NamedBox::new("Decimal separator", None, decimal_combo.clone()).bx);
+        list.append(&NamedBox::new("Thousands separator", None, thousands_combo.clone()).bx);
+        list.append(&NamedBox::new("Detect delimiter and encoding", Some("Guess the delimiter and encoding\nof imported files from their content"), detect_switch.clone()).bx);
+        set_all_not_selectable(&list);
+
+        let scrolled = ScrolledWindow::new();
+        scrolled.set_child(Some(&list));
+        Self {
+            scrolled,
+            delimiter_combo,
+            quote_combo,
+            quoting_combo,
+            header_switch,
+            null_entry,
+            terminator_combo,
+            encoding_combo,
+            decimal_combo,
+            thousands_combo,
+            detect_switch
+        }
     }
 
 }
@@ -769,10 +873,11 @@ pub struct QueriesSettings {
     pub conn_bx : ConnBox,
     pub exec_bx : ExecutionBox,
     pub editor_bx : EditorBox,
+    pub csv_bx : CsvBox,
     pub security_bx : SecurityBox
 }
 
-const SETTINGS : [&'static str; 4] = ["Connection", "Editor", "Execution", "Security"];
+const SETTINGS : [&'static str; 5] = ["Connection", "Editor", "Execution", "CSV", "Security"];
 
 impl QueriesSettings {
 
@@ -781,12 +886,14 @@ impl QueriesSettings {
         let conn_bx = ConnBox::build();
         let editor_bx = EditorBox::build();
         let exec_bx = ExecutionBox::build();
+        let csv_bx = CsvBox::build();
         let security_bx = SecurityBox::build();
         settings.stack.add_named(&conn_bx.list, Some(SETTINGS[0]));
         settings.stack.add_named(&editor_bx.list, Some(SETTINGS[1]));
         settings.stack.add_named(&exec_bx.list, Some(SETTINGS[2]));
-        settings.stack.add_named(&security_bx.scrolled, Some(SETTINGS[3]));
-        Self { settings, conn_bx, editor_bx, exec_bx, security_bx }
+        settings.stack.add_named(&csv_bx.scrolled, Some(SETTINGS[3]));
+        settings.stack.add_named(&security_bx.scrolled, Some(SETTINGS[4]));
+        Self { settings, conn_bx, editor_bx, exec_bx, csv_bx, security_bx }
     }
 
 }
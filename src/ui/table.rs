@@ -1,401 +1,1379 @@
 /*Copyright (c) 2022 Diego da Silva Lima. All rights reserved.
 
-This work is licensed under the terms of the GPL v3.0 License.  
+This work is licensed under the terms of the GPL v3.0 License.
 For a copy, see http://www.gnu.org/licenses.*/
 
 use gtk4::*;
 use gtk4::prelude::*;
-
-
 use crate::tables::table::Table;
-use std::iter::ExactSizeIterator;
+use crate::tables::filter::RowFilter;
+use crate::sql::edit::{self, RowChange};
+use crate::tables::sql_script;
+use std::rc::Rc;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::borrow::Cow;
+use std::time::Duration;
 
-#[derive(Clone, Debug)]
+/* The table is shown by a ColumnView over a model holding one item per row (carrying the row index
+at the table), so cell widgets are created only for the visible rows and recycled as the view scrolls.
+Rows are filtered and sorted by the models between this one and the view, without changing the table.
+Styles that depend on the row or column of a cell are set when the cell is bound, and updated for the
+bound cells (kept here with their (row, column) position) when they change. */
+type BoundCells = Rc<RefCell<HashMap<Widget, (usize, usize)>>>;
+
+#[derive(Clone)]
 pub struct TableWidget {
 
-    pub grid : Grid,
+    pub view : ColumnView,
 
     pub scroll_window : ScrolledWindow,
 
-    _parent_ctx : StyleContext,
+    // Holds the scroll window, followed by the edition controls for editable tables.
+    pub bx : Box,
+
+    model : gio::ListStore,
+
+    // Rows as shown by the view (after filtering and sorting).
+    rows : SortListModel,
+
+    cells : Cells
+
+}
+
+/* State shared by the cell factories. Those do not hold the view itself, which owns them. */
+#[derive(Clone)]
+struct Cells {
+
+    tbl : Rc<Table>,
+
+    // Rows as shown by the view, which the view owns.
+    rows : glib::WeakRef<SortListModel>,
 
     provider : CssProvider,
 
+    bound : BoundCells,
+
+    selection : Rc<RefCell<ColumnSelection>>,
+
+    range : Rc<RefCell<CellRange>>,
+
+    // Shows the number of selected cells, and statistics of their numeric values.
+    summary : Label,
+
+    // Shows the fields of a row when one of its cells is double-clicked.
+    record : super::RecordView,
+
+    // Lowercase text searched at the cells.
+    search : Rc<RefCell<String>>,
+
+    search_index : Rc<RefCell<SearchIndex>>,
+
+    // Positions of the rows at the shown rows, cleared when they change.
+    positions : Rc<RefCell<HashMap<usize, u32>>>,
+
+    nulls_first : Rc<Cell<bool>>,
+
+    sorters : Rc<RefCell<Vec<CustomSorter>>>
+
 }
 
 const TABLE_CSS : &'static str = r#"
-.scrolledwindow {
-  background-color : #FFFFFF;
+.table-cell {
+  padding-left: 6px;
+  padding-right: 6px;
+  padding-top: 4px;
+  padding-bottom: 4px;
 }
 
-.table-cell {
-  padding-left: 10px;
-  padding-right: 10px;
-  padding-top: 10px;
-  padding-bottom: 10px;
-  border : 1px solid #F0F0F0;
-  /*border : 1px solid #000000;*/
-  background-color : #FFFFFF;
+.table-cell.selected {
+  background-color : #E8F0FB;
+}
+
+.table-cell.match {
+  background-color : #FCE94F;
+}
+
+.table-cell.edited {
+  background-color : #FFF5CC;
 }
 
-.selected {
-  background-color : #F5F6F7;
-  border : 1px solid #E9E9E9;
+.table-cell.inserted {
+  background-color : #E1F5E0;
 }
 
-.first-row {
-  /*background-color : #E9E9E9;*/
-  font-weight : bold;
-  border-bottom : 1px solid #F0F0F0;
+.table-cell.deleted {
+  background-color : #FBE3E4;
+  text-decoration-line : line-through;
 }
 "#;
 
-impl TableWidget {
+// Values longer than this are shortened at read-only cells.
+const MAX_CELL_CHARS : usize = 140;
 
-    pub fn new_from_table(tbl : &Table, max_nrows : usize, max_ncols : usize) -> Self {
-        let mut tbl_wid = Self::new();
-        let data = tbl.text_rows(Some(max_nrows), Some(max_ncols));
-        tbl_wid.update_data(data);
-        tbl_wid
+/// Columns selected from the header menus.
+#[derive(Default)]
+struct ColumnSelection {
+
+    selected : Vec<bool>,
+
+    on_selected : Vec<Rc<dyn Fn(Vec<usize>, usize)>>
+
+}
+
+impl ColumnSelection {
+
+    fn selected_cols(&self) -> Vec<usize> {
+        self.selected.iter().enumerate().filter(|(_, s)| **s ).map(|(ix, _)| ix ).collect()
     }
 
-    pub fn new() -> TableWidget {
-        let grid = Grid::new();
-        // let _message = Label::new(None);
-        let provider = CssProvider::new();
-        
-        // provider.load_from_path("assets/styles/tables.css");
-        provider.load_from_data(TABLE_CSS.as_bytes());
-        
-        let parent_ctx = grid.style_context();
-        parent_ctx.add_provider(&provider,800);
-
-        // let msg = Label::new(None);
-        // let box_container = Box::new(Orientation::Vertical, 0);
-        // box_container.append(&grid, true, true, 0);
-        // box_container.pack_start(&msg, true, true, 0);
-        let scroll_window = ScrolledWindow::new();
-        scroll_window.set_vexpand(true);
-        scroll_window.set_valign(Align::Fill);
+}
 
-        // Some(&Adjustment::new(0.0, 0.0, 100.0, 10.0, 10.0, 100.0)),
-        //    Some(&Adjustment::new(0.0, 0.0, 100.0, 10.0, 10.0, 100.0))
-        // );
-        /*let scroll_window = ScrolledWindow::new(
-            Some(&Adjustment::new(0.0, 0.0, 100.0, 0.0, 0.0, 100.0)),
-            Some(&Adjustment::new(0.0, 0.0, 100.0, 0.0, 0.0, 100.0))
-        );*/
-        // scroll_window.set_shadow_type(ShadowType::None);
-        scroll_window.set_child(Some(&grid));
-        // scroll_window.show_all();
-        // let selected = Rc::new(RefCell::new(Vec::new()));
-        // let dims = Rc::new(RefCell::new((0, 0)));
-        // let tbl = Table::new_empty(None);
-        TableWidget { grid, scroll_window, /*box_container,*/ _parent_ctx : parent_ctx, provider, /*selected, dims,*/ /*tbl*/ }
+/// Cells selected by clicking at them. A click selects a single cell, and a shift-click extends
+/// the selection to the rectangle between it and the first clicked cell. Control-clicking selects
+/// whole rows instead.
+#[derive(Default)]
+struct CellRange {
+
+    // Position (at the shown rows) and column of the first clicked cell.
+    anchor : Option<(u32, usize)>,
+
+    // Selected rows (indexed as in the table) in the order they are shown.
+    rows : Vec<usize>,
+
+    row_set : HashSet<usize>,
+
+    cols : Vec<usize>
+
+}
+
+impl CellRange {
+
+    fn set_rows(&mut self, rows : Vec<usize>) {
+        self.row_set = rows.iter().cloned().collect();
+        self.rows = rows;
     }
 
-    pub fn parent(&self) -> ScrolledWindow {
-        self.scroll_window.clone()
+    fn clear(&mut self) {
+        self.anchor = None;
+        self.rows.clear();
+        self.row_set.clear();
+        self.cols.clear();
     }
 
-    /*fn create_header_cell(
-        &self,
-        data : &str,
-        row : usize,
-        col : usize,
-        nrows : usize,
-        ncols : usize
-    ) -> gtk::EventBox {
-        let label = self.create_data_cell(data, row, col, nrows, ncols);
-        let ev_box = gtk::EventBox::new();
-        //ev_box.set_above_child(true);
-        //ev_box.set_visible_window(true);
-        ev_box.add(&label);
-        if let Ok(mut sel) = self.selected.try_borrow_mut() {
-            sel.push((data.to_string(), col, false));
-        }
-        ev_box
-    }*/
-
-    fn create_data_cell(
-        &self,
-        data : &str,
-        row : usize,
-        col : usize,
-        nrows : usize,
-        ncols : usize
-    ) -> Label {
-
-        // No allocation happens here
-        let mut trimmed_data = String::new();
-
-        let label_data : &str = if data.len() <= 140 {
-            &data[..]
+}
+
+/// Source table of an editable result. The query that produced the result is
+/// executed again after the changes are applied.
+#[derive(Debug, Clone)]
+pub struct EditTarget {
+
+    pub table : String,
+
+    pub query : String,
+
+    // Indices of the primary key columns.
+    pub pks : Vec<usize>,
+
+    // Which result columns belong to the table.
+    pub editable : Vec<bool>
+
+}
+
+/// Changes made at an editable table, kept until they are applied or discarded. Rows are
+/// indexed as in the table; new rows are indexed after them.
+struct StagedChanges {
+
+    tbl : Rc<Table>,
+
+    nrows : usize,
+
+    // New values of the original cells (None for NULL), keyed by (row, column).
+    updated : BTreeMap<(usize, usize), Option<String>>,
+
+    deleted : BTreeSet<usize>,
+
+    // Values of the new rows. Rows removed before being applied are None.
+    inserted : Vec<Option<Vec<Option<String>>>>
+
+}
+
+impl StagedChanges {
+
+    fn new(tbl : Rc<Table>, nrows : usize) -> Self {
+        Self { tbl, nrows, updated : BTreeMap::new(), deleted : BTreeSet::new(), inserted : Vec::new() }
+    }
+
+    fn len(&self) -> usize {
+        let updated_rows : BTreeSet<usize> = self.updated.keys()
+            .map(|(row, _)| *row )
+            .filter(|row| !self.deleted.contains(row) )
+            .collect();
+        updated_rows.len() + self.deleted.len() + self.inserted.iter().flatten().count()
+    }
+
+    fn original(&self, row : usize, col : usize) -> Option<String> {
+        if self.tbl.get_column(col).map(|c| c.is_null_at(row) ).unwrap_or(true) {
+            None
         } else {
-            // Only if content is too big, allocate it.
-            trimmed_data += &data[0..140];
-            trimmed_data += "...";
-            &trimmed_data[..]
-        };
-        let label = Label::new(None);
-        label.set_use_markup(true);
-        label.set_markup(label_data);
-        label.set_hexpand(true);
-        let ctx = label.style_context();
-
-        /*label.connect_activate_link(move |_, uri| {
-            if uri.starts_with("queries://") {
-                let split = uri.trim_start_matches("queries://").split("+");
-                if let Some(fn_name) = split.next() {
-                    let mut params = Vec::new();
-                    while let Some(param) = split.next() {
-                        params.push(param.to_string());
-                    }
+            self.tbl.text_at(row, col).map(|txt| txt.to_string() )
+        }
+    }
+
+    fn value(&self, row : usize, col : usize) -> Option<String> {
+        if row >= self.nrows {
+            self.inserted.get(row - self.nrows)
+                .and_then(|r| r.as_ref()?.get(col).cloned() )
+                .unwrap_or_default()
+        } else {
+            match self.updated.get(&(row, col)) {
+                Some(value) => value.clone(),
+                None => self.original(row, col)
+            }
+        }
+    }
+
+    fn set_value(&mut self, row : usize, col : usize, value : Option<String>) {
+        if row >= self.nrows {
+            if let Some(Some(new_row)) = self.inserted.get_mut(row - self.nrows) {
+                if let Some(cell) = new_row.get_mut(col) {
+                    *cell = value;
                 }
-                Inhibit(true)
-            } else {
-                Inhibit(false)
             }
-        });*/
+        } else if value == self.original(row, col) {
+            self.updated.remove(&(row, col));
+        } else {
+            self.updated.insert((row, col), value);
+        }
+    }
 
-        ctx.add_provider(&(self.provider),800); // PROVIDER_CONTEXT_USER
-        ctx.add_class("table-cell");
+    fn style_cell(&self, cell : &Widget, row : usize, col : usize) {
+        let ctx = cell.style_context();
+        for (class, active) in [
+            ("dim-label", self.value(row, col).is_none()),
+            ("edited", self.updated.contains_key(&(row, col))),
+            ("inserted", row >= self.nrows),
+            ("deleted", self.deleted.contains(&row))
+        ] {
+            if active {
+                ctx.add_class(class);
+            } else {
+                ctx.remove_class(class);
+            }
+        }
+    }
 
-        // Add this only when all columns have a title, maybe on a set_header method.
-        if row == 0 {
-            ctx.add_class("first-row");
+    fn row_changes(&self, names : &[String], target : &EditTarget) -> Vec<RowChange> {
+        let key = |row : usize| -> Vec<(String, String)> {
+            target.pks.iter()
+                .map(|ix| {
+                    let lit = self.tbl.get_column(*ix)
+                        .map(|col| sql_script::sql_literal(col, row) )
+                        .unwrap_or_else(|| String::from("NULL") );
+                    (names[*ix].clone(), lit)
+                })
+                .collect()
+        };
+        let mut changes = Vec::new();
+        for row in self.deleted.iter() {
+            changes.push(RowChange::Delete { key : key(*row) });
         }
-        if row == nrows - 1 {
-            ctx.add_class("last-row");
+        let mut updated : BTreeMap<usize, Vec<(String, Option<String>)>> = BTreeMap::new();
+        for ((row, col), value) in self.updated.iter() {
+            if !self.deleted.contains(row) {
+                updated.entry(*row).or_default().push((names[*col].clone(), value.clone()));
+            }
         }
-        if col % 2 != 0 {
-            ctx.add_class("odd-col");
-        } else {
-            ctx.add_class("even-col");
+        for (row, values) in updated {
+            changes.push(RowChange::Update { key : key(row), values });
         }
-        if col == ncols-1 {
-            ctx.add_class("last-col");
+
+        // Empty cells of new rows are left to the column defaults.
+        for new_row in self.inserted.iter().flatten() {
+            let values : Vec<(String, Option<String>)> = new_row.iter()
+                .enumerate()
+                .filter(|(j, value)| target.editable[*j] && value.as_ref().map(|v| !v.is_empty() ).unwrap_or(true) )
+                .map(|(j, value)| (names[j].clone(), value.clone()) )
+                .collect();
+            if values.len() > 0 {
+                changes.push(RowChange::Insert { values });
+            }
         }
-        if (row + 1) % 2 != 0 {
-            ctx.add_class("odd-row");
+        changes
+    }
+
+}
+
+#[derive(Clone, Debug)]
+struct EditControls {
+    status : Label,
+    apply_btn : Button,
+    discard_btn : Button
+}
+
+impl EditControls {
+
+    fn update(&self, changes : &StagedChanges) {
+        let n = changes.len();
+        self.apply_btn.set_sensitive(n > 0);
+        self.discard_btn.set_sensitive(n > 0);
+        match n {
+            0 => self.status.set_text(""),
+            1 => self.status.set_text("1 change staged"),
+            n => self.status.set_text(&format!("{} changes staged", n))
         }
+    }
 
-        label
+}
+
+fn row_of(obj : &glib::Object) -> Option<usize> {
+    let obj = obj.downcast_ref::<glib::BoxedAnyObject>()?;
+    let ix = *obj.borrow::<usize>();
+    Some(ix)
+}
+
+fn row_index(item : &ListItem) -> Option<usize> {
+    row_of(&item.item()?)
+}
+
+fn cell_text(cell : &Widget) -> String {
+    if let Some(lbl) = cell.downcast_ref::<Label>() {
+        lbl.text().to_string()
+    } else if let Some(lbl) = cell.downcast_ref::<EditableLabel>() {
+        lbl.text().to_string()
+    } else {
+        String::new()
     }
+}
 
-    /*/// Returns selected columns, as a continuous index from the first
-    /// column of the current table
-    pub fn selected_cols(&self) -> Vec<usize> {
-        if let Ok(sel) = self.selected.try_borrow() {
-            sel.iter().filter(|s| s.2 == true ).map(|s| s.1 ).collect()
+// Milliseconds without typing before the table is searched.
+const SEARCH_DELAY : u64 = 250;
+
+/// Lowercase text of the cells (built on the first search), and the cells matching the last
+/// searched text. When the text is extended, only the cells that matched it are searched again.
+#[derive(Default)]
+struct SearchIndex {
+
+    texts : Vec<Vec<String>>,
+
+    last : String,
+
+    // Matching columns of each row with any match.
+    matches : HashMap<usize, Vec<usize>>
+
+}
+
+impl SearchIndex {
+
+    fn update(&mut self, tbl : &Table, ncols : usize, txt : &str) {
+        if txt.is_empty() {
+            self.matches.clear();
+            self.last.clear();
+            return;
+        }
+        if self.texts.is_empty() {
+            self.texts = (0..tbl.nrows())
+                .map(|row| {
+                    (0..ncols).map(|col| tbl.text_at(row, col).map(|t| t.to_lowercase() ).unwrap_or_default() ).collect()
+                })
+                .collect();
+        }
+        let texts = &self.texts;
+        let matching = |row : usize, cols : &mut dyn Iterator<Item=usize>| -> Vec<usize> {
+            cols.filter(|col| texts[row].get(*col).map(|t| t.contains(txt) ).unwrap_or(false) ).collect()
+        };
+        let prev = std::mem::take(&mut self.matches);
+        let found : Vec<(usize, Vec<usize>)> = if !self.last.is_empty() && txt.starts_with(&self.last[..]) {
+            prev.into_iter().map(|(row, cols)| (row, matching(row, &mut cols.into_iter())) ).collect()
         } else {
-            println!("Selected is borrowed");
-            Vec::new()
+            (0..texts.len()).map(|row| (row, matching(row, &mut (0..ncols))) ).collect()
+        };
+        self.matches = found.into_iter().filter(|(_, cols)| cols.len() > 0 ).collect();
+        self.last = txt.to_string();
+    }
+
+}
+
+fn shorten(data : &str) -> Cow<'_, str> {
+    match data.char_indices().nth(MAX_CELL_CHARS) {
+        Some((end, _)) => Cow::Owned(format!("{}...", &data[..end])),
+        None => Cow::Borrowed(data)
+    }
+}
+
+
+fn format_number(value : f64) -> String {
+    let txt = format!("{:.4}", value);
+    txt.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn summary_text(ncells : usize, values : &[f64]) -> String {
+    let mut txt = match ncells {
+        1 => String::from("1 cell"),
+        n => format!("{} cells", n)
+    };
+    if values.len() > 0 {
+        let sum : f64 = values.iter().sum();
+        let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        txt += &format!(
+            "   Sum: {}   Mean: {}   Min: {}   Max: {}",
+            format_number(sum),
+            format_number(sum / values.len() as f64),
+            format_number(min),
+            format_number(max)
+        );
+    }
+    txt
+}
+
+// Formats offered to copy the selected cells, as (parameter of table.copy, menu label).
+const COPY_FORMATS : [(&'static str, &'static str); 6] = [
+    ("csv", "Copy as CSV"),
+    ("tsv", "Copy as TSV"),
+    ("markdown", "Copy as Markdown"),
+    ("latex", "Copy as LaTeX"),
+    ("html", "Copy as HTML"),
+    ("sql", "Copy as INSERT statements")
+];
+
+fn remove_row(model : &gio::ListStore, row : usize) {
+    for pos in 0..model.n_items() {
+        let is_row = model.item(pos).and_then(|obj| row_of(&obj) ) == Some(row);
+        if is_row {
+            model.remove(pos);
+            return;
         }
     }
+}
 
-    pub fn unselected_cols(&self) -> Vec<usize> {
-        let n = if let Ok(sel) = self.selected.try_borrow() {
-            sel.len()
-        } else {
-            println!("Selected is borrowed");
-            return Vec::new();
+impl TableWidget {
+
+    pub fn new_from_table(tbl : &Table, max_nrows : usize, max_ncols : usize) -> Self {
+        let tbl = Rc::new(tbl.clone());
+        let tbl_wid = Self::new(&tbl);
+        for (j, name) in tbl.names().iter().take(max_ncols).enumerate() {
+            let factory = tbl_wid.cells.text_factory(&tbl, j);
+            tbl_wid.append_column(&tbl, name, j, &factory);
+        }
+        tbl_wid.set_rows(tbl.nrows().min(max_nrows));
+        tbl_wid
+    }
+
+    /// Builds a table with editable cells, where rows can also be inserted or deleted. When the
+    /// user applies the staged changes, the statements writing them to the source table (followed by
+    /// the original query) are sent as the parameter of apply_action.
+    pub fn new_editable(
+        tbl : &Table,
+        max_nrows : usize,
+        max_ncols : usize,
+        target : EditTarget,
+        apply_action : &gio::SimpleAction
+    ) -> Self {
+        let names : Vec<String> = tbl.names().into_iter().take(max_ncols).collect();
+        if target.pks.iter().any(|ix| *ix >= names.len() ) {
+            return Self::new_from_table(tbl, max_nrows, max_ncols);
+        }
+        let tbl = Rc::new(tbl.clone());
+        let tbl_wid = Self::new(&tbl);
+        let nrows = tbl.nrows().min(max_nrows);
+
+        let controls = EditControls {
+            status : Label::new(None),
+            apply_btn : Button::with_label("Apply"),
+            discard_btn : Button::with_label("Discard")
         };
-        let selected = self.selected_cols();
-        let mut unselected = Vec::new();
-        for i in 0..n {
-            if !selected.iter().any(|s| i == *s) {
-                unselected.push(i);
-            }
+        controls.apply_btn.style_context().add_class("suggested-action");
+        let add_btn = Button::builder().icon_name("list-add-symbolic").build();
+        add_btn.set_tooltip_text(Some("Add row"));
+        let action_bar = ActionBar::new();
+        action_bar.pack_start(&add_btn);
+        action_bar.pack_start(&controls.status);
+        action_bar.pack_end(&controls.apply_btn);
+        action_bar.pack_end(&controls.discard_btn);
+        tbl_wid.bx.append(&action_bar);
+        let changes = Rc::new(RefCell::new(StagedChanges::new(tbl.clone(), nrows)));
+        controls.update(&changes.borrow());
+
+        // The first column holds the buttons that delete each row.
+        let delete_factory = tbl_wid.cells.delete_factory(&tbl_wid.model, &changes, &controls);
+        tbl_wid.view.append_column(&ColumnViewColumn::new(None, Some(&delete_factory)));
+        for (j, name) in names.iter().enumerate() {
+            let factory = if target.editable[j] {
+                tbl_wid.cells.editable_factory(&changes, &controls, j)
+            } else {
+                tbl_wid.cells.text_factory(&tbl, j)
+            };
+            tbl_wid.append_column(&tbl, name, j, &factory);
         }
-        unselected
+        tbl_wid.set_rows(nrows);
+
+        add_btn.connect_clicked({
+            let model = tbl_wid.model.clone();
+            let adj = tbl_wid.scroll_window.vadjustment();
+            let changes = changes.clone();
+            let controls = controls.clone();
+            let ncols = names.len();
+            move |_| {
+                let mut changes = changes.borrow_mut();
+                let row = changes.nrows + changes.inserted.len();
+                changes.inserted.push(Some(vec![Some(String::new()); ncols]));
+                controls.update(&changes);
+                model.append(&glib::BoxedAnyObject::new(row));
+                let adj = adj.clone();
+                glib::idle_add_local_once(move || adj.set_value(adj.upper()) );
+            }
+        });
+
+        controls.discard_btn.connect_clicked({
+            let model = tbl_wid.model.clone();
+            let changes = changes.clone();
+            let controls = controls.clone();
+            move |_| {
+                {
+                    let mut changes = changes.borrow_mut();
+                    changes.updated.clear();
+                    changes.deleted.clear();
+                    changes.inserted.clear();
+                    controls.update(&changes);
+                }
+                let n_new = model.n_items() - nrows as u32;
+                model.splice(nrows as u32, n_new, &[] as &[glib::Object]);
+
+                // Binds all rows again, which resets the cell values and styles.
+                model.items_changed(0, nrows as u32, nrows as u32);
+            }
+        });
+
+        controls.apply_btn.connect_clicked({
+            let changes = changes.clone();
+            let apply_action = apply_action.clone();
+            move |_| {
+                let row_changes = changes.borrow().row_changes(&names[..], &target);
+                if row_changes.len() > 0 {
+                    let stmts = format!(
+                        "{}{};",
+                        edit::change_statements(&target.table, &row_changes[..]),
+                        target.query.trim_end_matches(';')
+                    );
+                    apply_action.activate(Some(&stmts.to_variant()));
+                }
+            }
+        });
+
+        tbl_wid
     }
 
-    pub fn unselect_all(&self) {
-        let selected = self.selected_cols();
-        if let Ok(mut sel) = self.selected.try_borrow_mut() {
-            for s in selected {
-                Self::switch_selected(self.grid.clone(), &mut sel, s);
+    fn new(tbl : &Rc<Table>) -> TableWidget {
+        let model = gio::ListStore::new(glib::BoxedAnyObject::static_type());
+        let row_filter : Rc<RefCell<Option<RowFilter>>> = Default::default();
+
+        // Rows inserted at editable tables are always shown.
+        let filter = CustomFilter::new({
+            let tbl = tbl.clone();
+            let row_filter = row_filter.clone();
+            move |obj| {
+                match (row_of(obj), &*row_filter.borrow()) {
+                    (Some(row), Some(f)) if row < tbl.nrows() => f.matches(&tbl, row),
+                    _ => true
+                }
             }
-        } else {
-            println!("Could not retrieve mutable reference to selected");
+        });
+        let filter_model = FilterListModel::new(Some(&model), Some(&filter));
+        let view = ColumnView::new(None::<&NoSelection>);
+        let rows = SortListModel::new(Some(&filter_model), view.sorter().as_ref());
+        let positions : Rc<RefCell<HashMap<usize, u32>>> = Default::default();
+        rows.connect_items_changed({
+            let positions = positions.clone();
+            move |_, _, _, _| positions.borrow_mut().clear()
+        });
+        view.set_model(Some(&NoSelection::new(Some(&rows))));
+        view.set_reorderable(true);
+        view.set_show_column_separators(true);
+        view.set_show_row_separators(true);
+        view.set_hexpand(true);
+        view.set_vexpand(true);
+
+        let provider = CssProvider::new();
+        provider.load_from_data(TABLE_CSS.as_bytes());
+
+        let scroll_window = ScrolledWindow::new();
+        scroll_window.set_vexpand(true);
+        scroll_window.set_valign(Align::Fill);
+        scroll_window.set_child(Some(&view));
+
+        let filter_entry = Entry::new();
+        filter_entry.set_hexpand(true);
+        filter_entry.set_placeholder_text(Some("Filter rows (e.g. price > 10 and name contains pen and notes is null)"));
+        filter_entry.set_primary_icon_name(Some("view-list-symbolic"));
+        let search_entry = SearchEntry::new();
+        search_entry.set_placeholder_text(Some("Find"));
+        let matches_lbl = Label::new(None);
+        let copy_menu = gio::Menu::new();
+        for (format, label) in COPY_FORMATS.iter() {
+            let item = gio::MenuItem::new(Some(label), None);
+            item.set_action_and_target_value(Some("table.copy"), Some(&format.to_variant()));
+            copy_menu.append_item(&item);
         }
-    }
+        let select_section = gio::Menu::new();
+        select_section.append(Some("Select all"), Some("table.select_all"));
+        select_section.append(Some("Clear selection"), Some("table.clear_selection"));
+        copy_menu.append_section(None, &select_section);
+        let copy_btn = MenuButton::new();
+        copy_btn.set_icon_name("edit-copy-symbolic");
+        copy_btn.set_tooltip_text(Some("Copy selection (or all rows when nothing is selected)"));
+        copy_btn.set_menu_model(Some(&copy_menu));
+        let top_bx = Box::new(Orientation::Horizontal, 6);
+        super::set_margins(&top_bx, 6, 6);
+        top_bx.append(&filter_entry);
+        top_bx.append(&search_entry);
+        top_bx.append(&matches_lbl);
+        top_bx.append(&copy_btn);
+
+        let summary = Label::new(None);
+        summary.set_xalign(0.0);
+        summary.set_selectable(true);
+        summary.set_visible(false);
+        super::set_margins(&summary, 6, 6);
+
+        let record = super::RecordView::build();
+        let paned = Paned::new(Orientation::Horizontal);
+        paned.set_start_child(Some(&scroll_window));
+        paned.set_end_child(Some(&record.bx));
+        paned.set_resize_end_child(false);
+        paned.set_shrink_end_child(false);
 
-    fn switch_all(grid : Grid, cols : &mut [(String, usize, bool)]) {
-        let switch_to = !cols.iter().any(|c| c.2);
-        let n = cols.len();
-        for i in 0..n {
-            Self::switch_to(grid.clone(), &mut cols[i], n, switch_to);
+        let bx = Box::new(Orientation::Vertical, 0);
+        bx.append(&top_bx);
+        bx.append(&paned);
+        bx.append(&summary);
+
+        let cells = Cells {
+            tbl : tbl.clone(),
+            rows : rows.downgrade(),
+            provider,
+            bound : Default::default(),
+            selection : Default::default(),
+            range : Default::default(),
+            summary,
+            record,
+            search : Default::default(),
+            search_index : Default::default(),
+            positions,
+            nulls_first : Default::default(),
+            sorters : Default::default()
+        };
+
+        // Set at the box, so the actions are also reached from the copy menu.
+        bx.insert_action_group("table", Some(&cells.actions()));
+        let shortcuts = ShortcutController::new();
+        for (trigger, action, arg) in [
+            ("<Control>c", "table.copy", Some("tsv".to_variant())),
+            ("<Control>a", "table.select_all", None)
+        ] {
+            let shortcut = Shortcut::new(ShortcutTrigger::parse_string(trigger).as_ref(), Some(&NamedAction::new(action)));
+            shortcut.set_arguments(arg.as_ref());
+            shortcuts.add_shortcut(&shortcut);
         }
+        bx.add_controller(&shortcuts);
+
+        filter_entry.connect_changed({
+            let names = tbl.names();
+            move |entry| {
+                match RowFilter::parse(&entry.text(), &names[..]) {
+                    Ok(new_filter) => {
+                        entry.style_context().remove_class("error");
+                        entry.set_tooltip_text(None);
+                        row_filter.replace(new_filter);
+                        filter.changed(FilterChange::Different);
+                    },
+                    Err(e) => {
+                        entry.style_context().add_class("error");
+                        entry.set_tooltip_text(Some(&e));
+                    }
+                }
+            }
+        });
+
+        // Position (at the shown rows) of the last match the view was scrolled to.
+        let match_pos : Rc<Cell<Option<u32>>> = Default::default();
+        let n_changes = Rc::new(Cell::new(0usize));
+        search_entry.connect_search_changed({
+            let cells = cells.clone();
+            let rows = rows.downgrade();
+            let tbl = tbl.clone();
+            let match_pos = match_pos.clone();
+            move |entry| {
+                let curr_change = n_changes.get() + 1;
+                n_changes.set(curr_change);
+                let txt = entry.text().to_lowercase();
+                let (n_changes, cells, rows, tbl, match_pos, matches_lbl) = (
+                    n_changes.clone(),
+                    cells.clone(),
+                    rows.clone(),
+                    tbl.clone(),
+                    match_pos.clone(),
+                    matches_lbl.clone()
+                );
+                glib::timeout_add_local_once(Duration::from_millis(SEARCH_DELAY), move || {
+                    let rows = match rows.upgrade() {
+                        Some(rows) if n_changes.get() == curr_change => rows,
+                        _ => return
+                    };
+                    cells.search.replace(txt.clone());
+                    cells.search_index.borrow_mut().update(&tbl, cells.ncols(), &txt);
+                    for (cell, _) in cells.bound.borrow().iter() {
+                        cells.highlight(cell, &cell_text(cell));
+                    }
+                    match_pos.set(None);
+                    if txt.is_empty() {
+                        matches_lbl.set_text("");
+                        return;
+                    }
+                    let index = cells.search_index.borrow();
+                    let n = (0..rows.n_items())
+                        .filter_map(|pos| rows.item(pos).and_then(|obj| row_of(&obj) ) )
+                        .filter_map(|row| index.matches.get(&row).map(|cols| cols.len() ) )
+                        .sum::<usize>();
+                    match n {
+                        1 => matches_lbl.set_text("1 match"),
+                        n => matches_lbl.set_text(&format!("{} matches", n))
+                    }
+                });
+            }
+        });
+
+        let find_next = {
+            let cells = cells.clone();
+            let rows = rows.clone();
+            let adj = scroll_window.vadjustment();
+            move |forward : bool| {
+                let txt = cells.search.borrow().clone();
+                let n = rows.n_items();
+                if txt.is_empty() || n == 0 {
+                    return;
+                }
+                let index = cells.search_index.borrow();
+                let start = match (match_pos.get(), forward) {
+                    (Some(pos), true) => pos + 1,
+                    (Some(pos), false) => pos + n - 1,
+                    (None, true) => 0,
+                    (None, false) => n - 1
+                };
+                for i in 0..n {
+                    let pos = if forward { (start + i) % n } else { (start + n - i) % n };
+                    let found = rows.item(pos).and_then(|obj| row_of(&obj) )
+                        .map(|row| index.matches.contains_key(&row) )
+                        .unwrap_or(false);
+                    if found {
+                        match_pos.set(Some(pos));
+
+                        // Rows have about the same height, so the position maps to a fraction of the scroll range.
+                        let frac = pos as f64 / n as f64;
+                        adj.set_value(frac * adj.upper() - adj.page_size() / 2.);
+                        return;
+                    }
+                }
+            }
+        };
+        let find_next = Rc::new(find_next);
+        search_entry.connect_activate({
+            let find_next = find_next.clone();
+            move |_| find_next(true)
+        });
+        search_entry.connect_next_match({
+            let find_next = find_next.clone();
+            move |_| find_next(true)
+        });
+        search_entry.connect_previous_match(move |_| find_next(false) );
+
+        TableWidget { view, scroll_window, bx, model, rows, cells }
     }
 
-    pub fn expose_event_box(&self, ix : usize) -> Option<EventBox> {
-        // let children = self.grid.get_children();
+    pub fn parent(&self) -> ScrolledWindow {
+        self.scroll_window.clone()
+    }
 
-        // TODO correct "attempted to subtract with overflow: changing from one
-        // mapping to the other leads to search for inexistent table.
-        let (nrows, ncols) = self.dimensions();
-        let children = self.grid.get_children();
-        let header_iter = children.iter()
-            .skip(ncols * nrows - ncols);
-        let n = header_iter.clone().count();
-        let wid = header_iter.skip(n-ix-1).next()?;
-        if let Ok(ev_box) = wid.clone().downcast::<EventBox>() {
-            Some(ev_box.clone())
-        } else {
-            None
+    /// Indices (at the table) of the rows as currently shown, after filtering and sorting.
+    pub fn shown_rows(&self) -> Vec<usize> {
+        (0..self.rows.n_items())
+            .filter_map(|pos| self.rows.item(pos).and_then(|obj| row_of(&obj) ) )
+            .collect()
+    }
+
+    fn set_rows(&self, nrows : usize) {
+        let rows : Vec<glib::BoxedAnyObject> = (0..nrows).map(|ix| glib::BoxedAnyObject::new(ix) ).collect();
+        self.model.splice(0, self.model.n_items(), &rows[..]);
+    }
+
+    fn append_column(&self, tbl : &Rc<Table>, name : &str, ix : usize, factory : &SignalListItemFactory) {
+        let col = ColumnViewColumn::new(Some(name), Some(factory));
+        col.set_resizable(true);
+
+        // Rows inserted at editable tables stay after the table rows.
+        let sorter = CustomSorter::new({
+            let tbl = tbl.clone();
+            let nulls_first = self.cells.nulls_first.clone();
+            move |a, b| {
+                let (a, b) = (row_of(a).unwrap_or(0), row_of(b).unwrap_or(0));
+                let nrows = tbl.nrows();
+                let ord = match tbl.get_column(ix) {
+                    Some(col) if a < nrows && b < nrows => col.compare_rows(a, b, nulls_first.get()),
+                    _ => a.cmp(&b)
+                };
+                ord.into()
+            }
+        });
+        col.set_sorter(Some(&sorter));
+        self.cells.sorters.borrow_mut().push(sorter);
+
+        let menu = gio::Menu::new();
+        let select_item = gio::MenuItem::new(Some("Select column"), None);
+        select_item.set_action_and_target_value(Some("table.select_column"), Some(&(ix as i32).to_variant()));
+        menu.append_item(&select_item);
+        menu.append(Some("Clear selection"), Some("table.clear_selection"));
+        menu.append(Some("Nulls first"), Some("table.nulls_first"));
+        col.set_header_menu(Some(&menu));
+        self.view.append_column(&col);
+        self.cells.selection.borrow_mut().selected.push(false);
+    }
+
+    /// Returns selected columns, as a continuous index from the first
+    /// column of the current table
+    pub fn selected_cols(&self) -> Vec<usize> {
+        self.cells.selection.borrow().selected_cols()
+    }
+
+    pub fn unselected_cols(&self) -> Vec<usize> {
+        self.cells.selection.borrow().selected.iter()
+            .enumerate()
+            .filter(|(_, s)| !**s )
+            .map(|(ix, _)| ix )
+            .collect()
+    }
+
+    pub fn unselect_all(&self) {
+        self.cells.unselect_all();
+    }
+
+    pub fn set_selected(&self, new_sel : &[usize]) {
+        self.cells.unselect_all();
+        for ix in new_sel.iter() {
+            self.cells.switch_selected(*ix);
         }
     }
 
-    /// Function supplied by user should take all selected columns at
-    /// the third argument and the index of the last selected column at
-    /// the last argument.
-    pub fn set_selected_action<F>(&self, f : F, btn : u32)
+    /// Function supplied by user takes all selected columns at the first argument
+    /// and the index of the last (un)selected column at the second argument.
+    pub fn set_selected_action<F>(&self, f : F)
     where
-        F : Clone,
-        for<'r,'s> F : Fn(&'r EventBox, &'s gdk::EventButton, Vec<usize>, usize)->Inhibit+'static
+        F : Fn(Vec<usize>, usize) + 'static
     {
-        let children = self.grid.get_children();
-        let (nrows, ncols) = self.dimensions();
-        let header_iter = children.iter()
-            .skip(ncols * nrows - ncols);
-        let n = header_iter.clone().count();
-        for (i, wid) in header_iter.enumerate() {
-            if let Ok(ev_box) = wid.clone().downcast::<EventBox>() {
-                let selected = self.selected.clone();
-                let f = f.clone();
-                ev_box.connect_button_press_event(move |ev_box, ev| {
-                    if ev.get_button() == btn {
-                        if let Ok(sel) = selected.try_borrow() {
-                            let sel_ix : Vec<_> = sel.iter()
-                                .filter(|c| c.2)
-                                .map(|c| c.1)
-                                .collect();
-                            f(ev_box, ev, sel_ix, n - i - 1);
-                        } else {
-                            println!("Unable to retrieve reference to selected vector");
-                        }
-                    }
-                    glib::signal::Inhibit(false)
-                });
-            } else {
-                println!("Could not convert widget to event box");
+        self.cells.selection.borrow_mut().on_selected.push(Rc::new(f));
+    }
+
+}
+
+impl Cells {
+
+    fn style(&self, cell : &impl IsA<Widget>) {
+        cell.set_hexpand(true);
+        let ctx = cell.style_context();
+        ctx.add_provider(&(self.provider), 800); // PROVIDER_CONTEXT_USER
+        ctx.add_class("table-cell");
+    }
+
+    fn ncols(&self) -> usize {
+        self.selection.borrow().selected.len()
+    }
+
+    /* Keeps track of the cells bound to data, and sets their selection and search styles. */
+    fn bind(&self, cell : &Widget, row : usize, col : usize, text : &str) {
+        self.style_selected(cell, row, col);
+        self.highlight(cell, text);
+        self.bound.borrow_mut().insert(cell.clone(), (row, col));
+    }
+
+    /* Position of the row at the shown rows. Positions are indexed once after the shown rows
+change (e.g. when sorted or filtered), instead of searching them at every click. */
+    fn position_of(&self, rows : &SortListModel, row : usize) -> Option<u32> {
+        let mut positions = self.positions.borrow_mut();
+        if positions.is_empty() {
+            for pos in 0..rows.n_items() {
+                if let Some(r) = rows.item(pos).and_then(|obj| row_of(&obj) ) {
+                    positions.insert(r, pos);
+                }
             }
         }
-    }*/
+        positions.get(&row).cloned()
+    }
 
-    fn update_data<I, S>(&mut self, mut data : Vec<I>)
-    where
-        I : ExactSizeIterator<Item=S>,
-        S : AsRef<str>
-    {
-        self.clear_table();
-        if data.len() == 0 {
-            return;
+    fn is_selected(&self, row : usize, col : usize) -> bool {
+        let range = self.range.borrow();
+        if range.row_set.contains(&row) && range.cols.contains(&col) {
+            return true;
+        }
+        self.selection.borrow().selected.get(col).cloned().unwrap_or(false)
+    }
+
+    fn style_selected(&self, cell : &Widget, row : usize, col : usize) {
+        if self.is_selected(row, col) {
+            cell.style_context().add_class("selected");
+        } else {
+            cell.style_context().remove_class("selected");
+        }
+    }
+
+    fn restyle_selected(&self) {
+        for (cell, (row, col)) in self.bound.borrow().iter() {
+            self.style_selected(cell, *row, *col);
         }
-        let nrows = data.len(); /*.min(200);*/
-        let mut ncols = 0;
-        for (i, row) in data.iter_mut().enumerate().take(nrows) {
-            if i == 0 {
-                ncols = row.len();
-                if ncols == 0 {
+        self.update_summary();
+    }
+
+    fn add_click(&self, cell : &impl IsA<Widget>) {
+        let click = GestureClick::new();
+        let cells = self.clone();
+        click.connect_pressed(move |gesture, n_press, _, _| {
+            let pos = cells.bound.borrow().get(&gesture.widget()).cloned();
+            if let Some((row, col)) = pos {
+                if n_press == 2 {
+                    cells.record.show_row(&cells.tbl, row);
                     return;
                 }
-                self.update_table_dimensions(nrows as i32, ncols as i32);
-            }
-            for (j, col) in row.enumerate() {
-                let cell = self.create_data_cell(col.as_ref(), i, j, nrows, ncols);
-                self.grid.attach(&cell, j as i32, i as i32, 1, 1);
+                let state = gesture.current_event_state();
+                cells.select_cells(
+                    row,
+                    col,
+                    state.contains(gdk::ModifierType::SHIFT_MASK),
+                    state.contains(gdk::ModifierType::CONTROL_MASK)
+                );
             }
-        }
+        });
+        cell.add_controller(&click);
     }
 
-    /*fn clear_tail(&self, remaining_rows : usize) {
-        while self.grid.child_at(0, (remaining_rows-1) as i32).is_some() {
-            self.grid.remove_row((remaining_rows-1) as i32);
+    /* Selects the clicked cell (or its whole row), or extends the selection up to it. A cell selection
+    replaces the column selection. */
+    fn select_cells(&self, row : usize, col : usize, extend : bool, whole_rows : bool) {
+        let rows = match self.rows.upgrade() {
+            Some(rows) => rows,
+            None => return
+        };
+        let pos = match self.position_of(&rows, row) {
+            Some(pos) => pos,
+            None => return
+        };
+        self.unselect_all();
+        {
+            let mut range = self.range.borrow_mut();
+            let (anchor_pos, anchor_col) = match range.anchor {
+                Some(anchor) if extend => anchor,
+                _ => (pos, col)
+            };
+            range.anchor = Some((anchor_pos, anchor_col));
+            let shown : Vec<usize> = (anchor_pos.min(pos)..=anchor_pos.max(pos))
+                .filter_map(|p| rows.item(p).and_then(|obj| row_of(&obj) ) )
+                .collect();
+            range.set_rows(shown);
+            range.cols = if whole_rows {
+                (0..self.ncols()).collect()
+            } else {
+                (anchor_col.min(col)..=anchor_col.max(col)).collect()
+            };
         }
+        self.restyle_selected();
     }
 
-    fn grow_tail(&self, _new_sz : usize) {
+    fn select_all(&self) {
+        let rows = match self.rows.upgrade() {
+            Some(rows) => rows,
+            None => return
+        };
+        self.unselect_all();
+        {
+            let mut range = self.range.borrow_mut();
+            range.anchor = None;
+            range.set_rows((0..rows.n_items()).filter_map(|p| rows.item(p).and_then(|obj| row_of(&obj) ) ).collect());
+            range.cols = (0..self.ncols()).collect();
+        }
+        self.restyle_selected();
+    }
 
-    }*/
+    fn clear_range(&self) {
+        self.range.borrow_mut().clear();
+        self.restyle_selected();
+    }
 
-    fn clear_table(&self) {
-        while self.grid.child_at(0, 0).is_some() {
-            self.grid.remove_row(0);
+    /* Returns the selected (rows, columns), taking cells selected by clicking first, then selected columns
+    over the shown rows. Rows inserted at editable tables are not part of the table, and are left out. */
+    fn selected_cells(&self) -> Option<(Vec<usize>, Vec<usize>)> {
+        let nrows = self.tbl.nrows();
+        let range = self.range.borrow();
+        if range.rows.len() > 0 {
+            let rows = range.rows.iter().cloned().filter(|row| *row < nrows ).collect();
+            return Some((rows, range.cols.clone()));
+        }
+        let cols = self.selection.borrow().selected_cols();
+        if cols.len() > 0 {
+            let rows = self.rows.upgrade()?;
+            let rows = (0..rows.n_items())
+                .filter_map(|p| rows.item(p).and_then(|obj| row_of(&obj) ) )
+                .filter(|row| *row < nrows )
+                .collect();
+            Some((rows, cols))
+        } else {
+            None
         }
     }
 
-    fn update_table_dimensions(&self, nrows : i32, ncols : i32) {
+    fn update_summary(&self) {
+        match self.selected_cells() {
+            Some((rows, cols)) => {
+                let mut values = Vec::new();
+                for col in cols.iter().filter_map(|ix| self.tbl.get_column(*ix) ) {
+                    values.extend(rows.iter().filter_map(|row| col.real_at(*row) ));
+                }
+                self.summary.set_text(&summary_text(rows.len() * cols.len(), &values[..]));
+                self.summary.set_visible(true);
+            },
+            None => {
+                self.summary.set_text("");
+                self.summary.set_visible(false);
+            }
+        }
+    }
 
-        for c in 0..ncols {
-            self.grid.insert_column(c);
+    /* Copies the selected cells (or all shown rows when nothing is selected) in the given format. */
+    fn copy(&self, format : &str) {
+        let (rows, cols) = match self.selected_cells() {
+            Some(sel) => sel,
+            None => {
+                let rows = match self.rows.upgrade() {
+                    Some(rows) => rows,
+                    None => return
+                };
+                let shown = (0..rows.n_items())
+                    .filter_map(|p| rows.item(p).and_then(|obj| row_of(&obj) ) )
+                    .filter(|row| *row < self.tbl.nrows() )
+                    .collect();
+                (shown, (0..self.ncols()).collect())
+            }
+        };
+        if rows.len() == 0 || cols.len() == 0 {
+            return;
         }
+        let sel = self.tbl.select(&rows[..], &cols[..]);
+        let txt = match format {
+            "csv" => sel.to_csv(),
+            "tsv" => sel.to_tsv(),
+            "markdown" => sel.to_markdown(),
+            "latex" => sel.to_tex(),
+            "html" => sel.to_html(),
+            "sql" => {
+                let source = sel.source();
+                let name = match (source.name, source.relation) {
+                    (Some(name), None) if !name.contains(" : ") => name,
+                    _ => String::from("selection")
+                };
+                match sel.sql_table_insertion(&sql_script::quote_target(&name), &sel.names()[..]) {
+                    Ok(stmt) => stmt,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return;
+                    }
+                }
+            },
+            _ => return
+        };
+        if let Some(display) = gdk::Display::default() {
+            display.clipboard().set_text(&txt);
+        }
+    }
 
-        for r in 0..nrows {
-            self.grid.insert_row(r);
+    fn highlight(&self, cell : &Widget, text : &str) {
+        let search = self.search.borrow();
+        if !search.is_empty() && text.to_lowercase().contains(&search[..]) {
+            cell.style_context().add_class("match");
+        } else {
+            cell.style_context().remove_class("match");
         }
+    }
 
+    fn unbind(&self, item : &ListItem) {
+        if let Some(child) = item.child() {
+            self.bound.borrow_mut().remove(&child);
+        }
     }
 
-    /*pub fn set_selected(&self, new_sel : &[usize]) {
-        self.unselect_all();
-        if let Ok(mut sel) = self.selected.try_borrow_mut() {
-            for i in new_sel.iter() {
-                Self::switch_selected(self.grid.clone(), &mut sel[..], *i);
+    fn text_factory(&self, tbl : &Rc<Table>, col : usize) -> SignalListItemFactory {
+        let factory = SignalListItemFactory::new();
+        factory.connect_setup({
+            let cells = self.clone();
+            move |_, item| {
+                let lbl = Label::new(None);
+                lbl.set_xalign(0.0);
+                cells.style(&lbl);
+                cells.add_click(&lbl);
+                item.set_child(Some(&lbl));
             }
-        } else {
-            println!("Failed to retrieve mutable reference to selected columns");
-        }
-    }*/
+        });
+        factory.connect_bind({
+            let cells = self.clone();
+            let tbl = tbl.clone();
+            move |_, item| {
+                if let (Some(row), Some(child)) = (row_index(item), item.child()) {
+                    let txt = tbl.text_at(row, col).unwrap_or(Cow::Borrowed(""));
+                    child.clone().downcast::<Label>().unwrap().set_text(&shorten(&txt));
+                    cells.bind(&child, row, col, &txt);
+                }
+            }
+        });
+        factory.connect_unbind({
+            let cells = self.clone();
+            move |_, item| cells.unbind(item)
+        });
+        factory
+    }
+
+    fn editable_factory(
+        &self,
+        changes : &Rc<RefCell<StagedChanges>>,
+        controls : &EditControls,
+        col : usize
+    ) -> SignalListItemFactory {
+        let factory = SignalListItemFactory::new();
+        factory.connect_setup({
+            let cells = self.clone();
+            let changes = changes.clone();
+            let controls = controls.clone();
+            move |_, item| {
+                let lbl = EditableLabel::new("");
+                cells.style(&lbl);
+                cells.add_click(&lbl);
 
-    /*pub fn dimensions(&self) -> (usize, usize) {
-        self.dims.borrow().clone()
-    }*/
+                // Setting the text when the cell is bound also calls this, but leaves the changes as they are.
+                let weak_item = item.downgrade();
+                let cells = cells.clone();
+                let changes = changes.clone();
+                let controls = controls.clone();
+                lbl.connect_changed({
+                    let weak_item = weak_item.clone();
+                    let changes = changes.clone();
+                    let controls = controls.clone();
+                    move |lbl| {
+                        if let Some(row) = weak_item.upgrade().and_then(|item| row_index(&item) ) {
+                            let mut changes = changes.borrow_mut();
 
-}
+                            // The text of NULL cells is a placeholder, kept as NULL until edited.
+                            let text = lbl.text().to_string();
+                            let value = if changes.value(row, col).is_none() && &text[..] == edit::NULL_TEXT {
+                                None
+                            } else {
+                                Some(text)
+                            };
+                            changes.set_value(row, col, value);
+                            changes.style_cell(lbl.upcast_ref(), row, col);
+                            cells.highlight(lbl.upcast_ref(), &lbl.text());
+                            controls.update(&changes);
+                        }
+                    }
+                });
 
-/*fn switch_to(
-    grid : Grid,
-    col : &mut (String, usize, bool),
-    ncols : usize,
-    selected : bool
-) {
-    set_selected_style(grid.clone(), ncols, col.1, selected);
-    *col = (col.0.clone(), col.1, selected);
-}
+                // Typing NULL sets the text 'NULL', so the value is set to NULL with a secondary click.
+                let null_click = GestureClick::new();
+                null_click.set_button(3);
+                null_click.connect_pressed({
+                    let lbl = lbl.downgrade();
+                    move |_, _, _, _| {
+                        let (lbl, row) = match (lbl.upgrade(), weak_item.upgrade().and_then(|item| row_index(&item) )) {
+                            (Some(lbl), Some(row)) => (lbl, row),
+                            _ => return
+                        };
+                        {
+                            let mut changes = changes.borrow_mut();
+                            changes.set_value(row, col, None);
+                            changes.style_cell(lbl.upcast_ref(), row, col);
+                            controls.update(&changes);
+                        }
+                        lbl.set_text(edit::NULL_TEXT);
+                    }
+                });
+                lbl.add_controller(&null_click);
+                lbl.set_tooltip_text(Some("Right-click to set NULL"));
+                item.set_child(Some(&lbl));
+            }
+        });
+        factory.connect_bind({
+            let cells = self.clone();
+            let changes = changes.clone();
+            move |_, item| {
+                if let (Some(row), Some(child)) = (row_index(item), item.child()) {
+                    let value = {
+                        let changes = changes.borrow();
+                        changes.style_cell(&child, row, col);
+                        changes.value(row, col).unwrap_or_else(|| String::from(edit::NULL_TEXT) )
+                    };
+                    child.clone().downcast::<EditableLabel>().unwrap().set_text(&value);
+                    cells.bind(&child, row, col, &value);
+                }
+            }
+        });
+        factory.connect_unbind({
+            let cells = self.clone();
+            move |_, item| cells.unbind(item)
+        });
+        factory
+    }
 
-fn _switch_selected(grid : Grid, cols : &mut [(String, usize, bool)], pos : usize) {
-    let ncols = cols.len();
-    if let Some(col) = cols.get_mut(pos) {
-        if col.2 == true {
-            switch_to(grid.clone(), col, ncols, false);
-        } else {
-            switch_to(grid.clone(), col, ncols, true);
-        }
+    fn delete_factory(
+        &self,
+        model : &gio::ListStore,
+        changes : &Rc<RefCell<StagedChanges>>,
+        controls : &EditControls
+    ) -> SignalListItemFactory {
+        let factory = SignalListItemFactory::new();
+        factory.connect_setup({
+            let cells = self.clone();
+            let model = model.clone();
+            let changes = changes.clone();
+            let controls = controls.clone();
+            move |_, item| {
+                let btn = ToggleButton::new();
+                btn.set_icon_name("user-trash-symbolic");
+                btn.set_tooltip_text(Some("Delete row"));
+                btn.set_has_frame(false);
+                let weak_item = item.downgrade();
+                let cells = cells.clone();
+                let model = model.clone();
+                let changes = changes.clone();
+                let controls = controls.clone();
+                btn.connect_toggled(move |btn| {
+                    let row = match weak_item.upgrade().and_then(|item| row_index(&item) ) {
+                        Some(row) => row,
+                        None => return
+                    };
+                    let mut changes = changes.borrow_mut();
+                    if row >= changes.nrows {
+
+                        // New rows are just removed.
+                        if btn.is_active() {
+                            if let Some(new_row) = changes.inserted.get_mut(row - changes.nrows) {
+                                *new_row = None;
+                            }
+                            controls.update(&changes);
+                            let model = model.clone();
+                            glib::idle_add_local_once(move || remove_row(&model, row) );
+                        }
+                        return;
+                    }
+                    if btn.is_active() {
+                        changes.deleted.insert(row);
+                    } else {
+                        changes.deleted.remove(&row);
+                    }
+                    for (cell, (cell_row, cell_col)) in cells.bound.borrow().iter() {
+                        if *cell_row == row {
+                            changes.style_cell(cell, row, *cell_col);
+                        }
+                    }
+                    controls.update(&changes);
+                });
+                item.set_child(Some(&btn));
+            }
+        });
+        factory.connect_bind({
+            let changes = changes.clone();
+            move |_, item| {
+                if let (Some(row), Some(child)) = (row_index(item), item.child()) {
+                    let deleted = changes.borrow().deleted.contains(&row);
+                    child.downcast::<ToggleButton>().unwrap().set_active(deleted);
+                }
+            }
+        });
+        factory
     }
-}
 
-fn _set_selected_style(_grid : Grid, _ncols : usize, _col : usize, _selected : bool) {
-    /*for wid in grid.get_children().iter().skip(ncols - col - 1).step_by(ncols) {
-        let wid = wid.clone().downcast::<Label>(); /*if let Ok(ev) = wid.clone().downcast::<Label>() {
-            ev.get_child().unwrap()
-        } else {
-            wid.clone()
-        };*/
-        let ctx = wid.get_style_context();
-        if selected {
-            if !ctx.has_class("selected") {
-                ctx.add_class("selected");
+    fn actions(&self) -> gio::SimpleActionGroup {
+        let group = gio::SimpleActionGroup::new();
+        let nulls_action = gio::SimpleAction::new_stateful("nulls_first", None, &(false).to_variant());
+        nulls_action.connect_change_state({
+            let cells = self.clone();
+            move |action, state| {
+                if let Some(nulls_first) = state.and_then(|s| s.get::<bool>() ) {
+                    action.set_state(&nulls_first.to_variant());
+                    cells.nulls_first.set(nulls_first);
+                    for sorter in cells.sorters.borrow().iter() {
+                        sorter.changed(SorterChange::Different);
+                    }
+                }
             }
-        } else {
-            if ctx.has_class("selected") {
-                ctx.remove_class("selected");
+        });
+        group.add_action(&nulls_action);
+        let select_action = gio::SimpleAction::new("select_column", Some(&i32::static_variant_type()));
+        let clear_action = gio::SimpleAction::new("clear_selection", None);
+        select_action.connect_activate({
+            let cells = self.clone();
+            move |_, param| {
+                if let Some(ix) = param.and_then(|p| p.get::<i32>() ) {
+                    cells.switch_selected(ix as usize);
+                }
+            }
+        });
+        clear_action.connect_activate({
+            let cells = self.clone();
+            move |_, _| {
+                cells.unselect_all();
+                cells.clear_range();
             }
+        });
+        group.add_action(&select_action);
+        group.add_action(&clear_action);
+        let select_all_action = gio::SimpleAction::new("select_all", None);
+        select_all_action.connect_activate({
+            let cells = self.clone();
+            move |_, _| cells.select_all()
+        });
+        group.add_action(&select_all_action);
+        let copy_action = gio::SimpleAction::new("copy", Some(&String::static_variant_type()));
+        copy_action.connect_activate({
+            let cells = self.clone();
+            move |_, param| {
+                if let Some(format) = param.and_then(|p| p.get::<String>() ) {
+                    cells.copy(&format);
+                }
+            }
+        });
+        group.add_action(&copy_action);
+        group
+    }
+
+    fn unselect_all(&self) {
+        let selected = self.selection.borrow().selected_cols();
+        for ix in selected {
+            self.switch_selected(ix);
         }
-    }*/
-    unimplemented!()
-}*/
+    }
 
+    fn switch_selected(&self, col : usize) {
+        let (selected, callbacks) = {
+            let mut selection = self.selection.borrow_mut();
+            let is_selected = match selection.selected.get_mut(col) {
+                Some(s) => {
+                    *s = !*s;
+                    *s
+                },
+                None => return
+            };
+            if is_selected {
+                self.range.borrow_mut().clear();
+            }
+            (selection.selected_cols(), selection.on_selected.clone())
+        };
+        self.restyle_selected();
+        for f in callbacks.iter() {
+            f(selected.clone(), col);
+        }
+    }
 
+}
@@ -13,6 +13,8 @@ use crate::client::OpenedScripts;
 use super::QueriesContent;
 use crate::client::ActiveConnection;
 use filecase::MultiArchiverImpl;
+use std::rc::Rc;
+use std::cell::RefCell;
 
 #[derive(Debug, Clone)]
 pub struct QueriesTitlebar {
@@ -21,7 +23,13 @@ pub struct QueriesTitlebar {
     pub exec_btn : ExecButton,
     pub sidebar_toggle : ToggleButton,
     pub main_menu : MainMenu,
-    pub sidebar_hide_action : gio::SimpleAction
+    pub sidebar_hide_action : gio::SimpleAction,
+
+    // Lists the open connections. The active item is the connection bound to the selected script.
+    pub session_combo : ComboBoxText,
+    session_ids : Rc<RefCell<Vec<usize>>>,
+
+    pub tx_box : TransactionBox
 }
 
 impl QueriesTitlebar {
@@ -38,6 +46,15 @@ impl QueriesTitlebar {
         let exec_btn = ExecButton::build();
         left_bx.append(&sidebar_toggle);
         left_bx.append(&exec_btn.btn);
+
+        let session_combo = ComboBoxText::new();
+        session_combo.set_sensitive(false);
+        session_combo.set_margin_start(6);
+        session_combo.set_tooltip_text(Some("Connection bound to the current script"));
+        left_bx.append(&session_combo);
+
+        let tx_box = TransactionBox::build();
+        left_bx.append(&tx_box.bx);
         header.pack_start(&left_bx);
 
         let menu_button = MenuButton::builder().icon_name("open-menu-symbolic").build();
@@ -47,7 +64,197 @@ impl QueriesTitlebar {
         menu_button.set_popover(Some(&main_menu.popover));
         let sidebar_hide_action = gio::SimpleAction::new_stateful("sidebar_hide", None, &(0).to_variant());
 
-        Self { header, menu_button, exec_btn, sidebar_toggle, main_menu, sidebar_hide_action }
+        let session_ids = Rc::new(RefCell::new(Vec::new()));
+        Self { header, menu_button, exec_btn, sidebar_toggle, main_menu, sidebar_hide_action, session_combo, session_ids, tx_box }
+    }
+
+}
+
+impl React<ActiveConnection> for QueriesTitlebar {
+
+    fn react(&self, conn : &ActiveConnection) {
+        conn.connect_session_switched({
+            let combo = self.session_combo.clone();
+            let session_ids = self.session_ids.clone();
+            move |opt_session| {
+                if let Some((id, info, _)) = opt_session {
+                    let mut ids = session_ids.borrow_mut();
+                    if !ids.contains(&id) {
+                        combo.append(Some(&id.to_string()), &crate::client::session_label(&info));
+                        ids.push(id);
+                    }
+                    combo.set_active_id(Some(&id.to_string()));
+                    combo.set_sensitive(true);
+                } else {
+                    combo.set_active_id(None);
+                }
+            }
+        });
+        conn.connect_session_closed({
+            let combo = self.session_combo.clone();
+            let session_ids = self.session_ids.clone();
+            move |(id, _)| {
+                let mut ids = session_ids.borrow_mut();
+                if let Some(pos) = ids.iter().position(|i| *i == id ) {
+                    ids.remove(pos);
+                    combo.remove(pos as i32);
+                }
+                combo.set_sensitive(ids.len() > 0);
+            }
+        });
+        self.tx_box.react(conn);
+    }
+
+}
+
+/* Controls for the manual transaction mode of the focused session. When auto-commit is
+off, the statements of all executions are kept within a single transaction, which
+stays open until the user commits or rolls it back here. */
+#[derive(Debug, Clone)]
+pub struct TransactionBox {
+    pub bx : Box,
+    pub manual_btn : ToggleButton,
+    pub status : Label,
+    pub commit_btn : Button,
+    pub rollback_btn : Button,
+    pub savepoint_btn : MenuButton,
+    pub savepoint_entry : Entry,
+    pub create_savepoint_btn : Button,
+    pub savepoint_list : ListBox,
+    pub rollback_to_btn : Button
+}
+
+impl TransactionBox {
+
+    fn build() -> Self {
+        let bx = Box::new(Orientation::Horizontal, 0);
+        bx.set_margin_start(6);
+
+        let manual_btn = ToggleButton::builder()
+            .icon_name("document-edit-symbolic")
+            .tooltip_text("Manual transactions (auto-commit off)")
+            .sensitive(false)
+            .build();
+        bx.append(&manual_btn);
+
+        let tx_bx = Box::new(Orientation::Horizontal, 0);
+        tx_bx.style_context().add_class("linked");
+        tx_bx.set_margin_start(6);
+
+        let status = Label::new(Some("Transaction open"));
+        status.style_context().add_class("caption-heading");
+        status.set_margin_end(6);
+
+        let commit_btn = Button::builder().label("Commit").build();
+        commit_btn.style_context().add_class("suggested-action");
+        let rollback_btn = Button::builder().label("Rollback").build();
+        rollback_btn.style_context().add_class("destructive-action");
+
+        let savepoint_btn = MenuButton::builder().icon_name("view-more-symbolic").tooltip_text("Savepoints").build();
+        let popover = Popover::new();
+        let pop_bx = Box::new(Orientation::Vertical, 6);
+        pop_bx.set_margin_top(6);
+        pop_bx.set_margin_bottom(6);
+        pop_bx.set_margin_start(6);
+        pop_bx.set_margin_end(6);
+
+        let create_bx = Box::new(Orientation::Horizontal, 0);
+        create_bx.style_context().add_class("linked");
+        let savepoint_entry = Entry::builder().placeholder_text("Savepoint name").build();
+        let create_savepoint_btn = Button::builder().icon_name("list-add-symbolic").tooltip_text("Create savepoint").build();
+        create_bx.append(&savepoint_entry);
+        create_bx.append(&create_savepoint_btn);
+        pop_bx.append(&create_bx);
+
+        let savepoint_list = ListBox::new();
+        savepoint_list.set_selection_mode(SelectionMode::Single);
+        pop_bx.append(&savepoint_list);
+        let rollback_to_btn = Button::builder().label("Rollback to savepoint").sensitive(false).build();
+        pop_bx.append(&rollback_to_btn);
+
+        savepoint_list.connect_row_selected({
+            let rollback_to_btn = rollback_to_btn.clone();
+            move |_, opt_row| {
+                rollback_to_btn.set_sensitive(opt_row.is_some());
+            }
+        });
+
+        popover.set_child(Some(&pop_bx));
+        savepoint_btn.set_popover(Some(&popover));
+
+        tx_bx.append(&status);
+        tx_bx.append(&commit_btn);
+        tx_bx.append(&rollback_btn);
+        tx_bx.append(&savepoint_btn);
+        tx_bx.set_visible(false);
+        bx.append(&tx_bx);
+
+        Self {
+            bx,
+            manual_btn,
+            status,
+            commit_btn,
+            rollback_btn,
+            savepoint_btn,
+            savepoint_entry,
+            create_savepoint_btn,
+            savepoint_list,
+            rollback_to_btn
+        }
+    }
+
+    pub fn selected_savepoint(&self) -> Option<String> {
+        self.savepoint_list.selected_row()
+            .and_then(|row| row.child() )
+            .and_then(|child| child.downcast::<Label>().ok() )
+            .map(|lbl| lbl.text().to_string() )
+    }
+
+}
+
+impl React<ActiveConnection> for TransactionBox {
+
+    fn react(&self, conn : &ActiveConnection) {
+        conn.connect_session_switched({
+            let manual_btn = self.manual_btn.clone();
+            move |opt_session| {
+                manual_btn.set_sensitive(opt_session.is_some());
+            }
+        });
+        conn.connect_transaction_changed({
+            let tx_box = self.clone();
+            move |tx| {
+                tx_box.manual_btn.set_active(tx.manual);
+                if let Some(tx_bx) = tx_box.status.parent() {
+                    tx_bx.set_visible(tx.open);
+                }
+                if tx.failed {
+                    tx_box.status.set_text("Transaction aborted");
+                    tx_box.status.style_context().add_class("error");
+                } else {
+                    tx_box.status.set_text("Transaction open");
+                    tx_box.status.style_context().remove_class("error");
+                }
+                if tx.savepoints.len() > 0 {
+                    tx_box.status.set_tooltip_text(Some(&format!("Savepoints: {}", tx.savepoints.join(", "))));
+                } else {
+                    tx_box.status.set_tooltip_text(None);
+                }
+
+                // A failed transaction accepts only a rollback.
+                tx_box.commit_btn.set_sensitive(!tx.failed);
+
+                while let Some(row) = tx_box.savepoint_list.row_at_index(0) {
+                    tx_box.savepoint_list.remove(&row);
+                }
+                for name in tx.savepoints.iter() {
+                    let lbl = Label::new(Some(name));
+                    lbl.set_halign(Align::Start);
+                    tx_box.savepoint_list.append(&lbl);
+                }
+                tx_box.rollback_to_btn.set_sensitive(false);
+            }
+        });
     }
 
 }
@@ -86,6 +293,17 @@ pub struct ExecButton {
     // Carries user intent to execute current SQL script.
     pub queue_exec_action : gio::SimpleAction,
 
+    // Carries user intent to execute only the statement under the cursor. Enabled
+    // together with queue_exec_action.
+    pub queue_current_action : gio::SimpleAction,
+
+    // Carries user intent to export the result of the current script to a file. Enabled
+    // together with queue_exec_action.
+    pub queue_export_action : gio::SimpleAction,
+
+    // Carries the content of the current script to be exported as its parameter.
+    pub export_action : gio::SimpleAction,
+
     // This closes all queried tables. The table tabs can be restored with the restore action.
     pub clear_action : gio::SimpleAction,
 
@@ -122,6 +340,11 @@ impl ExecButton {
         exec_section.append(Some("Scheduled"), Some("win.schedule"));
         exec_menu.append_section(Some("Execution mode"), &exec_section);
 
+        let current_section = gio::Menu::new();
+        current_section.append(Some("Current statement"), Some("win.queue_current_execution"));
+        current_section.append(Some("Export query to file…"), Some("win.queue_query_export"));
+        exec_menu.append_section(None, &current_section);
+
         let workspace_section = gio::Menu::new();
         workspace_section.append(Some("Restore"), Some("win.restore"));
         workspace_section.append(Some("Clear"), Some("win.clear"));
@@ -130,6 +353,9 @@ impl ExecButton {
         let btn = SplitButton::builder().icon_name("download-db-symbolic").menu_model(&exec_menu).sensitive(false).build();
         let exec_action = gio::SimpleAction::new_stateful("execute", Some(&String::static_variant_type()), &(-1i32).to_variant());
         let queue_exec_action = gio::SimpleAction::new("queue_execution", None);
+        let queue_current_action = gio::SimpleAction::new("queue_current_execution", None);
+        let queue_export_action = gio::SimpleAction::new("queue_query_export", None);
+        let export_action = gio::SimpleAction::new("export_query", Some(&String::static_variant_type()));
         let clear_action = gio::SimpleAction::new("clear", None);
         let restore_action = gio::SimpleAction::new("restore", None);
         exec_action.set_enabled(false);
@@ -144,6 +370,12 @@ impl ExecButton {
             }
         });
         queue_exec_action.set_enabled(false);
+        queue_exec_action.bind_property("enabled", &queue_current_action, "enabled")
+            .flags(glib::BindingFlags::SYNC_CREATE)
+            .build();
+        queue_exec_action.bind_property("enabled", &queue_export_action, "enabled")
+            .flags(glib::BindingFlags::SYNC_CREATE)
+            .build();
         
         let schedule_action = gio::SimpleAction::new_stateful("schedule", None, &(false).to_variant());
         let single_action = gio::SimpleAction::new_stateful("single", None, &(true).to_variant());
@@ -167,7 +399,7 @@ impl ExecButton {
         // single_action.set_enabled(true);
         // schedule_action.
         // btn.activate_action(&exec_action, None);
-        Self { btn, queue_exec_action, exec_action, clear_action, restore_action, schedule_action, single_action }
+        Self { btn, queue_exec_action, queue_current_action, queue_export_action, export_action, exec_action, clear_action, restore_action, schedule_action, single_action }
     }
 
 }
@@ -12,6 +12,12 @@ use super::table::*;
 use crate::tables::table::Table;
 use crate::ui::PlotView;
 use papyri::render::Panel;
+use crate::sql::ServerNotice;
+use crate::sql::object::DBObject;
+use crate::sql::edit;
+use crate::client::ActiveConnection;
+use std::rc::Rc;
+use std::cell::RefCell;
 
 use crate::client::UserState;
 
@@ -19,7 +25,19 @@ use crate::client::UserState;
 pub struct QueriesWorkspace {
     pub tab_view : libadwaita::TabView,
     pub tab_bar : libadwaita::TabBar,
-    pub bx : Box
+    pub bx : Box,
+
+    // Activated with the statements that write the changes made to an editable table.
+    pub apply_action : gio::SimpleAction,
+
+    // Server notices of the last execution, shown at a page after the result tables.
+    notices : Rc<RefCell<Vec<ServerNotice>>>,
+
+    // Queries that produced the current tables.
+    queries : Rc<RefCell<Vec<String>>>,
+
+    // Schema of the focused connection, used to find the primary keys of editable tables.
+    schema : Rc<RefCell<Option<Vec<DBObject>>>>
 }
 
 fn configure_tab(tab_view : &libadwaita::TabView, tab_bar : &libadwaita::TabBar) {
@@ -48,7 +66,11 @@ impl QueriesWorkspace {
         bx.set_margin_bottom(0);
         bx.append(&tab_view);
         bx.append(&tab_bar);
-        Self { tab_view, tab_bar, bx }
+        let notices = Rc::new(RefCell::new(Vec::new()));
+        let queries = Rc::new(RefCell::new(Vec::new()));
+        let schema = Rc::new(RefCell::new(None));
+        let apply_action = gio::SimpleAction::new("apply_changes", Some(&String::static_variant_type()));
+        Self { tab_view, tab_bar, bx, apply_action, notices, queries, schema }
     }
 
 }
@@ -60,17 +82,94 @@ pub fn close_all_pages(tab_view : &libadwaita::TabView) {
     }
 }
 
-const COLUMN_LIMIT : usize = 50;
+const COLUMN_LIMIT : usize = 1000;
+
+const MESSAGES_TITLE : &'static str = "Messages";
+
+fn is_messages_page(page : &libadwaita::TabPage) -> bool {
+    page.child().widget_name().as_str() == MESSAGES_TITLE
+}
+
+/* Replaces the messages page (always the last one) by a page listing the notices,
+or removes it if there are none. */
+pub fn update_messages_page(tab_view : &libadwaita::TabView, notices : &[ServerNotice]) {
+    let n = tab_view.n_pages();
+    if n > 0 {
+        let last = tab_view.nth_page(n - 1);
+        if is_messages_page(&last) {
+            tab_view.close_page(&last);
+        }
+    }
+    if notices.len() == 0 {
+        return;
+    }
+
+    let list = ListBox::new();
+    list.set_selection_mode(SelectionMode::None);
+    for notice in notices.iter() {
+        let mut txt = format!(
+            "<b>{}</b>  <tt>{}</tt>  Statement {}\n{}",
+            glib::markup_escape_text(&notice.severity),
+            glib::markup_escape_text(&notice.code),
+            notice.stmt + 1,
+            glib::markup_escape_text(&notice.message)
+        );
+        if let Some(detail) = &notice.detail {
+            txt += &format!("\n<i>Detail:</i> {}", glib::markup_escape_text(detail));
+        }
+        if let Some(hint) = &notice.hint {
+            txt += &format!("\n<i>Hint:</i> {}", glib::markup_escape_text(hint));
+        }
+        let lbl = Label::new(Some(&txt));
+        lbl.set_use_markup(true);
+        lbl.set_wrap(true);
+        lbl.set_selectable(true);
+        lbl.set_xalign(0.0);
+        lbl.set_margin_top(6);
+        lbl.set_margin_bottom(6);
+        lbl.set_margin_start(12);
+        lbl.set_margin_end(12);
+        list.append(&lbl);
+    }
+    let scroll = ScrolledWindow::new();
+    scroll.set_widget_name(MESSAGES_TITLE);
+    scroll.set_child(Some(&list));
+    let page = tab_view.append(&scroll);
+    page.set_title(&format!("{} ({})", MESSAGES_TITLE, notices.len()));
+    page.set_icon(Some(&gio::ThemedIcon::new("dialog-information-symbolic")));
+}
+
+/* Tables are editable when they come from the query of a single table, and the result carries its
+primary key. Results of parameterized queries are not editable, since their query (appended to the
+edits to refresh the table) would be sent without the parameter values. */
+fn edit_target(tbl : &Table, query : Option<&String>, schema : Option<&[DBObject]>) -> Option<EditTarget> {
+    let source = tbl.source();
+    let name = source.name?;
+    if source.relation.is_some() || name.contains(" : ") {
+        return None;
+    }
+    let query = query.filter(|q| !q.trim().is_empty() && !crate::sql::parsing::has_placeholders(q) )?;
+    let cols = edit::find_table_columns(&name, schema?)?;
+    let (pks, editable) = edit::editable_columns(&tbl.names()[..], &cols[..])?;
+    Some(EditTarget { table : name, query : query.clone(), pks, editable })
+}
 
-pub fn populate_with_tables(tab_view : &libadwaita::TabView, tables : &[Table], state : &UserState) -> Vec<libadwaita::TabPage> {
+pub fn populate_with_tables(
+    tab_view : &libadwaita::TabView,
+    tables : &[Table],
+    queries : &[String],
+    schema : Option<&[DBObject]>,
+    apply_action : &gio::SimpleAction,
+    state : &UserState
+) -> Vec<libadwaita::TabPage> {
     close_all_pages(&tab_view);
     let mut new_pages = Vec::new();
-    for tbl in tables.iter() {
+    for (ix, tbl) in tables.iter().enumerate() {
         if let Some(val) = tbl.single_json_field() {
             match Panel::new_from_json(&val.to_string()) {
                 Ok(panel) => {
                     let view = PlotView::new_from_panel(panel.clone());
-                    let tab_page = tab_view.append(&view.parent);
+                    let tab_page = tab_view.append(&view.overlay);
                     configure_plot_page(&tab_page, &panel);
                     new_pages.push(tab_page);
                     continue;
@@ -78,8 +177,12 @@ pub fn populate_with_tables(tab_view : &libadwaita::TabView, tables : &[Table],
                 _ => { }
             }
         }
-        let tbl_wid = TableWidget::new_from_table(&tbl, state.execution.row_limit as usize, COLUMN_LIMIT);
-        let tab_page = tab_view.append(&tbl_wid.scroll_window);
+        let row_limit = state.execution.row_limit as usize;
+        let tbl_wid = match edit_target(&tbl, queries.get(ix), schema) {
+            Some(target) => TableWidget::new_editable(&tbl, row_limit, COLUMN_LIMIT, target, apply_action),
+            None => TableWidget::new_from_table(&tbl, row_limit, COLUMN_LIMIT)
+        };
+        let tab_page = tab_view.append(&tbl_wid.bx);
         new_pages.push(tab_page.clone());
         configure_table_page(&tab_page, &tbl);
     }
@@ -91,17 +194,56 @@ impl<'a> React<Environment> for QueriesWorkspace {
     fn react(&self, env : &Environment) {
         let tab_view = self.tab_view.clone();
         let user_state = env.user_state.clone();
+        let notices = self.notices.clone();
+        let queries = self.queries.clone();
+        let schema = self.schema.clone();
+        let apply_action = self.apply_action.clone();
         env.connect_table_update(move |tables| {
             let user_state = user_state.borrow();
             let past_sel_page = tab_view.selected_page().map(|page| tab_view.page_position(&page) as usize );
             let past_n_pages = tab_view.n_pages() as usize;
-            let new_pages = populate_with_tables(&tab_view, &tables[..], &*user_state);
+            let new_pages = populate_with_tables(
+                &tab_view,
+                &tables[..],
+                &queries.borrow()[..],
+                schema.borrow().as_ref().map(|s| &s[..] ),
+                &apply_action,
+                &*user_state
+            );
+            update_messages_page(&tab_view, &notices.borrow()[..]);
             if let Some(page_ix) = past_sel_page {
-                if new_pages.len() == past_n_pages {
+                if new_pages.len() == past_n_pages && page_ix < new_pages.len() {
                     tab_view.set_selected_page(&new_pages[page_ix]);
                 }
             }
         });
+        let queries = self.queries.clone();
+        env.connect_queries_update(move |new_queries| {
+            queries.replace(new_queries);
+        });
+        let tab_view = self.tab_view.clone();
+        let notices = self.notices.clone();
+        env.connect_table_clear(move |_| {
+            close_all_pages(&tab_view);
+            update_messages_page(&tab_view, &notices.borrow()[..]);
+        });
+        let tab_view = self.tab_view.clone();
+        let notices = self.notices.clone();
+        env.connect_notices_update(move |new_notices| {
+            update_messages_page(&tab_view, &new_notices[..]);
+            notices.replace(new_notices);
+        });
+    }
+
+}
+
+impl React<ActiveConnection> for QueriesWorkspace {
+
+    fn react(&self, conn : &ActiveConnection) {
+        let schema = self.schema.clone();
+        conn.connect_schema_update(move |new_schema| {
+            schema.replace(new_schema);
+        });
     }
 
 }
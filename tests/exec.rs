@@ -4,6 +4,7 @@ use gtk4::glib;
 mod common;
 use std::thread;
 use queries::sql::StatementOutput;
+use queries::sql::SafetyPolicies;
 use std::rc::Rc;
 use std::cell::RefCell;
 
@@ -404,12 +405,6 @@ pub fn execution() {
         let stmt_ix = Rc::new(RefCell::new(0));
         
         let user_state = SharedUserState::default();
-        {
-            let mut us = user_state.borrow_mut();
-            us.execution.accept_dml = true;
-            us.execution.accept_ddl = true;
-        }
-        
         let privs = privileges();
         let all_stmts = [
             TABLE_CREATION.to_string(),
@@ -472,6 +467,9 @@ pub fn execution() {
                                 println!("Statement rejected by client: {}", msg);
                             }                            
                         },
+                        StatementOutput::Rejected(e) => {
+                            println!("Statement rejected by server: {} ({})", e.message, e.code);
+                        },
                         out => { 
                             println!("Statement executed: {:?}", out);
                         }
@@ -495,7 +493,9 @@ pub fn execution() {
             }
         });
         
-        conn.send(ActiveConnectionAction::ConnectRequest(temp.uri()));
+        let mut uri = temp.uri();
+        uri.info.policies = SafetyPolicies::allow_all();
+        conn.send(ActiveConnectionAction::ConnectRequest(uri));
         println!("Running...");
         common::run_loop_for_ms(10_000);
         println!("Done");